@@ -0,0 +1,240 @@
+use std::time::Duration;
+
+use lib::{asset::AssetId, events::gas_station::TransactionSequenceSigned, pyth::PriceIdentifier};
+use near_fetch::signer::SignerExt;
+use near_jsonrpc_client::{
+    NEAR_MAINNET_ARCHIVAL_RPC_URL, NEAR_MAINNET_RPC_URL, NEAR_TESTNET_ARCHIVAL_RPC_URL,
+    NEAR_TESTNET_RPC_URL,
+};
+use near_primitives::types::AccountId;
+use near_token::NearToken;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::TransactionSequenceCreation;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const SIGNED_PAGE_SIZE: u32 = 100;
+
+pub struct App {
+    pub near: near_fetch::Client,
+    pub contract_id: AccountId,
+}
+
+#[derive(Deserialize)]
+struct LocalAssetConfiguration {
+    oracle_asset_id: [u8; 32],
+    decimals: u8,
+}
+
+#[derive(Deserialize)]
+struct ForeignChainConfiguration {
+    oracle_asset_id: String,
+}
+
+impl App {
+    pub fn new(near_network: &str, contract_id: AccountId) -> Self {
+        let rpc_url = match &near_network.to_lowercase()[..] {
+            "mainnet" => NEAR_MAINNET_RPC_URL,
+            "testnet" => NEAR_TESTNET_RPC_URL,
+            "mainnet-archival" => NEAR_MAINNET_ARCHIVAL_RPC_URL,
+            "testnet-archival" => NEAR_TESTNET_ARCHIVAL_RPC_URL,
+            _ => near_network,
+        };
+
+        Self {
+            near: near_fetch::Client::new(rpc_url),
+            contract_id,
+        }
+    }
+
+    async fn view<T: serde::de::DeserializeOwned>(
+        &self,
+        contract_id: &AccountId,
+        function: &str,
+        args: Value,
+    ) -> T {
+        self.near
+            .view(contract_id, function)
+            .args_json(args)
+            .await
+            .unwrap()
+            .json::<T>()
+            .unwrap()
+    }
+
+    /// Estimates the fee in NEAR using the same oracle prices the contract
+    /// will use.
+    pub async fn estimate_fee(&self, transaction_rlp_hex: &str, chain_id: u64) -> u128 {
+        let (oracle_id, local_asset, foreign_chain) = tokio::join!(
+            self.view::<AccountId>(&self.contract_id, "get_oracle_id", json!({})),
+            self.view::<LocalAssetConfiguration>(
+                &self.contract_id,
+                "get_accepted_local_asset",
+                json!({ "asset_id": AssetId::Native }),
+            ),
+            self.view::<ForeignChainConfiguration>(
+                &self.contract_id,
+                "get_foreign_chain",
+                json!({ "chain_id": chain_id.to_string() }),
+            ),
+        );
+
+        let foreign_price_id: [u8; 32] = bs58::decode(&foreign_chain.oracle_asset_id)
+            .into_vec()
+            .unwrap()
+            .try_into()
+            .unwrap();
+
+        let (local_asset_price, foreign_asset_price) = tokio::join!(
+            self.view::<Value>(
+                &oracle_id,
+                "get_ema_price",
+                json!({ "price_id": PriceIdentifier(local_asset.oracle_asset_id) }),
+            ),
+            self.view::<Value>(
+                &oracle_id,
+                "get_ema_price",
+                json!({ "price_id": PriceIdentifier(foreign_price_id) }),
+            ),
+        );
+
+        self.view::<String>(
+            &self.contract_id,
+            "estimate_fee",
+            json!({
+                "transaction_rlp_hex": transaction_rlp_hex,
+                "local_asset_price": local_asset_price,
+                "local_asset_decimals": local_asset.decimals,
+                "foreign_asset_price": foreign_asset_price,
+            }),
+        )
+        .await
+        .parse()
+        .unwrap()
+    }
+
+    pub async fn create_transaction(
+        &self,
+        signer: &dyn SignerExt,
+        token_id: &str,
+        transaction_rlp_hex: &str,
+        use_paymaster: bool,
+        deposit: NearToken,
+    ) -> TransactionSequenceCreation {
+        self.near
+            .call(signer, &self.contract_id, "create_transaction")
+            .args_json(json!({
+                "token_id": token_id,
+                "transaction_rlp_hex": transaction_rlp_hex,
+                "use_paymaster": use_paymaster,
+            }))
+            .deposit(deposit)
+            .max_gas()
+            .transact()
+            .await
+            .unwrap()
+            .json::<TransactionSequenceCreation>()
+            .unwrap()
+    }
+
+    pub async fn sign_next(&self, signer: &dyn SignerExt, id: u64) -> String {
+        self.near
+            .call(signer, &self.contract_id, "sign_next")
+            .args_json(json!({
+                "id": id.to_string(),
+            }))
+            .max_gas()
+            .transact()
+            .await
+            .unwrap()
+            .json::<String>()
+            .unwrap()
+    }
+
+    pub async fn get_pending_transaction_sequence(&self, id: u64) -> Option<Value> {
+        self.view(
+            &self.contract_id,
+            "get_pending_transaction_sequence",
+            json!({ "id": id.to_string() }),
+        )
+        .await
+    }
+
+    pub async fn list_signed_transaction_sequences_after(
+        &self,
+        block_height: u64,
+        tag: Option<String>,
+        offset: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<TransactionSequenceSigned> {
+        self.view(
+            &self.contract_id,
+            "list_signed_transaction_sequences_after",
+            json!({
+                "block_height": block_height.to_string(),
+                "tag": tag,
+                "offset": offset,
+                "limit": limit,
+            }),
+        )
+        .await
+    }
+
+    pub async fn report_relayed(
+        &self,
+        signer: &dyn SignerExt,
+        id: &str,
+        transaction_hashes: Vec<String>,
+    ) {
+        self.near
+            .call(signer, &self.contract_id, "report_relayed")
+            .args_json(json!({
+                "id": id,
+                "transaction_hashes": transaction_hashes,
+            }))
+            .transact()
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap();
+    }
+
+    /// Searches the signed sequence history for `id`.
+    pub async fn find_signed(&self, id: u64) -> Option<TransactionSequenceSigned> {
+        let mut offset = 0;
+
+        loop {
+            let page = self
+                .list_signed_transaction_sequences_after(
+                    0,
+                    None,
+                    Some(offset),
+                    Some(SIGNED_PAGE_SIZE),
+                )
+                .await;
+
+            if page.is_empty() {
+                return None;
+            }
+
+            if let Some(signed) = page.into_iter().find(|s| s.id.0 == id) {
+                return Some(signed);
+            }
+
+            offset += SIGNED_PAGE_SIZE;
+        }
+    }
+
+    /// Polls until the sequence is no longer pending, then returns its signed
+    /// transactions.
+    pub async fn wait_for_signed(&self, id: u64) -> TransactionSequenceSigned {
+        while self.get_pending_transaction_sequence(id).await.is_some() {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+
+        self.find_signed(id)
+            .await
+            .expect("Sequence was removed without being signed")
+    }
+}
@@ -0,0 +1,379 @@
+use clap::{Args, Parser, Subcommand};
+use ethers_core::{
+    types::{transaction::eip1559::Eip1559TransactionRequest, Address, Bytes, U256},
+    utils::hex,
+};
+use lib::events::gas_station::TransactionSequenceSigned;
+use near_crypto::InMemorySigner;
+use near_primitives::types::AccountId;
+use near_token::NearToken;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+mod app;
+use app::App;
+
+mod relay;
+use relay::Relayer;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Gas station contract ID.
+    #[arg(long, short)]
+    contract_id: AccountId,
+
+    /// NEAR RPC to use. Specify one of "mainnet", "testnet", or a URL.
+    #[arg(long, short, default_value_t = default_network())]
+    network: String,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a transaction sequence. If a paymaster is used and no deposit is
+    /// specified, the deposit is estimated with `estimate_fee`.
+    CreateTx {
+        #[command(flatten)]
+        transaction: TransactionArgs,
+
+        /// NFT chain key token ID, previously approved to the gas station.
+        #[arg(long, short)]
+        token_id: String,
+
+        /// Fund the transaction with a paymaster.
+        #[arg(long, short = 'p')]
+        use_paymaster: bool,
+
+        /// Deposit to attach. Excess is refunded by the contract.
+        #[arg(long, short)]
+        deposit: Option<NearToken>,
+
+        /// Percentage added to the estimated fee to absorb price movement
+        /// between estimation and execution.
+        #[arg(long, default_value_t = 5)]
+        fee_margin_percent: u32,
+
+        /// Sign every transaction in the sequence after creating it.
+        #[arg(long, short)]
+        sign: bool,
+
+        /// Path to the key file to use for signing.
+        #[arg(long, short)]
+        key_file: PathBuf,
+    },
+    /// Request the signature of the next transaction in a sequence.
+    SignNext {
+        id: u64,
+
+        /// Keep signing until the sequence is complete.
+        #[arg(long, short)]
+        all: bool,
+
+        /// Path to the key file to use for signing.
+        #[arg(long, short)]
+        key_file: PathBuf,
+    },
+    /// Show a pending or signed transaction sequence.
+    Status {
+        id: u64,
+
+        /// Poll until the sequence is fully signed.
+        #[arg(long, short)]
+        wait: bool,
+    },
+    /// List signed transaction sequences.
+    ListSigned {
+        /// Only list sequences signed at or after this block height.
+        #[arg(long, short, default_value_t = 0)]
+        after_block: u64,
+
+        /// Only list sequences with this tag.
+        #[arg(long, short)]
+        tag: Option<String>,
+
+        #[arg(long, short)]
+        limit: Option<u32>,
+
+        /// Output raw JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Continuously broadcast signed sequences to foreign chain RPCs, in
+    /// order, waiting for each transaction to be mined.
+    Relay {
+        /// EVM JSON-RPC endpoint for a chain, as `<chain ID>=<url>`. May be
+        /// repeated.
+        #[arg(long, short, value_parser = relay::parse_rpc, required = true)]
+        rpc: Vec<(u64, String)>,
+
+        /// File in which relaying progress is recorded.
+        #[arg(long, short, default_value = "relay-state.json")]
+        state_file: PathBuf,
+
+        /// Key file of an account with the relayer role. If specified,
+        /// `report_relayed` is called after each sequence is confirmed.
+        #[arg(long, short)]
+        key_file: Option<PathBuf>,
+    },
+    /// Estimate the fee, in yoctoNEAR, to sponsor a transaction with a
+    /// paymaster.
+    EstimateFee {
+        #[command(flatten)]
+        transaction: TransactionArgs,
+    },
+}
+
+#[derive(Args)]
+struct TransactionArgs {
+    /// Foreign chain ID.
+    #[arg(long)]
+    chain_id: u64,
+
+    /// Receiver address (hex).
+    #[arg(long)]
+    to: Address,
+
+    /// Value in the foreign chain's smallest unit (decimal).
+    #[arg(long, default_value = "0", value_parser = parse_u256)]
+    value: U256,
+
+    /// Call data (hex).
+    #[arg(long)]
+    data: Option<Bytes>,
+
+    #[arg(long, default_value_t = 21000)]
+    gas: u64,
+
+    #[arg(long)]
+    max_fee_per_gas: u128,
+
+    #[arg(long)]
+    max_priority_fee_per_gas: u128,
+
+    #[arg(long)]
+    nonce: u64,
+}
+
+impl TransactionArgs {
+    fn rlp_hex(&self) -> String {
+        let request = Eip1559TransactionRequest {
+            chain_id: Some(self.chain_id.into()),
+            from: None,
+            to: Some(self.to.into()),
+            data: self.data.clone(),
+            gas: Some(self.gas.into()),
+            max_fee_per_gas: Some(self.max_fee_per_gas.into()),
+            max_priority_fee_per_gas: Some(self.max_priority_fee_per_gas.into()),
+            access_list: vec![].into(),
+            value: Some(self.value),
+            nonce: Some(self.nonce.into()),
+        };
+
+        hex::encode_prefixed(request.rlp())
+    }
+}
+
+fn parse_u256(s: &str) -> Result<U256, String> {
+    U256::from_dec_str(s).map_err(|e| e.to_string())
+}
+
+fn default_network() -> String {
+    std::env::var("NEAR_ENV")
+        .ok()
+        .unwrap_or_else(|| "testnet".to_string())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeyFile {
+    private_key: String,
+    account_id: AccountId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TransactionSequenceCreation {
+    id: String,
+    pending_signature_count: u32,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Cli::parse();
+
+    let app = App::new(&args.network, args.contract_id);
+
+    match args.command {
+        Command::CreateTx {
+            transaction,
+            token_id,
+            use_paymaster,
+            deposit,
+            fee_margin_percent,
+            sign,
+            key_file,
+        } => {
+            let signer = get_signer_from_key_file(&key_file);
+            println!("Acting account: {}", signer.account_id);
+
+            let transaction_rlp_hex = transaction.rlp_hex();
+
+            let deposit = match deposit {
+                Some(deposit) => deposit,
+                None if use_paymaster => {
+                    estimate_deposit(
+                        &app,
+                        &transaction_rlp_hex,
+                        transaction.chain_id,
+                        fee_margin_percent,
+                    )
+                    .await
+                }
+                None => NearToken::from_yoctonear(0),
+            };
+
+            let creation = app
+                .create_transaction(
+                    &signer,
+                    &token_id,
+                    &transaction_rlp_hex,
+                    use_paymaster,
+                    deposit,
+                )
+                .await;
+
+            println!("Sequence ID: {}", creation.id);
+            println!("Pending signatures: {}", creation.pending_signature_count);
+
+            if sign {
+                let id = creation.id.parse().unwrap();
+                for _ in 0..creation.pending_signature_count {
+                    println!("{}", app.sign_next(&signer, id).await);
+                }
+            }
+        }
+        Command::SignNext { id, all, key_file } => {
+            let signer = get_signer_from_key_file(&key_file);
+            println!("Acting account: {}", signer.account_id);
+
+            loop {
+                println!("{}", app.sign_next(&signer, id).await);
+
+                if !all || app.get_pending_transaction_sequence(id).await.is_none() {
+                    break;
+                }
+            }
+        }
+        Command::Status { id, wait } => print_status(&app, id, wait).await,
+        Command::ListSigned {
+            after_block,
+            tag,
+            limit,
+            json,
+        } => {
+            let signed = app
+                .list_signed_transaction_sequences_after(after_block, tag, None, limit)
+                .await;
+
+            print_signed_list(&signed, json);
+        }
+        Command::Relay {
+            rpc,
+            state_file,
+            key_file,
+        } => {
+            let signer = key_file.as_deref().map(get_signer_from_key_file);
+
+            if let Some(signer) = &signer {
+                println!("Reporting as: {}", signer.account_id);
+            }
+
+            Relayer {
+                app: &app,
+                http: reqwest::Client::new(),
+                rpcs: rpc.into_iter().collect(),
+                state_file,
+                reporter: signer
+                    .as_ref()
+                    .map(|s| s as &dyn near_fetch::signer::SignerExt),
+            }
+            .run()
+            .await;
+        }
+        Command::EstimateFee { transaction } => {
+            let fee = app
+                .estimate_fee(&transaction.rlp_hex(), transaction.chain_id)
+                .await;
+            println!("{}", NearToken::from_yoctonear(fee));
+        }
+    }
+}
+
+/// Estimates the fee of a sponsored transaction, plus a safety margin.
+async fn estimate_deposit(
+    app: &App,
+    transaction_rlp_hex: &str,
+    chain_id: u64,
+    fee_margin_percent: u32,
+) -> NearToken {
+    let fee = app.estimate_fee(transaction_rlp_hex, chain_id).await;
+    let deposit = fee + fee * u128::from(fee_margin_percent) / 100;
+    println!(
+        "Estimated fee: {}, depositing {}",
+        NearToken::from_yoctonear(fee),
+        NearToken::from_yoctonear(deposit),
+    );
+    NearToken::from_yoctonear(deposit)
+}
+
+async fn print_status(app: &App, id: u64, wait: bool) {
+    let signed = if wait {
+        Some(app.wait_for_signed(id).await)
+    } else if let Some(pending) = app.get_pending_transaction_sequence(id).await {
+        println!("Pending:");
+        println!("{}", serde_json::to_string_pretty(&pending).unwrap());
+        None
+    } else {
+        app.find_signed(id).await
+    };
+
+    if let Some(signed) = signed {
+        println!("Signed:");
+        print_signed(&signed);
+    } else if !wait {
+        println!("Sequence {id} not found. It may have been removed or expired.");
+    }
+}
+
+fn print_signed_list(signed: &[TransactionSequenceSigned], json: bool) {
+    if json {
+        println!("{}", serde_json::to_string(signed).unwrap());
+    } else {
+        for s in signed {
+            print_signed(s);
+            println!();
+        }
+    }
+}
+
+fn print_signed(signed: &TransactionSequenceSigned) {
+    println!("ID: {}", signed.id.0);
+    println!("Chain ID: {}", signed.foreign_chain_id);
+    println!("Created by: {}", signed.created_by_account_id);
+    if let Some(tag) = &signed.tag {
+        println!("Tag: {tag}");
+    }
+    for (i, tx) in signed.signed_transactions.iter().enumerate() {
+        println!("Transaction {i}: {tx}");
+    }
+}
+
+fn get_signer_from_key_file(key_file: &Path) -> InMemorySigner {
+    let key_file = std::fs::read_to_string(key_file).unwrap();
+    let KeyFile {
+        account_id,
+        private_key,
+    } = serde_json::from_str::<KeyFile>(&key_file).unwrap();
+    InMemorySigner::from_secret_key(account_id.clone(), private_key.parse().unwrap())
+}
@@ -0,0 +1,190 @@
+//! Broadcasts signed transaction sequences to foreign chains.
+
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use ethers_core::utils::{hex, keccak256};
+use lib::events::gas_station::TransactionSequenceSigned;
+use near_fetch::signer::SignerExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::app::App;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const RECEIPT_POLL_INTERVAL: Duration = Duration::from_secs(3);
+const RECEIPT_TIMEOUT: Duration = Duration::from_secs(300);
+const PAGE_SIZE: u32 = 50;
+
+/// Progress of the relayer, persisted between runs.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RelayState {
+    /// Index into the contract's signed sequence history of the next
+    /// sequence to relay.
+    pub next_offset: u32,
+    /// Confirmed transaction hashes by sequence ID.
+    pub relayed: HashMap<String, Vec<String>>,
+}
+
+impl RelayState {
+    fn load(path: &PathBuf) -> Self {
+        std::fs::read_to_string(path)
+            .ok()
+            .map(|s| serde_json::from_str(&s).unwrap())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, path: &PathBuf) {
+        std::fs::write(path, serde_json::to_string_pretty(self).unwrap()).unwrap();
+    }
+}
+
+pub struct Relayer<'a> {
+    pub app: &'a App,
+    pub http: reqwest::Client,
+    /// EVM JSON-RPC endpoints by chain ID.
+    pub rpcs: HashMap<u64, String>,
+    pub state_file: PathBuf,
+    /// If set, `report_relayed` is called on the contract after each
+    /// sequence is confirmed.
+    pub reporter: Option<&'a dyn SignerExt>,
+}
+
+impl Relayer<'_> {
+    pub async fn run(&self) -> ! {
+        let mut state = RelayState::load(&self.state_file);
+
+        loop {
+            let page = self
+                .app
+                .list_signed_transaction_sequences_after(
+                    0,
+                    None,
+                    Some(state.next_offset),
+                    Some(PAGE_SIZE),
+                )
+                .await;
+
+            if page.is_empty() {
+                tokio::time::sleep(POLL_INTERVAL).await;
+                continue;
+            }
+
+            for sequence in page {
+                let id = sequence.id.0.to_string();
+                match self.relay(&sequence).await {
+                    Ok(hashes) => {
+                        println!("Relayed sequence {id}: {hashes:?}");
+                        state.relayed.insert(id.clone(), hashes.clone());
+
+                        if let Some(reporter) = self.reporter {
+                            self.app.report_relayed(reporter, &id, hashes).await;
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to relay sequence {id}: {e}");
+                    }
+                }
+
+                state.next_offset += 1;
+                state.save(&self.state_file);
+            }
+        }
+    }
+
+    /// Broadcasts each transaction of the sequence in order, waiting for each
+    /// to be mined before sending the next, since later transactions may
+    /// depend on funds from earlier ones.
+    async fn relay(&self, sequence: &TransactionSequenceSigned) -> Result<Vec<String>, String> {
+        let chain_id: u64 = sequence
+            .foreign_chain_id
+            .parse()
+            .map_err(|_| format!("Unparseable foreign chain ID {}", sequence.foreign_chain_id,))?;
+
+        let rpc = self
+            .rpcs
+            .get(&chain_id)
+            .ok_or_else(|| format!("No RPC configured for chain ID {chain_id}"))?;
+
+        let mut hashes = Vec::with_capacity(sequence.signed_transactions.len());
+
+        for raw in &sequence.signed_transactions {
+            let bytes = hex::decode(raw).map_err(|e| e.to_string())?;
+            let hash = hex::encode_prefixed(keccak256(&bytes));
+
+            // Already-known transactions (e.g. after a restart) are fine.
+            if let Err(e) = self.rpc(rpc, "eth_sendRawTransaction", json!([raw])).await {
+                if !e.contains("already known") && !e.contains("nonce too low") {
+                    return Err(e);
+                }
+            }
+
+            self.wait_for_receipt(rpc, &hash).await?;
+
+            hashes.push(hash);
+        }
+
+        Ok(hashes)
+    }
+
+    async fn wait_for_receipt(&self, rpc: &str, hash: &str) -> Result<(), String> {
+        let deadline = tokio::time::Instant::now() + RECEIPT_TIMEOUT;
+
+        loop {
+            let receipt = self
+                .rpc(rpc, "eth_getTransactionReceipt", json!([hash]))
+                .await?;
+
+            if let Some(status) = receipt.get("status").and_then(Value::as_str) {
+                return if status == "0x1" {
+                    Ok(())
+                } else {
+                    Err(format!("Transaction {hash} reverted"))
+                };
+            }
+
+            if tokio::time::Instant::now() > deadline {
+                return Err(format!("Timed out waiting for receipt of {hash}"));
+            }
+
+            tokio::time::sleep(RECEIPT_POLL_INTERVAL).await;
+        }
+    }
+
+    async fn rpc(&self, url: &str, method: &str, params: Value) -> Result<Value, String> {
+        let response = self
+            .http
+            .post(url)
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .json::<Value>()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if let Some(error) = response.get("error") {
+            return Err(error.to_string());
+        }
+
+        Ok(response.get("result").cloned().unwrap_or(Value::Null))
+    }
+}
+
+/// Parses `<chain ID>=<url>`.
+pub fn parse_rpc(s: &str) -> Result<(u64, String), String> {
+    let (chain_id, url) = s
+        .split_once('=')
+        .ok_or_else(|| "Expected <chain ID>=<url>".to_string())?;
+
+    Ok((
+        chain_id
+            .parse()
+            .map_err(|_| "Invalid chain ID".to_string())?,
+        url.to_string(),
+    ))
+}
@@ -1,4 +1,7 @@
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use futures_util::StreamExt;
 use lib::pyth::PriceIdentifier;
@@ -11,7 +14,7 @@ use near_primitives::types::AccountId;
 use near_token::NearToken;
 use reqwest::Url;
 use serde_json::json;
-use tokio::{sync::mpsc, task::JoinSet};
+use tokio::sync::mpsc;
 
 use crate::{PriceResponse, PythFeedDescription, PythPrice};
 
@@ -168,11 +171,45 @@ impl App {
             .unwrap()
     }
 
+    /// Whether every feed's on-chain price was published within
+    /// `max_staleness`.
+    async fn onchain_prices_fresh(
+        &self,
+        price_ids: &[PriceIdentifier],
+        max_staleness: Duration,
+    ) -> bool {
+        if max_staleness.is_zero() {
+            return false;
+        }
+
+        let now = chrono::Utc::now().timestamp();
+
+        let prices = futures_util::future::join_all(price_ids.iter().map(|&id| async move {
+            self.near
+                .view(&self.contract_id, "get_price")
+                .args_json(json!({
+                    "price_identifier": id,
+                }))
+                .await
+                .ok()
+                .and_then(|r| r.json::<Option<PythPrice>>().ok())
+                .flatten()
+        }))
+        .await;
+
+        prices.into_iter().all(|price| {
+            price
+                .and_then(|p| u64::try_from(now - p.publish_time).ok())
+                .map_or(false, |age| age < max_staleness.as_secs())
+        })
+    }
+
     pub async fn stream_update(
         self: Arc<Self>,
         signer: Arc<dyn SignerExt>,
         price_ids: &[PriceIdentifier],
         max_fee: NearToken,
+        limits: StreamUpdateLimits,
     ) -> ! {
         let (send, mut recv) = mpsc::unbounded_channel::<Vec<String>>();
 
@@ -216,6 +253,9 @@ impl App {
             }
         });
 
+        let mut newest_data = None;
+        let mut last_push: Option<Instant> = None;
+
         loop {
             tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
 
@@ -223,23 +263,41 @@ impl App {
 
             recv.recv_many(&mut msgs, recv.len()).await;
 
-            if let Some(newest_data) = msgs.pop() {
-                println!("Skipping {}, pushing newest data only", msgs.len());
-                let mut set = JoinSet::new();
-                for data in newest_data {
-                    let signer = Arc::clone(&signer);
-                    let s = Arc::clone(&self);
-                    set.spawn(async move {
-                        let res = s
-                            .push_update_to_chain(signer.as_ref(), &data, &max_fee)
-                            .await
-                            .unwrap();
-                        println!("TXID: {}", res.details.transaction.hash);
-                    });
-                }
+            if let Some(data) = msgs.pop() {
+                newest_data = Some(data);
+            }
 
-                while set.join_next().await.is_some() {}
+            if newest_data.is_none()
+                || last_push.map_or(false, |t| t.elapsed() < limits.min_interval)
+            {
+                continue;
             }
+
+            if self
+                .onchain_prices_fresh(price_ids, limits.max_staleness)
+                .await
+            {
+                continue;
+            }
+
+            // The update messages from the stream are accumulator updates
+            // that cover every requested feed, so a single push updates all
+            // of them.
+            for data in newest_data.take().unwrap_or_default() {
+                let res = self
+                    .push_update_to_chain(signer.as_ref(), &data, &max_fee)
+                    .await;
+                println!("TXID: {}", res.details.transaction.hash);
+            }
+
+            last_push = Some(Instant::now());
         }
     }
 }
+
+/// Limits on how often `stream_update` pushes updates.
+#[derive(Debug, Clone, Copy)]
+pub struct StreamUpdateLimits {
+    pub min_interval: Duration,
+    pub max_staleness: Duration,
+}
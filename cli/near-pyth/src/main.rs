@@ -10,10 +10,14 @@ use std::{
     path::{Path, PathBuf},
     str::FromStr,
     sync::Arc,
+    time::Duration,
 };
 
 mod app;
-use app::App;
+use app::{App, StreamUpdateLimits};
+
+mod monitor;
+use monitor::MonitorOptions;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -64,13 +68,53 @@ enum Command {
         key_file: PathBuf,
     },
     /// Continuously push price updates to the Pyth oracle contract for each of
-    /// the queried feeds.
+    /// the queried feeds. Each push is a single batched update covering all of
+    /// the feeds.
     StreamUpdate {
         queries: Vec<String>,
 
         /// Path to the key file to use for signing.
         #[arg(long, short)]
         key_file: PathBuf,
+
+        /// Minimum number of seconds between pushes.
+        #[arg(long, default_value_t = 1)]
+        min_interval: u64,
+
+        /// Skip pushes while every on-chain price was published less than
+        /// this many seconds ago.
+        #[arg(long, default_value_t = 0)]
+        max_staleness: u64,
+    },
+    /// Compare the gas station's tracked paymaster balances with their
+    /// balances on the foreign chains. Exits with a non-zero code if any
+    /// alerts are raised, unless `--interval` is specified.
+    MonitorGasStation {
+        /// Gas station contract ID.
+        gas_station_id: AccountId,
+
+        /// EVM JSON-RPC endpoint for a chain, as `<chain ID>=<url>`. May be
+        /// repeated.
+        #[arg(long, short, value_parser = monitor::parse_rpc, required = true)]
+        rpc: Vec<(u64, String)>,
+
+        /// Alert when a paymaster's balance is below this amount, in the
+        /// foreign chain's smallest unit.
+        #[arg(long, default_value_t = 0)]
+        min_balance: u128,
+
+        /// Alert when a paymaster's balance exceeds the tracked balance by
+        /// more than this percentage.
+        #[arg(long, default_value_t = 10)]
+        max_drift_percent: u32,
+
+        /// URL to POST alerts to, as JSON.
+        #[arg(long, short)]
+        webhook: Option<Url>,
+
+        /// Check continuously, every this many seconds.
+        #[arg(long, short)]
+        interval: Option<u64>,
     },
 }
 
@@ -163,7 +207,12 @@ async fn main() {
         .with_endpoint(args.endpoint);
 
     match args.command {
-        Command::StreamUpdate { queries, key_file } => {
+        Command::StreamUpdate {
+            queries,
+            key_file,
+            min_interval,
+            max_staleness,
+        } => {
             let price_ids = app.resolve_price_ids(queries).await;
 
             let signer = get_signer_from_key_file(&key_file);
@@ -171,9 +220,35 @@ async fn main() {
             println!("Acting account: {}", signer.account_id);
 
             Arc::new(app)
-                .stream_update(Arc::new(signer), &price_ids, args.max_fee)
+                .stream_update(
+                    Arc::new(signer),
+                    &price_ids,
+                    args.max_fee,
+                    StreamUpdateLimits {
+                        min_interval: Duration::from_secs(min_interval),
+                        max_staleness: Duration::from_secs(max_staleness),
+                    },
+                )
                 .await;
         }
+        Command::MonitorGasStation {
+            gas_station_id,
+            rpc,
+            min_balance,
+            max_drift_percent,
+            webhook,
+            interval,
+        } => {
+            let options = MonitorOptions {
+                gas_station_id,
+                rpcs: rpc.into_iter().collect(),
+                min_balance,
+                max_drift_percent,
+                webhook,
+            };
+
+            monitor_gas_station(&app, &options, interval).await;
+        }
         Command::ContractGet { queries } => {
             let price_ids = app.resolve_price_ids(queries).await;
 
@@ -231,6 +306,24 @@ async fn main() {
     }
 }
 
+/// Runs the monitor once, or every `interval` seconds if given. A single run
+/// exits with a failure status if any alert was raised.
+async fn monitor_gas_station(app: &App, options: &MonitorOptions, interval: Option<u64>) {
+    loop {
+        let alerts = app.monitor_gas_station(options).await;
+
+        for alert in &alerts {
+            eprintln!("ALERT: {alert}");
+        }
+
+        match interval {
+            Some(interval) => tokio::time::sleep(Duration::from_secs(interval)).await,
+            None if alerts.is_empty() => break,
+            None => std::process::exit(1),
+        }
+    }
+}
+
 fn get_signer_from_key_file(key_file: &Path) -> InMemorySigner {
     let key_file = std::fs::read_to_string(key_file).unwrap();
     let KeyFile {
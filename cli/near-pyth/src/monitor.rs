@@ -0,0 +1,171 @@
+//! Compares the gas station's view of paymaster balances with the balances
+//! on the foreign chains.
+
+use std::collections::HashMap;
+
+use near_primitives::types::AccountId;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::app::App;
+
+#[derive(Debug, Clone, Deserialize)]
+struct ForeignChain {
+    chain_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Paymaster {
+    token_id: String,
+    foreign_address: String,
+    minimum_available_balance: String,
+}
+
+pub struct MonitorOptions {
+    pub gas_station_id: AccountId,
+    /// EVM JSON-RPC endpoints by chain ID.
+    pub rpcs: HashMap<u64, String>,
+    /// Alert when a paymaster's on-chain balance is below this amount.
+    pub min_balance: u128,
+    /// Alert when the on-chain balance exceeds the contract's tracked balance
+    /// by more than this percentage.
+    pub max_drift_percent: u32,
+    pub webhook: Option<reqwest::Url>,
+}
+
+impl App {
+    /// Checks every paymaster of every chain once, returning the alerts.
+    pub async fn monitor_gas_station(&self, options: &MonitorOptions) -> Vec<String> {
+        let chains = self
+            .near
+            .view(&options.gas_station_id, "get_foreign_chains")
+            .args_json(json!({}))
+            .await
+            .unwrap()
+            .json::<Vec<ForeignChain>>()
+            .unwrap();
+
+        let mut alerts = vec![];
+
+        for chain in chains {
+            let chain_id: u64 = chain.chain_id.parse().unwrap();
+
+            let Some(rpc) = options.rpcs.get(&chain_id) else {
+                println!("Chain {chain_id}: no RPC configured, skipping");
+                continue;
+            };
+
+            let paymasters = self
+                .near
+                .view(&options.gas_station_id, "get_paymasters")
+                .args_json(json!({ "chain_id": chain.chain_id }))
+                .await
+                .unwrap()
+                .json::<Vec<Paymaster>>()
+                .unwrap();
+
+            for paymaster in paymasters {
+                let tracked: u128 = paymaster.minimum_available_balance.parse().unwrap();
+
+                let actual = match self.evm_balance(rpc, &paymaster.foreign_address).await {
+                    Ok(actual) => actual,
+                    Err(e) => {
+                        alerts.push(format!(
+                            "Chain {chain_id}, paymaster {}: failed to fetch balance: {e}",
+                            paymaster.token_id,
+                        ));
+                        continue;
+                    }
+                };
+
+                println!(
+                    "Chain {chain_id}, paymaster {} ({}): tracked {tracked}, actual {actual}",
+                    paymaster.token_id, paymaster.foreign_address,
+                );
+
+                let prefix = format!(
+                    "Chain {chain_id}, paymaster {} ({})",
+                    paymaster.token_id, paymaster.foreign_address,
+                );
+
+                if actual < options.min_balance {
+                    alerts.push(format!(
+                        "{prefix}: low balance {actual} < {}",
+                        options.min_balance,
+                    ));
+                }
+
+                if actual < tracked {
+                    alerts.push(format!(
+                        "{prefix}: contract tracks {tracked} but only {actual} is available",
+                    ));
+                } else if (actual - tracked) > tracked / 100 * u128::from(options.max_drift_percent)
+                {
+                    alerts.push(format!(
+                        "{prefix}: actual balance {actual} exceeds tracked {tracked} by more than {}%",
+                        options.max_drift_percent,
+                    ));
+                }
+            }
+        }
+
+        if let Some(webhook) = &options.webhook {
+            if !alerts.is_empty() {
+                let result = self
+                    .http
+                    .post(webhook.clone())
+                    .json(&json!({
+                        "gas_station_id": options.gas_station_id,
+                        "alerts": alerts,
+                    }))
+                    .send()
+                    .await;
+
+                if let Err(e) = result {
+                    eprintln!("Failed to call webhook: {e}");
+                }
+            }
+        }
+
+        alerts
+    }
+
+    async fn evm_balance(&self, rpc: &str, address: &str) -> Result<u128, String> {
+        let response = self
+            .http
+            .post(rpc)
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "eth_getBalance",
+                "params": [address, "latest"],
+            }))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .json::<Value>()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let hex = response
+            .get("result")
+            .and_then(Value::as_str)
+            .ok_or_else(|| format!("Unexpected response: {response}"))?;
+
+        u128::from_str_radix(hex.trim_start_matches("0x"), 16).map_err(|e| e.to_string())
+    }
+}
+
+/// Parses `<chain ID>=<url>`.
+pub fn parse_rpc(s: &str) -> Result<(u64, String), String> {
+    let (chain_id, url) = s
+        .split_once('=')
+        .ok_or_else(|| "Expected <chain ID>=<url>".to_string())?;
+
+    Ok((
+        chain_id
+            .parse()
+            .map_err(|_| "Invalid chain ID".to_string())?,
+        url.to_string(),
+    ))
+}
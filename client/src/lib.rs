@@ -0,0 +1,99 @@
+//! Types and cross-contract interfaces for calling the gas station contract
+//! from other NEAR contracts and off-chain services, without depending on
+//! the contract crate.
+
+// `#[ext_contract]` generates a function per trait method outside of the
+// trait, where allows on the methods do not apply.
+#![allow(clippy::too_many_arguments)]
+
+use lib::asset::AssetId;
+use near_sdk::{
+    ext_contract,
+    json_types::{U128, U64},
+    AccountId,
+};
+
+mod types;
+pub use types::{
+    CreateTransactionOptions, Eip1559JsonRequest, FundingOptions, FundingTransactionPreview,
+    Nep141ReceiverCreateTransactionArgs, PaymasterFunding, SequenceCallback, SigningOrder,
+    TransactionSequenceCreation,
+};
+
+pub use lib::sequence_callback::{ext_sequence_callback_receiver, SequenceCallbackReceiver};
+
+/// The public methods of the gas station contract most commonly called by
+/// other contracts. Sponsored creation methods take the fee as the attached
+/// deposit, so attach one with `with_attached_deposit`.
+#[ext_contract(ext_gas_station)]
+pub trait GasStation {
+    fn create_transaction(
+        &mut self,
+        token_id: String,
+        transaction_rlp_hex: String,
+        use_paymaster: Option<bool>,
+        options: Option<CreateTransactionOptions>,
+    ) -> TransactionSequenceCreation;
+
+    fn create_transaction_json(
+        &mut self,
+        token_id: String,
+        transaction: Eip1559JsonRequest,
+        use_paymaster: Option<bool>,
+        options: Option<CreateTransactionOptions>,
+    ) -> TransactionSequenceCreation;
+
+    /// Creates a sponsored sequence with one transaction on each of several
+    /// chains.
+    fn create_multichain_transaction(
+        &mut self,
+        token_id: String,
+        transactions_rlp_hex: Vec<String>,
+        tag: Option<String>,
+        callback: Option<SequenceCallback>,
+    ) -> TransactionSequenceCreation;
+
+    /// Creates a sequence on behalf of the owner of a session granted to the
+    /// predecessor, paying `max_fee` from the session budget.
+    fn create_transaction_with_session(
+        &mut self,
+        session_id: U64,
+        transaction_rlp_hex: String,
+        use_paymaster: Option<bool>,
+        max_fee: U128,
+        options: Option<CreateTransactionOptions>,
+    ) -> TransactionSequenceCreation;
+
+    /// Creates the sequence of the next due occurrence of a recurring
+    /// authorization, paying its `max_fee` from the prepaid balance.
+    fn execute_recurring_authorization(
+        &mut self,
+        authorization_id: U64,
+    ) -> TransactionSequenceCreation;
+
+    /// Returns the signed transaction, RLP-encoded and `0x`-prefixed.
+    fn sign_next(&mut self, id: U64) -> String;
+
+    fn remove_transaction(&mut self, id: U64);
+
+    fn report_relayed(&mut self, id: U64, transaction_hashes: Vec<String>);
+
+    fn get_failed_refund(&self, account_id: AccountId, asset_id: AssetId) -> U128;
+
+    fn claim_failed_refund(&mut self, asset_id: AssetId);
+
+    fn get_relayer_tips(&self, account_id: AccountId, asset_id: AssetId) -> U128;
+
+    fn claim_relayer_tips(&mut self, asset_id: AssetId);
+
+    fn get_sighash_for(&self, transaction_rlp_hex: String) -> String;
+
+    /// The funding transaction a sponsored sequence would receive if it
+    /// were created now, without creating it.
+    fn preview_funding_transaction(
+        &self,
+        transaction_rlp_hex: String,
+        token_id: String,
+        funding: Option<FundingOptions>,
+    ) -> FundingTransactionPreview;
+}
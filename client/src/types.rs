@@ -0,0 +1,157 @@
+//! Argument and return types of the gas station contract.
+
+use lib::foreign_address::ForeignAddress;
+use near_sdk::{
+    json_types::{U128, U64},
+    near, AccountId,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct TransactionSequenceCreation {
+    pub id: U64,
+    pub pending_signature_count: u32,
+    pub paymaster_funding: Option<PaymasterFunding>,
+}
+
+/// Details of the paymaster funding transaction of a sequence, allowing
+/// relayers to verify the paymaster's balance on the foreign chain before
+/// signing begins.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct PaymasterFunding {
+    pub paymaster_token_id: String,
+    pub paymaster_address: ForeignAddress,
+    pub to: ForeignAddress,
+    pub value: U128,
+    pub nonce: U64,
+}
+
+/// How the paymaster funds the sender of a sponsored transaction.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct FundingOptions {
+    /// The sender is a contract (e.g. a smart contract wallet), so the
+    /// funding transfer runs its receive hook and costs more than a plain
+    /// transfer.
+    #[serde(default)]
+    pub is_contract_target: bool,
+    /// Overrides the gas limit of the funding transaction. Cannot exceed the
+    /// chain's `max_transfer_gas`.
+    pub transfer_gas: Option<U128>,
+    #[serde(default)]
+    pub signing_order: SigningOrder,
+}
+
+/// Optional parameters of `create_transaction` and `create_transaction_json`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct CreateTransactionOptions {
+    /// Arbitrary label stored with the sequence. Its storage is paid from
+    /// the attached deposit, which must then be in NEAR.
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(default)]
+    pub funding: Option<FundingOptions>,
+    /// Priority tier of a sponsored sequence; 0 if omitted.
+    #[serde(default)]
+    pub priority: Option<u8>,
+    /// Hex-encoded Merkle proof that the receiver is whitelisted.
+    #[serde(default)]
+    pub receiver_proof: Option<Vec<String>>,
+    #[serde(default)]
+    pub callback: Option<SequenceCallback>,
+    #[serde(default)]
+    pub tenant_id: Option<String>,
+    /// Repeating a creation with the same key returns the original result
+    /// instead of creating another sequence.
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+}
+
+/// Order in which the transactions of a sponsored sequence are signed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+#[serde(rename_all = "snake_case")]
+pub enum SigningOrder {
+    /// The funding transaction is signed first.
+    #[default]
+    PaymasterFirst,
+    /// The user transaction is signed first. The funding transaction is only
+    /// signed once a market maker calls `release_funding`, e.g. after
+    /// confirming that the user is ready to broadcast.
+    UserFirst,
+}
+
+/// JSON representation of an EIP-1559 transaction request. Amounts are
+/// decimal strings and `data` is hex-encoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct Eip1559JsonRequest {
+    pub chain_id: U64,
+    pub to: ForeignAddress,
+    pub value: U128,
+    #[serde(default)]
+    pub data: Option<String>,
+    pub gas: U128,
+    pub max_fee_per_gas: U128,
+    pub max_priority_fee_per_gas: U128,
+    #[serde(default)]
+    pub nonce: Option<U64>,
+}
+
+/// The funding transaction a sponsored sequence would currently receive, as
+/// returned by `preview_funding_transaction`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct FundingTransactionPreview {
+    pub paymaster_token_id: String,
+    pub transaction: Eip1559JsonRequest,
+    /// The unsigned transaction, RLP-encoded, e.g. for simulation.
+    pub transaction_rlp_hex: String,
+}
+
+/// A contract to notify once a sequence is fully signed. The gas station
+/// calls `on_sequence_signed(id, signed_transactions, msg)` on `receiver_id`
+/// (see [`SequenceCallbackReceiver`](crate::SequenceCallbackReceiver)) with a
+/// fixed amount of gas. The result of the call is ignored.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[near(serializers = [borsh, json])]
+pub struct SequenceCallback {
+    pub receiver_id: AccountId,
+    pub msg: String,
+}
+
+/// The `msg` of an `ft_transfer_call` (or `mt_transfer_call`) to the gas
+/// station that pays for a new sequence.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[near(serializers = [json])]
+pub struct Nep141ReceiverCreateTransactionArgs {
+    pub token_id: String,
+    pub transaction_rlp_hex: String,
+    pub use_paymaster: Option<bool>,
+    /// Caps the part of the transferred amount used to pay the fee. The rest
+    /// (less the tip) is returned to the sender right away.
+    #[serde(default)]
+    pub max_fee: Option<U128>,
+    /// Set aside for the relayer that broadcasts the signed transactions.
+    #[serde(default)]
+    pub tip: Option<U128>,
+    #[serde(default)]
+    pub callback: Option<SequenceCallback>,
+}
+
+impl Nep141ReceiverCreateTransactionArgs {
+    /// Splits the transferred `amount` into the fee deposit, the tip, and the
+    /// unused amount to return to the sender. `None` if the tip exceeds the
+    /// amount.
+    pub fn split_deposit(&self, amount: u128) -> Option<(u128, u128, u128)> {
+        let tip = self.tip.map_or(0, |tip| tip.0);
+        let remaining = amount.checked_sub(tip)?;
+        let fee_deposit = self
+            .max_fee
+            .map_or(remaining, |max_fee| remaining.min(max_fee.0));
+
+        Some((fee_deposit, tip, remaining - fee_deposit))
+    }
+}
@@ -0,0 +1,228 @@
+//! Example integration with the gas station.
+//!
+//! Users deposit a NEP-141 token with `ft_transfer_call`, naming a recipient
+//! on a foreign chain. The bridger asks the gas station to sponsor an ERC-20
+//! `transfer` of the same amount from its own chain key to the recipient,
+//! paying the fee in NEAR. Once the sequence is fully signed, the gas station
+//! notifies the bridger, which stores the signed transactions for relayers.
+
+use ethers_core::utils::hex;
+use gas_station_client::{
+    ext_gas_station, CreateTransactionOptions, Eip1559JsonRequest, SequenceCallback,
+    SequenceCallbackReceiver, TransactionSequenceCreation,
+};
+use lib::{foreign_address::ForeignAddress, Rejectable};
+use near_sdk::{
+    collections::LookupMap,
+    env,
+    json_types::{U128, U64},
+    near, require, AccountId, BorshStorageKey, Gas, NearToken, PanicOnDefault, Promise,
+    PromiseError, PromiseOrValue,
+};
+use near_sdk_contract_tools::ft::Nep141Receiver;
+
+const CREATE_WITHDRAWAL_GAS: Gas = Gas::from_tgas(60);
+const ON_WITHDRAWAL_CREATED_GAS: Gas = Gas::from_tgas(10);
+
+/// `transfer(address,uint256)`
+const ERC20_TRANSFER_SELECTOR: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+
+/// The `msg` of a deposit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct BridgeMsg {
+    pub recipient: ForeignAddress,
+}
+
+/// How withdrawals are built on the foreign chain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct WithdrawalSettings {
+    pub chain_id: U64,
+    /// The ERC-20 contract that releases the bridged tokens.
+    pub foreign_token: ForeignAddress,
+    pub gas: U128,
+    pub max_fee_per_gas: U128,
+    pub max_priority_fee_per_gas: U128,
+    /// Attached to each `create_transaction_json` call to pay the gas station
+    /// fee, in yoctoNEAR. The gas station refunds the excess.
+    pub fee_deposit: U128,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct Withdrawal {
+    pub sender_id: AccountId,
+    pub recipient: ForeignAddress,
+    pub amount: U128,
+    /// Set once the gas station has signed the sequence, in the order to
+    /// broadcast them.
+    pub signed_transactions: Option<Vec<String>>,
+}
+
+#[derive(BorshStorageKey)]
+#[near]
+enum StorageKey {
+    Withdrawals,
+}
+
+#[derive(PanicOnDefault)]
+#[near(contract_state)]
+pub struct Contract {
+    pub gas_station_id: AccountId,
+    /// The only NEP-141 token accepted for deposits.
+    pub token_contract_id: AccountId,
+    /// A chain key held by this contract and approved for use by the gas
+    /// station.
+    pub chain_key_token_id: String,
+    pub settings: WithdrawalSettings,
+    /// Nonce of the next withdrawal signed with the chain key.
+    pub next_nonce: u64,
+    /// Withdrawals, by gas station sequence ID.
+    pub withdrawals: LookupMap<u64, Withdrawal>,
+}
+
+#[near]
+impl Contract {
+    #[init]
+    pub fn new(
+        gas_station_id: AccountId,
+        token_contract_id: AccountId,
+        chain_key_token_id: String,
+        settings: WithdrawalSettings,
+        next_nonce: U64,
+    ) -> Self {
+        Self {
+            gas_station_id,
+            token_contract_id,
+            chain_key_token_id,
+            settings,
+            next_nonce: next_nonce.0,
+            withdrawals: LookupMap::new(StorageKey::Withdrawals),
+        }
+    }
+
+    pub fn get_withdrawal(&self, id: U64) -> Option<Withdrawal> {
+        self.withdrawals.get(&id.0)
+    }
+
+    /// Signs the next transaction of a withdrawal. Anyone may call this.
+    pub fn sign_withdrawal(&mut self, id: U64) -> Promise {
+        ext_gas_station::ext(self.gas_station_id.clone()).sign_next(id)
+    }
+
+    /// Keeps the deposit if the withdrawal was created, and returns it to the
+    /// sender otherwise.
+    #[private]
+    pub fn on_withdrawal_created(
+        &mut self,
+        sender_id: AccountId,
+        recipient: ForeignAddress,
+        amount: U128,
+        nonce: U64,
+        #[callback_result] result: Result<TransactionSequenceCreation, PromiseError>,
+    ) -> U128 {
+        let Ok(creation) = result else {
+            // Release the nonce, unless a later withdrawal has taken the next one.
+            if self.next_nonce == nonce.0 + 1 {
+                self.next_nonce = nonce.0;
+            }
+            return amount;
+        };
+
+        self.withdrawals.insert(
+            &creation.id.0,
+            &Withdrawal {
+                sender_id,
+                recipient,
+                amount,
+                signed_transactions: None,
+            },
+        );
+
+        U128(0)
+    }
+}
+
+#[near]
+impl Nep141Receiver for Contract {
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        require!(
+            env::predecessor_account_id() == self.token_contract_id,
+            "Unsupported token",
+        );
+
+        let BridgeMsg { recipient } =
+            near_sdk::serde_json::from_str(&msg).expect_or_reject("Invalid message");
+
+        let nonce = self.next_nonce;
+        self.next_nonce = nonce.checked_add(1).unwrap_or_reject();
+
+        let transaction = Eip1559JsonRequest {
+            chain_id: self.settings.chain_id,
+            to: self.settings.foreign_token,
+            value: U128(0),
+            data: Some(erc20_transfer_calldata(&recipient, amount.0)),
+            gas: self.settings.gas,
+            max_fee_per_gas: self.settings.max_fee_per_gas,
+            max_priority_fee_per_gas: self.settings.max_priority_fee_per_gas,
+            nonce: Some(U64(nonce)),
+        };
+
+        ext_gas_station::ext(self.gas_station_id.clone())
+            .with_attached_deposit(NearToken::from_yoctonear(self.settings.fee_deposit.0))
+            .with_static_gas(CREATE_WITHDRAWAL_GAS)
+            .create_transaction_json(
+                self.chain_key_token_id.clone(),
+                transaction,
+                Some(true),
+                Some(CreateTransactionOptions {
+                    callback: Some(SequenceCallback {
+                        receiver_id: env::current_account_id(),
+                        msg: String::new(),
+                    }),
+                    ..Default::default()
+                }),
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(ON_WITHDRAWAL_CREATED_GAS)
+                    .on_withdrawal_created(sender_id, recipient, amount, U64(nonce)),
+            )
+            .into()
+    }
+}
+
+#[near]
+impl SequenceCallbackReceiver for Contract {
+    fn on_sequence_signed(&mut self, id: U64, signed_transactions: Vec<String>, msg: String) {
+        let _ = msg;
+
+        require!(
+            env::predecessor_account_id() == self.gas_station_id,
+            "Only the gas station can report signed sequences",
+        );
+
+        if let Some(mut withdrawal) = self.withdrawals.get(&id.0) {
+            withdrawal.signed_transactions = Some(signed_transactions);
+            self.withdrawals.insert(&id.0, &withdrawal);
+        }
+    }
+}
+
+/// ABI-encoded `transfer(recipient, amount)` call, hex-encoded.
+fn erc20_transfer_calldata(recipient: &ForeignAddress, amount: u128) -> String {
+    let mut data = Vec::with_capacity(4 + 32 + 32);
+    data.extend_from_slice(&ERC20_TRANSFER_SELECTOR);
+    data.extend_from_slice(&[0; 12]);
+    data.extend_from_slice(&recipient.0);
+    data.extend_from_slice(&[0; 16]);
+    data.extend_from_slice(&amount.to_be_bytes());
+
+    hex::encode_prefixed(data)
+}
@@ -0,0 +1,132 @@
+#![allow(clippy::too_many_lines)]
+
+use auto_bridger::{Withdrawal, WithdrawalSettings};
+use lib::foreign_address::ForeignAddress;
+use near_sdk::{
+    json_types::{U128, U64},
+    serde_json::json,
+};
+use near_workspaces::types::{Gas, NearToken};
+use test_support::{approve, mint, TestEnvBuilder};
+
+#[tokio::test]
+async fn deposit_is_bridged_through_gas_station() {
+    let env = TestEnvBuilder::new().accept_local_ft(false).build().await;
+
+    let wasm = near_workspaces::compile_project(env!("CARGO_MANIFEST_DIR"))
+        .await
+        .unwrap();
+    let bridger = env.worker.dev_deploy(&wasm).await.unwrap();
+
+    env.alice
+        .call(env.nft_key.id(), "storage_deposit")
+        .args_json(json!({ "account_id": bridger.id() }))
+        .deposit(NearToken::from_near(1))
+        .transact()
+        .await
+        .unwrap()
+        .unwrap();
+
+    let bridger_key = mint(bridger.as_account(), &env.nft_key).await;
+    approve(
+        bridger.as_account(),
+        &env.nft_key,
+        &env.gas_station,
+        &bridger_key,
+        None,
+    )
+    .await;
+
+    bridger
+        .call("new")
+        .args_json(json!({
+            "gas_station_id": env.gas_station.id(),
+            "token_contract_id": env.local_ft.id(),
+            "chain_key_token_id": bridger_key,
+            "settings": WithdrawalSettings {
+                chain_id: U64(env.chain_ids[0]),
+                foreign_token: ForeignAddress([2; 20]),
+                gas: U128(60_000),
+                max_fee_per_gas: U128(15_000_000_000),
+                max_priority_fee_per_gas: U128(50_000_000),
+                fee_deposit: U128(NearToken::from_near(1).as_yoctonear()),
+            },
+            "next_nonce": U64(0),
+        }))
+        .transact()
+        .await
+        .unwrap()
+        .unwrap();
+
+    env.alice
+        .call(env.local_ft.id(), "mint")
+        .args_json(json!({ "amount": U128(1_000) }))
+        .transact()
+        .await
+        .unwrap()
+        .unwrap();
+
+    let recipient = ForeignAddress([3; 20]);
+
+    env.alice
+        .call(env.local_ft.id(), "ft_transfer_call")
+        .args_json(json!({
+            "receiver_id": bridger.id(),
+            "amount": U128(600),
+            "msg": json!({ "recipient": recipient }).to_string(),
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await
+        .unwrap()
+        .unwrap();
+
+    let alice_balance = env
+        .local_ft
+        .view("ft_balance_of")
+        .args_json(json!({ "account_id": env.alice.id() }))
+        .await
+        .unwrap()
+        .json::<U128>()
+        .unwrap();
+    assert_eq!(alice_balance, U128(400), "Deposit should be kept");
+
+    let withdrawal = bridger
+        .view("get_withdrawal")
+        .args_json(json!({ "id": U64(0) }))
+        .await
+        .unwrap()
+        .json::<Option<Withdrawal>>()
+        .unwrap()
+        .expect("Withdrawal should be recorded");
+    assert_eq!(withdrawal.recipient, recipient);
+    assert_eq!(withdrawal.amount, U128(600));
+    assert_eq!(withdrawal.signed_transactions, None);
+
+    // Paymaster funding, then the withdrawal itself.
+    for _ in 0..2 {
+        env.alice
+            .call(bridger.id(), "sign_withdrawal")
+            .args_json(json!({ "id": U64(0) }))
+            .gas(Gas::from_tgas(300))
+            .transact()
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    let withdrawal = bridger
+        .view("get_withdrawal")
+        .args_json(json!({ "id": U64(0) }))
+        .await
+        .unwrap()
+        .json::<Option<Withdrawal>>()
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        withdrawal.signed_transactions.map(|t| t.len()),
+        Some(2),
+        "Gas station should report the signed transactions",
+    );
+}
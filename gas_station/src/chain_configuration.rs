@@ -1,14 +1,21 @@
-use std::cmp::Ordering;
+use std::{cmp::Ordering, collections::BTreeMap};
 
 use ethers_core::types::U256;
-use lib::{foreign_address::ForeignAddress, pyth};
-use near_sdk::{json_types::U128, near};
+pub use gas_station_client::FundingOptions;
+use lib::{asset::AssetId, foreign_address::ForeignAddress, pyth};
+use near_sdk::{
+    json_types::{U128, U64},
+    near, AccountId,
+};
 
 use crate::{
+    decode_hex,
     error::{
-        ConfidenceIntervalTooLargeError, ExponentTooLargeError, NegativePriceError,
+        ConfidenceIntervalTooLargeError, ConversionRateOutOfBoundsError, DecimalsOutOfRangeError,
+        ExponentTooLargeError, InvalidFeeRateError, NegativePriceError,
         NoPaymasterConfigurationForChainError, PaymasterInsufficientFundsError, PriceDataError,
-        RequestNonceError,
+        RequestNonceError, SponsorshipPausedError, TransactionDataTooLargeError,
+        TransferGasAboveMaximumError, TryCreateTransactionCallbackError, ZeroPriceError,
     },
     valid_transaction_request::ValidTransactionRequest,
     ExpressionOverflowError, NonceOverflowError,
@@ -42,6 +49,34 @@ impl PaymasterConfiguration {
     }
 }
 
+/// Paymaster balance (and nonce) set aside for a funding transaction of a
+/// pending sequence. Released if the sequence is removed before the funding
+/// transaction is signed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct PaymasterReservation {
+    pub chain_id: u64,
+    pub token_id: String,
+    pub amount: [u64; 4],
+    /// `None` if the funding transaction re-uses a nonce consumed earlier,
+    /// e.g. when rebumping.
+    pub nonce: Option<u32>,
+}
+
+impl PaymasterReservation {
+    /// The reservation made for a funding transaction issued by
+    /// `with_request_nonce`, which deducts the transferred value and
+    /// consumes the transaction's nonce.
+    pub fn for_funding_transaction(token_id: &str, transaction: &ValidTransactionRequest) -> Self {
+        Self {
+            chain_id: transaction.chain_id,
+            token_id: token_id.to_string(),
+            amount: transaction.value,
+            nonce: Some(transaction.nonce().low_u32()),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[near(serializers = [json])]
 pub struct ViewPaymasterConfiguration {
@@ -51,16 +86,387 @@ pub struct ViewPaymasterConfiguration {
     pub minimum_available_balance: U128,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct PaymasterOnboardingArgs {
+    pub token_id: String,
+    pub nonce: u32,
+    pub balance: Option<U128>,
+}
+
+/// Projection of how long a paymaster can keep funding transactions at the
+/// recent sponsorship rate of its chain. `None` when there is no recent
+/// sponsorship activity to project from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct ViewPaymasterRunway {
+    pub token_id: String,
+    pub minimum_available_balance: U128,
+    /// Number of typical funding transactions the balance covers.
+    pub estimated_transactions: Option<U64>,
+    /// Blocks until depletion, assuming sponsorships keep rotating evenly
+    /// across the chain's paymasters.
+    pub estimated_blocks: Option<U64>,
+}
+
+/// Sponsorship totals of a chain over fixed windows of blocks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[near]
+pub struct SponsorshipStatistics {
+    pub window_start_block_height: u64,
+    pub window_count: u64,
+    pub window_amount: [u64; 4],
+    /// Totals of the window immediately preceding the current one.
+    pub previous_window_count: u64,
+    pub previous_window_amount: [u64; 4],
+}
+
+impl SponsorshipStatistics {
+    pub const WINDOW_BLOCKS: u64 = 60 * 60; // ~1 hour at 1s/block
+
+    pub fn record(&mut self, amount: U256, block_height: u64) {
+        self.roll(block_height);
+        self.window_count = self.window_count.saturating_add(1);
+        self.window_amount = U256(self.window_amount).saturating_add(amount).0;
+    }
+
+    fn roll(&mut self, block_height: u64) {
+        let elapsed = block_height.saturating_sub(self.window_start_block_height);
+        if elapsed < Self::WINDOW_BLOCKS {
+            return;
+        }
+
+        if elapsed < 2 * Self::WINDOW_BLOCKS {
+            self.previous_window_count = self.window_count;
+            self.previous_window_amount = self.window_amount;
+        } else {
+            self.previous_window_count = 0;
+            self.previous_window_amount = [0; 4];
+        }
+
+        self.window_count = 0;
+        self.window_amount = [0; 4];
+        self.window_start_block_height = block_height - elapsed % Self::WINDOW_BLOCKS;
+    }
+
+    /// Amount sponsored over the last `WINDOW_BLOCKS` blocks, assuming the
+    /// previous window's amount was spread evenly across it.
+    pub fn sliding_window_amount(&self, block_height: u64) -> U256 {
+        let mut stats = self.clone();
+        stats.roll(block_height);
+
+        let previous_weight = Self::WINDOW_BLOCKS
+            .saturating_sub(block_height.saturating_sub(stats.window_start_block_height));
+
+        (U256(stats.previous_window_amount).saturating_mul(U256::from(previous_weight))
+            / U256::from(Self::WINDOW_BLOCKS))
+        .saturating_add(U256(stats.window_amount))
+    }
+
+    /// Recent `(sponsorship count, total amount, blocks)`. Prefers the last
+    /// complete window; falls back to the current one.
+    pub fn recent(&self, block_height: u64) -> Option<(u64, U256, u64)> {
+        let mut stats = self.clone();
+        stats.roll(block_height);
+
+        if stats.previous_window_count > 0 {
+            Some((
+                stats.previous_window_count,
+                U256(stats.previous_window_amount),
+                Self::WINDOW_BLOCKS,
+            ))
+        } else if stats.window_count > 0 {
+            Some((
+                stats.window_count,
+                U256(stats.window_amount),
+                block_height
+                    .saturating_sub(stats.window_start_block_height)
+                    .max(1),
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// Display information for frontends. Not used by the contract itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct ForeignChainMetadata {
+    /// e.g. `"Ethereum Mainnet"`
+    pub chain_name: Option<String>,
+    /// e.g. `"ETH"`
+    pub native_symbol: Option<String>,
+    /// URL with a `{tx_hash}` placeholder, e.g.
+    /// `"https://etherscan.io/tx/{tx_hash}"`
+    pub explorer_url_template: Option<String>,
+}
+
+/// A deposit function on a foreign contract (e.g. `depositTo(address)` on an
+/// ERC-4337 EntryPoint) through which contract senders are funded, for
+/// wallets that cannot receive a plain transfer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct FundingCall {
+    pub target: ForeignAddress,
+    /// Hex-encoded 4-byte function selector, e.g. `"0xb760faf9"`. The
+    /// function must take the sender address as its only argument.
+    pub selector: String,
+}
+
+impl FundingCall {
+    pub fn selector_bytes(&self) -> Option<[u8; 4]> {
+        decode_hex(&self.selector).ok()?.try_into().ok()
+    }
+
+    /// ABI-encoded call crediting `recipient`.
+    pub fn calldata(&self, recipient: &ForeignAddress) -> Vec<u8> {
+        let mut data = Vec::with_capacity(4 + 32);
+        data.extend_from_slice(&self.selector_bytes().unwrap_or_default());
+        data.extend_from_slice(&[0u8; 12]);
+        data.extend_from_slice(&recipient.0);
+        data
+    }
+}
+
+/// Accepted band for the oracle-derived conversion rate between a local
+/// asset and a chain's gas token, in smallest units of the local asset per
+/// whole gas token (before fees). Sponsorship is refused outside the band.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct ConversionRateBounds {
+    pub min_rate: Option<U128>,
+    pub max_rate: Option<U128>,
+}
+
+/// Cost of a transaction's calldata on chains that charge for it on top of
+/// execution gas, e.g. the L1 data fee of rollups.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct CalldataPricing {
+    /// Gas tokens (smallest unit) charged per byte of calldata.
+    pub per_byte_cost: U128,
+    /// Gas tokens (smallest unit) charged per sponsored transaction.
+    pub fixed_overhead: U128,
+    /// Sponsored transactions with more calldata are rejected.
+    pub max_data_bytes: Option<u32>,
+}
+
+/// EIP-1559 fees used for transactions built by the contract itself, e.g. by
+/// `transfer_native`. Kept up to date by market makers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct FeeSuggestion {
+    pub max_fee_per_gas: U128,
+    pub max_priority_fee_per_gas: U128,
+}
+
+/// L1 data fee of rollups (e.g. OP Stack and Arbitrum chains), charged for
+/// posting transactions to L1 in addition to L2 execution gas:
+/// `l1_base_fee * (l1_data_gas + overhead) * scalar`, where `l1_data_gas`
+/// is 16 gas per non-zero and 4 gas per zero byte of the user transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct RollupFeeModel {
+    /// L1 base fee, in the smallest unit of the chain's gas token per L1
+    /// gas. Kept up to date by market makers with
+    /// `set_foreign_chain_l1_base_fee`.
+    pub l1_base_fee: U128,
+    /// Fraction applied to the L1 fee, as `(numerator, denominator)`.
+    pub scalar: (U128, U128),
+    /// L1 gas added per sponsored transaction, e.g. for the signature and
+    /// the funding transaction.
+    pub overhead: U128,
+}
+
+impl RollupFeeModel {
+    /// L1 gas used to post `bytes`.
+    pub fn l1_data_gas(bytes: &[u8]) -> u64 {
+        bytes.iter().map(|b| if *b == 0 { 4 } else { 16 }).sum()
+    }
+
+    /// L1 data fee of a transaction whose encoding is `bytes`, rounded up.
+    pub fn l1_fee(&self, bytes: &[u8]) -> Option<U256> {
+        let numerator = U256::from(Self::l1_data_gas(bytes))
+            .checked_add(U256::from(self.overhead.0))?
+            .checked_mul(U256::from(self.l1_base_fee.0))?
+            .checked_mul(U256::from(self.scalar.0 .0))?;
+        let denominator = U256::from(self.scalar.1 .0);
+
+        if denominator.is_zero() {
+            return None;
+        }
+
+        let (fee, rem) = numerator.div_mod(denominator);
+        if rem.is_zero() {
+            Some(fee)
+        } else {
+            fee.checked_add(U256::one())
+        }
+    }
+}
+
+/// Largest number of decimals accepted for an asset, so that one whole token
+/// (`10^decimals` smallest units) fits in a `u128`.
+pub const MAX_ASSET_DECIMALS: u8 = 38;
+
+/// Largest accepted fee rate, as a multiple of the cost of the sponsored gas.
+pub const MAX_FEE_RATE: u128 = 10;
+
+/// # Errors
+///
+/// - If either part of the fee rate is zero.
+/// - If the fee rate is above [`MAX_FEE_RATE`].
+pub fn validate_fee_rate(fee_rate: (u128, u128)) -> Result<(), InvalidFeeRateError> {
+    let (numerator, denominator) = fee_rate;
+
+    if numerator == 0
+        || denominator == 0
+        || denominator
+            .checked_mul(MAX_FEE_RATE)
+            .is_some_and(|maximum| numerator > maximum)
+    {
+        return Err(InvalidFeeRateError {
+            numerator,
+            denominator,
+            maximum: MAX_FEE_RATE,
+        });
+    }
+
+    Ok(())
+}
+
+/// # Errors
+///
+/// - If `decimals` is above [`MAX_ASSET_DECIMALS`].
+pub fn validate_decimals(decimals: u8) -> Result<(), DecimalsOutOfRangeError> {
+    if decimals > MAX_ASSET_DECIMALS {
+        return Err(DecimalsOutOfRangeError {
+            decimals,
+            maximum: MAX_ASSET_DECIMALS,
+        });
+    }
+
+    Ok(())
+}
+
+/// Everything required to bring a new foreign chain online in a single call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct ChainOnboardingArgs {
+    pub chain_id: U64,
+    pub oracle_asset_id: String,
+    pub transfer_gas: U128,
+    pub contract_transfer_gas: Option<U128>,
+    pub max_transfer_gas: Option<U128>,
+    pub funding_call: Option<FundingCall>,
+    pub calldata_pricing: Option<CalldataPricing>,
+    pub rollup_fee_model: Option<RollupFeeModel>,
+    pub sponsorship_cap: Option<U128>,
+    pub min_fee_local: Option<BTreeMap<AssetId, U128>>,
+    pub conversion_rate_bounds: Option<BTreeMap<AssetId, ConversionRateBounds>>,
+    pub nonce_oracle_id: Option<AccountId>,
+    pub fee_rate: (U128, U128),
+    pub decimals: u8,
+    pub paymasters: Vec<PaymasterOnboardingArgs>,
+    pub metadata: Option<ForeignChainMetadata>,
+}
+
 #[derive(Debug)]
 #[near]
 pub struct ForeignChainConfiguration {
     pub chain_id: u64,
     pub paymasters: near_sdk::collections::TreeMap<String, PaymasterConfiguration>,
+    /// Paymasters taken out of the rotation with `disable_paymaster`.
+    pub disabled_paymasters: near_sdk::collections::TreeMap<String, PaymasterConfiguration>,
     pub next_paymaster: String,
     pub transfer_gas: [u64; 4],
+    /// Gas for funding contract senders. Defaults to `transfer_gas`.
+    pub contract_transfer_gas: Option<[u64; 4]>,
+    /// Upper bound on caller-provided funding gas. Defaults to the larger of
+    /// `transfer_gas` and `contract_transfer_gas`.
+    pub max_transfer_gas: Option<[u64; 4]>,
+    /// If set, contract senders are funded through this call instead of a
+    /// plain transfer.
+    pub funding_call: Option<FundingCall>,
+    /// If set, the calldata of sponsored transactions is charged for in
+    /// addition to their gas.
+    pub calldata_pricing: Option<CalldataPricing>,
+    /// If set, the L1 data fee of sponsored transactions is charged for in
+    /// addition to their gas.
+    pub rollup_fee_model: Option<RollupFeeModel>,
+    /// Minimum fee charged per sponsored transaction, by local asset,
+    /// regardless of oracle prices.
+    pub min_fee_local: BTreeMap<AssetId, U128>,
+    /// Sanity bounds on oracle prices, by local asset.
+    pub conversion_rate_bounds: BTreeMap<AssetId, ConversionRateBounds>,
+    /// If set, paymaster nonces are synchronized with this oracle before
+    /// funding transactions are built.
+    pub nonce_oracle_id: Option<AccountId>,
     pub fee_rate: (u128, u128),
     pub oracle_asset_id: [u8; 32],
     pub decimals: u8,
+    pub metadata: ForeignChainMetadata,
+    pub sponsorship_statistics: SponsorshipStatistics,
+    /// Circuit breaker: if more gas tokens than this are sponsored within
+    /// `SponsorshipStatistics::WINDOW_BLOCKS`, sponsorship on the chain is
+    /// paused until an administrator resumes it.
+    pub sponsorship_cap: Option<[u64; 4]>,
+    pub is_sponsorship_paused: bool,
+    pub fee_suggestion: Option<FeeSuggestion>,
+    /// Flat fee charged per sequence that is not sponsored (the sender
+    /// already holds gas), by local asset. Free if absent.
+    pub unsponsored_fee: BTreeMap<AssetId, U128>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct ViewForeignChainConfiguration {
+    pub chain_id: U64,
+    pub oracle_asset_id: String,
+    pub transfer_gas: U128,
+    pub contract_transfer_gas: U128,
+    pub max_transfer_gas: U128,
+    pub funding_call: Option<FundingCall>,
+    pub calldata_pricing: Option<CalldataPricing>,
+    pub rollup_fee_model: Option<RollupFeeModel>,
+    pub min_fee_local: BTreeMap<AssetId, U128>,
+    pub conversion_rate_bounds: BTreeMap<AssetId, ConversionRateBounds>,
+    pub nonce_oracle_id: Option<AccountId>,
+    pub fee_rate: (U128, U128),
+    pub decimals: u8,
+    pub paymaster_count: U64,
+    pub metadata: ForeignChainMetadata,
+    pub sponsorship_cap: Option<U128>,
+    pub is_sponsorship_paused: bool,
+    pub fee_suggestion: Option<FeeSuggestion>,
+    pub unsponsored_fee: BTreeMap<AssetId, U128>,
+}
+
+impl From<&ForeignChainConfiguration> for ViewForeignChainConfiguration {
+    fn from(config: &ForeignChainConfiguration) -> Self {
+        Self {
+            chain_id: config.chain_id.into(),
+            oracle_asset_id: near_sdk::bs58::encode(&config.oracle_asset_id).into_string(),
+            transfer_gas: config.transfer_gas().as_u128().into(),
+            contract_transfer_gas: config.contract_transfer_gas().as_u128().into(),
+            max_transfer_gas: config.max_transfer_gas().as_u128().into(),
+            funding_call: config.funding_call.clone(),
+            calldata_pricing: config.calldata_pricing.clone(),
+            rollup_fee_model: config.rollup_fee_model.clone(),
+            min_fee_local: config.min_fee_local.clone(),
+            conversion_rate_bounds: config.conversion_rate_bounds.clone(),
+            nonce_oracle_id: config.nonce_oracle_id.clone(),
+            fee_rate: (config.fee_rate.0.into(), config.fee_rate.1.into()),
+            decimals: config.decimals,
+            paymaster_count: config.paymasters.len().into(),
+            metadata: config.metadata.clone(),
+            sponsorship_cap: config.sponsorship_cap.map(|c| U256(c).as_u128().into()),
+            is_sponsorship_paused: config.is_sponsorship_paused,
+            fee_suggestion: config.fee_suggestion.clone(),
+            unsponsored_fee: config.unsponsored_fee.clone(),
+        }
+    }
 }
 
 impl ForeignChainConfiguration {
@@ -68,6 +474,150 @@ impl ForeignChainConfiguration {
         U256(self.transfer_gas)
     }
 
+    pub fn contract_transfer_gas(&self) -> U256 {
+        self.contract_transfer_gas
+            .map_or_else(|| self.transfer_gas(), U256)
+    }
+
+    pub fn max_transfer_gas(&self) -> U256 {
+        self.max_transfer_gas.map_or_else(
+            || self.transfer_gas().max(self.contract_transfer_gas()),
+            U256,
+        )
+    }
+
+    /// Gas limit of the funding transaction for the requested funding mode.
+    ///
+    /// # Errors
+    ///
+    /// - If the requested gas exceeds the chain maximum.
+    pub fn funding_transfer_gas(
+        &self,
+        funding: &FundingOptions,
+    ) -> Result<U256, TransferGasAboveMaximumError> {
+        let transfer_gas = match funding.transfer_gas {
+            Some(gas) => U256::from(gas.0),
+            None if funding.is_contract_target => self.contract_transfer_gas(),
+            None => self.transfer_gas(),
+        };
+
+        let maximum = self.max_transfer_gas();
+        if transfer_gas > maximum {
+            return Err(TransferGasAboveMaximumError {
+                transfer_gas,
+                maximum,
+            });
+        }
+
+        Ok(transfer_gas)
+    }
+
+    /// Receiver and calldata of the funding transaction for `sender`.
+    pub fn funding_destination(
+        &self,
+        funding: &FundingOptions,
+        sender: ForeignAddress,
+    ) -> (ForeignAddress, Vec<u8>) {
+        match self.funding_call {
+            Some(ref call) if funding.is_contract_target => (call.target, call.calldata(&sender)),
+            _ => (sender, vec![]),
+        }
+    }
+
+    /// The funding transaction that sends `value` gas tokens to `sender`
+    /// for `transaction`, with the fees of `transaction`.
+    pub fn funding_transaction(
+        &self,
+        transaction: &ValidTransactionRequest,
+        sender: ForeignAddress,
+        value: U256,
+        transfer_gas: U256,
+        funding: &FundingOptions,
+        nonce: u32,
+    ) -> ValidTransactionRequest {
+        let (to, data) = self.funding_destination(funding, sender);
+
+        ValidTransactionRequest {
+            chain_id: transaction.chain_id,
+            to,
+            value: value.0,
+            gas: transfer_gas.0,
+            data,
+            nonce: U256::from(nonce).0,
+            access_list_rlp: vec![0xc0 /* rlp encoding for empty list */],
+            max_priority_fee_per_gas: transaction.max_priority_fee_per_gas,
+            max_fee_per_gas: transaction.max_fee_per_gas,
+        }
+    }
+
+    /// # Errors
+    ///
+    /// - If the circuit breaker has paused sponsorship on the chain.
+    pub fn check_sponsorship_unpaused(&self) -> Result<(), SponsorshipPausedError> {
+        if self.is_sponsorship_paused {
+            return Err(SponsorshipPausedError {
+                chain_id: self.chain_id,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Records a sponsorship, pausing further sponsorship if it takes the
+    /// amount sponsored over the sliding window above the cap. Returns the
+    /// window amount if the circuit breaker was tripped.
+    pub fn record_sponsorship(&mut self, amount: U256, block_height: u64) -> Option<U256> {
+        self.sponsorship_statistics.record(amount, block_height);
+
+        let cap = U256(self.sponsorship_cap?);
+        let window_amount = self
+            .sponsorship_statistics
+            .sliding_window_amount(block_height);
+
+        if self.is_sponsorship_paused || window_amount <= cap {
+            return None;
+        }
+
+        self.is_sponsorship_paused = true;
+        Some(window_amount)
+    }
+
+    /// Projects the runway of each paymaster in the rotation from the recent
+    /// sponsorship statistics.
+    pub fn estimate_paymaster_runway(&self, block_height: u64) -> Vec<ViewPaymasterRunway> {
+        let recent = self.sponsorship_statistics.recent(block_height);
+        let paymaster_count = self.paymasters.len();
+
+        self.paymasters
+            .iter()
+            .map(|(_, p)| {
+                let balance = U256(p.minimum_available_balance);
+
+                let projection = recent.and_then(|(count, amount, blocks)| {
+                    let average_amount = amount.checked_div(U256::from(count))?;
+                    let transactions = if average_amount.is_zero() {
+                        U256::from(u64::MAX)
+                    } else {
+                        balance / average_amount
+                    };
+                    // Each paymaster funds one in `paymaster_count` sponsorships.
+                    let blocks = transactions
+                        .saturating_mul(U256::from(paymaster_count))
+                        .saturating_mul(U256::from(blocks))
+                        / U256::from(count);
+                    Some((saturating_u64(transactions), saturating_u64(blocks)))
+                });
+
+                ViewPaymasterRunway {
+                    token_id: p.token_id.clone(),
+                    minimum_available_balance: balance.as_u128().into(),
+                    estimated_transactions: projection.map(|(t, _)| t.into()),
+                    estimated_blocks: projection.map(|(_, b)| b.into()),
+                }
+            })
+            .collect()
+    }
+
     fn next_paymaster_key(&self) -> Option<String> {
         self.paymasters
             .ceil_key(&self.next_paymaster)
@@ -97,6 +647,8 @@ impl ForeignChainConfiguration {
         deduct_amount: U256,
         f: impl FnOnce(&Self, &PaymasterConfiguration) -> R,
     ) -> Result<R, RequestNonceError> {
+        self.check_sponsorship_unpaused()?;
+
         let (mut paymaster_config, paymaster_key, paymaster_key_after) = self
             .next_paymaster()
             .ok_or(NoPaymasterConfigurationForChainError {
@@ -119,6 +671,58 @@ impl ForeignChainConfiguration {
         Ok(r)
     }
 
+    /// Returns a reservation to the balance of its paymaster. The nonce is
+    /// only handed back if no later nonce has been consumed since, so that
+    /// no gap is left in the paymaster's nonces.
+    pub fn release_reservation(&mut self, reservation: &PaymasterReservation) {
+        let paymasters = if self.paymasters.contains_key(&reservation.token_id) {
+            &mut self.paymasters
+        } else {
+            &mut self.disabled_paymasters
+        };
+
+        let Some(mut paymaster) = paymasters.get(&reservation.token_id) else {
+            return;
+        };
+
+        paymaster.minimum_available_balance = U256(paymaster.minimum_available_balance)
+            .saturating_add(U256(reservation.amount))
+            .0;
+
+        if let Some(nonce) = reservation.nonce {
+            if nonce.checked_add(1) == Some(paymaster.nonce) {
+                paymaster.nonce = nonce;
+            }
+        }
+
+        paymasters.insert(&reservation.token_id, &paymaster);
+    }
+
+    /// The paymaster that the next funding transaction will be requested
+    /// from.
+    pub fn peek_next_paymaster(&self) -> Option<PaymasterConfiguration> {
+        self.next_paymaster().map(|(config, _, _)| config)
+    }
+
+    /// Advances the tracked nonce of a paymaster to `nonce`, e.g. after the
+    /// paymaster sent transactions outside of the gas station. The tracked
+    /// nonce is never lowered, since it may be ahead of the foreign chain
+    /// while funding transactions are waiting to be signed or mined.
+    pub fn sync_paymaster_nonce(&mut self, token_id: &String, nonce: u64) {
+        let Some(mut paymaster) = self.paymasters.get(token_id) else {
+            return;
+        };
+
+        let Ok(nonce) = u32::try_from(nonce) else {
+            return;
+        };
+
+        if nonce > paymaster.nonce {
+            paymaster.nonce = nonce;
+            self.paymasters.insert(token_id, &paymaster);
+        }
+    }
+
     fn next_paymaster(&self) -> Option<(PaymasterConfiguration, String, String)> {
         let paymaster_key = self.next_paymaster_key()?;
         let paymaster_key_after = self.paymaster_key_after(&paymaster_key)?;
@@ -127,8 +731,39 @@ impl ForeignChainConfiguration {
             .map(|c| (c, paymaster_key, paymaster_key_after))
     }
 
+    /// Rejects sponsoring a transaction with more calldata than the chain
+    /// allows.
+    ///
+    /// # Errors
+    ///
+    /// - If the calldata exceeds the configured maximum.
+    pub fn check_data_size(
+        &self,
+        transaction: &ValidTransactionRequest,
+    ) -> Result<(), TransactionDataTooLargeError> {
+        let Some(max_data_bytes) = self
+            .calldata_pricing
+            .as_ref()
+            .and_then(|p| p.max_data_bytes)
+        else {
+            return Ok(());
+        };
+
+        if transaction.data.len() > max_data_bytes as usize {
+            return Err(TransactionDataTooLargeError {
+                chain_id: self.chain_id,
+                data_bytes: transaction.data.len(),
+                max_data_bytes,
+            });
+        }
+
+        Ok(())
+    }
+
     /// Calculate the gas tokens that this chain configuration charges to
-    /// sponsor this transaction.
+    /// sponsor this transaction, given the gas limit of the funding
+    /// transaction. Includes the cost of the transaction's calldata and its
+    /// L1 data fee, if the chain charges for them.
     ///
     /// # Errors
     ///
@@ -136,27 +771,103 @@ impl ForeignChainConfiguration {
     pub fn calculate_gas_tokens_to_sponsor_transaction(
         &self,
         transaction: &ValidTransactionRequest,
+        transfer_gas: U256,
     ) -> Result<U256, ExpressionOverflowError> {
-        transaction
+        let mut cost = transaction
             .gas()
-            .checked_add(U256(self.transfer_gas))
+            .checked_add(transfer_gas)
             .and_then(|x| x.checked_mul(transaction.max_fee_per_gas()))
-            .ok_or(ExpressionOverflowError)
+            .ok_or(ExpressionOverflowError)?;
+
+        if let Some(ref pricing) = self.calldata_pricing {
+            cost = U256::from(pricing.per_byte_cost.0)
+                .checked_mul(U256::from(transaction.data.len()))
+                .and_then(|x| x.checked_add(U256::from(pricing.fixed_overhead.0)))
+                .and_then(|x| x.checked_add(cost))
+                .ok_or(ExpressionOverflowError)?;
+        }
+
+        if let Some(ref model) = self.rollup_fee_model {
+            let encoded = transaction.clone().into_typed_transaction().rlp();
+            cost = model
+                .l1_fee(&encoded)
+                .and_then(|x| x.checked_add(cost))
+                .ok_or(ExpressionOverflowError)?;
+        }
+
+        Ok(cost)
     }
 
-    /// Calculate the price that this chain configuration charges to convert
-    /// assets. Applies a fee on top of the provided market rates.
+    /// Raises `fee` to the minimum fee configured for the local asset, if
+    /// any. Protects paymasters against oracle prices glitching to near zero.
+    pub fn apply_min_fee_local(&self, asset_id: &AssetId, fee: u128) -> u128 {
+        self.min_fee_local
+            .get(asset_id)
+            .map_or(fee, |min_fee| fee.max(min_fee.0))
+    }
+
+    /// Rejects the conversion rate between `asset_id` and the gas token if
+    /// it falls outside the configured bounds for the asset.
     ///
     /// # Errors
     ///
-    /// - If the price data is invalid (negative, confidence interval too large).
-    pub fn price_for_gas_tokens(
+    /// - If the price data is invalid.
+    /// - If the rate is outside the bounds.
+    pub fn check_conversion_rate_bounds(
         &self,
-        quantity_to_convert: U256,
+        asset_id: &AssetId,
         this_asset_price_in_usd: &pyth::Price,
         into_asset_price_in_usd: &pyth::Price,
         into_asset_decimals: u8,
-    ) -> Result<u128, PriceDataError> {
+    ) -> Result<(), TryCreateTransactionCallbackError> {
+        let Some(bounds) = self.conversion_rate_bounds.get(asset_id) else {
+            return Ok(());
+        };
+
+        let (numerator, denominator) = self.conversion_rate(
+            this_asset_price_in_usd,
+            into_asset_price_in_usd,
+            into_asset_decimals,
+        )?;
+
+        let per_whole_token = 10u128
+            .checked_pow(u32::from(self.decimals))
+            .ok_or(ExponentTooLargeError)
+            .map_err(PriceDataError::from)?;
+        let rate = U256::from(numerator)
+            .checked_mul(U256::from(per_whole_token))
+            .ok_or(ExpressionOverflowError)?
+            / U256::from(denominator);
+
+        let below = bounds
+            .min_rate
+            .map_or(false, |min_rate| rate < U256::from(min_rate.0));
+        let above = bounds
+            .max_rate
+            .map_or(false, |max_rate| rate > U256::from(max_rate.0));
+
+        if below || above {
+            return Err(Box::new(ConversionRateOutOfBoundsError {
+                chain_id: self.chain_id,
+                asset_id: asset_id.clone(),
+                rate,
+                bounds: bounds.clone(),
+            })
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Conversion rate from the smallest unit of this asset into the smallest
+    /// unit of the other, as a fraction, without fees. Pessimistic with
+    /// respect to the confidence intervals.
+    fn conversion_rate(
+        &self,
+        this_asset_price_in_usd: &pyth::Price,
+        into_asset_price_in_usd: &pyth::Price,
+        into_asset_decimals: u8,
+    ) -> Result<(u128, u128), PriceDataError> {
         // Construct conversion rate
         let mut conversion_rate = (
             u128::try_from(this_asset_price_in_usd.price.0)
@@ -175,6 +886,10 @@ impl ForeignChainConfiguration {
                 .ok_or(ConfidenceIntervalTooLargeError)?,
         );
 
+        if conversion_rate.1 == 0 {
+            return Err(ZeroPriceError.into());
+        }
+
         let exp = this_asset_price_in_usd
             .expo
             .checked_sub(into_asset_price_in_usd.expo)
@@ -206,23 +921,125 @@ impl ForeignChainConfiguration {
             Ordering::Equal => {}
         }
 
+        Ok(conversion_rate)
+    }
+
+    /// Calculate the price that this chain configuration charges to convert
+    /// assets. Applies a fee on top of the provided market rates.
+    ///
+    /// # Errors
+    ///
+    /// - If the price data is invalid (negative, confidence interval too large).
+    /// - If the price of the asset being converted into is zero.
+    /// - If the result does not fit in a `u128`.
+    pub fn price_for_gas_tokens(
+        &self,
+        quantity_to_convert: U256,
+        this_asset_price_in_usd: &pyth::Price,
+        into_asset_price_in_usd: &pyth::Price,
+        into_asset_decimals: u8,
+    ) -> Result<u128, PriceDataError> {
+        self.price_for_gas_tokens_at_fee_rate(
+            self.fee_rate,
+            quantity_to_convert,
+            this_asset_price_in_usd,
+            into_asset_price_in_usd,
+            into_asset_decimals,
+        )
+    }
+
+    /// [`Self::price_for_gas_tokens`] with a fee rate other than the
+    /// chain's, e.g. that of a tenant.
+    ///
+    /// # Errors
+    ///
+    /// - As [`Self::price_for_gas_tokens`].
+    pub fn price_for_gas_tokens_at_fee_rate(
+        &self,
+        fee_rate: (u128, u128),
+        quantity_to_convert: U256,
+        this_asset_price_in_usd: &pyth::Price,
+        into_asset_price_in_usd: &pyth::Price,
+        into_asset_decimals: u8,
+    ) -> Result<u128, PriceDataError> {
+        let conversion_rate = self.conversion_rate(
+            this_asset_price_in_usd,
+            into_asset_price_in_usd,
+            into_asset_decimals,
+        )?;
+
         // Apply conversion rate to quantity in two steps: multiply, then divide.
         let numerator = quantity_to_convert
             .checked_mul(U256::from(conversion_rate.0))
-            .and_then(|x| x.checked_mul(U256::from(self.fee_rate.0)))
+            .and_then(|x| x.checked_mul(U256::from(fee_rate.0)))
             .ok_or(ExpressionOverflowError)?;
         let denominator = U256::from(conversion_rate.1)
-            .checked_mul(U256::from(self.fee_rate.1))
+            .checked_mul(U256::from(fee_rate.1))
             .ok_or(ExpressionOverflowError)?;
+
+        if denominator.is_zero() {
+            // Zero fee rate denominator.
+            return Err(ExpressionOverflowError.into());
+        }
+
         let (b, rem) = numerator.div_mod(denominator);
 
         // Round up. Again, pessimistic pricing.
-        Ok(if rem.is_zero() {
+        let result = if rem.is_zero() {
             b
         } else {
             // It should be impossible for this to overflow, given the above calculations, but better safe than sorry.
             b.checked_add(U256::one()).ok_or(ExpressionOverflowError)?
+        };
+
+        if result > U256::from(u128::MAX) {
+            return Err(ExpressionOverflowError.into());
+        }
+
+        Ok(result.as_u128())
+    }
+}
+
+fn saturating_u64(value: U256) -> u64 {
+    if value > U256::from(u64::MAX) {
+        u64::MAX
+    } else {
+        value.as_u64()
+    }
+}
+
+#[cfg(test)]
+impl ForeignChainConfiguration {
+    /// A chain without paymasters or optional settings, charging gas at
+    /// cost, with 18 decimals and 21000 transfer gas.
+    pub fn for_test(chain_id: u64) -> Self {
+        use near_sdk::collections::TreeMap;
+
+        use crate::StorageKey;
+
+        Self {
+            chain_id,
+            paymasters: TreeMap::new(StorageKey::Paymasters(chain_id)),
+            disabled_paymasters: TreeMap::new(StorageKey::DisabledPaymasters(chain_id)),
+            next_paymaster: String::new(),
+            transfer_gas: U256::from(21_000).0,
+            contract_transfer_gas: None,
+            max_transfer_gas: None,
+            funding_call: None,
+            calldata_pricing: None,
+            rollup_fee_model: None,
+            min_fee_local: BTreeMap::new(),
+            conversion_rate_bounds: BTreeMap::new(),
+            nonce_oracle_id: None,
+            fee_rate: (1, 1),
+            oracle_asset_id: [0; 32],
+            decimals: 18,
+            metadata: ForeignChainMetadata::default(),
+            sponsorship_statistics: SponsorshipStatistics::default(),
+            sponsorship_cap: None,
+            is_sponsorship_paused: false,
+            fee_suggestion: None,
+            unsponsored_fee: BTreeMap::new(),
         }
-        .as_u128())
     }
 }
@@ -1,7 +1,22 @@
-use near_sdk::{json_types::U64, near, AccountId};
+pub use lib::events::gas_station::{
+    ChainKeyPending, ChainKeyRegistered, DenylistUpdated, IntentSettlementSequenceCreated,
+    OracleAnomalyDetected, PaymasterBalanceProven, PaymasterBondWithdrawn, PaymasterBonded,
+    PaymasterSlashed, PaymasterUnbonding, RecurringAuthorizationCreated,
+    RecurringAuthorizationEnded, RecurringAuthorizationExecuted, RelayerTipCredited, SessionEnded,
+    SessionGranted, SessionUsed, SponsorshipCircuitBreakerTripped,
+    TransactionSequenceCreationFailed, TransactionSequenceDoomed, TransactionSequenceRebumped,
+    TransactionSequenceRelayed, TransactionSequenceSigned, Whitelist, WhitelistUpdated,
+};
 use near_sdk_contract_tools::event;
 
-use crate::PendingTransactionSequence;
+use crate::{
+    chain_configuration::ViewForeignChainConfiguration, Flags, PendingTransactionSequence,
+};
+
+pub type TransactionSequenceCreated =
+    lib::events::gas_station::TransactionSequenceCreated<PendingTransactionSequence>;
+pub type ConfigSnapshot =
+    lib::events::gas_station::ConfigSnapshot<Flags, ViewForeignChainConfiguration>;
 
 /// A successful request will emit two events, one for the request and one for
 /// the finalized transaction, in that order. The `id` field will be the same
@@ -9,25 +24,39 @@ use crate::PendingTransactionSequence;
 ///
 /// IDs are arbitrarily chosen by the contract. An ID is guaranteed to be unique
 /// within the contract.
+///
+/// Payloads are declared in [`lib::events::gas_station`], which off-chain
+/// consumers use to decode these events. New events may be added to the
+/// current version; changing the shape of an existing event requires a new
+/// version there as well.
 #[event(version = "0.1.0", standard = "x-gas-station")]
 pub enum ContractEvent {
-    TransactionSequenceCreated(TransactionSequenceCreated),
+    /// Boxed, as it carries the whole sequence and is much larger than the
+    /// other variants.
+    TransactionSequenceCreated(Box<TransactionSequenceCreated>),
     TransactionSequenceSigned(TransactionSequenceSigned),
-}
-
-#[derive(Clone, Debug, PartialEq, Eq)]
-#[near(serializers = [borsh, json])]
-pub struct TransactionSequenceCreated {
-    pub id: U64,
-    pub foreign_chain_id: String,
-    pub pending_transaction_sequence: PendingTransactionSequence,
-}
-
-#[derive(Clone, Debug, PartialEq, Eq)]
-#[near(serializers = [borsh, json])]
-pub struct TransactionSequenceSigned {
-    pub id: U64,
-    pub foreign_chain_id: String,
-    pub created_by_account_id: AccountId,
-    pub signed_transactions: Vec<String>,
+    ChainKeyRegistered(ChainKeyRegistered),
+    IntentSettlementSequenceCreated(IntentSettlementSequenceCreated),
+    ConfigSnapshot(ConfigSnapshot),
+    TransactionSequenceRelayed(TransactionSequenceRelayed),
+    PaymasterBonded(PaymasterBonded),
+    PaymasterUnbonding(PaymasterUnbonding),
+    PaymasterBondWithdrawn(PaymasterBondWithdrawn),
+    PaymasterSlashed(PaymasterSlashed),
+    TransactionSequenceRebumped(TransactionSequenceRebumped),
+    OracleAnomalyDetected(OracleAnomalyDetected),
+    PaymasterBalanceProven(PaymasterBalanceProven),
+    RelayerTipCredited(RelayerTipCredited),
+    SponsorshipCircuitBreakerTripped(SponsorshipCircuitBreakerTripped),
+    SessionGranted(SessionGranted),
+    SessionUsed(SessionUsed),
+    SessionEnded(SessionEnded),
+    ChainKeyPending(ChainKeyPending),
+    TransactionSequenceDoomed(TransactionSequenceDoomed),
+    DenylistUpdated(DenylistUpdated),
+    WhitelistUpdated(WhitelistUpdated),
+    TransactionSequenceCreationFailed(TransactionSequenceCreationFailed),
+    RecurringAuthorizationCreated(RecurringAuthorizationCreated),
+    RecurringAuthorizationExecuted(RecurringAuthorizationExecuted),
+    RecurringAuthorizationEnded(RecurringAuthorizationEnded),
 }
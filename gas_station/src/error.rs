@@ -1,7 +1,10 @@
 use ethers_core::types::U256;
-use near_sdk::AccountId;
+use lib::{asset::AssetId, contract_error::ContractError, foreign_address::ForeignAddress};
+use near_sdk::{json_types::U64, serde_json::json, AccountId};
 use thiserror::Error;
 
+use crate::chain_configuration::ConversionRateBounds;
+
 #[derive(Debug, Error, Clone)]
 #[error("Configuration for chain ID {chain_id} does not exist")]
 pub struct ChainConfigurationDoesNotExistError {
@@ -45,10 +48,20 @@ pub struct InsufficientDepositForFeeError {
     pub deposit: u128,
 }
 
+#[derive(Debug, Error, Clone)]
+#[error("Signer deposits must be paid in NEAR, not in {asset_id:?}")]
+pub struct SignerDepositRequiresNativeAssetError {
+    pub asset_id: AssetId,
+}
+
 #[derive(Debug, Error, Clone)]
 #[error("Reported price is negative")]
 pub struct NegativePriceError;
 
+#[derive(Debug, Error, Clone)]
+#[error("Price is zero")]
+pub struct ZeroPriceError;
+
 #[derive(Debug, Error, Clone)]
 #[error("Price confidence interval is too large")]
 pub struct ConfidenceIntervalTooLargeError;
@@ -71,6 +84,8 @@ pub enum PriceDataError {
     ExponentTooLarge(#[from] ExponentTooLargeError),
     #[error(transparent)]
     ExpressionOverflow(#[from] ExpressionOverflowError),
+    #[error(transparent)]
+    ZeroPrice(#[from] ZeroPriceError),
 }
 
 #[derive(Debug, Error, Clone)]
@@ -81,6 +96,59 @@ pub enum RequestNonceError {
     PaymasterInsufficientFunds(#[from] PaymasterInsufficientFundsError),
     #[error(transparent)]
     NonceOverflow(#[from] NonceOverflowError),
+    #[error(transparent)]
+    SponsorshipPaused(#[from] SponsorshipPausedError),
+}
+
+#[derive(Debug, Error, Clone)]
+#[error("Nonce {nonce} has already been signed for token {token_id} on chain ID {chain_id}; next nonce is {next_nonce}")]
+pub struct NonceAlreadySignedError {
+    pub token_id: String,
+    pub chain_id: u64,
+    pub nonce: U256,
+    pub next_nonce: u64,
+}
+
+#[derive(Debug, Error, Clone)]
+#[error("A transaction with nonce {nonce} has already been signed for token {token_id} on chain ID {chain_id}")]
+pub struct TransactionAlreadySignedError {
+    pub token_id: String,
+    pub chain_id: u64,
+    pub nonce: u64,
+}
+
+#[derive(Debug, Error, Clone)]
+#[error("Rate limit exceeded for {account_id}: at most {max_sequences} transaction sequences per {window_blocks} blocks")]
+pub struct RateLimitExceededError {
+    pub account_id: AccountId,
+    pub max_sequences: u32,
+    pub window_blocks: u64,
+}
+
+#[derive(Debug, Error, Clone)]
+#[error("Unknown priority tier {priority}")]
+pub struct UnknownPriorityTierError {
+    pub priority: u8,
+}
+
+#[derive(Debug, Error, Clone)]
+#[error("Tag is too long: length {length} > maximum {maximum}")]
+pub struct TagTooLongError {
+    pub length: usize,
+    pub maximum: usize,
+}
+
+#[derive(Debug, Error, Clone)]
+#[error("Attached deposit does not cover tag storage: deposit {deposit} < cost {cost}")]
+pub struct InsufficientDepositForTagStorageError {
+    pub deposit: u128,
+    pub cost: u128,
+}
+
+#[derive(Debug, Error, Clone)]
+#[error("Tags require a deposit in NEAR, not in {asset_id:?}")]
+pub struct TagRequiresNativeDepositError {
+    pub asset_id: AssetId,
 }
 
 #[derive(Debug, Error, Clone)]
@@ -94,6 +162,44 @@ pub struct SenderUnauthorizedForNftChainKeyError {
     pub token_id: String,
 }
 
+#[derive(Debug, Error, Clone)]
+#[error("Transfer gas exceeds the chain maximum: transfer gas {transfer_gas} > maximum {maximum}")]
+pub struct TransferGasAboveMaximumError {
+    pub transfer_gas: U256,
+    pub maximum: U256,
+}
+
+#[derive(Debug, Error, Clone)]
+#[error("Sponsorship on chain ID {chain_id} is paused by the circuit breaker")]
+pub struct SponsorshipPausedError {
+    pub chain_id: u64,
+}
+
+#[derive(Debug, Error, Clone)]
+#[error("Transaction data of {data_bytes} bytes exceeds the maximum of {max_data_bytes} bytes for chain ID {chain_id}")]
+pub struct TransactionDataTooLargeError {
+    pub chain_id: u64,
+    pub data_bytes: usize,
+    pub max_data_bytes: u32,
+}
+
+/// A check that runs before a sequence is created failed.
+#[derive(Debug, Error, Clone)]
+#[error("{message}")]
+pub struct TransactionRejectedError {
+    pub message: String,
+}
+
+#[derive(Debug, Error, Clone)]
+#[error("Conversion rate {rate} for asset {asset_id:?} on chain ID {chain_id} is outside the accepted bounds")]
+pub struct ConversionRateOutOfBoundsError {
+    pub chain_id: u64,
+    pub asset_id: AssetId,
+    /// Smallest units of the local asset per whole foreign gas token.
+    pub rate: U256,
+    pub bounds: ConversionRateBounds,
+}
+
 #[derive(Debug, Error, Clone)]
 pub enum TryCreateTransactionCallbackError {
     #[error(transparent)]
@@ -107,7 +213,198 @@ pub enum TryCreateTransactionCallbackError {
     #[error(transparent)]
     InsufficientDepositForFee(#[from] InsufficientDepositForFeeError),
     #[error(transparent)]
+    SignerDepositRequiresNativeAsset(#[from] SignerDepositRequiresNativeAssetError),
+    #[error(transparent)]
     RequestNonce(#[from] RequestNonceError),
     #[error(transparent)]
     ExpressionOverflow(#[from] ExpressionOverflowError),
+    #[error(transparent)]
+    TransferGasAboveMaximum(#[from] TransferGasAboveMaximumError),
+    /// Boxed, as it is much larger than the other variants.
+    #[error(transparent)]
+    ConversionRateOutOfBounds(#[from] Box<ConversionRateOutOfBoundsError>),
+    #[error(transparent)]
+    UnknownPriorityTier(#[from] UnknownPriorityTierError),
+    #[error(transparent)]
+    ForeignAddressBlocked(#[from] ForeignAddressBlockedError),
+    #[error(transparent)]
+    TransactionRejected(#[from] TransactionRejectedError),
+}
+
+impl TryCreateTransactionCallbackError {
+    /// The error with a stable code, as reported to clients.
+    pub fn to_contract_error(&self) -> ContractError {
+        let (code, context) = match self {
+            Self::OracleQueryFailure(_) => ("oracle_query_failure", json!({})),
+            Self::SenderUnauthorizedForNftChainKey(e) => (
+                "sender_unauthorized_for_nft_chain_key",
+                json!({ "sender": e.sender, "token_id": e.token_id }),
+            ),
+            Self::ChainConfigurationDoesNotExist(e) => (
+                "chain_configuration_does_not_exist",
+                json!({ "chain_id": U64(e.chain_id) }),
+            ),
+            Self::PriceData(e) => (
+                match e {
+                    PriceDataError::NegativePrice(_) => "negative_price",
+                    PriceDataError::ConfidenceIntervalTooLarge(_) => {
+                        "confidence_interval_too_large"
+                    }
+                    PriceDataError::ExponentTooLarge(_) => "exponent_too_large",
+                    PriceDataError::ExpressionOverflow(_) => "expression_overflow",
+                    PriceDataError::ZeroPrice(_) => "zero_price",
+                },
+                json!({}),
+            ),
+            Self::InsufficientDepositForFee(e) => (
+                "insufficient_deposit_for_fee",
+                json!({ "fee": e.fee.to_string(), "deposit": e.deposit.to_string() }),
+            ),
+            Self::SignerDepositRequiresNativeAsset(e) => (
+                "signer_deposit_requires_native_asset",
+                json!({ "asset_id": e.asset_id }),
+            ),
+            Self::RequestNonce(RequestNonceError::NoPaymasterConfigurationForChain(e)) => (
+                "no_paymaster_configuration_for_chain",
+                json!({ "chain_id": U64(e.chain_id) }),
+            ),
+            Self::RequestNonce(RequestNonceError::PaymasterInsufficientFunds(e)) => (
+                "paymaster_insufficient_funds",
+                json!({
+                    "minimum_available_balance": e.minimum_available_balance.to_string(),
+                    "amount": e.amount.to_string(),
+                }),
+            ),
+            Self::RequestNonce(RequestNonceError::NonceOverflow(_)) => {
+                ("nonce_overflow", json!({}))
+            }
+            Self::RequestNonce(RequestNonceError::SponsorshipPaused(e)) => {
+                ("sponsorship_paused", json!({ "chain_id": U64(e.chain_id) }))
+            }
+            Self::ExpressionOverflow(_) => ("expression_overflow", json!({})),
+            Self::TransferGasAboveMaximum(e) => (
+                "transfer_gas_above_maximum",
+                json!({
+                    "transfer_gas": e.transfer_gas.to_string(),
+                    "maximum": e.maximum.to_string(),
+                }),
+            ),
+            Self::ConversionRateOutOfBounds(e) => (
+                "conversion_rate_out_of_bounds",
+                json!({
+                    "chain_id": U64(e.chain_id),
+                    "asset_id": e.asset_id,
+                    "rate": e.rate.to_string(),
+                    "bounds": e.bounds,
+                }),
+            ),
+            Self::UnknownPriorityTier(e) => {
+                ("unknown_priority_tier", json!({ "priority": e.priority }))
+            }
+            Self::ForeignAddressBlocked(e) => (
+                "foreign_address_blocked",
+                json!({ "foreign_address": e.foreign_address }),
+            ),
+            Self::TransactionRejected(_) => ("transaction_rejected", json!({})),
+        };
+
+        ContractError::new(code, self, context)
+    }
+}
+
+#[derive(Debug, Error, Clone)]
+pub enum ChargeTagStorageError {
+    #[error(transparent)]
+    TagTooLong(#[from] TagTooLongError),
+    #[error(transparent)]
+    InsufficientDepositForTagStorage(#[from] InsufficientDepositForTagStorageError),
+    #[error(transparent)]
+    TagRequiresNativeDeposit(#[from] TagRequiresNativeDepositError),
+}
+
+#[derive(Debug, Error, Clone)]
+#[error("Session expired at block height {expires_at_block_height}")]
+pub struct SessionExpiredError {
+    pub expires_at_block_height: u64,
+}
+
+#[derive(Debug, Error, Clone)]
+#[error("Session has reached its limit of {max_sequences} sequences")]
+pub struct SessionSequenceLimitReachedError {
+    pub max_sequences: u32,
+}
+
+#[derive(Debug, Error, Clone)]
+#[error("Session budget of {budget} is insufficient for {amount}")]
+pub struct InsufficientSessionBudgetError {
+    pub budget: u128,
+    pub amount: u128,
+}
+
+#[derive(Debug, Error, Clone)]
+pub enum DrawSessionError {
+    #[error(transparent)]
+    SessionExpired(#[from] SessionExpiredError),
+    #[error(transparent)]
+    SessionSequenceLimitReached(#[from] SessionSequenceLimitReachedError),
+    #[error(transparent)]
+    InsufficientSessionBudget(#[from] InsufficientSessionBudgetError),
+}
+
+#[derive(Debug, Error, Clone)]
+#[error("Recurring authorization is not due until block height {next_block_height}")]
+pub struct RecurringAuthorizationNotDueError {
+    pub next_block_height: u64,
+}
+
+#[derive(Debug, Error, Clone)]
+#[error("Recurring authorization has reached its limit of {max_occurrences} occurrences")]
+pub struct RecurringAuthorizationCompletedError {
+    pub max_occurrences: u32,
+}
+
+#[derive(Debug, Error, Clone)]
+#[error("Recurring authorization balance of {balance} is insufficient for {amount}")]
+pub struct InsufficientRecurringBalanceError {
+    pub balance: u128,
+    pub amount: u128,
+}
+
+#[derive(Debug, Error, Clone)]
+pub enum DrawRecurringAuthorizationError {
+    #[error(transparent)]
+    RecurringAuthorizationNotDue(#[from] RecurringAuthorizationNotDueError),
+    #[error(transparent)]
+    RecurringAuthorizationCompleted(#[from] RecurringAuthorizationCompletedError),
+    #[error(transparent)]
+    InsufficientRecurringBalance(#[from] InsufficientRecurringBalanceError),
+}
+
+#[derive(Debug, Error, Clone)]
+#[error("Fee rate {numerator}/{denominator} must be positive and at most {maximum}")]
+pub struct InvalidFeeRateError {
+    pub numerator: u128,
+    pub denominator: u128,
+    pub maximum: u128,
+}
+
+#[derive(Debug, Error, Clone)]
+#[error("Decimals {decimals} exceed the maximum of {maximum}")]
+pub struct DecimalsOutOfRangeError {
+    pub decimals: u8,
+    pub maximum: u8,
+}
+
+#[derive(Debug, Error, Clone)]
+#[error(
+    "Public key of chain key {token_id} does not match the key derived from the signer root key"
+)]
+pub struct ChainKeyPublicKeyMismatchError {
+    pub token_id: String,
+}
+
+#[derive(Debug, Error, Clone)]
+#[error("Foreign address {foreign_address} did not pass screening")]
+pub struct ForeignAddressBlockedError {
+    pub foreign_address: ForeignAddress,
 }
@@ -0,0 +1,59 @@
+use ethers_core::utils::hex;
+use near_sdk::{env, json_types::U64, near, near_bindgen, AccountId};
+
+use crate::{Contract, ContractExt};
+
+/// Number of administrative actions retained. Older actions are overwritten.
+const ADMIN_ACTION_LOG_CAPACITY: u64 = 256;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct AdminAction {
+    pub method: String,
+    /// SHA-256 of the raw call arguments.
+    pub args_hash: String,
+    pub actor: AccountId,
+    pub block_height: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Lists the most recent administrative actions, newest first.
+    pub fn list_admin_actions(&self, offset: Option<u32>, limit: Option<u32>) -> Vec<AdminAction> {
+        let len = self.admin_actions.len();
+        // Index of the newest entry in the ring buffer.
+        let newest = self
+            .admin_action_count
+            .checked_sub(1)
+            .map(|c| c % len.max(1));
+
+        (0..len)
+            .filter_map(|i| newest.map(|newest| (newest + len - i) % len))
+            .skip(offset.map_or(0, |o| o as usize))
+            .take(limit.map_or(usize::MAX, |l| l as usize))
+            .filter_map(|i| self.admin_actions.get(i))
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Records the current call in the administrative audit log. Call after
+    /// the caller has been authorized.
+    pub(crate) fn record_admin_action(&mut self, method: &str) {
+        let action = AdminAction {
+            method: method.to_string(),
+            args_hash: hex::encode_prefixed(env::sha256_array(&env::input().unwrap_or_default())),
+            actor: env::predecessor_account_id(),
+            block_height: env::block_height().into(),
+        };
+
+        if self.admin_actions.len() < ADMIN_ACTION_LOG_CAPACITY {
+            self.admin_actions.push(&action);
+        } else {
+            self.admin_actions
+                .replace(self.admin_action_count % ADMIN_ACTION_LOG_CAPACITY, &action);
+        }
+
+        self.admin_action_count += 1;
+    }
+}
@@ -0,0 +1,127 @@
+use ethers_core::types::U256;
+use lib::{
+    balance_prover::{ext_balance_prover, ProvenAccountState},
+    foreign_address::ForeignAddress,
+    Rejectable,
+};
+use near_sdk::{
+    env,
+    json_types::{Base64VecU8, U64},
+    near_bindgen, require, AccountId, Gas, Promise, PromiseError,
+};
+use near_sdk_contract_tools::{pause::Pause, rbac::Rbac, standard::nep297::Event};
+
+use crate::{
+    contract_event::{ContractEvent, PaymasterBalanceProven},
+    Contract, ContractExt, Role,
+};
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the contract that verifies proofs of paymaster balances on
+    /// foreign chains. `None` disables `submit_paymaster_balance_proof`.
+    pub fn set_balance_prover_id(&mut self, account_id: Option<AccountId>) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_balance_prover_id");
+
+        self.balance_prover_id = account_id;
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn get_balance_prover_id(&self) -> Option<AccountId> {
+        self.balance_prover_id.clone()
+    }
+
+    const VERIFY_ACCOUNT_STATE_GAS: Gas = Gas::from_tgas(100);
+    const SUBMIT_PAYMASTER_BALANCE_PROOF_CALLBACK_GAS: Gas = Gas::from_tgas(10);
+
+    /// Updates the tracked balance of a paymaster from a proof of its state
+    /// on the foreign chain, verified by the balance prover. Callable by
+    /// anyone, since the proof is verified.
+    ///
+    /// The proof is only accepted if the proven nonce matches the
+    /// paymaster's tracked nonce, i.e. every funding transaction the
+    /// contract has signed for it had been mined at the proven block.
+    pub fn submit_paymaster_balance_proof(
+        &mut self,
+        chain_id: U64,
+        token_id: String,
+        proof: Base64VecU8,
+    ) -> Promise {
+        <Self as Pause>::require_unpaused();
+
+        let prover_id = self
+            .balance_prover_id
+            .clone()
+            .expect_or_reject("No balance prover is configured");
+
+        let chain_config = self.get_chain(chain_id.0).unwrap_or_reject();
+        require!(
+            chain_config.paymasters.get(&token_id).is_some(),
+            "Paymaster is not in the rotation",
+        );
+
+        let address = ForeignAddress::from_raw_public_key(
+            self.paymaster_keys
+                .get(&token_id)
+                .unwrap_or_reject()
+                .public_key_bytes,
+        );
+
+        ext_balance_prover::ext(prover_id)
+            .with_static_gas(Self::VERIFY_ACCOUNT_STATE_GAS)
+            .verify_account_state(chain_id, address, proof)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(Self::SUBMIT_PAYMASTER_BALANCE_PROOF_CALLBACK_GAS)
+                    .with_unused_gas_weight(0)
+                    .submit_paymaster_balance_proof_callback(chain_id, token_id),
+            )
+    }
+
+    #[private]
+    pub fn submit_paymaster_balance_proof_callback(
+        &mut self,
+        chain_id: U64,
+        token_id: String,
+        #[callback_result] result: Result<ProvenAccountState, PromiseError>,
+    ) -> bool {
+        let Ok(state) = result else {
+            near_sdk::log!("Balance proof verification failed");
+            return false;
+        };
+
+        let Some(mut chain_config) = self.foreign_chains.get(&chain_id.0) else {
+            return false;
+        };
+
+        let Some(mut paymaster) = chain_config.paymasters.get(&token_id) else {
+            near_sdk::log!("Paymaster is no longer in the rotation");
+            return false;
+        };
+
+        if u64::from(paymaster.nonce) != state.nonce.0 {
+            near_sdk::log!(
+                "Balance proof is stale: proven nonce {} != tracked nonce {}",
+                state.nonce.0,
+                paymaster.nonce,
+            );
+            return false;
+        }
+
+        paymaster.minimum_available_balance = U256::from(state.balance.0).0;
+        chain_config.paymasters.insert(&token_id, &paymaster);
+        self.foreign_chains.insert(&chain_id.0, &chain_config);
+
+        ContractEvent::PaymasterBalanceProven(PaymasterBalanceProven {
+            chain_id,
+            token_id,
+            balance: state.balance,
+            block_number: state.block_number,
+        })
+        .emit();
+
+        true
+    }
+}
@@ -1,24 +1,53 @@
 use lib::{
-    chain_key::{ext_chain_key_token, ChainKeyTokenApprovalReceiver},
+    asset::AssetBalance,
+    chain_key::{
+        ext_chain_key_token, ChainKeyApprovalResponse, ChainKeyTokenApprovalReceiver,
+        ForwardedDepositStatus,
+    },
+    foreign_address::ForeignAddress,
     Rejectable,
 };
 use near_sdk::{
-    collections::UnorderedMap, env, near, near_bindgen, require, AccountId, Promise, PromiseError,
-    PromiseOrValue, PublicKey,
+    collections::UnorderedMap,
+    env,
+    json_types::{U128, U64},
+    near, near_bindgen, require, AccountId, NearToken, Promise, PromiseError, PromiseOrValue,
+    PublicKey,
 };
 use near_sdk_contract_tools::{
     nft::{ext_nep171, Nep171Receiver, TokenId},
     rbac::Rbac,
+    standard::nep297::Event,
 };
 
 #[allow(unused_imports)]
 use crate::ContractExt;
-use crate::{ChainKeyAuthorization, ChainKeyData, Contract, Role, StorageKey};
+use crate::{
+    contract_event::{ChainKeyPending, ChainKeyRegistered, ContractEvent},
+    error::TransactionRejectedError,
+    try_decode_transaction_request,
+    valid_transaction_request::ValidTransactionRequest,
+    ChainKeyAuthorization, ChainKeyData, Contract, CreateTransactionOptions, Role, StorageKey,
+    TransactionSequenceCreation,
+};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[near(serializers = [json])]
 pub struct ChainKeyReceiverMsg {
+    #[serde(default)]
     pub is_paymaster: bool,
+    /// A transaction to create as soon as the key is registered. Any deposit
+    /// forwarded with the approval is used to pay for it, and is refunded if
+    /// the transaction cannot be created.
+    #[serde(default)]
+    pub create_transaction: Option<ChainKeyReceiverCreateTransaction>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct ChainKeyReceiverCreateTransaction {
+    pub transaction_rlp_hex: String,
+    pub use_paymaster: Option<bool>,
 }
 
 #[near_bindgen]
@@ -56,7 +85,6 @@ impl Nep171Receiver for Contract {
                         Self::ext(env::current_account_id()).receive_chain_key_callback(
                             previous_owner_id,
                             token_id,
-                            ChainKeyAuthorization::Owned,
                             msg,
                         ),
                     ),
@@ -65,6 +93,17 @@ impl Nep171Receiver for Contract {
     }
 }
 
+/// Outcome of registering a chain key received by transfer or approval.
+enum ChainKeyReception {
+    /// The key was not registered, and the deposit was refunded.
+    Rejected,
+    /// The key was registered, and the deposit was kept or refunded.
+    Registered(ForwardedDepositStatus),
+    /// The key was registered, and the promise settles the deposit: it is
+    /// kept if the promise succeeds, and refunded otherwise.
+    Pending(Promise),
+}
+
 #[near_bindgen]
 impl Contract {
     #[private]
@@ -72,39 +111,260 @@ impl Contract {
         &mut self,
         #[serializer(borsh)] account_id: AccountId,
         #[serializer(borsh)] token_id: TokenId,
-        #[serializer(borsh)] authorization: ChainKeyAuthorization,
         #[serializer(borsh)] msg: String,
         #[callback_result] result: Result<PublicKey, PromiseError>,
     ) -> PromiseOrValue<bool> {
-        let Ok(public_key) = result else {
-            env::panic_str("Failed to retrieve public key from signer contract");
+        match self.receive_chain_key(
+            account_id,
+            token_id,
+            ChainKeyAuthorization::Owned,
+            msg,
+            AssetBalance::native(0),
+            result,
+        ) {
+            // Returns the key to its previous owner.
+            ChainKeyReception::Rejected => PromiseOrValue::Value(true),
+            ChainKeyReception::Registered(_) => PromiseOrValue::Value(false),
+            ChainKeyReception::Pending(promise) => promise
+                .then(Self::ext(env::current_account_id()).return_false())
+                .into(),
+        }
+    }
+
+    #[private]
+    pub fn receive_approved_chain_key_callback(
+        &mut self,
+        #[serializer(borsh)] account_id: AccountId,
+        #[serializer(borsh)] token_id: TokenId,
+        #[serializer(borsh)] approval_id: u32,
+        #[serializer(borsh)] msg: String,
+        #[serializer(borsh)] deposit: NearToken,
+        #[callback_result] result: Result<PublicKey, PromiseError>,
+    ) -> PromiseOrValue<ChainKeyApprovalResponse> {
+        let deposit = match self.receive_chain_key(
+            account_id,
+            token_id,
+            ChainKeyAuthorization::Approved(approval_id),
+            msg,
+            AssetBalance::native(deposit.as_yoctonear()),
+            result,
+        ) {
+            ChainKeyReception::Rejected => ForwardedDepositStatus::Refunded,
+            ChainKeyReception::Registered(deposit) => deposit,
+            ChainKeyReception::Pending(promise) => {
+                return promise
+                    .then(
+                        Self::ext(env::current_account_id()).approved_chain_key_deposit_callback(),
+                    )
+                    .into();
+            }
         };
 
-        let sent_from_contract_administrator =
-            <Self as Rbac>::has_role(&account_id, &Role::Administrator);
+        PromiseOrValue::Value(ChainKeyApprovalResponse {
+            revoke: false,
+            deposit,
+        })
+    }
 
-        let marked_as_paymaster_key = || {
-            near_sdk::serde_json::from_str::<ChainKeyReceiverMsg>(&msg)
-                .map_or(false, |m| m.is_paymaster)
-        };
+    /// Reports what became of the deposit forwarded with an approval once
+    /// the transaction requested with it has been created, or has failed to
+    /// be created and the deposit has been refunded.
+    #[private]
+    pub fn approved_chain_key_deposit_callback(
+        &self,
+        #[callback_result] result: Result<TransactionSequenceCreation, PromiseError>,
+    ) -> ChainKeyApprovalResponse {
+        ChainKeyApprovalResponse {
+            revoke: false,
+            deposit: if result.is_ok() {
+                ForwardedDepositStatus::Kept
+            } else {
+                ForwardedDepositStatus::Refunded
+            },
+        }
+    }
 
-        let key_data = ChainKeyData {
-            public_key_bytes: public_key.into_bytes(),
-            authorization,
+    /// Storage staked by this contract to register the first chain key of a
+    /// new user, in yoctoNEAR. Registration is not charged to the user, who
+    /// only attaches 1 yoctoNEAR to `ckt_approve_call`.
+    ///
+    /// Estimated from the most recent registration of a new user, so it can
+    /// be off by a few bytes when account or token IDs differ in length.
+    pub fn estimate_onboarding_cost(&self) -> U128 {
+        env::storage_byte_cost()
+            .saturating_mul(u128::from(self.registration_storage_usage))
+            .as_yoctonear()
+            .into()
+    }
+
+    #[private]
+    pub fn return_false(&self) -> bool {
+        false
+    }
+
+    /// Enables or disables rejection of transactions that reuse a nonce
+    /// already signed by this contract for the predecessor's chain key.
+    pub fn set_nonce_tracking(&mut self, token_id: TokenId, enabled: bool) {
+        let predecessor = env::predecessor_account_id();
+
+        let mut user_chain_keys = self
+            .user_chain_keys
+            .get(&predecessor)
+            .expect_or_reject("No managed keys for predecessor");
+        let mut key_data = user_chain_keys
+            .get(&token_id)
+            .expect_or_reject("Predecessor unauthorized for the requested chain key token ID");
+        key_data.is_nonce_tracking_enabled = enabled;
+        user_chain_keys.insert(&token_id, &key_data);
+        self.user_chain_keys.insert(&predecessor, &user_chain_keys);
+    }
+
+    /// Routes signing of the predecessor's transactions with a chain key
+    /// through a policy contract, which must approve each transaction before
+    /// a signature is requested. `None` removes the policy.
+    pub fn set_signing_policy(&mut self, token_id: TokenId, policy_id: Option<AccountId>) {
+        let predecessor = env::predecessor_account_id();
+
+        let mut user_chain_keys = self
+            .user_chain_keys
+            .get(&predecessor)
+            .expect_or_reject("No managed keys for predecessor");
+        let mut key_data = user_chain_keys
+            .get(&token_id)
+            .expect_or_reject("Predecessor unauthorized for the requested chain key token ID");
+        key_data.signing_policy_id = policy_id;
+        user_chain_keys.insert(&token_id, &key_data);
+        self.user_chain_keys.insert(&predecessor, &user_chain_keys);
+    }
+
+    /// Returns the highest nonce this contract has signed for the chain key
+    /// on the given chain.
+    pub fn get_last_signed_foreign_nonce(&self, token_id: TokenId, chain_id: U64) -> Option<U64> {
+        self.foreign_nonces
+            .get(&(token_id, chain_id.0))
+            .map(Into::into)
+    }
+
+    /// Returns the sighash of the transaction signed for the chain key with
+    /// the given nonce on the given chain, if any.
+    pub fn get_signed_transaction_hash(
+        &self,
+        token_id: TokenId,
+        chain_id: U64,
+        nonce: U64,
+    ) -> Option<String> {
+        self.signed_transaction_hashes
+            .get(&(token_id, chain_id.0, nonce.0))
+            .map(|hash| self.flags.encode_hex(hash))
+    }
+
+    /// Allows another transaction with the given nonce to be signed for the
+    /// chain key, e.g. to replace a stuck transaction with a higher fee.
+    pub fn allow_foreign_transaction_resign(
+        &mut self,
+        token_id: TokenId,
+        chain_id: U64,
+        nonce: U64,
+    ) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("allow_foreign_transaction_resign");
+
+        self.signed_transaction_hashes
+            .remove(&(token_id, chain_id.0, nonce.0));
+    }
+
+    /// Token IDs of the account's chain keys that are registered without a
+    /// public key, and must be refreshed with `refresh_user_chain_key`
+    /// before use.
+    pub fn list_pending_chain_keys(&self, account_id: AccountId) -> Vec<TokenId> {
+        self.user_chain_keys
+            .get(&account_id)
+            .map(|keys| {
+                keys.iter()
+                    .filter(|(_, key)| !key.has_public_key())
+                    .map(|(token_id, _)| token_id)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Re-queries the public key of a chain key from the signer contract.
+    /// Useful when the initial query during registration failed.
+    ///
+    /// Administrators may also refresh paymaster keys.
+    pub fn refresh_user_chain_key(&mut self, token_id: TokenId) -> Promise {
+        let predecessor = env::predecessor_account_id();
+        self.require_unpaused_or_administrator(&predecessor);
+
+        let is_user_key = self
+            .user_chain_keys
+            .get(&predecessor)
+            .map_or(false, |keys| keys.get(&token_id).is_some());
+
+        let is_paymaster = !is_user_key
+            && <Self as Rbac>::has_role(&predecessor, &Role::Administrator)
+            && self.paymaster_keys.get(&token_id).is_some();
+
+        require!(
+            is_user_key || is_paymaster,
+            "No chain key registered for predecessor with this token ID",
+        );
+
+        ext_chain_key_token::ext(self.signer_contract_id.clone())
+            .ckt_public_key_for(token_id.clone(), None)
+            .then(
+                Self::ext(env::current_account_id()).refresh_user_chain_key_callback(
+                    predecessor,
+                    token_id,
+                    is_paymaster,
+                ),
+            )
+    }
+
+    #[private]
+    pub fn refresh_user_chain_key_callback(
+        &mut self,
+        #[serializer(borsh)] account_id: AccountId,
+        #[serializer(borsh)] token_id: TokenId,
+        #[serializer(borsh)] is_paymaster: bool,
+        #[callback_result] result: Result<PublicKey, PromiseError>,
+    ) {
+        let Ok(public_key) = result else {
+            env::panic_str("Failed to retrieve public key from signer contract");
         };
 
-        if sent_from_contract_administrator && marked_as_paymaster_key() {
+        self.verify_chain_key_public_key(&token_id, &public_key)
+            .unwrap_or_reject();
+
+        let key_data = if is_paymaster {
+            let mut key_data = self
+                .paymaster_keys
+                .get(&token_id)
+                .expect_or_reject("Paymaster key was removed during refresh");
+            key_data.public_key_bytes = public_key.into_bytes();
             self.paymaster_keys.insert(&token_id, &key_data);
+            key_data
         } else {
-            let mut user_chain_keys = self.user_chain_keys.get(&account_id).unwrap_or_else(|| {
-                UnorderedMap::new(StorageKey::UserChainKeysFor(account_id.clone()))
-            });
-
+            let mut user_chain_keys = self
+                .user_chain_keys
+                .get(&account_id)
+                .expect_or_reject("No managed keys for account");
+            let mut key_data = user_chain_keys
+                .get(&token_id)
+                .expect_or_reject("Chain key was removed during refresh");
+            key_data.public_key_bytes = public_key.into_bytes();
             user_chain_keys.insert(&token_id, &key_data);
             self.user_chain_keys.insert(&account_id, &user_chain_keys);
-        }
+            key_data
+        };
+
+        emit_chain_key_registered(account_id, token_id, is_paymaster, &key_data);
+    }
 
-        PromiseOrValue::Value(false)
+    /// Transfers a key that the caller transferred to this contract with
+    /// `nft_transfer_call` back to them, unregistering it.
+    pub fn reclaim_key(&mut self, token_id: TokenId) -> Promise {
+        self.recover_nft_key(token_id, None)
     }
 
     pub fn recover_nft_key(&mut self, token_id: TokenId, msg: Option<String>) -> Promise {
@@ -121,6 +381,7 @@ impl Contract {
             .expect_or_reject("Token was not sent to this contract by predecessor");
 
         self.user_chain_keys.insert(&predecessor, &user_keys);
+        self.user_chain_key_registrants.remove(&token_id);
 
         require!(
             owned.authorization.is_owned(),
@@ -146,15 +407,185 @@ impl Contract {
     }
 }
 
+fn emit_chain_key_registered(
+    account_id: AccountId,
+    token_id: TokenId,
+    is_paymaster: bool,
+    key_data: &ChainKeyData,
+) {
+    ContractEvent::ChainKeyRegistered(ChainKeyRegistered {
+        account_id,
+        token_id,
+        is_paymaster,
+        foreign_address: ForeignAddress::from_raw_public_key(&key_data.public_key_bytes),
+    })
+    .emit();
+}
+
+impl Contract {
+    /// Registers a chain key received by transfer or approval, then creates
+    /// the transaction requested in `msg`, if any, paying with `deposit`.
+    fn receive_chain_key(
+        &mut self,
+        account_id: AccountId,
+        token_id: TokenId,
+        authorization: ChainKeyAuthorization,
+        msg: String,
+        deposit: AssetBalance,
+        result: Result<PublicKey, PromiseError>,
+    ) -> ChainKeyReception {
+        if let Ok(ref public_key) = result {
+            if let Err(e) = self.verify_chain_key_public_key(&token_id, public_key) {
+                near_sdk::log!("Chain key not registered: {e}");
+                Self::refund_deposit(&account_id, &deposit);
+                return ChainKeyReception::Rejected;
+            }
+        }
+
+        // If the public key could not be retrieved, the key is still
+        // registered, but it is unusable until `refresh_user_chain_key`
+        // succeeds.
+        let public_key_bytes = result.map_or_else(|_| vec![], PublicKey::into_bytes);
+
+        let sent_from_contract_administrator =
+            <Self as Rbac>::has_role(&account_id, &Role::Administrator);
+
+        let receiver_msg = near_sdk::serde_json::from_str::<ChainKeyReceiverMsg>(&msg).ok();
+
+        let key_data = ChainKeyData {
+            public_key_bytes,
+            authorization,
+            is_nonce_tracking_enabled: false,
+            signing_policy_id: None,
+        };
+
+        let is_paymaster = sent_from_contract_administrator
+            && receiver_msg.as_ref().map_or(false, |m| m.is_paymaster);
+
+        // A key can only be registered to one account at a time. If the key
+        // was transferred, the previous holder's registration is stale.
+        self.remove_user_chain_key_registration(&token_id);
+
+        if is_paymaster {
+            self.paymaster_keys.insert(&token_id, &key_data);
+        } else {
+            let storage_usage_start = env::storage_usage();
+            let existing_user_chain_keys = self.user_chain_keys.get(&account_id);
+            let is_new_user = existing_user_chain_keys.is_none();
+            let mut user_chain_keys = existing_user_chain_keys.unwrap_or_else(|| {
+                UnorderedMap::new(StorageKey::UserChainKeysFor(account_id.clone()))
+            });
+
+            user_chain_keys.insert(&token_id, &key_data);
+            self.user_chain_keys.insert(&account_id, &user_chain_keys);
+            self.user_chain_key_registrants
+                .insert(&token_id, &account_id);
+
+            if is_new_user {
+                self.registration_storage_usage =
+                    env::storage_usage().saturating_sub(storage_usage_start);
+            }
+        }
+
+        if key_data.has_public_key() {
+            emit_chain_key_registered(
+                account_id.clone(),
+                token_id.clone(),
+                is_paymaster,
+                &key_data,
+            );
+        } else {
+            ContractEvent::ChainKeyPending(ChainKeyPending {
+                account_id: account_id.clone(),
+                token_id: token_id.clone(),
+                is_paymaster,
+            })
+            .emit();
+        }
+
+        let Some(create_transaction) = receiver_msg
+            .and_then(|m| m.create_transaction)
+            .filter(|_| !is_paymaster)
+        else {
+            Self::refund_deposit(&account_id, &deposit);
+            return ChainKeyReception::Registered(ForwardedDepositStatus::Refunded);
+        };
+
+        let use_paymaster = create_transaction.use_paymaster.unwrap_or(false);
+        let options = CreateTransactionOptions::default();
+
+        // The creation is validated in full first, since a panic would lose
+        // the deposit. The approval itself is still accepted.
+        let transaction =
+            match try_decode_transaction_request(&create_transaction.transaction_rlp_hex)
+                .map_err(|e| e.to_string())
+                .and_then(|t| ValidTransactionRequest::try_from(t).map_err(|e| e.to_string()))
+                .and_then(|transaction| {
+                    self.validate_creation(
+                        &account_id,
+                        &token_id,
+                        &transaction,
+                        use_paymaster,
+                        deposit.clone(),
+                        &options,
+                    )
+                    .map(|_| transaction)
+                }) {
+                Ok(transaction) => transaction,
+                Err(message) => {
+                    return ChainKeyReception::Pending(Self::refund_and_throw(
+                        account_id,
+                        deposit,
+                        &TransactionRejectedError { message }.into(),
+                    ));
+                }
+            };
+
+        // Deposit refunds on failure are handled by the creation callback.
+        match self.create_validated_transaction(
+            token_id,
+            account_id,
+            transaction,
+            Some(use_paymaster),
+            deposit,
+            None,
+            options,
+        ) {
+            PromiseOrValue::Promise(promise) => ChainKeyReception::Pending(promise),
+            PromiseOrValue::Value(_) => ChainKeyReception::Registered(ForwardedDepositStatus::Kept),
+        }
+    }
+
+    /// Removes the registration of a user chain key from whichever account
+    /// it is currently registered to.
+    pub(crate) fn remove_user_chain_key_registration(&mut self, token_id: &TokenId) {
+        let Some(registrant) = self.user_chain_key_registrants.remove(token_id) else {
+            return;
+        };
+
+        if let Some(mut user_chain_keys) = self.user_chain_keys.get(&registrant) {
+            user_chain_keys.remove(token_id);
+            self.user_chain_keys.insert(&registrant, &user_chain_keys);
+        }
+    }
+
+    fn refund_deposit(account_id: &AccountId, deposit: &AssetBalance) {
+        if deposit.amount.0 > 0 {
+            Self::refund(account_id.clone(), deposit.clone());
+        }
+    }
+}
+
 #[near_bindgen]
 impl ChainKeyTokenApprovalReceiver for Contract {
+    #[payable]
     fn ckt_on_approved(
         &mut self,
         approver_id: AccountId,
         token_id: String,
         approval_id: u32,
         msg: String,
-    ) -> PromiseOrValue<bool> {
+    ) -> PromiseOrValue<ChainKeyApprovalResponse> {
         self.require_unpaused_or_administrator(&approver_id);
 
         let predecessor = env::predecessor_account_id();
@@ -168,11 +599,12 @@ impl ChainKeyTokenApprovalReceiver for Contract {
             ext_chain_key_token::ext(predecessor)
                 .ckt_public_key_for(token_id.clone(), None)
                 .then(
-                    Self::ext(env::current_account_id()).receive_chain_key_callback(
+                    Self::ext(env::current_account_id()).receive_approved_chain_key_callback(
                         approver_id,
                         token_id,
-                        ChainKeyAuthorization::Approved(approval_id),
+                        approval_id,
                         msg,
+                        env::attached_deposit(),
                     ),
                 ),
         )
@@ -203,6 +635,10 @@ impl ChainKeyTokenApprovalReceiver for Contract {
         let removed = user_chain_keys.remove(&token_id);
         self.user_chain_keys.insert(&approver_id, &user_chain_keys);
 
+        if self.user_chain_key_registrants.get(&token_id).as_ref() == Some(&approver_id) {
+            self.user_chain_key_registrants.remove(&token_id);
+        }
+
         if let Some(removed) = removed {
             require!(
                 removed.authorization.is_approved(),
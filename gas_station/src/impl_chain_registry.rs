@@ -0,0 +1,74 @@
+use ethers_core::types::U256;
+use lib::{
+    chain_registry::{ext_chain_registry, ChainRegistryEntry},
+    oracle::decode_pyth_price_id,
+    Rejectable,
+};
+use near_sdk::{env, json_types::U64, near_bindgen, AccountId, Promise, PromiseError};
+use near_sdk_contract_tools::rbac::Rbac;
+
+use crate::{
+    chain_configuration::{validate_decimals, validate_fee_rate},
+    Contract, ContractExt, Role,
+};
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the contract that `sync_chain_from_registry` pulls chain
+    /// parameters from (see `lib::chain_registry::ChainRegistry`). `None`
+    /// disables syncing.
+    pub fn set_chain_registry_id(&mut self, registry_id: Option<AccountId>) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_chain_registry_id");
+
+        self.chain_registry_id = registry_id;
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn get_chain_registry_id(&self) -> Option<AccountId> {
+        self.chain_registry_id.clone()
+    }
+
+    /// Replaces the base parameters of an existing foreign chain (as in
+    /// `update_foreign_chain`) with those held by the chain registry.
+    /// Paymasters and other settings are kept.
+    pub fn sync_chain_from_registry(&mut self, chain_id: U64) -> Promise {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("sync_chain_from_registry");
+
+        let registry_id = self
+            .chain_registry_id
+            .clone()
+            .expect_or_reject("No chain registry is set");
+        self.get_chain(chain_id.0).unwrap_or_reject();
+
+        ext_chain_registry::ext(registry_id)
+            .get_chain(chain_id)
+            .then(Self::ext(env::current_account_id()).sync_chain_from_registry_callback(chain_id))
+    }
+
+    #[private]
+    pub fn sync_chain_from_registry_callback(
+        &mut self,
+        chain_id: U64,
+        #[callback_result] result: Result<Option<ChainRegistryEntry>, PromiseError>,
+    ) {
+        let entry = result
+            .ok()
+            .expect_or_reject("Failed to query chain registry")
+            .expect_or_reject("Chain is not in the registry");
+
+        validate_fee_rate((entry.fee_rate.0 .0, entry.fee_rate.1 .0)).unwrap_or_reject();
+        validate_decimals(entry.decimals).unwrap_or_reject();
+
+        self.with_mut_chain(chain_id.0, |config| {
+            config.oracle_asset_id = decode_pyth_price_id(&entry.oracle_asset_id);
+            config.transfer_gas = U256::from(entry.transfer_gas.0).0;
+            config.fee_rate = (entry.fee_rate.0.into(), entry.fee_rate.1.into());
+            config.decimals = entry.decimals;
+        });
+
+        self.emit_config_snapshot_if_due();
+    }
+}
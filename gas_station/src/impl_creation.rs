@@ -0,0 +1,1255 @@
+use ethers_core::{
+    types::U256,
+    utils::{hex, keccak256},
+};
+use lib::{
+    asset::{AssetBalance, AssetId},
+    foreign_address::ForeignAddress,
+    nonce_oracle::ext_nonce_oracle,
+    pyth::{self, ext_pyth},
+    screening::ext_screening,
+    Rejectable,
+};
+use near_sdk::{
+    env,
+    json_types::{U128, U64},
+    near, near_bindgen, require, AccountId, Promise, PromiseError, PromiseOrValue, PromiseResult,
+};
+use near_sdk_contract_tools::{pause::Pause, standard::nep297::Event};
+
+use crate::{
+    chain_configuration::{ForeignChainConfiguration, FundingOptions, PaymasterReservation},
+    contract_event::{
+        ContractEvent, OracleAnomalyDetected, TransactionSequenceCreated,
+        TransactionSequenceCreationFailed,
+    },
+    decode_hash, decode_transaction_request, erc20_transfer_calldata,
+    error::{
+        ChargeTagStorageError, ForeignAddressBlockedError, InsufficientDepositForFeeError,
+        InsufficientDepositForTagStorageError, OracleQueryFailureError, RequestNonceError,
+        SenderUnauthorizedForNftChainKeyError, TagRequiresNativeDepositError, TagTooLongError,
+        TransactionSequenceDoesNotExistError, TryCreateTransactionCallbackError,
+    },
+    signature_request::SignatureRequest,
+    valid_transaction_request::{Eip1559JsonRequest, ValidTransactionRequest},
+    verify_merkle_proof, ChainKeyData, Contract, ContractExt, CreateTransactionOptions,
+    PaymasterFunding, PendingTransactionSequence, SequenceCallback, SigningOrder,
+    TransactionSequenceCreation, MAX_CALLBACK_MSG_LENGTH, MAX_TAG_LENGTH, NATIVE_TRANSFER_GAS,
+};
+
+/// Carried from `create_checked_transaction` to `create_transaction_callback`
+/// while the oracles are queried.
+#[derive(Clone, Debug)]
+#[near(serializers = [borsh])]
+pub struct CreateTransactionCallbackArgs {
+    sender: AccountId,
+    token_id: String,
+    deposit: AssetBalance,
+    tip: Option<U128>,
+    transaction_request: ValidTransactionRequest,
+    options: CreateTransactionOptions,
+    nonce_queried_for: Option<String>,
+    screened_addresses: Vec<ForeignAddress>,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn create_transaction(
+        &mut self,
+        token_id: String,
+        transaction_rlp_hex: String,
+        use_paymaster: Option<bool>,
+        options: Option<CreateTransactionOptions>,
+    ) -> PromiseOrValue<TransactionSequenceCreation> {
+        let account_id = env::predecessor_account_id();
+        let mut options = options.unwrap_or_default();
+
+        self.with_idempotency_key(
+            account_id.clone(),
+            options.idempotency_key.take(),
+            AssetBalance::native(env::attached_deposit().as_yoctonear()),
+            |contract, deposit| {
+                contract.create_transaction_inner(
+                    token_id,
+                    account_id,
+                    transaction_rlp_hex,
+                    use_paymaster,
+                    deposit,
+                    None,
+                    options,
+                )
+            },
+        )
+    }
+
+    /// Alternative to `create_transaction` for callers that cannot produce
+    /// RLP-encoded transaction requests. If `nonce` is omitted, it is filled
+    /// in with the nonce following the last one this contract signed for the
+    /// chain key on the requested chain.
+    #[payable]
+    pub fn create_transaction_json(
+        &mut self,
+        token_id: String,
+        transaction: Eip1559JsonRequest,
+        use_paymaster: Option<bool>,
+        options: Option<CreateTransactionOptions>,
+    ) -> PromiseOrValue<TransactionSequenceCreation> {
+        let account_id = env::predecessor_account_id();
+        let mut options = options.unwrap_or_default();
+
+        self.with_idempotency_key(
+            account_id.clone(),
+            options.idempotency_key.take(),
+            AssetBalance::native(env::attached_deposit().as_yoctonear()),
+            |contract, deposit| {
+                let nonce = transaction.nonce.map_or_else(
+                    || {
+                        contract
+                            .next_foreign_nonce(&token_id, transaction.chain_id.0)
+                            .expect_or_reject(
+                                "No nonce has been signed for this key yet; specify `nonce`",
+                            )
+                    },
+                    u64::from,
+                );
+
+                let transaction = ValidTransactionRequest::from_json_request(transaction, nonce)
+                    .unwrap_or_reject();
+
+                contract.create_validated_transaction(
+                    token_id,
+                    account_id,
+                    transaction,
+                    use_paymaster,
+                    deposit,
+                    None,
+                    options,
+                )
+            },
+        )
+    }
+
+    /// Sends `amount` of the native gas token of `chain_id` to `to`, without
+    /// the caller having to build the transaction. The transfer uses
+    /// `NATIVE_TRANSFER_GAS`, the chain's fee suggestion (see
+    /// `set_foreign_chain_fee_suggestion`), and the nonce following the last
+    /// one signed for the chain key on the chain. It is always funded by a
+    /// paymaster, so the attached deposit must cover the fee.
+    #[payable]
+    pub fn transfer_native(
+        &mut self,
+        token_id: String,
+        chain_id: U64,
+        to: ForeignAddress,
+        amount: U128,
+        tag: Option<String>,
+    ) -> PromiseOrValue<TransactionSequenceCreation> {
+        self.create_facade_transaction(
+            token_id,
+            chain_id,
+            to,
+            amount,
+            None,
+            NATIVE_TRANSFER_GAS,
+            tag,
+        )
+    }
+
+    /// Sends `amount` (in the token's smallest unit) of the ERC-20 token at
+    /// `token_address` on `chain_id` to `to`, without the caller having to
+    /// build the transaction. The gas limit is the one an administrator set
+    /// for the token with `set_erc20_transfer_gas`; otherwise as
+    /// `transfer_native`.
+    #[payable]
+    pub fn transfer_erc20(
+        &mut self,
+        token_id: String,
+        chain_id: U64,
+        token_address: ForeignAddress,
+        to: ForeignAddress,
+        amount: U128,
+        tag: Option<String>,
+    ) -> PromiseOrValue<TransactionSequenceCreation> {
+        let gas = self
+            .erc20_transfer_gas
+            .get(&(chain_id.0, token_address))
+            .expect_or_reject("No transfer gas configured for this token");
+
+        self.create_facade_transaction(
+            token_id,
+            chain_id,
+            token_address,
+            U128(0),
+            Some(hex::encode_prefixed(erc20_transfer_calldata(&to, amount.0))),
+            gas,
+            tag,
+        )
+    }
+
+    pub fn get_erc20_transfer_gas(
+        &self,
+        chain_id: U64,
+        token_address: ForeignAddress,
+    ) -> Option<U128> {
+        self.erc20_transfer_gas
+            .get(&(chain_id.0, token_address))
+            .map(Into::into)
+    }
+
+    /// Creates a paymaster-funded sequence for a transaction built by the
+    /// contract, using the chain's fee suggestion and the key's next nonce.
+    #[allow(clippy::too_many_arguments)]
+    fn create_facade_transaction(
+        &mut self,
+        token_id: String,
+        chain_id: U64,
+        to: ForeignAddress,
+        value: U128,
+        data: Option<String>,
+        gas: u128,
+        tag: Option<String>,
+    ) -> PromiseOrValue<TransactionSequenceCreation> {
+        let fee_suggestion = self
+            .get_chain(chain_id.0)
+            .unwrap_or_reject()
+            .fee_suggestion
+            .expect_or_reject("No fee suggestion for this chain");
+
+        let nonce = self
+            .next_foreign_nonce(&token_id, chain_id.0)
+            .expect_or_reject(
+                "No nonce has been signed for this key yet; use `create_transaction_json`",
+            );
+
+        let transaction = ValidTransactionRequest::from_json_request(
+            Eip1559JsonRequest {
+                chain_id,
+                to,
+                value,
+                data,
+                gas: U128(gas),
+                max_fee_per_gas: fee_suggestion.max_fee_per_gas,
+                max_priority_fee_per_gas: fee_suggestion.max_priority_fee_per_gas,
+                nonce: None,
+            },
+            nonce,
+        )
+        .unwrap_or_reject();
+
+        self.create_validated_transaction(
+            token_id,
+            env::predecessor_account_id(),
+            transaction,
+            Some(true),
+            AssetBalance::native(env::attached_deposit().as_yoctonear()),
+            None,
+            CreateTransactionOptions {
+                tag,
+                ..Default::default()
+            },
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn create_transaction_inner(
+        &mut self,
+        token_id: String,
+        account_id: AccountId,
+        transaction_rlp_hex: String,
+        use_paymaster: Option<bool>,
+        deposit: AssetBalance,
+        tip: Option<U128>,
+        options: CreateTransactionOptions,
+    ) -> PromiseOrValue<TransactionSequenceCreation> {
+        let transaction =
+            ValidTransactionRequest::try_from(decode_transaction_request(&transaction_rlp_hex))
+                .unwrap_or_reject();
+
+        self.create_validated_transaction(
+            token_id,
+            account_id,
+            transaction,
+            use_paymaster,
+            deposit,
+            tip,
+            options,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn create_validated_transaction(
+        &mut self,
+        token_id: String,
+        account_id: AccountId,
+        transaction: ValidTransactionRequest,
+        use_paymaster: Option<bool>,
+        deposit: AssetBalance,
+        tip: Option<U128>,
+        mut options: CreateTransactionOptions,
+    ) -> PromiseOrValue<TransactionSequenceCreation> {
+        let use_paymaster = use_paymaster.unwrap_or(false);
+
+        let (user_chain_key, deposit) = self
+            .validate_creation(
+                &account_id,
+                &token_id,
+                &transaction,
+                use_paymaster,
+                deposit,
+                &options,
+            )
+            .unwrap_or_reject();
+
+        // The proof is only needed to check the receiver whitelist.
+        options.receiver_proof = None;
+
+        self.create_checked_transaction(
+            token_id,
+            account_id,
+            &user_chain_key,
+            transaction,
+            use_paymaster,
+            deposit,
+            tip,
+            options,
+        )
+    }
+
+    /// Creates the sequence once the caller has checked that `account_id`
+    /// may use the chain key to sign `transaction`.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn create_checked_transaction(
+        &mut self,
+        token_id: String,
+        account_id: AccountId,
+        user_chain_key: &ChainKeyData,
+        transaction: ValidTransactionRequest,
+        use_paymaster: bool,
+        deposit: AssetBalance,
+        tip: Option<U128>,
+        options: CreateTransactionOptions,
+    ) -> PromiseOrValue<TransactionSequenceCreation> {
+        self.check_creation_payment(&account_id, &transaction, use_paymaster, &deposit, &options)
+            .unwrap_or_reject();
+
+        self.consume_rate_limit(&account_id, env::block_height())
+            .unwrap_or_reject();
+
+        let priority = options.priority.unwrap_or(0);
+
+        if use_paymaster {
+            let deposit = self.take_dust(&account_id, deposit);
+
+            let (queries, nonce_queried_for, screened_addresses) =
+                self.sponsorship_queries(&deposit.asset_id, user_chain_key, &transaction);
+
+            queries
+                .then(
+                    Self::ext(env::current_account_id()).create_transaction_callback(
+                        CreateTransactionCallbackArgs {
+                            sender: account_id,
+                            token_id,
+                            deposit,
+                            tip,
+                            transaction_request: transaction,
+                            options,
+                            nonce_queried_for,
+                            screened_addresses,
+                        },
+                    ),
+                )
+                .into()
+        } else {
+            let chain_id = transaction.chain_id;
+
+            let (escrow, signer_deposit) =
+                self.take_unsponsored_fee(&account_id, chain_id, &deposit);
+
+            let tip = tip.map(|amount| AssetBalance {
+                asset_id: deposit.asset_id,
+                amount,
+            });
+
+            let pending_transaction_sequence = PendingTransactionSequence {
+                signature_requests: vec![SignatureRequest::new(
+                    &token_id,
+                    user_chain_key.authorization,
+                    transaction,
+                    false,
+                )],
+                created_by_account_id: account_id,
+                created_at_block_height: env::block_height().into(),
+                escrow,
+                tag: options.tag,
+                paymaster_token_id: None,
+                settled_escrow: None,
+                priority,
+                tip,
+                callback: options.callback,
+                funding_shares: vec![],
+                paymaster_reservations: vec![],
+                signing_order: SigningOrder::default(),
+                is_funding_released: false,
+                signer_deposit: signer_deposit.into(),
+                tenant_id: options.tenant_id,
+                recurring_authorization_id: None,
+                is_cancellation: false,
+                replaced_sequence_id: None,
+                session_grantee_id: None,
+            };
+
+            let creation = self.insert_transaction_sequence(chain_id, pending_transaction_sequence);
+
+            PromiseOrValue::Value(creation)
+        }
+    }
+
+    /// Queries the prices needed to price a sponsored transaction, then the
+    /// nonce of the next paymaster and the screening results, if configured.
+    /// Returns the queries, the paymaster whose nonce is queried, and the
+    /// screened addresses, in the order of their results.
+    fn sponsorship_queries(
+        &self,
+        asset_id: &AssetId,
+        user_chain_key: &ChainKeyData,
+        transaction: &ValidTransactionRequest,
+    ) -> (Promise, Option<String>, Vec<ForeignAddress>) {
+        let accepted_local_asset = self.accepted_local_assets.get(asset_id).unwrap_or_reject();
+
+        let chain_id = transaction.chain_id();
+        let foreign_chain_configuration = self.get_chain(chain_id.as_u64()).unwrap_or_reject();
+
+        let prices = ext_pyth::ext(self.oracle_id.clone())
+            .get_ema_price(pyth::PriceIdentifier(accepted_local_asset.oracle_asset_id))
+            .and(
+                ext_pyth::ext(self.oracle_id.clone()).get_ema_price(pyth::PriceIdentifier(
+                    foreign_chain_configuration.oracle_asset_id,
+                )),
+            );
+
+        // The nonce of the paymaster next in the rotation is fetched
+        // alongside the prices, so that it can be synchronized before its
+        // funding transaction is built.
+        let (queries, nonce_queried_for) = match foreign_chain_configuration
+            .nonce_oracle_id
+            .clone()
+            .zip(foreign_chain_configuration.peek_next_paymaster())
+        {
+            Some((nonce_oracle_id, paymaster)) => {
+                let paymaster_address = ForeignAddress::from_raw_public_key(
+                    self.paymaster_keys
+                        .get(&paymaster.token_id)
+                        .unwrap_or_reject() // inconsistent state if this fails
+                        .public_key_bytes,
+                );
+
+                (
+                    prices.and(
+                        ext_nonce_oracle::ext(nonce_oracle_id)
+                            .get_nonce(chain_id.as_u64().into(), paymaster_address),
+                    ),
+                    Some(paymaster.token_id),
+                )
+            }
+            None => (prices, None),
+        };
+
+        // The sender (which the paymaster funds) and the receiver are
+        // screened alongside, after the nonce if queried.
+        let (queries, screened_addresses) = match self.screening_id.clone() {
+            Some(screening_id) => {
+                let screened_addresses = vec![
+                    ForeignAddress::from_raw_public_key(&user_chain_key.public_key_bytes),
+                    transaction.to,
+                ];
+                let queries = screened_addresses.iter().fold(queries, |queries, address| {
+                    queries.and(ext_screening::ext(screening_id.clone()).is_blocked(*address))
+                });
+                (queries, screened_addresses)
+            }
+            None => (queries, vec![]),
+        };
+
+        (queries, nonce_queried_for, screened_addresses)
+    }
+
+    /// Takes the unsponsored fee and the signer deposit from `deposit`,
+    /// refunding the rest. Returns the fee escrow and the signer deposit.
+    fn take_unsponsored_fee(
+        &mut self,
+        account_id: &AccountId,
+        chain_id: u64,
+        deposit: &AssetBalance,
+    ) -> (Option<AssetBalance>, u128) {
+        let fee = self
+            .unsponsored_fee(chain_id, &deposit.asset_id)
+            .unwrap_or_reject();
+        let signer_deposit = self
+            .signer_deposit_for(&deposit.asset_id, 1)
+            .unwrap_or_reject();
+        let refund = refund_after_fee(deposit, fee, signer_deposit).unwrap_or_reject();
+
+        let escrow = (fee > 0).then(|| AssetBalance {
+            asset_id: deposit.asset_id.clone(),
+            amount: fee.into(),
+        });
+
+        if refund > 0 {
+            self.refund_or_keep_dust(
+                account_id.clone(),
+                AssetBalance {
+                    asset_id: deposit.asset_id.clone(),
+                    amount: refund.into(),
+                },
+            );
+        }
+
+        (escrow, signer_deposit)
+    }
+
+    /// Prices a sponsored transaction in the deposited asset, including the
+    /// priority fee, once the conversion rate has been checked.
+    pub(crate) fn sponsored_fee(
+        &self,
+        foreign_chain: &ForeignChainConfiguration,
+        asset_id: &AssetId,
+        options: &CreateTransactionOptions,
+        gas_tokens_to_sponsor_transaction: U256,
+        foreign_asset_price: &pyth::Price,
+        local_asset_price: &pyth::Price,
+    ) -> Result<u128, TryCreateTransactionCallbackError> {
+        let accepted_local_asset = self.accepted_local_assets.get(asset_id).unwrap_or_reject();
+
+        foreign_chain.check_conversion_rate_bounds(
+            asset_id,
+            foreign_asset_price,
+            local_asset_price,
+            accepted_local_asset.decimals,
+        )?;
+
+        let fee_rate = self
+            .tenant_fee_rate(options.tenant_id.as_ref())
+            .unwrap_or(foreign_chain.fee_rate);
+
+        Ok(foreign_chain.apply_min_fee_local(
+            asset_id,
+            self.apply_priority_fee(
+                options.priority.unwrap_or(0),
+                foreign_chain.price_for_gas_tokens_at_fee_rate(
+                    fee_rate,
+                    gas_tokens_to_sponsor_transaction,
+                    foreign_asset_price,
+                    local_asset_price,
+                    accepted_local_asset.decimals,
+                )?,
+            )?,
+        ))
+    }
+
+    pub(crate) fn sender_chain_key(
+        &self,
+        sender: &AccountId,
+        token_id: &str,
+    ) -> Result<ChainKeyData, SenderUnauthorizedForNftChainKeyError> {
+        self.user_chain_keys
+            .get(sender)
+            .and_then(|user_chain_keys| user_chain_keys.get(&token_id.to_string()))
+            .ok_or_else(|| SenderUnauthorizedForNftChainKeyError {
+                sender: sender.clone(),
+                token_id: token_id.to_string(),
+            })
+    }
+
+    fn try_create_transaction_callback(
+        &mut self,
+        args: CreateTransactionCallbackArgs,
+        paymaster_nonce: Option<(String, u64)>,
+        local_asset_price_result: Result<pyth::Price, PromiseError>,
+        foreign_asset_price_result: Result<pyth::Price, PromiseError>,
+    ) -> Result<(u128, TransactionSequenceCreation), TryCreateTransactionCallbackError> {
+        let CreateTransactionCallbackArgs {
+            sender,
+            token_id,
+            deposit,
+            tip,
+            transaction_request,
+            options,
+            ..
+        } = args;
+        let funding = options.funding.clone().unwrap_or_default();
+        let priority = options.priority.unwrap_or(0);
+
+        let local_asset_price = local_asset_price_result.map_err(|_| OracleQueryFailureError)?;
+        let foreign_asset_price =
+            foreign_asset_price_result.map_err(|_| OracleQueryFailureError)?;
+
+        let user_chain_key = self.sender_chain_key(&sender, &token_id)?;
+
+        let sender_foreign_address =
+            ForeignAddress::from_raw_public_key(&user_chain_key.public_key_bytes);
+
+        let mut foreign_chain = self.get_chain(transaction_request.chain_id)?;
+
+        let transfer_gas = foreign_chain.funding_transfer_gas(&funding)?;
+
+        let gas_tokens_to_sponsor_transaction = foreign_chain
+            .calculate_gas_tokens_to_sponsor_transaction(&transaction_request, transfer_gas)?;
+
+        let local_asset_fee = self.sponsored_fee(
+            &foreign_chain,
+            &deposit.asset_id,
+            &options,
+            gas_tokens_to_sponsor_transaction,
+            &foreign_asset_price,
+            &local_asset_price,
+        )?;
+
+        // One funding and one user signature request.
+        let signer_deposit = self.signer_deposit_for(&deposit.asset_id, 2)?;
+        let refund = refund_after_fee(&deposit, local_asset_fee, signer_deposit)?;
+
+        if let Some((paymaster_token_id, nonce)) = paymaster_nonce {
+            foreign_chain.sync_paymaster_nonce(&paymaster_token_id, nonce);
+        }
+
+        let paymaster_signature_request = self.create_funding_signature_request(
+            &mut foreign_chain,
+            &transaction_request,
+            sender_foreign_address,
+            gas_tokens_to_sponsor_transaction,
+            transfer_gas,
+            &funding,
+        )?;
+
+        Self::record_sponsorship(&mut foreign_chain, gas_tokens_to_sponsor_transaction);
+
+        self.foreign_chains
+            .insert(&transaction_request.chain_id, &foreign_chain);
+
+        self.record_account_spending(
+            &sender,
+            transaction_request.chain_id,
+            gas_tokens_to_sponsor_transaction,
+        );
+
+        // After this point, the function should be virtually infallible, excluding out-of-gas errors.
+
+        let paymaster_token_id = Some(paymaster_signature_request.token_id.clone());
+
+        let paymaster_reservations = vec![PaymasterReservation::for_funding_transaction(
+            &paymaster_signature_request.token_id,
+            &paymaster_signature_request.transaction,
+        )];
+
+        let signature_requests = vec![
+            paymaster_signature_request,
+            SignatureRequest::new(
+                &token_id,
+                user_chain_key.authorization,
+                transaction_request.clone(),
+                false,
+            ),
+        ];
+
+        let pending_transaction_sequence = PendingTransactionSequence {
+            signature_requests,
+            created_by_account_id: sender,
+            created_at_block_height: env::block_height().into(),
+            escrow: Some(AssetBalance {
+                amount: local_asset_fee.into(),
+                asset_id: deposit.asset_id.clone(),
+            }),
+            tag: options.tag,
+            paymaster_token_id,
+            settled_escrow: None,
+            priority,
+            tip: tip.map(|amount| AssetBalance {
+                asset_id: deposit.asset_id,
+                amount,
+            }),
+            callback: options.callback,
+            funding_shares: vec![],
+            paymaster_reservations,
+            signing_order: funding.signing_order,
+            is_funding_released: false,
+            signer_deposit: signer_deposit.into(),
+            tenant_id: options.tenant_id,
+            recurring_authorization_id: None,
+            is_cancellation: false,
+            replaced_sequence_id: None,
+            session_grantee_id: None,
+        };
+
+        let creation = self.insert_transaction_sequence(
+            transaction_request.chain_id,
+            pending_transaction_sequence,
+        );
+
+        Ok((refund, creation))
+    }
+
+    #[private]
+    pub fn create_transaction_callback(
+        &mut self,
+        #[serializer(borsh)] args: CreateTransactionCallbackArgs,
+        #[callback_result] local_asset_price_result: Result<pyth::Price, PromiseError>,
+        #[callback_result] foreign_asset_price_result: Result<pyth::Price, PromiseError>,
+    ) -> PromiseOrValue<TransactionSequenceCreation> {
+        // The nonce oracle result, if queried, follows the prices. If the
+        // query failed, the locally tracked nonce is used.
+        let screening_results_start = if args.nonce_queried_for.is_some() {
+            3
+        } else {
+            2
+        };
+        let paymaster_nonce = args.nonce_queried_for.clone().and_then(|token_id| {
+            let PromiseResult::Successful(value) = env::promise_result(2) else {
+                return None;
+            };
+            let nonce = near_sdk::serde_json::from_slice::<U64>(&value).ok()?;
+            Some((token_id, nonce.0))
+        });
+
+        let sender = args.sender.clone();
+        let deposit = args.deposit.clone();
+        let tip = args.tip;
+
+        let result = check_screening_results(&args.screened_addresses, screening_results_start)
+            .map_err(Into::into)
+            .and_then(|()| {
+                self.try_create_transaction_callback(
+                    args,
+                    paymaster_nonce,
+                    local_asset_price_result,
+                    foreign_asset_price_result,
+                )
+            });
+
+        let (refund, creation) = match result {
+            Ok((refund, creation)) => (refund, creation),
+            Err(e) => {
+                // Failure: return deposit, including the tip.
+                let deposit = AssetBalance {
+                    amount: deposit
+                        .amount
+                        .0
+                        .saturating_add(tip.map_or(0, |tip| tip.0))
+                        .into(),
+                    asset_id: deposit.asset_id,
+                };
+
+                return PromiseOrValue::Promise(Self::refund_and_throw(sender, deposit, &e));
+            }
+        };
+
+        if refund > 0 {
+            // Refund excess
+            self.refund_or_keep_dust(
+                sender,
+                AssetBalance {
+                    asset_id: deposit.asset_id,
+                    amount: refund.into(),
+                },
+            );
+        }
+
+        PromiseOrValue::Value(creation)
+    }
+
+    /// Fails with `error_str`, usually a JSON-serialized [`ContractError`](lib::contract_error::ContractError).
+    #[private]
+    pub fn throw(&mut self, #[serializer(borsh)] error_str: String) {
+        env::panic_str(&error_str);
+    }
+
+    pub fn remove_transaction(&mut self, id: U64) -> PromiseOrValue<()> {
+        <Self as Pause>::require_unpaused();
+
+        let mut transaction = self
+            .pending_transaction_sequences
+            .get(&id.0)
+            .expect_or_reject(TransactionSequenceDoesNotExistError {
+                transaction_sequence_id: id.0,
+            });
+
+        require!(
+            transaction.created_by_account_id == env::predecessor_account_id(),
+            "Unauthorized",
+        );
+
+        for signature_request in &transaction.signature_requests {
+            require!(
+                !signature_request.is_in_flight(),
+                "Signature request is in-flight and cannot be removed",
+            );
+        }
+
+        // The escrow and the tip are in the same asset.
+        Self::refund_signer_deposit(&mut transaction);
+
+        let ret = [transaction.escrow.clone(), transaction.tip.clone()]
+            .into_iter()
+            .flatten()
+            .reduce(|mut total, balance| {
+                total.amount.0 += balance.amount.0;
+                total
+            })
+            .map_or(PromiseOrValue::Value(()), |escrow| {
+                PromiseOrValue::Promise(Self::refund(
+                    transaction.created_by_account_id.clone(),
+                    escrow,
+                ))
+            });
+
+        self.release_paymaster_reservations(&transaction);
+
+        self.pending_transaction_sequences.remove(&id.0);
+        self.dequeue_from_signing(id.0, transaction.priority);
+
+        ret
+    }
+}
+
+/// Checks the screening results of `screened_addresses`, starting at promise
+/// result `start`. Addresses that could not be screened are treated as
+/// blocked.
+pub(crate) fn check_screening_results(
+    screened_addresses: &[ForeignAddress],
+    start: u64,
+) -> Result<(), ForeignAddressBlockedError> {
+    for (i, foreign_address) in (start..).zip(screened_addresses) {
+        let is_blocked = match env::promise_result(i) {
+            PromiseResult::Successful(value) => {
+                near_sdk::serde_json::from_slice::<bool>(&value).unwrap_or(true)
+            }
+            PromiseResult::Failed => true,
+        };
+
+        if is_blocked {
+            return Err(ForeignAddressBlockedError {
+                foreign_address: *foreign_address,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// What is left of `deposit` once the fee and the signer deposit are taken.
+pub(crate) fn refund_after_fee(
+    deposit: &AssetBalance,
+    fee: u128,
+    signer_deposit: u128,
+) -> Result<u128, InsufficientDepositForFeeError> {
+    deposit
+        .amount
+        .0
+        .checked_sub(fee)
+        .and_then(|r| r.checked_sub(signer_deposit))
+        .ok_or(InsufficientDepositForFeeError {
+            deposit: deposit.amount.0,
+            fee: fee.saturating_add(signer_deposit),
+        })
+}
+
+impl Contract {
+    /// Releases the reservations of a sequence whose funding transactions
+    /// have not been signed, since they can no longer be broadcast.
+    fn release_paymaster_reservations(&mut self, sequence: &PendingTransactionSequence) {
+        for reservation in &sequence.paymaster_reservations {
+            let is_funding_signed = sequence.signature_requests.iter().any(|r| {
+                r.is_paymaster
+                    && r.is_signed()
+                    && r.token_id == reservation.token_id
+                    && r.transaction.chain_id == reservation.chain_id
+            });
+
+            if !is_funding_signed && self.foreign_chains.get(&reservation.chain_id).is_some() {
+                self.with_mut_chain(reservation.chain_id, |config| {
+                    config.release_reservation(reservation);
+                });
+            }
+        }
+    }
+
+    pub(crate) fn check_callback(callback: Option<&SequenceCallback>) -> Result<(), String> {
+        match callback {
+            Some(callback) if callback.msg.len() > MAX_CALLBACK_MSG_LENGTH => Err(format!(
+                "Callback message must be at most {MAX_CALLBACK_MSG_LENGTH} bytes"
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Refunds the deposit of a sequence that could not be created, then
+    /// fails with `e` as a JSON-serialized [`ContractError`](lib::contract_error::ContractError).
+    pub(crate) fn refund_and_throw(
+        sender: AccountId,
+        deposit: AssetBalance,
+        e: &TryCreateTransactionCallbackError,
+    ) -> Promise {
+        if let TryCreateTransactionCallbackError::ConversionRateOutOfBounds(anomaly) = e {
+            ContractEvent::OracleAnomalyDetected(OracleAnomalyDetected {
+                chain_id: anomaly.chain_id.into(),
+                asset_id: anomaly.asset_id.clone(),
+                rate: u128::try_from(anomaly.rate).unwrap_or(u128::MAX).into(),
+                min_rate: anomaly.bounds.min_rate,
+                max_rate: anomaly.bounds.max_rate,
+            })
+            .emit();
+        }
+
+        let error = e.to_contract_error();
+
+        ContractEvent::TransactionSequenceCreationFailed(TransactionSequenceCreationFailed {
+            sender_id: sender.clone(),
+            error: error.clone(),
+        })
+        .emit();
+
+        Self::refund(sender, deposit)
+            .then(Self::ext(env::current_account_id()).throw(error.to_string()))
+    }
+
+    pub(crate) fn filter_transaction(
+        &self,
+        sender_id: &AccountId,
+        transaction: &ValidTransactionRequest,
+        receiver_proof: Option<&[[u8; 32]]>,
+    ) {
+        if let Err(e) = self.check_transaction_filter(sender_id, transaction, receiver_proof) {
+            env::panic_str(e);
+        }
+    }
+
+    pub(crate) fn check_transaction_filter(
+        &self,
+        sender_id: &AccountId,
+        transaction: &ValidTransactionRequest,
+        receiver_proof: Option<&[[u8; 32]]>,
+    ) -> Result<(), &'static str> {
+        self.check_denylist(transaction)?;
+
+        // Check receiver whitelist
+        if self.flags.is_receiver_whitelist_enabled
+            && !self.receiver_whitelist.contains(&transaction.to)
+            && !self.is_receiver_proven(&transaction.to, receiver_proof)
+        {
+            return Err("Receiver is not whitelisted");
+        }
+
+        // Check sender whitelist
+        if self.flags.is_sender_whitelist_enabled && !self.sender_whitelist.contains(sender_id) {
+            return Err("Sender is not whitelisted");
+        }
+
+        Ok(())
+    }
+
+    /// Whether `receiver_proof` proves that `receiver` is a leaf
+    /// (`keccak256(address)`) of the receiver whitelist Merkle tree.
+    fn is_receiver_proven(
+        &self,
+        receiver: &ForeignAddress,
+        receiver_proof: Option<&[[u8; 32]]>,
+    ) -> bool {
+        let (Some(root), Some(proof)) = (&self.receiver_whitelist_merkle_root, receiver_proof)
+        else {
+            return false;
+        };
+
+        verify_merkle_proof(root, keccak256(receiver.0), proof)
+    }
+
+    /// Runs every check that creating a sequence performs before the
+    /// sequence is created or the oracles are queried, for `account_id` to
+    /// sign `transaction` with the chain key `token_id`. Returns the chain
+    /// key and the deposit left after paying for the storage of the tag.
+    ///
+    /// Callers that hold a deposit on behalf of the user run the checks
+    /// first, so that they can refund the deposit instead of losing it.
+    pub(crate) fn validate_creation(
+        &self,
+        account_id: &AccountId,
+        token_id: &str,
+        transaction: &ValidTransactionRequest,
+        use_paymaster: bool,
+        deposit: AssetBalance,
+        options: &CreateTransactionOptions,
+    ) -> Result<(ChainKeyData, AssetBalance), String> {
+        if <Self as Pause>::is_paused() {
+            return Err("Disallowed while contract is paused".to_string());
+        }
+
+        let deposit = match options.tag {
+            Some(ref tag) => Self::charge_tag_storage(tag, deposit).map_err(|e| e.to_string())?,
+            None => deposit,
+        };
+
+        Self::check_callback(options.callback.as_ref())?;
+
+        let receiver_proof = options
+            .receiver_proof
+            .as_ref()
+            .map(|proof| {
+                proof
+                    .iter()
+                    .map(|node| decode_hash(node))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or("Invalid receiver proof")
+            })
+            .transpose()?;
+
+        // Whitelisting
+        self.check_transaction_filter(account_id, transaction, receiver_proof.as_deref())?;
+
+        if let Some(ref tenant_id) = options.tenant_id {
+            self.check_tenant_transaction(tenant_id, account_id, transaction)?;
+        }
+
+        // Assert the account can use the requested key path
+        let user_chain_key = self
+            .user_chain_keys
+            .get(account_id)
+            .ok_or("No managed keys for predecessor")?
+            .get(&token_id.to_string())
+            .ok_or("Predecessor unauthorized for the requested chain key token ID")?;
+
+        if !user_chain_key.has_public_key() {
+            return Err(
+                "Chain key registration is incomplete; call `refresh_user_chain_key` first"
+                    .to_string(),
+            );
+        }
+
+        self.check_foreign_nonce(token_id, &user_chain_key, transaction)
+            .map_err(|e| e.to_string())?;
+        self.check_transaction_replay(token_id, transaction)
+            .map_err(|e| e.to_string())?;
+
+        self.check_creation_payment(account_id, transaction, use_paymaster, &deposit, options)?;
+
+        Ok((user_chain_key, deposit))
+    }
+
+    /// Checks that `account_id` may create another sequence, and that
+    /// `deposit` can pay for `transaction`, either by sponsorship (up to the
+    /// oracle prices) or as the fee of an unsponsored sequence.
+    fn check_creation_payment(
+        &self,
+        account_id: &AccountId,
+        transaction: &ValidTransactionRequest,
+        use_paymaster: bool,
+        deposit: &AssetBalance,
+        options: &CreateTransactionOptions,
+    ) -> Result<(), String> {
+        self.check_rate_limit(account_id, env::block_height())
+            .map_err(|e| e.to_string())?;
+
+        let priority = options.priority.unwrap_or(0);
+
+        if !use_paymaster {
+            if priority != 0 {
+                return Err("Priority tiers are only available to sponsored sequences".to_string());
+            }
+
+            let fee = self
+                .unsponsored_fee(transaction.chain_id, &deposit.asset_id)?
+                .saturating_add(
+                    self.signer_deposit_for(&deposit.asset_id, 1)
+                        .map_err(|e| e.to_string())?,
+                );
+
+            if deposit.amount.0 < fee {
+                return Err(InsufficientDepositForFeeError {
+                    deposit: deposit.amount.0,
+                    fee,
+                }
+                .to_string());
+            }
+
+            return Ok(());
+        }
+
+        // Fail before querying the oracle.
+        self.apply_priority_fee(priority, 0)
+            .map_err(|e| e.to_string())?;
+        self.signer_deposit_for(&deposit.asset_id, 2)
+            .map_err(|e| e.to_string())?;
+
+        let dust = self
+            .dust_balances
+            .get(&(account_id.clone(), deposit.asset_id.clone()))
+            .unwrap_or(0);
+
+        if deposit.amount.0 == 0 && dust == 0 {
+            return Err("Deposit is required to pay for gas".to_string());
+        }
+
+        if self.accepted_local_assets.get(&deposit.asset_id).is_none() {
+            return Err("Unsupported deposit asset".to_string());
+        }
+
+        let foreign_chain_configuration = self
+            .get_chain(transaction.chain_id)
+            .map_err(|e| e.to_string())?;
+
+        foreign_chain_configuration
+            .funding_transfer_gas(&options.funding.clone().unwrap_or_default())
+            .map_err(|e| e.to_string())?;
+
+        foreign_chain_configuration
+            .check_data_size(transaction)
+            .map_err(|e| e.to_string())?;
+
+        foreign_chain_configuration
+            .check_sponsorship_unpaused()
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    /// Create a paymaster funding transaction that provides funding for the
+    /// maximum amount of gas required by the transaction. Contract senders
+    /// are funded through the chain's funding call, if one is configured.
+    ///
+    /// # Errors
+    ///
+    /// - If the foreign chain ID is not supported.
+    /// - If there is not a paymaster configured for the foreign chain.
+    /// - If the price data provided is invalid.
+    /// - If the paymaster does not have enough available balance.
+    pub fn create_funding_signature_request(
+        &self,
+        foreign_chain: &mut ForeignChainConfiguration,
+        transaction: &ValidTransactionRequest,
+        sender_foreign_address: ForeignAddress,
+        gas_tokens_to_sponsor_transaction: U256,
+        transfer_gas: U256,
+        funding: &FundingOptions,
+    ) -> Result<SignatureRequest, RequestNonceError> {
+        foreign_chain.with_request_nonce(
+            gas_tokens_to_sponsor_transaction,
+            |foreign_chain, paymaster| {
+                let paymaster_transaction = foreign_chain.funding_transaction(
+                    transaction,
+                    sender_foreign_address,
+                    gas_tokens_to_sponsor_transaction,
+                    transfer_gas,
+                    funding,
+                    paymaster.nonce,
+                );
+
+                let paymaster_authorization = self
+                    .paymaster_keys
+                    .get(&paymaster.token_id)
+                    .unwrap_or_reject() // inconsistent state if this fails
+                    .authorization;
+
+                SignatureRequest::new(
+                    &paymaster.token_id,
+                    paymaster_authorization,
+                    paymaster_transaction,
+                    true,
+                )
+            },
+        )
+    }
+
+    /// Deducts the storage cost of `tag` from `deposit`. The tag is stored
+    /// with the pending sequence and again in the signed sequence history,
+    /// so it is charged twice. Storage is paid in NEAR, so tagged sequences
+    /// must be paid for in NEAR too.
+    pub(crate) fn charge_tag_storage(
+        tag: &str,
+        mut deposit: AssetBalance,
+    ) -> Result<AssetBalance, ChargeTagStorageError> {
+        if tag.len() > MAX_TAG_LENGTH {
+            return Err(TagTooLongError {
+                length: tag.len(),
+                maximum: MAX_TAG_LENGTH,
+            }
+            .into());
+        }
+
+        // Borsh `Option<String>`: 1 byte discriminant + 4 byte length prefix.
+        let bytes = (tag.len() as u128 + 5) * 2;
+        let cost = env::storage_byte_cost().as_yoctonear() * bytes;
+
+        if deposit.asset_id != AssetId::Native {
+            return Err(TagRequiresNativeDepositError {
+                asset_id: deposit.asset_id,
+            }
+            .into());
+        }
+
+        if deposit.amount.0 < cost {
+            return Err(InsufficientDepositForTagStorageError {
+                deposit: deposit.amount.0,
+                cost,
+            }
+            .into());
+        }
+
+        deposit.amount.0 -= cost;
+
+        Ok(deposit)
+    }
+
+    /// Stores a new sequence, queues it for signing, and emits its
+    /// `TransactionSequenceCreated` event.
+    pub(crate) fn insert_transaction_sequence(
+        &mut self,
+        foreign_chain_id: u64,
+        pending_transaction: PendingTransactionSequence,
+    ) -> TransactionSequenceCreation {
+        #[allow(clippy::cast_possible_truncation)]
+        let pending_signature_count = pending_transaction.signature_requests.len() as u32;
+
+        let id = self.generate_unique_id();
+
+        self.pending_transaction_sequences
+            .insert(&id, &pending_transaction);
+        self.enqueue_for_signing(
+            id,
+            pending_transaction.priority,
+            pending_transaction.created_at_block_height.0,
+        );
+        self.record_sequence_gas(id);
+
+        let paymaster_funding = pending_transaction
+            .signature_requests
+            .iter()
+            .find(|r| r.is_paymaster)
+            .map(|r| PaymasterFunding {
+                paymaster_token_id: r.token_id.clone(),
+                paymaster_address: ForeignAddress::from_raw_public_key(
+                    self.paymaster_keys
+                        .get(&r.token_id)
+                        .unwrap_or_reject() // inconsistent state if this fails
+                        .public_key_bytes,
+                ),
+                to: r.transaction.to,
+                value: r.transaction.value().as_u128().into(),
+                nonce: r.transaction.nonce().as_u64().into(),
+            });
+
+        ContractEvent::TransactionSequenceCreated(Box::new(TransactionSequenceCreated {
+            id: id.into(),
+            foreign_chain_id: foreign_chain_id.to_string(),
+            expires_at_block_height: Some(
+                self.sequence_expiry(pending_transaction.created_at_block_height.0)
+                    .expires_at_block_height,
+            ),
+            pending_transaction_sequence: pending_transaction,
+        }))
+        .emit();
+
+        TransactionSequenceCreation {
+            id: id.into(),
+            pending_signature_count,
+            paymaster_funding,
+        }
+    }
+}
@@ -1,12 +1,17 @@
+use std::collections::BTreeMap;
+
 use near_sdk::{
-    collections::{UnorderedMap, UnorderedSet, Vector},
+    collections::{LookupMap, LookupSet, TreeMap, UnorderedMap, UnorderedSet, Vector},
     env,
     json_types::{Base64VecU8, U64},
-    near, AccountId,
+    near, require, AccountId,
 };
 use near_sdk_contract_tools::rbac::Rbac;
 
-use crate::{Contract, ContractExt, Flags, Role, StorageKey, DEFAULT_EXPIRE_SEQUENCE_AFTER_BLOCKS};
+use crate::{
+    Contract, ContractExt, Flags, Role, SigningGasBudget, StorageKey,
+    DEFAULT_EXPIRE_SEQUENCE_AFTER_BLOCKS, INITIAL_REGISTRATION_STORAGE_USAGE,
+};
 
 #[derive(Clone, Debug)]
 #[near(serializers = [json])]
@@ -23,6 +28,18 @@ impl Contract {
         }
     }
 
+    /// Restarts sequence IDs at `seed`, so that tests and replays assign
+    /// the same IDs on every run. Only allowed while no sequence is pending.
+    pub fn seed_unique_ids(&mut self, seed: U64) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        require!(
+            self.pending_transaction_sequences.is_empty(),
+            "Cannot reseed IDs while sequences are pending",
+        );
+
+        self.next_unique_id = seed.0;
+    }
+
     #[init(ignore_state)]
     pub fn new_debug(
         signer_contract_id: AccountId,
@@ -47,6 +64,52 @@ impl Contract {
             ),
             signed_transaction_sequences: Vector::new(StorageKey::SignedTransactionSequences),
             collected_fees: UnorderedMap::new(StorageKey::CollectedFees),
+            foreign_nonces: LookupMap::new(StorageKey::ForeignNonces),
+            failed_refunds: LookupMap::new(StorageKey::FailedRefunds),
+            user_chain_key_registrants: LookupMap::new(StorageKey::UserChainKeyRegistrants),
+            last_config_snapshot_block_height: 0,
+            paymaster_bond_settings: None,
+            bonded_paymasters: UnorderedMap::new(StorageKey::BondedPaymasters),
+            paymaster_fees: UnorderedMap::new(StorageKey::PaymasterFees),
+            signed_transaction_hashes: LookupMap::new(StorageKey::SignedTransactionHashes),
+            signed_sequences: LookupMap::new(StorageKey::SignedSequences),
+            admin_actions: Vector::new(StorageKey::AdminActions),
+            admin_action_count: 0,
+            rate_limit: None,
+            rate_limit_tiers: UnorderedMap::new(StorageKey::RateLimitTiers),
+            sender_rate_limit_tiers: LookupMap::new(StorageKey::SenderRateLimitTiers),
+            sequence_creation_windows: LookupMap::new(StorageKey::SequenceCreationWindows),
+            balance_prover_id: None,
+            priority_tiers: UnorderedMap::new(StorageKey::PriorityTiers),
+            signing_queue: TreeMap::new(StorageKey::SigningQueue),
+            sequence_gas_usage: LookupMap::new(StorageKey::SequenceGasUsage),
+            sequence_gas_totals: (0, 0),
+            receiver_whitelist_merkle_root: None,
+            relayer_tips: LookupMap::new(StorageKey::RelayerTips),
+            account_spending: LookupMap::new(StorageKey::AccountSpending),
+            sequences_by_foreign_tx_hash: LookupMap::new(StorageKey::SequencesByForeignTxHash),
+            dust_thresholds: LookupMap::new(StorageKey::DustThresholds),
+            dust_balances: LookupMap::new(StorageKey::DustBalances),
+            signing_gas_budget: SigningGasBudget::default(),
+            sessions: LookupMap::new(StorageKey::Sessions),
+            session_ids: LookupMap::new(StorageKey::SessionIds),
+            next_session_id: 0,
+            registration_storage_usage: INITIAL_REGISTRATION_STORAGE_USAGE,
+            signer_root_public_keys: BTreeMap::new(),
+            doomed_sequences: LookupMap::new(StorageKey::DoomedSequences),
+            signer_stats: UnorderedMap::new(StorageKey::SignerStats),
+            erc20_transfer_gas: LookupMap::new(StorageKey::Erc20TransferGas),
+            chain_registry_id: None,
+            receiver_denylist: UnorderedSet::new(StorageKey::ReceiverDenylist),
+            selector_denylist: UnorderedSet::new(StorageKey::SelectorDenylist),
+            screening_id: None,
+            tenants: UnorderedMap::new(StorageKey::Tenants),
+            tenant_sender_whitelist: LookupSet::new(StorageKey::TenantSenderWhitelist),
+            idempotency_keys: LookupMap::new(StorageKey::IdempotencyKeys),
+            idempotency_key_queue: LookupMap::new(StorageKey::IdempotencyKeyQueue),
+            idempotency_key_queue_bounds: (0, 0),
+            recurring_authorizations: LookupMap::new(StorageKey::RecurringAuthorizations),
+            next_recurring_authorization_id: 0,
         };
 
         Rbac::add_role(
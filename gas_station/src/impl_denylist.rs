@@ -0,0 +1,129 @@
+use lib::{foreign_address::ForeignAddress, Rejectable};
+use near_sdk::near_bindgen;
+use near_sdk_contract_tools::{rbac::Rbac, standard::nep297::Event};
+
+use crate::{
+    contract_event::{ContractEvent, DenylistUpdated},
+    decode_hex,
+    valid_transaction_request::ValidTransactionRequest,
+    Contract, ContractExt, Role,
+};
+
+fn decode_selector(selector: &str) -> [u8; 4] {
+    decode_hex(selector)
+        .ok()
+        .and_then(|s| s.try_into().ok())
+        .expect_or_reject("Invalid function selector: expected 4 hex-encoded bytes")
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_receiver_denylist(&self) -> Vec<ForeignAddress> {
+        self.receiver_denylist.iter().collect()
+    }
+
+    /// Denies transactions to the receivers, regardless of the whitelists.
+    pub fn add_to_receiver_denylist(&mut self, addresses: Vec<ForeignAddress>) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("add_to_receiver_denylist");
+
+        let receivers = addresses
+            .into_iter()
+            .filter(|address| self.receiver_denylist.insert(address))
+            .collect();
+
+        emit_denylist_updated(receivers, vec![], true);
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn remove_from_receiver_denylist(&mut self, addresses: Vec<ForeignAddress>) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("remove_from_receiver_denylist");
+
+        let receivers = addresses
+            .into_iter()
+            .filter(|address| self.receiver_denylist.remove(address))
+            .collect();
+
+        emit_denylist_updated(receivers, vec![], false);
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn get_selector_denylist(&self) -> Vec<String> {
+        self.selector_denylist
+            .iter()
+            .map(|selector| self.flags.encode_hex(selector))
+            .collect()
+    }
+
+    /// Denies transactions whose calldata starts with one of the 4-byte
+    /// function selectors (hex-encoded), regardless of the whitelists.
+    pub fn add_to_selector_denylist(&mut self, selectors: Vec<String>) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("add_to_selector_denylist");
+
+        let selectors = selectors
+            .iter()
+            .map(|selector| decode_selector(selector))
+            .filter(|selector| self.selector_denylist.insert(selector))
+            .map(|selector| self.flags.encode_hex(selector))
+            .collect();
+
+        emit_denylist_updated(vec![], selectors, true);
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn remove_from_selector_denylist(&mut self, selectors: Vec<String>) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("remove_from_selector_denylist");
+
+        let selectors = selectors
+            .iter()
+            .map(|selector| decode_selector(selector))
+            .filter(|selector| self.selector_denylist.remove(selector))
+            .map(|selector| self.flags.encode_hex(selector))
+            .collect();
+
+        emit_denylist_updated(vec![], selectors, false);
+
+        self.emit_config_snapshot_if_due();
+    }
+}
+
+fn emit_denylist_updated(receivers: Vec<ForeignAddress>, selectors: Vec<String>, is_denied: bool) {
+    if receivers.is_empty() && selectors.is_empty() {
+        return;
+    }
+
+    ContractEvent::DenylistUpdated(DenylistUpdated {
+        receivers,
+        selectors,
+        is_denied,
+    })
+    .emit();
+}
+
+impl Contract {
+    /// Checked before, and regardless of, the whitelists.
+    pub(crate) fn check_denylist(
+        &self,
+        transaction: &ValidTransactionRequest,
+    ) -> Result<(), &'static str> {
+        if self.receiver_denylist.contains(&transaction.to) {
+            return Err("Receiver is denied");
+        }
+
+        let selector = transaction
+            .data
+            .get(..4)
+            .and_then(|s| <[u8; 4]>::try_from(s).ok());
+        if selector.map_or(false, |s| self.selector_denylist.contains(&s)) {
+            return Err("Function selector is denied");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use lib::{asset::AssetBalance, Rejectable};
+use near_sdk::{
+    env,
+    json_types::{U128, U64},
+    near, near_bindgen, require, AccountId,
+};
+use near_sdk_contract_tools::{rbac::Rbac, standard::nep297::Event};
+
+use crate::{
+    contract_event::{ContractEvent, TransactionSequenceDoomed},
+    error::TransactionSequenceDoesNotExistError,
+    Contract, ContractExt, Role,
+};
+
+pub const MAX_DOOMED_REASON_LENGTH: usize = 256;
+
+/// Why a signed sequence can never be relayed, as reported by a market maker.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct DoomedSequence {
+    pub marked_by: AccountId,
+    pub reason: String,
+    pub block_height: U64,
+    /// Part of the fee refunded to the creator, if any.
+    pub refund: Option<AssetBalance>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Records that the transactions of a signed sequence cannot be relayed,
+    /// e.g. because their nonce has already been used on the foreign chain.
+    ///
+    /// The sequence's unclaimed relayer tip is refunded to its creator. A
+    /// market maker may also refund up to the sequence's fee with `refund`,
+    /// taken back from the paymasters and collected fees it was credited to.
+    #[payable]
+    pub fn mark_sequence_doomed(
+        &mut self,
+        id: U64,
+        reason: String,
+        refund: Option<U128>,
+    ) -> DoomedSequence {
+        near_sdk::assert_one_yocto();
+        <Self as Rbac>::require_role(&Role::MarketMaker);
+        self.record_admin_action("mark_sequence_doomed");
+
+        require!(
+            reason.len() <= MAX_DOOMED_REASON_LENGTH,
+            format!("Reason must be at most {MAX_DOOMED_REASON_LENGTH} bytes"),
+        );
+        require!(
+            self.doomed_sequences.get(&id.0).is_none(),
+            "Sequence is already marked as doomed",
+        );
+
+        let mut sequence = self.signed_sequences.get(&id.0).expect_or_reject(
+            TransactionSequenceDoesNotExistError {
+                transaction_sequence_id: id.0,
+            },
+        );
+
+        let refund = refund.filter(|r| r.0 > 0).map(|amount| {
+            let fee = sequence
+                .settled_escrow
+                .clone()
+                .expect_or_reject("Sequence did not pay a fee");
+            let refund = AssetBalance {
+                asset_id: fee.asset_id,
+                amount,
+            };
+
+            self.unsettle_escrow(&sequence, &refund).unwrap_or_reject();
+
+            refund
+        });
+
+        let tip = sequence.tip.take();
+        if tip.is_some() {
+            self.signed_sequences.insert(&id.0, &sequence);
+        }
+
+        // The tip and the fee are in the same asset.
+        let total_refund =
+            [refund.clone(), tip]
+                .into_iter()
+                .flatten()
+                .reduce(|mut total, balance| {
+                    total.amount.0 += balance.amount.0;
+                    total
+                });
+        if let Some(total_refund) = total_refund {
+            Self::refund(sequence.created_by_account_id.clone(), total_refund);
+        }
+
+        let doomed = DoomedSequence {
+            marked_by: env::predecessor_account_id(),
+            reason,
+            block_height: env::block_height().into(),
+            refund,
+        };
+
+        self.doomed_sequences.insert(&id.0, &doomed);
+
+        ContractEvent::TransactionSequenceDoomed(TransactionSequenceDoomed {
+            id,
+            marked_by: doomed.marked_by.clone(),
+            reason: doomed.reason.clone(),
+            refund: doomed.refund.clone(),
+        })
+        .emit();
+
+        doomed
+    }
+
+    pub fn get_doomed_sequence(&self, id: U64) -> Option<DoomedSequence> {
+        self.doomed_sequences.get(&id.0)
+    }
+}
@@ -0,0 +1,99 @@
+use lib::{
+    asset::{AssetBalance, AssetId},
+    Rejectable,
+};
+use near_sdk::{env, json_types::U128, near_bindgen, AccountId, Promise};
+use near_sdk_contract_tools::rbac::Rbac;
+
+use crate::{Contract, ContractExt, Role};
+
+#[near_bindgen]
+impl Contract {
+    /// Refunds of `asset_id` up to `threshold` are credited to the sender's
+    /// dust balance instead of being transferred. `None` disables dust
+    /// collection for the asset; existing balances are unaffected.
+    pub fn set_dust_threshold(&mut self, asset_id: AssetId, threshold: Option<U128>) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_dust_threshold");
+
+        if let Some(threshold) = threshold {
+            self.dust_thresholds.insert(&asset_id, &threshold.0);
+        } else {
+            self.dust_thresholds.remove(&asset_id);
+        }
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn get_dust_threshold(&self, asset_id: AssetId) -> U128 {
+        self.dust_thresholds.get(&asset_id).unwrap_or(0).into()
+    }
+
+    /// Refund dust held for an account. It is added to the deposit of the
+    /// next sponsored sequence the account creates with the same asset.
+    pub fn get_dust_balance(&self, account_id: AccountId, asset_id: AssetId) -> U128 {
+        self.dust_balances
+            .get(&(account_id, asset_id))
+            .unwrap_or(0)
+            .into()
+    }
+
+    /// Transfers the predecessor's dust balance for an asset.
+    pub fn withdraw_dust(&mut self, asset_id: AssetId) -> Promise {
+        let account_id = env::predecessor_account_id();
+
+        let amount = self
+            .dust_balances
+            .remove(&(account_id.clone(), asset_id.clone()))
+            .expect_or_reject("No dust balance for this asset");
+
+        Self::refund(
+            account_id,
+            AssetBalance {
+                asset_id,
+                amount: amount.into(),
+            },
+        )
+    }
+}
+
+impl Contract {
+    /// Refunds `refund` to `account_id`, unless it is small enough to be
+    /// kept as dust.
+    pub(crate) fn refund_or_keep_dust(&mut self, account_id: AccountId, refund: AssetBalance) {
+        let threshold = self.dust_thresholds.get(&refund.asset_id).unwrap_or(0);
+
+        if refund.amount.0 > threshold {
+            Self::refund(account_id, refund);
+            return;
+        }
+
+        let key = (account_id, refund.asset_id);
+        let balance = self
+            .dust_balances
+            .get(&key)
+            .unwrap_or(0)
+            .checked_add(refund.amount.0)
+            .unwrap_or_reject();
+        self.dust_balances.insert(&key, &balance);
+    }
+
+    /// Adds the account's dust balance of the deposit asset to the deposit.
+    pub(crate) fn take_dust(
+        &mut self,
+        account_id: &AccountId,
+        deposit: AssetBalance,
+    ) -> AssetBalance {
+        let Some(dust) = self
+            .dust_balances
+            .remove(&(account_id.clone(), deposit.asset_id.clone()))
+        else {
+            return deposit;
+        };
+
+        AssetBalance {
+            amount: deposit.amount.0.checked_add(dust).unwrap_or_reject().into(),
+            asset_id: deposit.asset_id,
+        }
+    }
+}
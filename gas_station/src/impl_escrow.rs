@@ -0,0 +1,159 @@
+use lib::{
+    asset::{AssetBalance, AssetId, TransferOptions},
+    nep145::{ext_nep145, StorageBalance},
+    Rejectable,
+};
+use near_sdk::{
+    env, json_types::U128, near_bindgen, require, AccountId, Gas, Promise, PromiseError,
+    PromiseOrValue, PromiseResult,
+};
+
+use crate::{transfer_options, Contract, ContractExt};
+
+#[near_bindgen]
+impl Contract {
+    const REFUND_CALLBACK_GAS: Gas = Gas::from_tgas(5);
+    const STORAGE_BALANCE_OF_GAS: Gas = Gas::from_tgas(5);
+    const REFUND_IF_REGISTERED_CALLBACK_GAS: Gas = Gas::from_tgas(35);
+
+    /// Transfers the NEP-141 refund if the receiver is registered with the
+    /// token contract. Otherwise, the refund is recorded as claimable
+    /// instead, since the transfer would fail anyway.
+    #[private]
+    pub fn refund_if_registered_callback(
+        &mut self,
+        #[serializer(borsh)] account_id: AccountId,
+        #[serializer(borsh)] refund: AssetBalance,
+        #[callback_result] storage_balance: Result<Option<StorageBalance>, PromiseError>,
+    ) -> PromiseOrValue<bool> {
+        match storage_balance {
+            Ok(None) => {
+                self.record_failed_refund(account_id, refund);
+                PromiseOrValue::Value(false)
+            }
+            // Tokens that do not implement NEP-145 are attempted anyway.
+            Ok(Some(_)) | Err(_) => PromiseOrValue::Promise(Self::transfer_refund(
+                account_id,
+                refund,
+                &transfer_options("Gas station refund"),
+            )),
+        }
+    }
+
+    /// Records the refund as claimable if the transfer failed, e.g. because
+    /// the receiver is not registered with the NEP-141 token contract.
+    #[private]
+    pub fn refund_callback(
+        &mut self,
+        #[serializer(borsh)] account_id: AccountId,
+        #[serializer(borsh)] refund: AssetBalance,
+    ) -> bool {
+        let success = matches!(env::promise_result(0), PromiseResult::Successful(_));
+
+        if !success {
+            self.record_failed_refund(account_id, refund);
+        }
+
+        success
+    }
+
+    /// Retries a refund that previously failed.
+    ///
+    /// If a deposit is attached to the claim of a NEP-141 refund, it is used
+    /// to register the predecessor with the token contract first (NEP-145
+    /// `storage_deposit`). Token contracts refund whatever is not needed for
+    /// the registration.
+    #[payable]
+    pub fn claim_failed_refund(&mut self, asset_id: AssetId) -> Promise {
+        let account_id = env::predecessor_account_id();
+
+        let amount = self
+            .failed_refunds
+            .remove(&(account_id.clone(), asset_id.clone()))
+            .expect_or_reject("No failed refund for this asset");
+
+        let refund = AssetBalance {
+            asset_id,
+            amount: amount.into(),
+        };
+
+        let storage_deposit = env::attached_deposit();
+        if storage_deposit.is_zero() {
+            return Self::refund(account_id, refund);
+        }
+
+        require!(
+            matches!(refund.asset_id, AssetId::Nep141(_)),
+            "Storage deposit can only be attached when claiming NEP-141 refunds",
+        );
+
+        Self::transfer_refund(
+            account_id,
+            refund,
+            &transfer_options("Gas station refund").with_storage_deposit(storage_deposit),
+        )
+    }
+
+    pub fn get_failed_refund(&self, account_id: AccountId, asset_id: AssetId) -> U128 {
+        self.failed_refunds
+            .get(&(account_id, asset_id))
+            .unwrap_or(0)
+            .into()
+    }
+}
+
+impl Contract {
+    /// Transfers `refund` to `account_id`. If the transfer fails, the amount
+    /// can be claimed later with `claim_failed_refund`.
+    ///
+    /// NEP-141 refunds are only transferred once the receiver is confirmed to
+    /// be registered with the token contract, since transfers to unregistered
+    /// receivers fail (or, with some tokens, lose the funds).
+    pub(crate) fn refund(account_id: AccountId, refund: AssetBalance) -> Promise {
+        let AssetId::Nep141(ref contract_id) = refund.asset_id else {
+            return Self::transfer_refund(
+                account_id,
+                refund,
+                &transfer_options("Gas station refund"),
+            );
+        };
+
+        ext_nep145::ext(contract_id.clone())
+            .with_static_gas(Self::STORAGE_BALANCE_OF_GAS)
+            .with_unused_gas_weight(0)
+            .storage_balance_of(account_id.clone())
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(Self::REFUND_IF_REGISTERED_CALLBACK_GAS)
+                    .with_unused_gas_weight(0)
+                    .refund_if_registered_callback(account_id, refund),
+            )
+    }
+
+    fn transfer_refund(
+        account_id: AccountId,
+        refund: AssetBalance,
+        options: &TransferOptions,
+    ) -> Promise {
+        refund
+            .asset_id
+            .transfer_with_options(account_id.clone(), refund.amount, options)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(Self::REFUND_CALLBACK_GAS)
+                    .with_unused_gas_weight(0)
+                    .refund_callback(account_id, refund),
+            )
+    }
+
+    fn record_failed_refund(&mut self, account_id: AccountId, refund: AssetBalance) {
+        let key = (account_id, refund.asset_id);
+        let owed = self
+            .failed_refunds
+            .get(&key)
+            .unwrap_or(0)
+            .checked_add(refund.amount.0)
+            .unwrap_or_reject();
+        self.failed_refunds.insert(&key, &owed);
+    }
+}
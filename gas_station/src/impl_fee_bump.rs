@@ -0,0 +1,364 @@
+use ethers_core::types::U256;
+use lib::{
+    asset::{AssetBalance, AssetId},
+    foreign_address::ForeignAddress,
+    Rejectable,
+};
+use near_sdk::{
+    env,
+    json_types::{U128, U64},
+    near_bindgen, require, Gas, PromiseError, PromiseOrValue,
+};
+use near_sdk_contract_tools::{pause::Pause, standard::nep297::Event};
+
+use crate::{
+    chain_configuration::PaymasterReservation,
+    contract_event::{ContractEvent, TransactionSequenceRebumped},
+    signature_request::SignatureRequest,
+    valid_transaction_request::ValidTransactionRequest,
+    Contract, ContractExt, CreateTransactionOptions, PendingTransactionSequence,
+    TransactionSequenceCreation, NATIVE_TRANSFER_GAS,
+};
+
+const CANCELLATION_SEQUENCE_CALLBACK_GAS: Gas = Gas::from_tgas(5);
+
+#[near_bindgen]
+impl Contract {
+    /// Replaces a fully signed sequence that is stuck on the foreign chain
+    /// with one that re-signs the same nonces at higher fees. Each sequence
+    /// can be replaced once; replace the replacement to bump again.
+    ///
+    /// If a paymaster funded the original sequence, its funding transfer is
+    /// increased to cover the new fees, and the creator is charged the
+    /// original fee scaled by the increase in `max_fee_per_gas`. The charge
+    /// must be attached in NEAR; any excess is refunded.
+    #[payable]
+    pub fn rebump_sequence(
+        &mut self,
+        id: U64,
+        new_max_fee_per_gas: U128,
+        new_max_priority_fee_per_gas: U128,
+    ) -> PromiseOrValue<TransactionSequenceCreation> {
+        <Self as Pause>::require_unpaused();
+
+        let predecessor = env::predecessor_account_id();
+
+        let original = self
+            .signed_sequences
+            .get(&id.0)
+            .expect_or_reject("Signed transaction sequence does not exist or was already replaced");
+
+        require!(
+            original.created_by_account_id == predecessor,
+            "Predecessor must be the transaction creator",
+        );
+
+        let new_max_fee = U256::from(new_max_fee_per_gas.0);
+        let new_priority_fee = U256::from(new_max_priority_fee_per_gas.0);
+
+        require!(
+            new_priority_fee <= new_max_fee,
+            "Priority fee cannot exceed max fee",
+        );
+
+        require!(
+            original.chain_ids().len() == 1,
+            "Sequences spanning several chains cannot be rebumped",
+        );
+
+        let first = original
+            .signature_requests
+            .first()
+            .expect_or_reject("Transaction sequence is empty");
+        let chain_id = first.transaction.chain_id;
+        let old_max_fee = first.transaction.max_fee_per_gas();
+
+        require!(!old_max_fee.is_zero(), "Original max fee must be nonzero");
+
+        let (signature_requests, gas_tokens_delta, paymaster_reservations) =
+            self.rebump_signature_requests(&original, chain_id, new_max_fee, new_priority_fee);
+
+        let deposit = env::attached_deposit().as_yoctonear();
+
+        let fee_delta =
+            Self::rebump_fee_delta(&original, gas_tokens_delta, old_max_fee, new_max_fee);
+
+        let signer_deposit = self
+            .signer_deposit_for(&AssetId::Native, original.signature_requests.len())
+            .unwrap_or_reject();
+
+        let charge = fee_delta
+            .as_ref()
+            .map_or(0, |f| f.amount.0)
+            .saturating_add(signer_deposit);
+        require!(
+            deposit >= charge,
+            format!(
+                "Attached deposit is less than fee delta and signer deposit: deposit {deposit} < fee {charge}"
+            ),
+        );
+        if deposit > charge {
+            Self::refund(predecessor.clone(), AssetBalance::native(deposit - charge));
+        }
+
+        self.signed_sequences.remove(&id.0);
+
+        let pending_transaction_sequence = PendingTransactionSequence {
+            created_by_account_id: predecessor,
+            signature_requests,
+            created_at_block_height: env::block_height().into(),
+            escrow: fee_delta.clone().filter(|f| f.amount.0 > 0),
+            tag: original.tag,
+            paymaster_token_id: original.paymaster_token_id,
+            settled_escrow: None,
+            priority: original.priority,
+            tip: original.tip,
+            callback: original.callback,
+            funding_shares: original.funding_shares,
+            paymaster_reservations,
+            signing_order: original.signing_order,
+            is_funding_released: false,
+            signer_deposit: signer_deposit.into(),
+            tenant_id: original.tenant_id,
+            recurring_authorization_id: original.recurring_authorization_id,
+            is_cancellation: false,
+            replaced_sequence_id: Some(id),
+            session_grantee_id: original.session_grantee_id,
+        };
+
+        let creation = self.insert_transaction_sequence(chain_id, pending_transaction_sequence);
+
+        ContractEvent::TransactionSequenceRebumped(TransactionSequenceRebumped {
+            id: creation.id,
+            replaced_id: id,
+            max_fee_per_gas: new_max_fee_per_gas,
+            max_priority_fee_per_gas: new_max_priority_fee_per_gas,
+            fee_delta,
+        })
+        .emit();
+
+        PromiseOrValue::Value(creation)
+    }
+
+    /// Creates a sequence signing the canonical cancellation of a foreign
+    /// transaction: a zero-value transfer from the chain key's address to
+    /// itself, with the given nonce and (higher) fees. Once mined, it burns
+    /// the nonce, unsticking any transactions queued behind it.
+    ///
+    /// With `use_paymaster`, the cancellation is sponsored by a paymaster
+    /// and the attached deposit pays for it; otherwise it pays the
+    /// unsponsored fee. `max_priority_fee_per_gas` defaults to
+    /// `max_fee_per_gas`.
+    #[payable]
+    pub fn create_cancellation(
+        &mut self,
+        chain_id: U64,
+        token_id: String,
+        nonce: U64,
+        max_fee_per_gas: U128,
+        max_priority_fee_per_gas: Option<U128>,
+        use_paymaster: bool,
+    ) -> PromiseOrValue<TransactionSequenceCreation> {
+        <Self as Pause>::require_unpaused();
+
+        let predecessor = env::predecessor_account_id();
+
+        // The receiver whitelist does not apply: the receiver is the sender.
+        require!(
+            !self.flags.is_sender_whitelist_enabled || self.sender_whitelist.contains(&predecessor),
+            "Sender is not whitelisted",
+        );
+
+        let user_chain_key = self
+            .user_chain_keys
+            .get(&predecessor)
+            .and_then(|keys| keys.get(&token_id))
+            .expect_or_reject("Predecessor unauthorized for the requested chain key token ID");
+
+        require!(
+            user_chain_key.has_public_key(),
+            "Chain key registration is incomplete; call `refresh_user_chain_key` first",
+        );
+
+        let max_priority_fee_per_gas = max_priority_fee_per_gas.unwrap_or(max_fee_per_gas);
+        require!(
+            max_priority_fee_per_gas.0 <= max_fee_per_gas.0,
+            "Priority fee cannot exceed max fee",
+        );
+
+        let sender = ForeignAddress::from_raw_public_key(&user_chain_key.public_key_bytes);
+
+        let transaction = ValidTransactionRequest {
+            chain_id: chain_id.0,
+            to: sender,
+            gas: U256::from(NATIVE_TRANSFER_GAS).0,
+            value: U256::zero().0,
+            data: vec![],
+            nonce: U256::from(nonce.0).0,
+            access_list_rlp: vec![0xc0 /* rlp encoding for empty list */],
+            max_priority_fee_per_gas: U256::from(max_priority_fee_per_gas.0).0,
+            max_fee_per_gas: U256::from(max_fee_per_gas.0).0,
+        };
+
+        // The replay record of the nonce is kept until the cancellation is
+        // signed: only the cancellation sequence may replace it.
+        let deposit = AssetBalance::native(env::attached_deposit().as_yoctonear());
+
+        match self.create_checked_transaction(
+            token_id,
+            predecessor,
+            &user_chain_key,
+            transaction,
+            use_paymaster,
+            deposit,
+            None,
+            CreateTransactionOptions::default(),
+        ) {
+            PromiseOrValue::Promise(promise) => promise
+                .then(
+                    Self::ext(env::current_account_id())
+                        .with_static_gas(CANCELLATION_SEQUENCE_CALLBACK_GAS)
+                        .with_unused_gas_weight(0)
+                        .cancellation_sequence_callback(),
+                )
+                .into(),
+            PromiseOrValue::Value(creation) => {
+                self.mark_cancellation_sequence(&creation);
+                PromiseOrValue::Value(creation)
+            }
+        }
+    }
+
+    #[private]
+    pub fn cancellation_sequence_callback(
+        &mut self,
+        #[callback_result] result: Result<TransactionSequenceCreation, PromiseError>,
+    ) -> TransactionSequenceCreation {
+        let creation = result
+            .ok()
+            .expect_or_reject("Failed to create cancellation sequence");
+
+        self.mark_cancellation_sequence(&creation);
+
+        creation
+    }
+}
+
+impl Contract {
+    /// Re-creates the signature requests of `original` at the new fees. The
+    /// increase of the funding transfer is set aside from its paymaster.
+    /// Returns the requests, the increase in gas tokens, and the reservation
+    /// of the increase.
+    fn rebump_signature_requests(
+        &mut self,
+        original: &PendingTransactionSequence,
+        chain_id: u64,
+        new_max_fee: U256,
+        new_priority_fee: U256,
+    ) -> (Vec<SignatureRequest>, U256, Vec<PaymasterReservation>) {
+        let mut signature_requests = Vec::with_capacity(original.signature_requests.len());
+        let mut gas_tokens_delta = U256::zero();
+        let mut paymaster_reservations = vec![];
+
+        for request in &original.signature_requests {
+            require!(
+                new_max_fee > request.transaction.max_fee_per_gas()
+                    && new_priority_fee >= request.transaction.max_priority_fee_per_gas(),
+                "New fees must exceed the original fees",
+            );
+
+            require!(
+                self.current_authorization(&original.created_by_account_id, request)
+                    == Some(request.authorization),
+                "Chain key authorization has changed since the transaction was signed",
+            );
+
+            let mut transaction = request.transaction.clone();
+
+            if request.is_paymaster {
+                // The funding transfer is proportional to the max fee.
+                let value = U256(transaction.value);
+                let new_value = value
+                    .checked_mul(new_max_fee)
+                    .expect_or_reject("Funding amount overflow")
+                    / transaction.max_fee_per_gas();
+                gas_tokens_delta = new_value - value;
+                transaction.value = new_value.0;
+
+                paymaster_reservations.push(PaymasterReservation {
+                    chain_id,
+                    token_id: request.token_id.clone(),
+                    amount: gas_tokens_delta.0,
+                    nonce: None,
+                });
+
+                self.with_mut_chain(chain_id, |chain_config| {
+                    let mut paymaster = chain_config
+                        .paymasters
+                        .get(&request.token_id)
+                        .expect_or_reject("Paymaster is no longer in the rotation");
+                    paymaster.minimum_available_balance = paymaster
+                        .sub_from_minimum_available_balance(gas_tokens_delta)
+                        .unwrap_or_reject()
+                        .0;
+                    chain_config
+                        .paymasters
+                        .insert(&request.token_id, &paymaster);
+                });
+            } else {
+                // The replay record is replaced once the replacement is signed.
+                require!(
+                    self.is_replaceable_signed_transaction(&request.token_id, &request.transaction),
+                    "A different transaction has been signed with the same nonce",
+                );
+            }
+
+            transaction.max_fee_per_gas = new_max_fee.0;
+            transaction.max_priority_fee_per_gas = new_priority_fee.0;
+
+            signature_requests.push(SignatureRequest::new(
+                &request.token_id,
+                request.authorization,
+                transaction,
+                request.is_paymaster,
+            ));
+        }
+
+        (signature_requests, gas_tokens_delta, paymaster_reservations)
+    }
+
+    /// The original fee scaled by the increase in `max_fee_per_gas`, if a
+    /// paymaster funded the sequence.
+    fn rebump_fee_delta(
+        original: &PendingTransactionSequence,
+        gas_tokens_delta: U256,
+        old_max_fee: U256,
+        new_max_fee: U256,
+    ) -> Option<AssetBalance> {
+        original
+            .settled_escrow
+            .as_ref()
+            .filter(|_| !gas_tokens_delta.is_zero())
+            .map(|fee| {
+                require!(
+                    fee.asset_id == AssetId::Native,
+                    "Only sequences paid for in NEAR can be rebumped",
+                );
+                let amount = U256::from(fee.amount.0)
+                    .checked_mul(new_max_fee - old_max_fee)
+                    .expect_or_reject("Fee overflow")
+                    / old_max_fee;
+                AssetBalance::native(amount.as_u128())
+            })
+    }
+
+    /// Records on the pending sequence that it is a cancellation, so that it
+    /// may be signed even though its nonce has been signed before.
+    fn mark_cancellation_sequence(&mut self, creation: &TransactionSequenceCreation) {
+        if let Some(mut sequence) = self.pending_transaction_sequences.get(&creation.id.0) {
+            sequence.is_cancellation = true;
+            self.pending_transaction_sequences
+                .insert(&creation.id.0, &sequence);
+        }
+    }
+}
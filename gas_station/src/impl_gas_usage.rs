@@ -0,0 +1,92 @@
+use near_sdk::{
+    env,
+    json_types::{U128, U64},
+    near, near_bindgen,
+};
+
+use crate::{Contract, ContractExt};
+
+/// Minimum gas price on NEAR, in yoctoNEAR per gas unit. Used to estimate
+/// the NEAR cost of gas burned by the contract.
+const MIN_GAS_PRICE_YOCTO: u128 = 100_000_000;
+
+/// NEAR gas burned by the contract on behalf of a sequence, summed over the
+/// calls that created and signed it. Does not include gas burned by the
+/// oracle, the signer contract, or token contracts.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[near]
+pub struct SequenceGasUsage {
+    pub gas_used: u64,
+    pub call_count: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct ViewSequenceGasUsage {
+    pub gas_used: U64,
+    pub call_count: u32,
+    /// `gas_used` at the minimum gas price, in yoctoNEAR.
+    pub estimated_cost: U128,
+}
+
+impl From<SequenceGasUsage> for ViewSequenceGasUsage {
+    fn from(usage: SequenceGasUsage) -> Self {
+        Self {
+            gas_used: usage.gas_used.into(),
+            call_count: usage.call_count,
+            estimated_cost: (u128::from(usage.gas_used) * MIN_GAS_PRICE_YOCTO).into(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct ViewSequenceGasTotals {
+    pub sequence_count: U64,
+    pub gas_used: U128,
+    /// `gas_used` at the minimum gas price, in yoctoNEAR.
+    pub estimated_cost: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_sequence_gas_usage(&self, id: U64) -> Option<ViewSequenceGasUsage> {
+        self.sequence_gas_usage.get(&id.0).map(Into::into)
+    }
+
+    /// Gas burned by the contract across all sequences. Divide by
+    /// `sequence_count` for the average cost of a sequence, e.g. to set fee
+    /// rates that cover it.
+    pub fn get_sequence_gas_totals(&self) -> ViewSequenceGasTotals {
+        ViewSequenceGasTotals {
+            sequence_count: self.sequence_gas_totals.0.into(),
+            gas_used: self.sequence_gas_totals.1.into(),
+            estimated_cost: self
+                .sequence_gas_totals
+                .1
+                .saturating_mul(MIN_GAS_PRICE_YOCTO)
+                .into(),
+        }
+    }
+}
+
+impl Contract {
+    /// Attributes the gas burned so far by the current call to the sequence.
+    /// Call as late as possible in the call.
+    pub(crate) fn record_sequence_gas(&mut self, id: u64) {
+        let gas_used = env::used_gas().as_gas();
+
+        let mut usage = self.sequence_gas_usage.get(&id).unwrap_or_else(|| {
+            self.sequence_gas_totals.0 += 1;
+            SequenceGasUsage::default()
+        });
+        usage.gas_used = usage.gas_used.saturating_add(gas_used);
+        usage.call_count = usage.call_count.saturating_add(1);
+        self.sequence_gas_usage.insert(&id, &usage);
+
+        self.sequence_gas_totals.1 = self
+            .sequence_gas_totals
+            .1
+            .saturating_add(u128::from(gas_used));
+    }
+}
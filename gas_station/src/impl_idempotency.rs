@@ -0,0 +1,219 @@
+use lib::{asset::AssetBalance, contract_error::ContractError, Rejectable};
+use near_sdk::{
+    env, near, near_bindgen, require, serde_json::json, AccountId, Gas, PromiseError,
+    PromiseOrValue,
+};
+
+use crate::{Contract, ContractExt, TransactionSequenceCreation};
+
+/// Blocks for which an idempotency key is remembered after first use.
+pub const IDEMPOTENCY_KEY_TTL_BLOCKS: u64 = 24 * 60 * 60; // 1ish day at 1s/block
+pub const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 64;
+
+const IDEMPOTENCY_KEY_CALLBACK_GAS: Gas = Gas::from_tgas(5);
+/// Expired keys removed each time a key is first used, so that expired keys
+/// are removed faster than new ones are added.
+const IDEMPOTENCY_KEYS_PRUNED_PER_USE: usize = 2;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near]
+pub enum IdempotencyKeyStatus {
+    /// The sequence is waiting for the oracle prices.
+    Pending,
+    Created(TransactionSequenceCreation),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near]
+pub struct IdempotencyKeyRecord {
+    pub status: IdempotencyKeyStatus,
+    pub expires_at_block_height: u64,
+}
+
+impl IdempotencyKeyRecord {
+    pub fn is_expired(&self, block_height: u64) -> bool {
+        block_height > self.expires_at_block_height
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Stores the creation of a sequence under its idempotency key, or
+    /// frees the key if the sequence could not be created.
+    #[private]
+    pub fn idempotency_key_callback(
+        &mut self,
+        account_id: AccountId,
+        idempotency_key: String,
+        #[callback_result] result: Result<TransactionSequenceCreation, PromiseError>,
+    ) -> PromiseOrValue<TransactionSequenceCreation> {
+        let key = (account_id, idempotency_key);
+
+        let Ok(creation) = result else {
+            self.idempotency_keys.remove(&key);
+            return PromiseOrValue::Promise(
+                Self::ext(env::current_account_id()).throw(
+                    ContractError::new(
+                        "sequence_creation_failed",
+                        "Transaction sequence could not be created",
+                        json!({ "idempotency_key": key.1 }),
+                    )
+                    .to_string(),
+                ),
+            );
+        };
+
+        // The key keeps the expiry of its first use, which its entry in the
+        // pruning queue was created with.
+        if let Some(mut record) = self.idempotency_keys.get(&key) {
+            record.status = IdempotencyKeyStatus::Created(creation.clone());
+            self.idempotency_keys.insert(&key, &record);
+        }
+
+        PromiseOrValue::Value(creation)
+    }
+
+    /// Removes an expired idempotency key. Callable by anyone. Expired keys
+    /// are also removed, oldest first, as new keys are used.
+    pub fn clear_expired_idempotency_key(
+        &mut self,
+        account_id: AccountId,
+        idempotency_key: String,
+    ) {
+        let key = (account_id, idempotency_key);
+        let record = self
+            .idempotency_keys
+            .get(&key)
+            .expect_or_reject("Idempotency key does not exist");
+
+        require!(
+            record.is_expired(env::block_height()),
+            "Idempotency key has not expired",
+        );
+
+        self.idempotency_keys.remove(&key);
+    }
+}
+
+impl Contract {
+    /// Runs `create` for `account_id` unless the account already used
+    /// `idempotency_key` within the last `IDEMPOTENCY_KEY_TTL_BLOCKS`, in
+    /// which case `deposit` is refunded and the original creation returned.
+    pub(crate) fn with_idempotency_key(
+        &mut self,
+        account_id: AccountId,
+        idempotency_key: Option<String>,
+        deposit: AssetBalance,
+        create: impl FnOnce(&mut Self, AssetBalance) -> PromiseOrValue<TransactionSequenceCreation>,
+    ) -> PromiseOrValue<TransactionSequenceCreation> {
+        let Some(idempotency_key) = idempotency_key else {
+            return create(self, deposit);
+        };
+
+        require!(
+            !idempotency_key.is_empty() && idempotency_key.len() <= MAX_IDEMPOTENCY_KEY_LENGTH,
+            format!("Idempotency key must be 1 to {MAX_IDEMPOTENCY_KEY_LENGTH} bytes"),
+        );
+
+        let block_height = env::block_height();
+        let key = (account_id, idempotency_key);
+
+        let record = self
+            .idempotency_keys
+            .get(&key)
+            .filter(|record| !record.is_expired(block_height));
+        if let Some(record) = record {
+            let IdempotencyKeyStatus::Created(creation) = record.status else {
+                env::panic_str("A request with this idempotency key is in progress");
+            };
+
+            if deposit.amount.0 > 0 {
+                Self::refund(key.0, deposit);
+            }
+            return PromiseOrValue::Value(creation);
+        }
+
+        match create(self, deposit) {
+            PromiseOrValue::Value(creation) => {
+                self.insert_idempotency_key(
+                    key,
+                    IdempotencyKeyStatus::Created(creation.clone()),
+                    block_height,
+                );
+                PromiseOrValue::Value(creation)
+            }
+            PromiseOrValue::Promise(promise) => {
+                self.insert_idempotency_key(
+                    key.clone(),
+                    IdempotencyKeyStatus::Pending,
+                    block_height,
+                );
+                let (account_id, idempotency_key) = key;
+                PromiseOrValue::Promise(
+                    promise.then(
+                        Self::ext(env::current_account_id())
+                            .with_static_gas(IDEMPOTENCY_KEY_CALLBACK_GAS)
+                            .with_unused_gas_weight(0)
+                            .idempotency_key_callback(account_id, idempotency_key),
+                    ),
+                )
+            }
+        }
+    }
+
+    /// Stores the first use of an idempotency key, after removing up to
+    /// `IDEMPOTENCY_KEYS_PRUNED_PER_USE` expired keys.
+    fn insert_idempotency_key(
+        &mut self,
+        key: (AccountId, String),
+        status: IdempotencyKeyStatus,
+        block_height: u64,
+    ) {
+        self.prune_idempotency_keys(block_height);
+
+        let expires_at_block_height = block_height + IDEMPOTENCY_KEY_TTL_BLOCKS;
+        self.idempotency_keys.insert(
+            &key,
+            &IdempotencyKeyRecord {
+                status,
+                expires_at_block_height,
+            },
+        );
+
+        let (start, end) = self.idempotency_key_queue_bounds;
+        self.idempotency_key_queue
+            .insert(&end, &(expires_at_block_height, key.0, key.1));
+        self.idempotency_key_queue_bounds = (start, end + 1);
+    }
+
+    fn prune_idempotency_keys(&mut self, block_height: u64) {
+        let mut start = self.idempotency_key_queue_bounds.0;
+
+        for _ in 0..IDEMPOTENCY_KEYS_PRUNED_PER_USE {
+            let Some((expires_at_block_height, account_id, idempotency_key)) =
+                self.idempotency_key_queue.get(&start)
+            else {
+                break;
+            };
+
+            if block_height <= expires_at_block_height {
+                break;
+            }
+
+            // The key may have been cleared already, or used again since.
+            let key = (account_id, idempotency_key);
+            if self
+                .idempotency_keys
+                .get(&key)
+                .is_some_and(|record| record.is_expired(block_height))
+            {
+                self.idempotency_keys.remove(&key);
+            }
+
+            self.idempotency_key_queue.remove(&start);
+            start += 1;
+        }
+
+        self.idempotency_key_queue_bounds.0 = start;
+    }
+}
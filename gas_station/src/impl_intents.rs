@@ -0,0 +1,100 @@
+use lib::{asset::AssetBalance, Rejectable};
+use near_sdk::{env, near, near_bindgen, AccountId, PromiseError, PromiseOrValue};
+use near_sdk_contract_tools::{rbac::Rbac, standard::nep297::Event};
+
+use crate::{
+    contract_event::{ContractEvent, IntentSettlementSequenceCreated},
+    Contract, ContractExt, CreateTransactionOptions, Role, TransactionSequenceCreation,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct IntentSettlementArgs {
+    /// Hash of the intent being settled. Only used to link the created
+    /// sequence to the intent in emitted events.
+    pub intent_hash: String,
+    /// The solver on whose behalf the sequence is created. Only used to link
+    /// the created sequence to the solver in emitted events.
+    pub solver_id: AccountId,
+    /// A chain key registered with this contract by the intents contract,
+    /// either owned by it or approved to it (e.g. by the solver).
+    pub token_id: String,
+    pub transaction_rlp_hex: String,
+    pub use_paymaster: Option<bool>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Entry point for the intents contract to create a sponsored sequence on
+    /// behalf of a solver. The attached deposit pays for the sequence.
+    ///
+    /// The sequence is created by the intents contract with one of its own
+    /// chain keys, so that a solver's key is only used if the solver approved
+    /// it to the intents contract. The intents contract signs the sequence,
+    /// and refunds are made to it.
+    #[payable]
+    pub fn on_intent_settlement(
+        &mut self,
+        args: IntentSettlementArgs,
+    ) -> PromiseOrValue<TransactionSequenceCreation> {
+        <Self as Rbac>::require_role(&Role::IntentsContract);
+
+        let intents_contract_id = env::predecessor_account_id();
+
+        let IntentSettlementArgs {
+            intent_hash,
+            solver_id,
+            token_id,
+            transaction_rlp_hex,
+            use_paymaster,
+        } = args;
+
+        match self.create_transaction_inner(
+            token_id,
+            intents_contract_id,
+            transaction_rlp_hex,
+            use_paymaster,
+            AssetBalance::native(env::attached_deposit().as_yoctonear()),
+            None,
+            CreateTransactionOptions::default(),
+        ) {
+            PromiseOrValue::Promise(p) => p
+                .then(
+                    Self::ext(env::current_account_id())
+                        .on_intent_settlement_callback(intent_hash, solver_id),
+                )
+                .into(),
+            PromiseOrValue::Value(creation) => {
+                emit_intent_settlement_sequence_created(intent_hash, solver_id, &creation);
+                PromiseOrValue::Value(creation)
+            }
+        }
+    }
+
+    #[private]
+    pub fn on_intent_settlement_callback(
+        &mut self,
+        intent_hash: String,
+        solver_id: AccountId,
+        #[callback_result] result: Result<TransactionSequenceCreation, PromiseError>,
+    ) -> TransactionSequenceCreation {
+        let creation = result
+            .ok()
+            .expect_or_reject("Failed to create transaction sequence for intent");
+        emit_intent_settlement_sequence_created(intent_hash, solver_id, &creation);
+        creation
+    }
+}
+
+fn emit_intent_settlement_sequence_created(
+    intent_hash: String,
+    solver_id: AccountId,
+    creation: &TransactionSequenceCreation,
+) {
+    ContractEvent::IntentSettlementSequenceCreated(IntentSettlementSequenceCreated {
+        intent_hash,
+        solver_id,
+        id: creation.id,
+    })
+    .emit();
+}
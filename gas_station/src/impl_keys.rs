@@ -0,0 +1,121 @@
+use lib::kdf::derive_public_key_for;
+use near_sdk::{near, AccountId, PublicKey};
+
+use crate::{error::ChainKeyPublicKeyMismatchError, signature_request::SignatureRequest, Contract};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct ChainKeyData {
+    pub public_key_bytes: Vec<u8>,
+    pub authorization: ChainKeyAuthorization,
+    /// When enabled, transactions reusing a nonce that the contract has
+    /// already signed for this key are rejected.
+    pub is_nonce_tracking_enabled: bool,
+    /// Contract that must approve each user transaction before it is sent
+    /// to the signer. See [`lib::signing_policy::SigningPolicy`].
+    #[serde(default)]
+    pub signing_policy_id: Option<AccountId>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub enum ChainKeyAuthorization {
+    Owned,
+    Approved(u32),
+}
+
+impl ChainKeyData {
+    /// Keys are registered without a public key when the signer contract
+    /// fails to provide one. Such keys cannot be used until refreshed.
+    pub fn has_public_key(&self) -> bool {
+        !self.public_key_bytes.is_empty()
+    }
+}
+
+impl ChainKeyAuthorization {
+    pub fn is_owned(&self) -> bool {
+        matches!(self, Self::Owned)
+    }
+
+    pub fn is_approved(&self) -> bool {
+        matches!(self, Self::Approved(..))
+    }
+
+    pub fn is_approved_with_id(&self, approval_id: u32) -> bool {
+        self == &Self::Approved(approval_id)
+    }
+
+    pub fn to_approval_id(&self) -> Option<u32> {
+        if let Self::Approved(approval_id) = self {
+            Some(*approval_id)
+        } else {
+            None
+        }
+    }
+}
+
+impl Contract {
+    /// The authorization this contract currently holds for the key used by
+    /// `request`, if any.
+    pub(crate) fn current_authorization(
+        &self,
+        created_by_account_id: &AccountId,
+        request: &SignatureRequest,
+    ) -> Option<ChainKeyAuthorization> {
+        self.chain_key_data(created_by_account_id, request)
+            .map(|k| k.authorization)
+    }
+
+    /// The currently registered data of the chain key of a signature request.
+    pub(crate) fn chain_key_data(
+        &self,
+        created_by_account_id: &AccountId,
+        request: &SignatureRequest,
+    ) -> Option<ChainKeyData> {
+        if request.is_paymaster {
+            self.paymaster_keys.get(&request.token_id)
+        } else {
+            self.user_chain_keys
+                .get(created_by_account_id)
+                .and_then(|keys| keys.get(&request.token_id))
+        }
+    }
+
+    /// Checks that `public_key` is the key the MPC signer derives for the
+    /// chain key NFT (the NFT contract's `{token_id},` path) with one of its
+    /// key versions. The token's version is not known here, and a key derived
+    /// for the token from any of the signer's roots is genuine. Keys cannot
+    /// be verified, and are trusted as reported, until an administrator sets
+    /// a signer root key.
+    pub(crate) fn verify_chain_key_public_key(
+        &self,
+        token_id: &str,
+        public_key: &PublicKey,
+    ) -> Result<(), ChainKeyPublicKeyMismatchError> {
+        if self.signer_root_public_keys.is_empty() {
+            return Ok(());
+        }
+
+        let path = format!("{token_id},");
+
+        // Both are uncompressed points behind a one-byte tag (SEC1 tag or
+        // NEAR curve type).
+        let is_match = self
+            .signer_root_public_keys
+            .values()
+            .any(|root_public_key| {
+                derive_public_key_for(root_public_key.clone(), &self.signer_contract_id, &path)
+                    .map_or(false, |expected| {
+                        expected.as_bytes().get(1..) == public_key.as_bytes().get(1..)
+                    })
+            });
+
+        if is_match {
+            Ok(())
+        } else {
+            Err(ChainKeyPublicKeyMismatchError {
+                token_id: token_id.to_string(),
+            })
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use near_sdk::{env, json_types::U64, near, near_bindgen, IntoStorageKey};
+use near_sdk_contract_tools::rbac::Rbac;
+
+use crate::{Contract, ContractExt, Role, StorageKey};
+
+/// Storage freed by [`Contract::clean_up_legacy_paymasters`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct LegacyStorageCleanup {
+    pub removed_keys: U64,
+    pub reclaimed_bytes: U64,
+}
+
+/// Key of an element of the `Vector<PaymasterConfiguration>` that older
+/// deployments stored under `StorageKey::Paymasters(chain_id)`.
+///
+/// The `TreeMap` now stored under the same prefix never writes an 8-byte
+/// suffix whose upper half is zero, so its keys cannot collide with the
+/// little-endian index of a legacy element below `2^32`.
+pub(crate) fn legacy_paymaster_key(chain_id: u64, index: u32) -> Vec<u8> {
+    let mut key = StorageKey::Paymasters(chain_id).into_storage_key();
+    key.extend_from_slice(&u64::from(index).to_le_bytes());
+    key
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Removes the paymaster configurations left in storage by the
+    /// `Vector`-based layout of older deployments for each chain, starting at
+    /// index 0 and stopping at the first missing element.
+    pub fn clean_up_legacy_paymasters(&mut self, chain_ids: Vec<U64>) -> LegacyStorageCleanup {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("clean_up_legacy_paymasters");
+
+        let storage_usage_before = env::storage_usage();
+        let mut removed_keys = 0u64;
+
+        for chain_id in chain_ids {
+            for index in 0..=u32::MAX {
+                if !env::storage_remove(&legacy_paymaster_key(chain_id.0, index)) {
+                    break;
+                }
+                removed_keys += 1;
+            }
+        }
+
+        LegacyStorageCleanup {
+            removed_keys: removed_keys.into(),
+            reclaimed_bytes: storage_usage_before
+                .saturating_sub(env::storage_usage())
+                .into(),
+        }
+    }
+}
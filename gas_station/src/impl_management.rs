@@ -1,36 +1,91 @@
+use std::collections::BTreeMap;
+
 use ethers_core::types::U256;
 use near_sdk::{
     collections::TreeMap,
     env,
     json_types::{U128, U64},
-    near_bindgen, require, AccountId, Promise,
+    near_bindgen, require, AccountId, Gas, Promise, PromiseOrValue, PromiseResult, PublicKey,
 };
-use near_sdk_contract_tools::{pause::Pause, rbac::Rbac};
+use near_sdk_contract_tools::{pause::Pause, rbac::Rbac, standard::nep297::Event};
 
 use crate::{
     chain_configuration::{
-        ForeignChainConfiguration, PaymasterConfiguration, ViewPaymasterConfiguration,
+        validate_decimals, validate_fee_rate, CalldataPricing, ChainOnboardingArgs,
+        ConversionRateBounds, FeeSuggestion, ForeignChainConfiguration, ForeignChainMetadata,
+        FundingCall, FundingOptions, PaymasterConfiguration, PaymasterOnboardingArgs,
+        PaymasterReservation, RollupFeeModel, SponsorshipStatistics, ViewForeignChainConfiguration,
+        ViewPaymasterConfiguration, ViewPaymasterRunway,
+    },
+    contract_event::{
+        ConfigSnapshot, ContractEvent, RelayerTipCredited, SponsorshipCircuitBreakerTripped,
+        TransactionSequenceRelayed, TransactionSequenceSigned, Whitelist, WhitelistUpdated,
     },
-    contract_event::TransactionSequenceSigned,
-    decode_transaction_request,
+    decode_hash, decode_transaction_request,
+    error::{NoPaymasterConfigurationForChainError, TransactionSequenceDoesNotExistError},
+    sighash_for_mpc_signing,
+    signature_request::SignatureRequest,
+    transfer_options,
     valid_transaction_request::ValidTransactionRequest,
-    Contract, ContractExt, Flags, GetForeignChain, LocalAssetConfiguration,
-    PendingTransactionSequence, Role, StorageKey,
+    Contract, ContractConfiguration, ContractExt, EstimateFeeOptions, Flags,
+    FundingTransactionPreview, GetForeignChain, LocalAssetConfiguration, LocalAssetOnboardingArgs,
+    PendingTransactionSequence, Role, SequenceExpiry, SigningOrder, StorageKey,
+    TransactionSequenceCreation, CONFIG_SNAPSHOT_INTERVAL_BLOCKS, MAX_WHITELIST_BATCH_SIZE,
 };
 use lib::{
-    asset::AssetId, foreign_address::ForeignAddress, oracle::decode_pyth_price_id, pyth, Rejectable,
+    asset::{AssetBalance, AssetId},
+    foreign_address::ForeignAddress,
+    kdf::near_public_key_to_affine,
+    oracle::decode_pyth_price_id,
+    pyth, Rejectable,
 };
 
+pub(crate) fn require_whitelist_batch_size(len: usize) {
+    require!(
+        len <= MAX_WHITELIST_BATCH_SIZE,
+        format!("At most {MAX_WHITELIST_BATCH_SIZE} whitelist entries may be changed per call"),
+    );
+}
+
+/// Emits the summary of a whitelist batch and returns the number of entries
+/// changed.
+#[allow(clippy::cast_possible_truncation)]
+fn emit_whitelist_updated(
+    whitelist: Whitelist,
+    is_whitelisted: bool,
+    changed: usize,
+    total: usize,
+) -> u32 {
+    let skipped = (total - changed) as u32;
+    let changed = changed as u32;
+
+    ContractEvent::WhitelistUpdated(WhitelistUpdated {
+        whitelist,
+        is_whitelisted,
+        changed,
+        skipped,
+    })
+    .emit();
+
+    changed
+}
+
 #[near_bindgen]
 impl Contract {
     pub fn add_administrator(&mut self, account_id: AccountId) {
         <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("add_administrator");
         self.add_role(&account_id, &Role::Administrator);
+
+        self.emit_config_snapshot_if_due();
     }
 
     pub fn remove_administrator(&mut self, account_id: AccountId) {
         <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("remove_administrator");
         self.remove_role(&account_id, &Role::Administrator);
+
+        self.emit_config_snapshot_if_due();
     }
 
     pub fn get_administrators(&self) -> Vec<AccountId> {
@@ -39,26 +94,142 @@ impl Contract {
 
     pub fn add_market_maker(&mut self, account_id: AccountId) {
         <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("add_market_maker");
         self.add_role(&account_id, &Role::MarketMaker);
+
+        self.emit_config_snapshot_if_due();
     }
 
     pub fn remove_market_maker(&mut self, account_id: AccountId) {
         <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("remove_market_maker");
         self.remove_role(&account_id, &Role::MarketMaker);
+
+        self.emit_config_snapshot_if_due();
     }
 
     pub fn get_market_makers(&self) -> Vec<AccountId> {
         <Self as Rbac>::iter_members_of(&Role::MarketMaker).collect()
     }
 
+    pub fn add_intents_contract(&mut self, account_id: AccountId) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("add_intents_contract");
+        self.add_role(&account_id, &Role::IntentsContract);
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn remove_intents_contract(&mut self, account_id: AccountId) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("remove_intents_contract");
+        self.remove_role(&account_id, &Role::IntentsContract);
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn get_intents_contracts(&self) -> Vec<AccountId> {
+        <Self as Rbac>::iter_members_of(&Role::IntentsContract).collect()
+    }
+
+    pub fn add_relayer(&mut self, account_id: AccountId) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("add_relayer");
+        self.add_role(&account_id, &Role::Relayer);
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn remove_relayer(&mut self, account_id: AccountId) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("remove_relayer");
+        self.remove_role(&account_id, &Role::Relayer);
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn get_relayers(&self) -> Vec<AccountId> {
+        <Self as Rbac>::iter_members_of(&Role::Relayer).collect()
+    }
+
+    /// Records that the signed transactions of a sequence have been
+    /// broadcast to the foreign chain. The contract does not verify the
+    /// report; it only emits an event for indexers. If the sequence carries
+    /// a tip, the first relayer to report it is credited the tip.
+    pub fn report_relayed(&mut self, id: U64, transaction_hashes: Vec<String>) {
+        <Self as Rbac>::require_role(&Role::Relayer);
+
+        let relayer_id = env::predecessor_account_id();
+
+        if let Some(mut sequence) = self.signed_sequences.get(&id.0) {
+            if let Some(tip) = sequence.tip.take() {
+                self.signed_sequences.insert(&id.0, &sequence);
+
+                let key = (relayer_id.clone(), tip.asset_id.clone());
+                let credited = self
+                    .relayer_tips
+                    .get(&key)
+                    .unwrap_or(0)
+                    .checked_add(tip.amount.0)
+                    .unwrap_or_reject();
+                self.relayer_tips.insert(&key, &credited);
+
+                ContractEvent::RelayerTipCredited(RelayerTipCredited {
+                    id,
+                    relayer_id: relayer_id.clone(),
+                    tip,
+                })
+                .emit();
+            }
+        }
+
+        ContractEvent::TransactionSequenceRelayed(TransactionSequenceRelayed {
+            id,
+            relayed_by: relayer_id,
+            transaction_hashes,
+        })
+        .emit();
+    }
+
+    pub fn get_relayer_tips(&self, account_id: AccountId, asset_id: AssetId) -> U128 {
+        self.relayer_tips
+            .get(&(account_id, asset_id))
+            .unwrap_or(0)
+            .into()
+    }
+
+    /// Transfers the tips credited to the predecessor for an asset.
+    pub fn claim_relayer_tips(&mut self, asset_id: AssetId) -> Promise {
+        let account_id = env::predecessor_account_id();
+
+        let amount = self
+            .relayer_tips
+            .remove(&(account_id.clone(), asset_id.clone()))
+            .expect_or_reject("No tips credited for this asset");
+
+        Self::refund(
+            account_id,
+            AssetBalance {
+                asset_id,
+                amount: amount.into(),
+            },
+        )
+    }
+
     pub fn pause(&mut self) {
         <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("pause");
         <Self as Pause>::pause(self);
+
+        self.emit_config_snapshot_if_due();
     }
 
     pub fn unpause(&mut self) {
         <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("unpause");
         <Self as Pause>::unpause(self);
+
+        self.emit_config_snapshot_if_due();
     }
 
     pub fn get_expire_sequence_after_blocks(&self) -> U64 {
@@ -67,16 +238,55 @@ impl Contract {
 
     pub fn set_expire_sequence_after_blocks(&mut self, expire_sequence_after_blocks: U64) {
         <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_expire_sequence_after_blocks");
         self.expire_sequence_after_blocks = expire_sequence_after_blocks.into();
+
+        self.emit_config_snapshot_if_due();
     }
 
     pub fn get_signer_contract_id(&self) -> &AccountId {
         &self.signer_contract_id
     }
 
+    pub fn get_oracle_id(&self) -> &AccountId {
+        &self.oracle_id
+    }
+
     pub fn set_signer_contract_id(&mut self, account_id: AccountId) {
         <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_signer_contract_id");
         self.signer_contract_id = account_id;
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn get_signer_root_public_keys(&self) -> &BTreeMap<u32, PublicKey> {
+        &self.signer_root_public_keys
+    }
+
+    /// Sets the root public key of a key version of the MPC signer used by
+    /// the chain key NFT contract (its `public_key`), or removes it with
+    /// `None`. Once any is set, chain keys whose reported public key is not
+    /// derived from one of them are rejected. Every key version that tokens
+    /// may use must be set, since tokens move to a new version on their
+    /// owner's upgrade. Removing every key disables verification.
+    pub fn set_signer_root_public_key(&mut self, key_version: u32, public_key: Option<PublicKey>) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_signer_root_public_key");
+
+        if let Some(ref public_key) = public_key {
+            require!(
+                near_public_key_to_affine(public_key.clone()).is_ok(),
+                "Signer root key must be a valid SECP256K1 key",
+            );
+        }
+
+        match public_key {
+            Some(public_key) => self.signer_root_public_keys.insert(key_version, public_key),
+            None => self.signer_root_public_keys.remove(&key_version),
+        };
+
+        self.emit_config_snapshot_if_due();
     }
 
     pub fn get_flags(&self) -> &Flags {
@@ -85,53 +295,125 @@ impl Contract {
 
     pub fn set_flags(&mut self, flags: Flags) {
         <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_flags");
         self.flags = flags;
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Emits a `ConfigSnapshot` event describing the current configuration.
+    /// Callable by anyone, so it does not delay the next periodic snapshot.
+    pub fn emit_snapshot(&mut self) {
+        self.emit_config_snapshot_now();
     }
 
     pub fn get_receiver_whitelist(&self) -> Vec<ForeignAddress> {
         self.receiver_whitelist.iter().collect()
     }
 
-    pub fn add_to_receiver_whitelist(&mut self, addresses: Vec<ForeignAddress>) {
+    /// Returns the number of addresses added. At most
+    /// [`MAX_WHITELIST_BATCH_SIZE`] addresses may be passed per call.
+    pub fn add_to_receiver_whitelist(&mut self, addresses: Vec<ForeignAddress>) -> u32 {
         <Self as Rbac>::require_role(&Role::Administrator);
-        for address in addresses {
-            self.receiver_whitelist.insert(&address);
-        }
+        self.record_admin_action("add_to_receiver_whitelist");
+        require_whitelist_batch_size(addresses.len());
+
+        let changed = addresses
+            .iter()
+            .filter(|address| self.receiver_whitelist.insert(address))
+            .count();
+        let changed = emit_whitelist_updated(Whitelist::Receiver, true, changed, addresses.len());
+
+        self.emit_config_snapshot_if_due();
+
+        changed
     }
 
-    pub fn remove_from_receiver_whitelist(&mut self, addresses: Vec<ForeignAddress>) {
+    pub fn remove_from_receiver_whitelist(&mut self, addresses: Vec<ForeignAddress>) -> u32 {
         <Self as Rbac>::require_role(&Role::Administrator);
-        for address in addresses {
-            self.receiver_whitelist.remove(&address);
-        }
+        self.record_admin_action("remove_from_receiver_whitelist");
+        require_whitelist_batch_size(addresses.len());
+
+        let changed = addresses
+            .iter()
+            .filter(|address| self.receiver_whitelist.remove(address))
+            .count();
+        let changed = emit_whitelist_updated(Whitelist::Receiver, false, changed, addresses.len());
+
+        self.emit_config_snapshot_if_due();
+
+        changed
     }
 
     pub fn clear_receiver_whitelist(&mut self) {
         <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("clear_receiver_whitelist");
         self.receiver_whitelist.clear();
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Sets the root of a Merkle tree of additional whitelisted receivers.
+    /// Leaves are `keccak256(address)` and pairs are hashed in sorted order.
+    /// Callers prove a receiver's inclusion by passing `receiver_proof` to
+    /// `create_transaction`. `None` removes the tree.
+    pub fn set_receiver_whitelist_merkle_root(&mut self, root: Option<String>) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_receiver_whitelist_merkle_root");
+
+        self.receiver_whitelist_merkle_root =
+            root.map(|root| decode_hash(&root).expect_or_reject("Invalid Merkle root"));
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn get_receiver_whitelist_merkle_root(&self) -> Option<String> {
+        self.receiver_whitelist_merkle_root
+            .map(|root| self.flags.encode_hex(root))
     }
 
     pub fn get_sender_whitelist(&self) -> Vec<AccountId> {
         self.sender_whitelist.iter().collect()
     }
 
-    pub fn add_to_sender_whitelist(&mut self, addresses: Vec<AccountId>) {
+    pub fn add_to_sender_whitelist(&mut self, addresses: Vec<AccountId>) -> u32 {
         <Self as Rbac>::require_role(&Role::Administrator);
-        for address in addresses {
-            self.sender_whitelist.insert(&address);
-        }
+        self.record_admin_action("add_to_sender_whitelist");
+        require_whitelist_batch_size(addresses.len());
+
+        let changed = addresses
+            .iter()
+            .filter(|address| self.sender_whitelist.insert(address))
+            .count();
+        let changed = emit_whitelist_updated(Whitelist::Sender, true, changed, addresses.len());
+
+        self.emit_config_snapshot_if_due();
+
+        changed
     }
 
-    pub fn remove_from_sender_whitelist(&mut self, addresses: Vec<AccountId>) {
+    pub fn remove_from_sender_whitelist(&mut self, addresses: Vec<AccountId>) -> u32 {
         <Self as Rbac>::require_role(&Role::Administrator);
-        for address in addresses {
-            self.sender_whitelist.remove(&address);
-        }
+        self.record_admin_action("remove_from_sender_whitelist");
+        require_whitelist_batch_size(addresses.len());
+
+        let changed = addresses
+            .iter()
+            .filter(|address| self.sender_whitelist.remove(address))
+            .count();
+        let changed = emit_whitelist_updated(Whitelist::Sender, false, changed, addresses.len());
+
+        self.emit_config_snapshot_if_due();
+
+        changed
     }
 
     pub fn clear_sender_whitelist(&mut self) {
         <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("clear_sender_whitelist");
         self.sender_whitelist.clear();
+
+        self.emit_config_snapshot_if_due();
     }
 
     pub fn add_accepted_local_asset(
@@ -141,20 +423,36 @@ impl Contract {
         decimals: u8,
     ) {
         <Self as Rbac>::require_role(&Role::Administrator);
-        self.accepted_local_assets.insert(
-            &asset_id,
-            &LocalAssetConfiguration {
-                oracle_asset_id: decode_pyth_price_id(&oracle_asset_id),
-                decimals,
-            },
-        );
+        self.record_admin_action("add_accepted_local_asset");
+
+        self.insert_accepted_local_asset(asset_id, &oracle_asset_id, decimals, false);
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Changes the oracle price ID and decimals of an accepted local asset.
+    pub fn update_accepted_local_asset(
+        &mut self,
+        asset_id: AssetId,
+        oracle_asset_id: String,
+        decimals: u8,
+    ) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("update_accepted_local_asset");
+
+        self.insert_accepted_local_asset(asset_id, &oracle_asset_id, decimals, true);
+
+        self.emit_config_snapshot_if_due();
     }
 
     pub fn remove_accepted_local_asset(&mut self, asset_id: AssetId) {
         <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("remove_accepted_local_asset");
         self.accepted_local_assets
             .remove(&asset_id)
             .expect_or_reject("Asset not found");
+
+        self.emit_config_snapshot_if_due();
     }
 
     pub fn get_accepted_local_asset(&self, asset_id: AssetId) -> LocalAssetConfiguration {
@@ -163,6 +461,25 @@ impl Contract {
             .expect_or_reject("Asset not found")
     }
 
+    /// Accepted local assets with their oracle price IDs (base58) and
+    /// decimals, e.g. to populate a payment asset picker.
+    pub fn list_accepted_local_assets(
+        &self,
+        offset: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<LocalAssetOnboardingArgs> {
+        self.accepted_local_assets
+            .iter()
+            .skip(offset.map_or(0, |o| o as usize))
+            .take(limit.map_or(usize::MAX, |l| l as usize))
+            .map(|(asset_id, config)| LocalAssetOnboardingArgs {
+                asset_id,
+                oracle_asset_id: near_sdk::bs58::encode(&config.oracle_asset_id).into_string(),
+                decimals: config.decimals,
+            })
+            .collect()
+    }
+
     pub fn add_foreign_chain(
         &mut self,
         chain_id: U64,
@@ -172,42 +489,405 @@ impl Contract {
         decimals: u8,
     ) {
         <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("add_foreign_chain");
+
+        require!(
+            self.foreign_chains.get(&chain_id.0).is_none(),
+            "Chain configuration already exists",
+        );
+        validate_fee_rate((fee_rate.0 .0, fee_rate.1 .0)).unwrap_or_reject();
+        validate_decimals(decimals).unwrap_or_reject();
+
+        self.foreign_chains.insert(
+            &chain_id.0,
+            &ForeignChainConfiguration {
+                chain_id: chain_id.0,
+                next_paymaster: String::new(),
+                oracle_asset_id: decode_pyth_price_id(&oracle_asset_id),
+                transfer_gas: U256::from(transfer_gas.0).0,
+                contract_transfer_gas: None,
+                max_transfer_gas: None,
+                funding_call: None,
+                calldata_pricing: None,
+                rollup_fee_model: None,
+                min_fee_local: BTreeMap::new(),
+                conversion_rate_bounds: BTreeMap::new(),
+                nonce_oracle_id: None,
+                fee_rate: (fee_rate.0.into(), fee_rate.1.into()),
+                paymasters: TreeMap::new(StorageKey::Paymasters(chain_id.0)),
+                disabled_paymasters: TreeMap::new(StorageKey::DisabledPaymasters(chain_id.0)),
+                decimals,
+                metadata: ForeignChainMetadata::default(),
+                sponsorship_statistics: SponsorshipStatistics::default(),
+                sponsorship_cap: None,
+                is_sponsorship_paused: false,
+                fee_suggestion: None,
+                unsponsored_fee: BTreeMap::new(),
+            },
+        );
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Changes the base parameters of an existing foreign chain. Paymasters
+    /// and other settings are kept.
+    pub fn update_foreign_chain(
+        &mut self,
+        chain_id: U64,
+        oracle_asset_id: String,
+        transfer_gas: U128,
+        fee_rate: (U128, U128),
+        decimals: u8,
+    ) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("update_foreign_chain");
+
+        validate_fee_rate((fee_rate.0 .0, fee_rate.1 .0)).unwrap_or_reject();
+        validate_decimals(decimals).unwrap_or_reject();
+
+        self.with_mut_chain(chain_id.0, |config| {
+            config.oracle_asset_id = decode_pyth_price_id(&oracle_asset_id);
+            config.transfer_gas = U256::from(transfer_gas.0).0;
+            config.fee_rate = (fee_rate.0.into(), fee_rate.1.into());
+            config.decimals = decimals;
+        });
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Adds a foreign chain and its initial set of paymasters in one call.
+    ///
+    /// All arguments are validated before any state is written, so the call
+    /// either onboards the chain completely or fails without side effects.
+    pub fn add_foreign_chain_full(&mut self, config: ChainOnboardingArgs) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("add_foreign_chain_full");
+        self.onboard_foreign_chain(config);
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn set_foreign_chain_oracle_asset_id(&mut self, chain_id: U64, oracle_asset_id: String) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_foreign_chain_oracle_asset_id");
+
+        self.with_mut_chain(chain_id.0, |config| {
+            config.oracle_asset_id = decode_pyth_price_id(&oracle_asset_id);
+        });
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn set_foreign_chain_transfer_gas(&mut self, chain_id: U64, transfer_gas: U128) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_foreign_chain_transfer_gas");
+
+        self.with_mut_chain(chain_id.0, |config| {
+            config.transfer_gas = U256::from(transfer_gas.0).0;
+        });
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Sets the funding gas for contract senders and the upper bound on
+    /// caller-provided funding gas. `None` restores the default.
+    pub fn set_foreign_chain_funding_gas(
+        &mut self,
+        chain_id: U64,
+        contract_transfer_gas: Option<U128>,
+        max_transfer_gas: Option<U128>,
+    ) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_foreign_chain_funding_gas");
+
+        self.with_mut_chain(chain_id.0, |config| {
+            config.contract_transfer_gas = contract_transfer_gas.map(|g| U256::from(g.0).0);
+            config.max_transfer_gas = max_transfer_gas.map(|g| U256::from(g.0).0);
+        });
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Funds contract senders by calling `funding_call` instead of with a
+    /// plain transfer. `None` reverts to plain transfers.
+    pub fn set_foreign_chain_funding_call(
+        &mut self,
+        chain_id: U64,
+        funding_call: Option<FundingCall>,
+    ) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_foreign_chain_funding_call");
+
+        if let Some(ref call) = funding_call {
+            require!(
+                call.selector_bytes().is_some(),
+                "Funding call selector must be 4 hex-encoded bytes",
+            );
+        }
+
+        self.with_mut_chain(chain_id.0, |config| {
+            config.funding_call = funding_call;
+        });
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Charges sponsored transactions on the chain for their calldata, and
+    /// optionally limits its size. `None` charges for gas only.
+    pub fn set_foreign_chain_calldata_pricing(
+        &mut self,
+        chain_id: U64,
+        calldata_pricing: Option<CalldataPricing>,
+    ) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_foreign_chain_calldata_pricing");
+
+        self.with_mut_chain(chain_id.0, |config| {
+            config.calldata_pricing = calldata_pricing;
+        });
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Charges sponsored transactions on the chain for their L1 data fee.
+    /// `None` disables the surcharge.
+    pub fn set_foreign_chain_rollup_fee_model(
+        &mut self,
+        chain_id: U64,
+        rollup_fee_model: Option<RollupFeeModel>,
+    ) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_foreign_chain_rollup_fee_model");
+
+        if let Some(ref model) = rollup_fee_model {
+            require!(model.scalar.1 .0 != 0, "Scalar denominator must be nonzero");
+        }
+
+        self.with_mut_chain(chain_id.0, |config| {
+            config.rollup_fee_model = rollup_fee_model;
+        });
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Reports the current L1 base fee of a rollup with a fee model.
+    pub fn set_foreign_chain_l1_base_fee(&mut self, chain_id: U64, l1_base_fee: U128) {
+        #[cfg(not(feature = "debug"))]
+        self.require_privileged();
+        self.record_admin_action("set_foreign_chain_l1_base_fee");
+
+        self.with_mut_chain(chain_id.0, |config| {
+            config
+                .rollup_fee_model
+                .as_mut()
+                .expect_or_reject("Chain has no rollup fee model")
+                .l1_base_fee = l1_base_fee;
+        });
+    }
+
+    /// Sets the circuit breaker of the chain: once more than `cap` gas tokens
+    /// are sponsored within an hour, sponsorship on the chain is paused until
+    /// `resume_foreign_chain_sponsorship` is called. `None` removes the cap.
+    pub fn set_foreign_chain_sponsorship_cap(&mut self, chain_id: U64, cap: Option<U128>) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_foreign_chain_sponsorship_cap");
+
+        self.with_mut_chain(chain_id.0, |config| {
+            config.sponsorship_cap = cap.map(|c| U256::from(c.0).0);
+        });
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Resumes sponsorship on a chain paused by its circuit breaker.
+    pub fn resume_foreign_chain_sponsorship(&mut self, chain_id: U64) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("resume_foreign_chain_sponsorship");
+
+        self.with_mut_chain(chain_id.0, |config| {
+            config.is_sponsorship_paused = false;
+        });
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Sets the minimum fee, in the smallest unit of `asset_id`, charged for
+    /// sponsoring a transaction on the chain. `None` removes the floor.
+    pub fn set_foreign_chain_min_fee_local(
+        &mut self,
+        chain_id: U64,
+        asset_id: AssetId,
+        min_fee: Option<U128>,
+    ) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_foreign_chain_min_fee_local");
+
+        self.with_mut_chain(chain_id.0, |config| {
+            if let Some(min_fee) = min_fee {
+                config.min_fee_local.insert(asset_id, min_fee);
+            } else {
+                config.min_fee_local.remove(&asset_id);
+            }
+        });
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Sets the flat fee, in the smallest unit of `asset_id`, charged for a
+    /// sequence on the chain that is not sponsored by a paymaster. Once a fee
+    /// is set for any asset, unsponsored sequences must pay in one of the
+    /// assets with a fee. `None` removes the fee for the asset.
+    pub fn set_foreign_chain_unsponsored_fee(
+        &mut self,
+        chain_id: U64,
+        asset_id: AssetId,
+        fee: Option<U128>,
+    ) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_foreign_chain_unsponsored_fee");
+
+        self.with_mut_chain(chain_id.0, |config| {
+            if let Some(fee) = fee {
+                config.unsponsored_fee.insert(asset_id, fee);
+            } else {
+                config.unsponsored_fee.remove(&asset_id);
+            }
+        });
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Sets the accepted band for the oracle-derived conversion rate between
+    /// `asset_id` and the chain's gas token. `None` removes the bounds.
+    pub fn set_foreign_chain_conversion_rate_bounds(
+        &mut self,
+        chain_id: U64,
+        asset_id: AssetId,
+        bounds: Option<ConversionRateBounds>,
+    ) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_foreign_chain_conversion_rate_bounds");
+
+        if let Some(ConversionRateBounds {
+            min_rate: Some(min_rate),
+            max_rate: Some(max_rate),
+        }) = &bounds
+        {
+            require!(
+                min_rate.0 <= max_rate.0,
+                "Minimum rate exceeds maximum rate"
+            );
+        }
+
+        self.with_mut_chain(chain_id.0, |config| {
+            if let Some(bounds) = bounds {
+                config.conversion_rate_bounds.insert(asset_id, bounds);
+            } else {
+                config.conversion_rate_bounds.remove(&asset_id);
+            }
+        });
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Sets the contract queried for paymaster nonces on the chain (see
+    /// `lib::nonce_oracle::NonceOracle`). `None` reverts to local nonce
+    /// tracking only.
+    pub fn set_foreign_chain_nonce_oracle(&mut self, chain_id: U64, oracle_id: Option<AccountId>) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_foreign_chain_nonce_oracle");
+
+        self.with_mut_chain(chain_id.0, |config| {
+            config.nonce_oracle_id = oracle_id;
+        });
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Sets the fees of transactions built by the contract on the chain, e.g.
+    /// by `transfer_native`. `None` disables such transactions on the chain.
+    pub fn set_foreign_chain_fee_suggestion(
+        &mut self,
+        chain_id: U64,
+        fee_suggestion: Option<FeeSuggestion>,
+    ) {
+        #[cfg(not(feature = "debug"))]
+        self.require_privileged();
+        self.record_admin_action("set_foreign_chain_fee_suggestion");
+
+        if let Some(ref fee_suggestion) = fee_suggestion {
+            require!(
+                fee_suggestion.max_priority_fee_per_gas.0 <= fee_suggestion.max_fee_per_gas.0,
+                "Priority fee exceeds maximum fee",
+            );
+        }
+
+        self.with_mut_chain(chain_id.0, |config| {
+            config.fee_suggestion = fee_suggestion;
+        });
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Sets the gas limit of `transfer_erc20` calls for a token. `None`
+    /// disables `transfer_erc20` for the token.
+    pub fn set_erc20_transfer_gas(
+        &mut self,
+        chain_id: U64,
+        token_address: ForeignAddress,
+        gas: Option<U128>,
+    ) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_erc20_transfer_gas");
 
-        self.foreign_chains.insert(
-            &chain_id.0,
-            &ForeignChainConfiguration {
-                chain_id: chain_id.0,
-                next_paymaster: String::new(),
-                oracle_asset_id: decode_pyth_price_id(&oracle_asset_id),
-                transfer_gas: U256::from(transfer_gas.0).0,
-                fee_rate: (fee_rate.0.into(), fee_rate.1.into()),
-                paymasters: TreeMap::new(StorageKey::Paymasters(chain_id.0)),
-                decimals,
-            },
-        );
+        self.get_chain(chain_id.0).unwrap_or_reject();
+
+        if let Some(gas) = gas {
+            self.erc20_transfer_gas
+                .insert(&(chain_id.0, token_address), &gas.0);
+        } else {
+            self.erc20_transfer_gas.remove(&(chain_id.0, token_address));
+        }
+
+        self.emit_config_snapshot_if_due();
     }
 
-    pub fn set_foreign_chain_oracle_asset_id(&mut self, chain_id: U64, oracle_asset_id: String) {
+    /// Sets the contract that screens the sender and receiver of sponsored
+    /// transactions (see `lib::screening::Screening`). `None` disables
+    /// screening.
+    pub fn set_screening_id(&mut self, screening_id: Option<AccountId>) {
         <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_screening_id");
 
-        self.with_mut_chain(chain_id.0, |config| {
-            config.oracle_asset_id = decode_pyth_price_id(&oracle_asset_id);
-        });
+        self.screening_id = screening_id;
+
+        self.emit_config_snapshot_if_due();
     }
 
-    pub fn set_foreign_chain_transfer_gas(&mut self, chain_id: U64, transfer_gas: U128) {
+    pub fn get_screening_id(&self) -> Option<AccountId> {
+        self.screening_id.clone()
+    }
+
+    pub fn set_foreign_chain_metadata(&mut self, chain_id: U64, metadata: ForeignChainMetadata) {
         <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_foreign_chain_metadata");
 
         self.with_mut_chain(chain_id.0, |config| {
-            config.transfer_gas = U256::from(transfer_gas.0).0;
+            config.metadata = metadata;
         });
+
+        self.emit_config_snapshot_if_due();
     }
 
     pub fn remove_foreign_chain(&mut self, chain_id: U64) {
         <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("remove_foreign_chain");
         if let Some(mut config) = self.foreign_chains.remove(&chain_id.0) {
             config.paymasters.clear();
+            config.disabled_paymasters.clear();
         }
+
+        self.emit_config_snapshot_if_due();
     }
 
     pub fn get_foreign_chains(&self) -> Vec<GetForeignChain> {
@@ -220,6 +900,29 @@ impl Contract {
             .collect()
     }
 
+    pub fn get_foreign_chain(&self, chain_id: U64) -> ViewForeignChainConfiguration {
+        ViewForeignChainConfiguration::from(&self.get_chain(chain_id.0).unwrap_or_reject())
+    }
+
+    pub fn list_foreign_chains(
+        &self,
+        offset: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<ViewForeignChainConfiguration> {
+        let mut v: Vec<_> = self
+            .foreign_chains
+            .values()
+            .map(|config| ViewForeignChainConfiguration::from(&config))
+            .collect();
+
+        v.sort_by_key(|c| c.chain_id.0);
+
+        v.into_iter()
+            .skip(offset.map_or(0, |o| o as usize))
+            .take(limit.map_or(usize::MAX, |l| l as usize))
+            .collect()
+    }
+
     pub fn add_paymaster(
         &mut self,
         chain_id: U64,
@@ -228,13 +931,22 @@ impl Contract {
         balance: Option<near_sdk::json_types::U128>,
     ) {
         <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("add_paymaster");
 
         require!(
-            self.paymaster_keys.get(&token_id).is_some(),
+            self.paymaster_keys
+                .get(&token_id)
+                .map_or(false, |k| k.has_public_key()),
             "Token ID is not registered as paymaster",
         );
 
         self.with_mut_chain(chain_id.0, |chain_config| {
+            require!(
+                chain_config.paymasters.get(&token_id).is_none()
+                    && chain_config.disabled_paymasters.get(&token_id).is_none(),
+                "Paymaster already exists on this chain",
+            );
+
             chain_config.paymasters.insert(
                 &token_id,
                 &PaymasterConfiguration {
@@ -244,6 +956,8 @@ impl Contract {
                 },
             );
         });
+
+        self.emit_config_snapshot_if_due();
     }
 
     #[cfg(not(feature = "debug"))]
@@ -259,6 +973,7 @@ impl Contract {
     pub fn set_paymaster_balance(&mut self, chain_id: U64, token_id: String, balance: U128) {
         #[cfg(not(feature = "debug"))]
         self.require_privileged();
+        self.record_admin_action("set_paymaster_balance");
 
         self.with_mut_chain(chain_id.0, |chain_config| {
             let mut paymaster = chain_config.paymasters.get(&token_id).unwrap_or_reject();
@@ -270,6 +985,7 @@ impl Contract {
     pub fn increase_paymaster_balance(&mut self, chain_id: U64, token_id: String, balance: U128) {
         #[cfg(not(feature = "debug"))]
         self.require_privileged();
+        self.record_admin_action("increase_paymaster_balance");
 
         self.with_mut_chain(chain_id.0, |chain_config| {
             let mut paymaster = chain_config.paymasters.get(&token_id).unwrap_or_reject();
@@ -284,6 +1000,7 @@ impl Contract {
     pub fn set_paymaster_nonce(&mut self, chain_id: U64, token_id: String, nonce: u32) {
         #[cfg(not(feature = "debug"))]
         self.require_privileged();
+        self.record_admin_action("set_paymaster_nonce");
 
         self.with_mut_chain(chain_id.0, |chain_config| {
             let mut paymaster = chain_config.paymasters.get(&token_id).unwrap_or_reject();
@@ -297,10 +1014,161 @@ impl Contract {
     /// payloads from getting signed.
     pub fn remove_paymaster(&mut self, chain_id: U64, token_id: String) {
         <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("remove_paymaster");
 
         self.with_mut_chain(chain_id.0, |chain_config| {
             chain_config.paymasters.remove(&token_id).unwrap_or_reject();
         });
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Takes a paymaster out of the rotation, keeping its configuration so
+    /// that it can be re-enabled or drained later. Sequences it has already
+    /// funded can still be signed.
+    pub fn disable_paymaster(&mut self, chain_id: U64, token_id: String) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("disable_paymaster");
+
+        self.with_mut_chain(chain_id.0, |chain_config| {
+            let paymaster = chain_config
+                .paymasters
+                .remove(&token_id)
+                .expect_or_reject("Paymaster is not enabled");
+            chain_config
+                .disabled_paymasters
+                .insert(&token_id, &paymaster);
+        });
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn enable_paymaster(&mut self, chain_id: U64, token_id: String) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("enable_paymaster");
+
+        self.with_mut_chain(chain_id.0, |chain_config| {
+            let paymaster = chain_config
+                .disabled_paymasters
+                .remove(&token_id)
+                .expect_or_reject("Paymaster is not disabled");
+            chain_config.paymasters.insert(&token_id, &paymaster);
+        });
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn get_disabled_paymasters(&self, chain_id: U64) -> Vec<ViewPaymasterConfiguration> {
+        self.get_chain(chain_id.0)
+            .unwrap_or_reject()
+            .disabled_paymasters
+            .iter()
+            .map(|(_, p)| self.view_paymaster(&p))
+            .collect()
+    }
+
+    /// Creates a sequence, signed by the administrator with `sign_next`,
+    /// that transfers the tracked balance of a disabled paymaster (less the
+    /// transfer's gas cost) to `receiver`. Any excess over the tracked
+    /// balance remains with the paymaster.
+    pub fn drain_paymaster(
+        &mut self,
+        chain_id: U64,
+        token_id: String,
+        receiver: ForeignAddress,
+        max_fee_per_gas: U128,
+        max_priority_fee_per_gas: U128,
+    ) -> TransactionSequenceCreation {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("drain_paymaster");
+
+        let authorization = self
+            .paymaster_keys
+            .get(&token_id)
+            .expect_or_reject("Token ID is not registered as paymaster")
+            .authorization;
+
+        let (transaction, reservation) = self.with_mut_chain(chain_id.0, |chain_config| {
+            let mut paymaster = chain_config
+                .disabled_paymasters
+                .get(&token_id)
+                .expect_or_reject("Paymaster must be disabled before it is drained");
+
+            let gas_cost = chain_config
+                .transfer_gas()
+                .checked_mul(U256::from(max_fee_per_gas.0))
+                .expect_or_reject("Gas cost overflow");
+            let value = U256(paymaster.minimum_available_balance)
+                .checked_sub(gas_cost)
+                .filter(|v| !v.is_zero())
+                .expect_or_reject("Paymaster balance does not cover the transfer's gas cost");
+
+            let transaction = ValidTransactionRequest {
+                chain_id: chain_id.0,
+                to: receiver,
+                value: value.0,
+                gas: chain_config.transfer_gas,
+                data: vec![],
+                nonce: U256::from(paymaster.nonce).0,
+                access_list_rlp: vec![0xc0 /* rlp encoding for empty list */],
+                max_priority_fee_per_gas: U256::from(max_priority_fee_per_gas.0).0,
+                max_fee_per_gas: U256::from(max_fee_per_gas.0).0,
+            };
+
+            let reservation = PaymasterReservation {
+                chain_id: chain_id.0,
+                token_id: token_id.clone(),
+                amount: paymaster.minimum_available_balance,
+                nonce: Some(paymaster.nonce),
+            };
+
+            paymaster.nonce = paymaster.nonce.checked_add(1).unwrap_or_reject();
+            paymaster.minimum_available_balance = U256::zero().0;
+            chain_config
+                .disabled_paymasters
+                .insert(&token_id, &paymaster);
+
+            (transaction, reservation)
+        });
+
+        let pending_transaction_sequence = PendingTransactionSequence {
+            created_by_account_id: env::predecessor_account_id(),
+            signature_requests: vec![SignatureRequest::new(
+                &token_id,
+                authorization,
+                transaction,
+                true,
+            )],
+            created_at_block_height: env::block_height().into(),
+            escrow: None,
+            tag: None,
+            paymaster_token_id: None,
+            settled_escrow: None,
+            priority: 0,
+            tip: None,
+            callback: None,
+            funding_shares: vec![],
+            paymaster_reservations: vec![reservation],
+            signing_order: SigningOrder::PaymasterFirst,
+            is_funding_released: false,
+            signer_deposit: U128(0),
+            tenant_id: None,
+            recurring_authorization_id: None,
+            is_cancellation: false,
+            replaced_sequence_id: None,
+            session_grantee_id: None,
+        };
+
+        self.insert_transaction_sequence(chain_id.0, pending_transaction_sequence)
+    }
+
+    /// Estimates how many more typical transactions each paymaster of the
+    /// chain can fund, and in how many blocks, based on the sponsorships of
+    /// roughly the last hour.
+    pub fn estimate_paymaster_runway(&self, chain_id: U64) -> Vec<ViewPaymasterRunway> {
+        self.get_chain(chain_id.0)
+            .unwrap_or_reject()
+            .estimate_paymaster_runway(env::block_height())
     }
 
     pub fn get_paymasters(&self, chain_id: U64) -> Vec<ViewPaymasterConfiguration> {
@@ -308,23 +1176,14 @@ impl Contract {
             .unwrap_or_reject()
             .paymasters
             .iter()
-            .map(|(_, p)| ViewPaymasterConfiguration {
-                nonce: p.nonce,
-                token_id: p.token_id.clone(),
-                foreign_address: ForeignAddress::from_raw_public_key(
-                    self.paymaster_keys
-                        .get(&p.token_id)
-                        .unwrap_or_reject()
-                        .public_key_bytes,
-                ),
-                minimum_available_balance: U256(p.minimum_available_balance).as_u128().into(),
-            })
+            .map(|(_, p)| self.view_paymaster(&p))
             .collect()
     }
 
     pub fn list_pending_transaction_sequences(
         &self,
         account_id: Option<AccountId>,
+        tag: Option<String>,
         offset: Option<u32>,
         limit: Option<u32>,
     ) -> std::collections::HashMap<String, PendingTransactionSequence> {
@@ -337,6 +1196,9 @@ impl Contract {
                 account_id
                     .as_ref()
                     .map_or(true, |account_id| &tx.created_by_account_id == account_id)
+                    && tag
+                        .as_ref()
+                        .map_or(true, |tag| tx.tag.as_ref() == Some(tag))
             })
             .skip(offset.map_or(0, |o| o as usize))
             .take(limit.map_or(usize::MAX, |l| l as usize))
@@ -348,30 +1210,107 @@ impl Contract {
         self.pending_transaction_sequences.get(&id.0)
     }
 
+    /// Expiry of a pending sequence, so that relayers can prioritize the
+    /// sequences closest to expiring.
+    pub fn get_sequence_expiry(&self, id: U64) -> SequenceExpiry {
+        let sequence = self
+            .pending_transaction_sequences
+            .get(&id.0)
+            .expect_or_reject(TransactionSequenceDoesNotExistError {
+                transaction_sequence_id: id.0,
+            });
+
+        self.sequence_expiry(sequence.created_at_block_height.0)
+    }
+
+    /// The ID the next created sequence will receive. IDs are assigned
+    /// sequentially, so a client can predict the ID of its sequence as long
+    /// as no other sequence is created first.
+    pub fn peek_next_id(&self) -> U64 {
+        self.next_unique_id.into()
+    }
+
+    /// The hash the contract asks the signer to sign for a transaction
+    /// request, so that clients can verify it off-chain.
+    pub fn get_sighash_for(&self, transaction_rlp_hex: String) -> String {
+        let transaction =
+            ValidTransactionRequest::try_from(decode_transaction_request(&transaction_rlp_hex))
+                .expect_or_reject("Invalid transaction request");
+
+        self.flags.encode_hex(sighash_for_mpc_signing(transaction))
+    }
+
+    /// The hashes the contract asks the signer to sign for each transaction
+    /// of a pending or fully signed sequence, in signing order.
+    pub fn get_sighashes_for_sequence(&self, id: U64) -> Vec<String> {
+        let sequence = self
+            .pending_transaction_sequences
+            .get(&id.0)
+            .or_else(|| self.signed_sequences.get(&id.0))
+            .expect_or_reject(TransactionSequenceDoesNotExistError {
+                transaction_sequence_id: id.0,
+            });
+
+        sequence
+            .signature_requests
+            .into_iter()
+            .map(|r| {
+                self.flags
+                    .encode_hex(sighash_for_mpc_signing(r.transaction))
+            })
+            .collect()
+    }
+
     pub fn list_signed_transaction_sequences_after(
         &self,
         block_height: U64,
+        tag: Option<String>,
         offset: Option<u32>,
         limit: Option<u32>,
     ) -> Vec<TransactionSequenceSigned> {
         self.signed_transaction_sequences
             .iter()
             .skip_while(|s| s.block_height < block_height.0)
+            .filter(|s| {
+                tag.as_ref()
+                    .map_or(true, |tag| s.event.tag.as_ref() == Some(tag))
+            })
             .skip(offset.map_or(0, |o| o as usize))
             .take(limit.map_or(usize::MAX, |l| l as usize))
             .map(|s| s.event)
             .collect()
     }
 
+    /// Withdraws fees that are not attributed to a paymaster, or, if
+    /// `paymaster_token_id` is given, the fees credited to that paymaster.
+    /// Fees credited to bonded paymasters can only be withdrawn by their
+    /// owners.
     #[payable]
     pub fn withdraw_collected_fees(
         &mut self,
         asset_id: AssetId,
         amount: Option<U128>,
         receiver_id: Option<AccountId>, // TODO: Pull method instead of push (danger of typos/locked accounts)
+        paymaster_token_id: Option<String>,
     ) -> Promise {
         near_sdk::assert_one_yocto();
         <Self as Rbac>::require_role(&Role::MarketMaker);
+        self.record_admin_action("withdraw_collected_fees");
+
+        if let Some(token_id) = paymaster_token_id {
+            require!(
+                self.bonded_paymasters.get(&token_id).is_none(),
+                "Fees of bonded paymasters are withdrawn by their owners",
+            );
+
+            return self.transfer_paymaster_fees(
+                token_id,
+                asset_id,
+                amount,
+                receiver_id.unwrap_or_else(env::predecessor_account_id),
+            );
+        }
+
         let mut fees = self
             .collected_fees
             .get(&asset_id)
@@ -386,10 +1325,179 @@ impl Contract {
 
         self.collected_fees.insert(&asset_id, &fees);
 
-        asset_id.transfer(
-            receiver_id.unwrap_or_else(env::predecessor_account_id),
-            amount,
-        )
+        asset_id
+            .transfer_with_options(
+                receiver_id.unwrap_or_else(env::predecessor_account_id),
+                amount,
+                &transfer_options("Gas station fee withdrawal"),
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(Self::WITHDRAW_COLLECTED_FEES_CALLBACK_GAS)
+                    .with_unused_gas_weight(0)
+                    .withdraw_collected_fees_callback(asset_id, amount),
+            )
+    }
+
+    /// Exports the administrative configuration of the contract. Runtime
+    /// state (pending sequences, collected fees, chain keys) is not included.
+    pub fn export_configuration(&self) -> ContractConfiguration {
+        ContractConfiguration {
+            expire_sequence_after_blocks: self.expire_sequence_after_blocks.into(),
+            flags: self.flags.clone(),
+            accepted_local_assets: self
+                .accepted_local_assets
+                .iter()
+                .map(|(asset_id, config)| LocalAssetOnboardingArgs {
+                    asset_id,
+                    oracle_asset_id: near_sdk::bs58::encode(&config.oracle_asset_id).into_string(),
+                    decimals: config.decimals,
+                })
+                .collect(),
+            foreign_chains: self
+                .foreign_chains
+                .iter()
+                .map(|(chain_id, config)| ChainOnboardingArgs {
+                    chain_id: chain_id.into(),
+                    oracle_asset_id: near_sdk::bs58::encode(&config.oracle_asset_id).into_string(),
+                    transfer_gas: U256(config.transfer_gas).as_u128().into(),
+                    contract_transfer_gas: config
+                        .contract_transfer_gas
+                        .map(|g| U256(g).as_u128().into()),
+                    max_transfer_gas: config.max_transfer_gas.map(|g| U256(g).as_u128().into()),
+                    funding_call: config.funding_call,
+                    calldata_pricing: config.calldata_pricing,
+                    rollup_fee_model: config.rollup_fee_model,
+                    sponsorship_cap: config.sponsorship_cap.map(|c| U256(c).as_u128().into()),
+                    min_fee_local: Some(config.min_fee_local),
+                    conversion_rate_bounds: Some(config.conversion_rate_bounds),
+                    nonce_oracle_id: config.nonce_oracle_id,
+                    fee_rate: (config.fee_rate.0.into(), config.fee_rate.1.into()),
+                    decimals: config.decimals,
+                    paymasters: config
+                        .paymasters
+                        .iter()
+                        .map(|(_, p)| PaymasterOnboardingArgs {
+                            token_id: p.token_id,
+                            nonce: p.nonce,
+                            balance: Some(U256(p.minimum_available_balance).as_u128().into()),
+                        })
+                        .collect(),
+                    metadata: Some(config.metadata),
+                })
+                .collect(),
+            sender_whitelist: self.sender_whitelist.iter().collect(),
+            receiver_whitelist: self.receiver_whitelist.iter().collect(),
+        }
+    }
+
+    /// Applies a configuration produced by `export_configuration`.
+    ///
+    /// Flags and the expiration window are overwritten; assets, chains, and
+    /// whitelist entries are added. Imported assets and chains must not
+    /// already exist, and their paymaster keys must already be registered with this contract
+    /// (omit `paymasters` when migrating to a deployment with different keys).
+    pub fn import_configuration(&mut self, configuration: ContractConfiguration) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("import_configuration");
+
+        let ContractConfiguration {
+            expire_sequence_after_blocks,
+            flags,
+            accepted_local_assets,
+            foreign_chains,
+            sender_whitelist,
+            receiver_whitelist,
+        } = configuration;
+
+        self.expire_sequence_after_blocks = expire_sequence_after_blocks.into();
+        self.flags = flags;
+
+        for asset in accepted_local_assets {
+            self.insert_accepted_local_asset(
+                asset.asset_id,
+                &asset.oracle_asset_id,
+                asset.decimals,
+                false,
+            );
+        }
+
+        for chain in foreign_chains {
+            self.onboard_foreign_chain(chain);
+        }
+
+        for account_id in sender_whitelist {
+            self.sender_whitelist.insert(&account_id);
+        }
+
+        for address in receiver_whitelist {
+            self.receiver_whitelist.insert(&address);
+        }
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Withdraws the entire balance of every asset with collected fees. Each
+    /// transfer is independent: if one fails, its amount is re-credited to
+    /// the collected fees without affecting the others.
+    #[payable]
+    pub fn withdraw_all_collected_fees(
+        &mut self,
+        receiver_id: Option<AccountId>,
+    ) -> PromiseOrValue<()> {
+        near_sdk::assert_one_yocto();
+        <Self as Rbac>::require_role(&Role::MarketMaker);
+        self.record_admin_action("withdraw_all_collected_fees");
+
+        let receiver_id = receiver_id.unwrap_or_else(env::predecessor_account_id);
+
+        let fees: Vec<(AssetId, U128)> = self
+            .collected_fees
+            .iter()
+            .filter(|(_, amount)| amount.0 > 0)
+            .collect();
+
+        let mut ret: Option<Promise> = None;
+
+        for (asset_id, amount) in fees {
+            self.collected_fees.insert(&asset_id, &U128(0));
+
+            let transfer = asset_id
+                .transfer_with_options(
+                    receiver_id.clone(),
+                    amount,
+                    &transfer_options("Gas station fee withdrawal"),
+                )
+                .then(
+                    Self::ext(env::current_account_id())
+                        .with_static_gas(Self::WITHDRAW_COLLECTED_FEES_CALLBACK_GAS)
+                        .with_unused_gas_weight(0)
+                        .withdraw_collected_fees_callback(asset_id, amount),
+                );
+
+            ret = Some(match ret {
+                Some(p) => p.and(transfer),
+                None => transfer,
+            });
+        }
+
+        ret.map_or(PromiseOrValue::Value(()), PromiseOrValue::Promise)
+    }
+
+    const WITHDRAW_COLLECTED_FEES_CALLBACK_GAS: Gas = Gas::from_tgas(5);
+
+    /// Re-credits the collected fees if the withdrawal transfer failed.
+    #[private]
+    pub fn withdraw_collected_fees_callback(&mut self, asset_id: AssetId, amount: U128) -> bool {
+        let success = matches!(env::promise_result(0), PromiseResult::Successful(_));
+
+        if !success {
+            let mut fees = self.collected_fees.get(&asset_id).unwrap_or(U128(0));
+            fees.0 = fees.0.checked_add(amount.0).unwrap_or_reject();
+            self.collected_fees.insert(&asset_id, &fees);
+        }
+
+        success
     }
 
     pub fn get_collected_fees(&self) -> std::collections::HashMap<AssetId, U128> {
@@ -401,14 +1509,19 @@ impl Contract {
         account_id: AccountId,
         token_id: String,
     ) -> ForeignAddress {
-        ForeignAddress::from_raw_public_key(
-            self.user_chain_keys
-                .get(&account_id)
-                .unwrap_or_reject()
-                .get(&token_id)
-                .unwrap_or_reject()
-                .public_key_bytes,
-        )
+        let key_data = self
+            .user_chain_keys
+            .get(&account_id)
+            .unwrap_or_reject()
+            .get(&token_id)
+            .unwrap_or_reject();
+
+        require!(
+            key_data.has_public_key(),
+            "Chain key registration is incomplete",
+        );
+
+        ForeignAddress::from_raw_public_key(key_data.public_key_bytes)
     }
 
     pub fn estimate_fee(
@@ -417,15 +1530,41 @@ impl Contract {
         local_asset_price: pyth::Price,
         local_asset_decimals: u8,
         foreign_asset_price: pyth::Price,
+        options: Option<EstimateFeeOptions>,
     ) -> U128 {
+        let EstimateFeeOptions {
+            funding,
+            local_asset_id,
+            priority,
+        } = options.unwrap_or_default();
+
         let transaction =
             ValidTransactionRequest::try_from(decode_transaction_request(&transaction_rlp_hex))
                 .expect_or_reject("Invalid transaction request");
 
         let foreign_chain_configuration = self.get_chain(transaction.chain_id).unwrap_or_reject();
 
+        let transfer_gas = foreign_chain_configuration
+            .funding_transfer_gas(&funding.unwrap_or_default())
+            .unwrap_or_reject();
+
+        foreign_chain_configuration
+            .check_data_size(&transaction)
+            .unwrap_or_reject();
+
         let gas_tokens_to_sponsor_transaction = foreign_chain_configuration
-            .calculate_gas_tokens_to_sponsor_transaction(&transaction)
+            .calculate_gas_tokens_to_sponsor_transaction(&transaction, transfer_gas)
+            .unwrap_or_reject();
+
+        let local_asset_id = local_asset_id.unwrap_or(AssetId::Native);
+
+        foreign_chain_configuration
+            .check_conversion_rate_bounds(
+                &local_asset_id,
+                &foreign_asset_price,
+                &local_asset_price,
+                local_asset_decimals,
+            )
             .unwrap_or_reject();
 
         let purchase_price_for_gas_tokens = foreign_chain_configuration
@@ -437,6 +1576,297 @@ impl Contract {
             )
             .unwrap_or_reject();
 
-        purchase_price_for_gas_tokens.into()
+        let purchase_price_for_gas_tokens = self
+            .apply_priority_fee(priority.unwrap_or(0), purchase_price_for_gas_tokens)
+            .unwrap_or_reject();
+
+        // One funding and one user signature request.
+        foreign_chain_configuration
+            .apply_min_fee_local(&local_asset_id, purchase_price_for_gas_tokens)
+            .saturating_add(
+                self.signer_deposit_for(&local_asset_id, 2)
+                    .unwrap_or_reject(),
+            )
+            .into()
+    }
+
+    /// The funding transaction that a sponsored sequence for
+    /// `transaction_rlp_hex`, sent from chain key `token_id`, would receive
+    /// if it were created now. No sequence is created, so the paymaster and
+    /// nonce may differ once other sequences are created.
+    pub fn preview_funding_transaction(
+        &self,
+        transaction_rlp_hex: String,
+        token_id: String,
+        funding: Option<FundingOptions>,
+    ) -> FundingTransactionPreview {
+        let transaction =
+            ValidTransactionRequest::try_from(decode_transaction_request(&transaction_rlp_hex))
+                .expect_or_reject("Invalid transaction request");
+
+        let user_chain_key = self
+            .user_chain_key_registrants
+            .get(&token_id)
+            .and_then(|account_id| self.user_chain_keys.get(&account_id))
+            .and_then(|user_chain_keys| user_chain_keys.get(&token_id))
+            .expect_or_reject("Chain key is not registered");
+        let sender_foreign_address =
+            ForeignAddress::from_raw_public_key(user_chain_key.public_key_bytes);
+
+        let foreign_chain_configuration = self.get_chain(transaction.chain_id).unwrap_or_reject();
+        let funding = funding.unwrap_or_default();
+
+        let transfer_gas = foreign_chain_configuration
+            .funding_transfer_gas(&funding)
+            .unwrap_or_reject();
+
+        let gas_tokens_to_sponsor_transaction = foreign_chain_configuration
+            .calculate_gas_tokens_to_sponsor_transaction(&transaction, transfer_gas)
+            .unwrap_or_reject();
+
+        foreign_chain_configuration
+            .check_sponsorship_unpaused()
+            .unwrap_or_reject();
+
+        let paymaster = foreign_chain_configuration
+            .peek_next_paymaster()
+            .expect_or_reject(NoPaymasterConfigurationForChainError {
+                chain_id: transaction.chain_id,
+            });
+        paymaster
+            .sub_from_minimum_available_balance(gas_tokens_to_sponsor_transaction)
+            .unwrap_or_reject();
+
+        let funding_transaction = foreign_chain_configuration.funding_transaction(
+            &transaction,
+            sender_foreign_address,
+            gas_tokens_to_sponsor_transaction,
+            transfer_gas,
+            &funding,
+            paymaster.nonce,
+        );
+
+        FundingTransactionPreview {
+            paymaster_token_id: paymaster.token_id,
+            transaction: funding_transaction.to_json_request().unwrap_or_reject(),
+            transaction_rlp_hex: self
+                .flags
+                .encode_hex(funding_transaction.into_typed_transaction().rlp()),
+        }
+    }
+}
+
+impl Contract {
+    /// The flat fee of an unsponsored sequence on the chain, paid in
+    /// `asset_id`. Free on chains without such fees.
+    pub(crate) fn unsponsored_fee(
+        &self,
+        chain_id: u64,
+        asset_id: &AssetId,
+    ) -> Result<u128, &'static str> {
+        let Some(config) = self.foreign_chains.get(&chain_id) else {
+            return Ok(0);
+        };
+
+        if config.unsponsored_fee.is_empty() {
+            return Ok(0);
+        }
+
+        config
+            .unsponsored_fee
+            .get(asset_id)
+            .map(|fee| fee.0)
+            .ok_or("Unsponsored sequences on this chain cannot be paid in this asset")
+    }
+
+    /// Records a sponsorship on the chain, emitting an alert if it trips the
+    /// chain's circuit breaker.
+    pub(crate) fn record_sponsorship(foreign_chain: &mut ForeignChainConfiguration, amount: U256) {
+        let Some(window_amount) = foreign_chain.record_sponsorship(amount, env::block_height())
+        else {
+            return;
+        };
+
+        ContractEvent::SponsorshipCircuitBreakerTripped(SponsorshipCircuitBreakerTripped {
+            chain_id: foreign_chain.chain_id.into(),
+            window_amount: U128(window_amount.try_into().unwrap_or(u128::MAX)),
+            cap: U256(foreign_chain.sponsorship_cap.unwrap_or_default())
+                .as_u128()
+                .into(),
+        })
+        .emit();
+    }
+
+    /// Fails if the asset is already accepted, or, with `is_update`, if it is
+    /// not.
+    fn insert_accepted_local_asset(
+        &mut self,
+        asset_id: AssetId,
+        oracle_asset_id: &str,
+        decimals: u8,
+        is_update: bool,
+    ) {
+        validate_decimals(decimals).unwrap_or_reject();
+
+        let previous = self.accepted_local_assets.insert(
+            &asset_id,
+            &LocalAssetConfiguration {
+                oracle_asset_id: decode_pyth_price_id(oracle_asset_id),
+                decimals,
+            },
+        );
+
+        if is_update {
+            require!(previous.is_some(), "Asset not found");
+        } else {
+            require!(previous.is_none(), "Asset is already accepted");
+        }
+    }
+
+    pub(crate) fn sequence_expiry(&self, created_at_block_height: u64) -> SequenceExpiry {
+        SequenceExpiry::new(
+            created_at_block_height,
+            self.expire_sequence_after_blocks,
+            env::block_height(),
+        )
+    }
+
+    fn onboard_foreign_chain(&mut self, config: ChainOnboardingArgs) {
+        let ChainOnboardingArgs {
+            chain_id,
+            oracle_asset_id,
+            transfer_gas,
+            contract_transfer_gas,
+            max_transfer_gas,
+            funding_call,
+            calldata_pricing,
+            rollup_fee_model,
+            sponsorship_cap,
+            min_fee_local,
+            conversion_rate_bounds,
+            nonce_oracle_id,
+            fee_rate,
+            decimals,
+            paymasters,
+            metadata,
+        } = config;
+
+        require!(
+            self.foreign_chains.get(&chain_id.0).is_none(),
+            "Chain configuration already exists",
+        );
+
+        validate_fee_rate((fee_rate.0 .0, fee_rate.1 .0)).unwrap_or_reject();
+        validate_decimals(decimals).unwrap_or_reject();
+
+        if let Some(ref model) = rollup_fee_model {
+            require!(model.scalar.1 .0 != 0, "Scalar denominator must be nonzero");
+        }
+
+        if let Some(ref call) = funding_call {
+            require!(
+                call.selector_bytes().is_some(),
+                "Funding call selector must be 4 hex-encoded bytes",
+            );
+        }
+
+        let oracle_asset_id = decode_pyth_price_id(&oracle_asset_id);
+
+        let mut seen_token_ids = std::collections::HashSet::new();
+        for paymaster in &paymasters {
+            require!(
+                seen_token_ids.insert(&paymaster.token_id),
+                "Duplicate paymaster token ID",
+            );
+            require!(
+                self.paymaster_keys
+                    .get(&paymaster.token_id)
+                    .map_or(false, |k| k.has_public_key()),
+                "Token ID is not registered as paymaster",
+            );
+        }
+
+        let mut chain_config = ForeignChainConfiguration {
+            chain_id: chain_id.0,
+            next_paymaster: String::new(),
+            oracle_asset_id,
+            transfer_gas: U256::from(transfer_gas.0).0,
+            contract_transfer_gas: contract_transfer_gas.map(|g| U256::from(g.0).0),
+            max_transfer_gas: max_transfer_gas.map(|g| U256::from(g.0).0),
+            funding_call,
+            calldata_pricing,
+            rollup_fee_model,
+            min_fee_local: min_fee_local.unwrap_or_default(),
+            conversion_rate_bounds: conversion_rate_bounds.unwrap_or_default(),
+            nonce_oracle_id,
+            fee_rate: (fee_rate.0.into(), fee_rate.1.into()),
+            paymasters: TreeMap::new(StorageKey::Paymasters(chain_id.0)),
+            disabled_paymasters: TreeMap::new(StorageKey::DisabledPaymasters(chain_id.0)),
+            decimals,
+            metadata: metadata.unwrap_or_default(),
+            sponsorship_statistics: SponsorshipStatistics::default(),
+            sponsorship_cap: sponsorship_cap.map(|c| U256::from(c.0).0),
+            is_sponsorship_paused: false,
+            fee_suggestion: None,
+            unsponsored_fee: BTreeMap::new(),
+        };
+
+        for paymaster in paymasters {
+            chain_config.paymasters.insert(
+                &paymaster.token_id,
+                &PaymasterConfiguration {
+                    nonce: paymaster.nonce,
+                    token_id: paymaster.token_id.clone(),
+                    minimum_available_balance: U256::from(paymaster.balance.map_or(0, |v| v.0)).0,
+                },
+            );
+        }
+
+        self.foreign_chains.insert(&chain_id.0, &chain_config);
+    }
+
+    fn view_paymaster(&self, p: &PaymasterConfiguration) -> ViewPaymasterConfiguration {
+        ViewPaymasterConfiguration {
+            nonce: p.nonce,
+            token_id: p.token_id.clone(),
+            foreign_address: ForeignAddress::from_raw_public_key(
+                self.paymaster_keys
+                    .get(&p.token_id)
+                    .unwrap_or_reject()
+                    .public_key_bytes,
+            ),
+            minimum_available_balance: U256(p.minimum_available_balance).as_u128().into(),
+        }
+    }
+
+    pub(crate) fn emit_config_snapshot_if_due(&mut self) {
+        if env::block_height()
+            >= self
+                .last_config_snapshot_block_height
+                .saturating_add(CONFIG_SNAPSHOT_INTERVAL_BLOCKS)
+        {
+            self.last_config_snapshot_block_height = env::block_height();
+            self.emit_config_snapshot_now();
+        }
+    }
+
+    fn emit_config_snapshot_now(&self) {
+        ContractEvent::ConfigSnapshot(ConfigSnapshot {
+            block_height: env::block_height().into(),
+            is_paused: <Self as Pause>::is_paused(),
+            flags: self.flags.clone(),
+            expire_sequence_after_blocks: self.expire_sequence_after_blocks.into(),
+            signer_contract_id: self.signer_contract_id.clone(),
+            oracle_id: self.oracle_id.clone(),
+            accepted_local_asset_count: self.accepted_local_assets.len().into(),
+            sender_whitelist_count: self.sender_whitelist.len().into(),
+            receiver_whitelist_count: self.receiver_whitelist.len().into(),
+            foreign_chains: self
+                .foreign_chains
+                .values()
+                .map(|config| ViewForeignChainConfiguration::from(&config))
+                .collect(),
+        })
+        .emit();
     }
 }
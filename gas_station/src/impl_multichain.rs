@@ -0,0 +1,372 @@
+use lib::{
+    asset::AssetBalance,
+    foreign_address::ForeignAddress,
+    pyth::{self, ext_pyth},
+    screening::ext_screening,
+    Rejectable,
+};
+use near_sdk::{env, near_bindgen, require, AccountId, Promise, PromiseOrValue, PromiseResult};
+use near_sdk_contract_tools::pause::Pause;
+
+use crate::{
+    chain_configuration::{FundingOptions, PaymasterReservation},
+    decode_transaction_request,
+    error::{
+        ExpressionOverflowError, ForeignAddressBlockedError, OracleQueryFailureError,
+        TryCreateTransactionCallbackError,
+    },
+    impl_creation::{check_screening_results, refund_after_fee},
+    signature_request::SignatureRequest,
+    valid_transaction_request::ValidTransactionRequest,
+    Contract, ContractExt, CreateTransactionOptions, PendingTransactionSequence, SequenceCallback,
+    SigningOrder, TransactionSequenceCreation,
+};
+
+/// Maximum number of chains a single sequence may span.
+pub const MAX_SEQUENCE_CHAINS: usize = 4;
+
+#[near_bindgen]
+impl Contract {
+    /// Creates a sponsored sequence with one user transaction on each of
+    /// several chains, e.g. both legs of a cross-chain trade. Each
+    /// transaction is funded by a paymaster of its chain, and the attached
+    /// deposit pays for all of them. Transactions are signed in the given order.
+    ///
+    /// Once fully signed, a `TransactionSequenceSigned` event is emitted for
+    /// each chain, containing only that chain's transactions.
+    #[payable]
+    pub fn create_multichain_transaction(
+        &mut self,
+        token_id: String,
+        transactions_rlp_hex: Vec<String>,
+        tag: Option<String>,
+        callback: Option<SequenceCallback>,
+    ) -> PromiseOrValue<TransactionSequenceCreation> {
+        <Self as Pause>::require_unpaused();
+
+        let account_id = env::predecessor_account_id();
+
+        let deposit = AssetBalance::native(env::attached_deposit().as_yoctonear());
+        let deposit = match tag {
+            Some(ref tag) => Self::charge_tag_storage(tag, deposit).unwrap_or_reject(),
+            None => deposit,
+        };
+
+        Self::check_callback(callback.as_ref()).unwrap_or_reject();
+
+        require!(
+            (2..=MAX_SEQUENCE_CHAINS).contains(&transactions_rlp_hex.len()),
+            format!("Sequence must span between 2 and {MAX_SEQUENCE_CHAINS} chains"),
+        );
+
+        let transactions = transactions_rlp_hex
+            .iter()
+            .map(|rlp_hex| {
+                ValidTransactionRequest::try_from(decode_transaction_request(rlp_hex))
+                    .unwrap_or_reject()
+            })
+            .collect::<Vec<_>>();
+
+        for (i, transaction) in transactions.iter().enumerate() {
+            require!(
+                transactions[..i]
+                    .iter()
+                    .all(|t| t.chain_id != transaction.chain_id),
+                "Each transaction must be for a different chain",
+            );
+        }
+
+        let user_chain_key = self
+            .user_chain_keys
+            .get(&account_id)
+            .and_then(|keys| keys.get(&token_id))
+            .expect_or_reject("Predecessor unauthorized for the requested chain key token ID");
+
+        require!(
+            user_chain_key.has_public_key(),
+            "Chain key registration is incomplete; call `refresh_user_chain_key` first",
+        );
+
+        for transaction in &transactions {
+            self.filter_transaction(&account_id, transaction, None);
+            self.check_foreign_nonce(&token_id, &user_chain_key, transaction)
+                .unwrap_or_reject();
+            self.check_transaction_replay(&token_id, transaction)
+                .unwrap_or_reject();
+        }
+
+        self.consume_rate_limit(&account_id, env::block_height())
+            .unwrap_or_reject();
+
+        let deposit = self.take_dust(&account_id, deposit);
+
+        require!(deposit.amount.0 > 0, "Deposit is required to pay for gas");
+
+        let accepted_local_asset = self
+            .accepted_local_assets
+            .get(&deposit.asset_id)
+            .expect_or_reject("Unsupported deposit asset");
+
+        // The local asset price is followed by the gas token price of each
+        // chain, in the order of `transactions`.
+        let mut prices = ext_pyth::ext(self.oracle_id.clone())
+            .get_ema_price(pyth::PriceIdentifier(accepted_local_asset.oracle_asset_id));
+
+        for transaction in &transactions {
+            let foreign_chain = self.get_chain(transaction.chain_id).unwrap_or_reject();
+
+            foreign_chain
+                .funding_transfer_gas(&FundingOptions::default())
+                .unwrap_or_reject();
+
+            foreign_chain
+                .check_data_size(transaction)
+                .unwrap_or_reject();
+
+            prices = prices.and(
+                ext_pyth::ext(self.oracle_id.clone())
+                    .get_ema_price(pyth::PriceIdentifier(foreign_chain.oracle_asset_id)),
+            );
+        }
+
+        let sender_foreign_address =
+            ForeignAddress::from_raw_public_key(&user_chain_key.public_key_bytes);
+
+        self.add_screening_queries(prices, sender_foreign_address, &transactions)
+            .then(
+                Self::ext(env::current_account_id()).create_multichain_transaction_callback(
+                    account_id,
+                    token_id,
+                    deposit,
+                    transactions,
+                    tag,
+                    callback,
+                ),
+            )
+            .into()
+    }
+
+    #[private]
+    pub fn create_multichain_transaction_callback(
+        &mut self,
+        #[serializer(borsh)] sender: AccountId,
+        #[serializer(borsh)] token_id: String,
+        #[serializer(borsh)] deposit: AssetBalance,
+        #[serializer(borsh)] transaction_requests: Vec<ValidTransactionRequest>,
+        #[serializer(borsh)] tag: Option<String>,
+        #[serializer(borsh)] callback: Option<SequenceCallback>,
+    ) -> PromiseOrValue<TransactionSequenceCreation> {
+        match self.try_create_multichain_transaction_callback(
+            &sender,
+            token_id,
+            &deposit,
+            &transaction_requests,
+            tag,
+            callback,
+        ) {
+            Ok((refund, creation)) => {
+                if refund > 0 {
+                    self.refund_or_keep_dust(
+                        sender,
+                        AssetBalance {
+                            asset_id: deposit.asset_id,
+                            amount: refund.into(),
+                        },
+                    );
+                }
+
+                PromiseOrValue::Value(creation)
+            }
+            Err(e) => PromiseOrValue::Promise(Self::refund_and_throw(sender, deposit, &e)),
+        }
+    }
+}
+
+impl Contract {
+    fn price_result(index: u64) -> Result<pyth::Price, OracleQueryFailureError> {
+        let PromiseResult::Successful(value) = env::promise_result(index) else {
+            return Err(OracleQueryFailureError);
+        };
+
+        near_sdk::serde_json::from_slice(&value).map_err(|_| OracleQueryFailureError)
+    }
+
+    /// The sender's foreign address, then the receiver of each transaction.
+    fn screened_addresses(
+        sender_foreign_address: ForeignAddress,
+        transactions: &[ValidTransactionRequest],
+    ) -> Vec<ForeignAddress> {
+        std::iter::once(sender_foreign_address)
+            .chain(transactions.iter().map(|t| t.to))
+            .collect()
+    }
+
+    /// Screens the sender and every receiver after the prices, if a
+    /// screening contract is configured.
+    fn add_screening_queries(
+        &self,
+        prices: Promise,
+        sender_foreign_address: ForeignAddress,
+        transactions: &[ValidTransactionRequest],
+    ) -> Promise {
+        let Some(screening_id) = self.screening_id.clone() else {
+            return prices;
+        };
+
+        Self::screened_addresses(sender_foreign_address, transactions)
+            .into_iter()
+            .fold(prices, |queries, address| {
+                queries.and(ext_screening::ext(screening_id.clone()).is_blocked(address))
+            })
+    }
+
+    /// Screening results follow the prices if screening was configured when
+    /// the sequence was requested.
+    fn check_multichain_screening(
+        sender_foreign_address: ForeignAddress,
+        transactions: &[ValidTransactionRequest],
+    ) -> Result<(), ForeignAddressBlockedError> {
+        let screening_results_start = 1 + transactions.len() as u64;
+        if env::promise_results_count() <= screening_results_start {
+            return Ok(());
+        }
+
+        check_screening_results(
+            &Self::screened_addresses(sender_foreign_address, transactions),
+            screening_results_start,
+        )
+    }
+
+    fn try_create_multichain_transaction_callback(
+        &mut self,
+        sender: &AccountId,
+        token_id: String,
+        deposit: &AssetBalance,
+        transaction_requests: &[ValidTransactionRequest],
+        tag: Option<String>,
+        callback: Option<SequenceCallback>,
+    ) -> Result<(u128, TransactionSequenceCreation), TryCreateTransactionCallbackError> {
+        let local_asset_price = Self::price_result(0)?;
+
+        let user_chain_key = self.sender_chain_key(sender, &token_id)?;
+
+        let sender_foreign_address =
+            ForeignAddress::from_raw_public_key(&user_chain_key.public_key_bytes);
+
+        Self::check_multichain_screening(sender_foreign_address, transaction_requests)?;
+
+        let funding = FundingOptions::default();
+
+        let mut total_fee = 0u128;
+        let mut signature_requests = Vec::with_capacity(transaction_requests.len() * 2);
+        let mut funding_shares = Vec::with_capacity(transaction_requests.len());
+        let mut paymaster_reservations = Vec::with_capacity(transaction_requests.len());
+        let mut sponsored_chains = Vec::with_capacity(transaction_requests.len());
+
+        // Chain configurations are only written back once every chain has
+        // been funded, so that a failure leaves no paymaster nonce consumed.
+        for (index, transaction_request) in (1..).zip(transaction_requests) {
+            let foreign_asset_price = Self::price_result(index)?;
+
+            let mut foreign_chain = self.get_chain(transaction_request.chain_id)?;
+
+            let transfer_gas = foreign_chain.funding_transfer_gas(&funding)?;
+
+            let gas_tokens_to_sponsor_transaction = foreign_chain
+                .calculate_gas_tokens_to_sponsor_transaction(transaction_request, transfer_gas)?;
+
+            // Multichain sequences have neither a tenant nor a priority.
+            let local_asset_fee = self.sponsored_fee(
+                &foreign_chain,
+                &deposit.asset_id,
+                &CreateTransactionOptions::default(),
+                gas_tokens_to_sponsor_transaction,
+                &foreign_asset_price,
+                &local_asset_price,
+            )?;
+
+            total_fee = total_fee
+                .checked_add(local_asset_fee)
+                .ok_or(ExpressionOverflowError)?;
+
+            let paymaster_signature_request = self.create_funding_signature_request(
+                &mut foreign_chain,
+                transaction_request,
+                sender_foreign_address,
+                gas_tokens_to_sponsor_transaction,
+                transfer_gas,
+                &funding,
+            )?;
+
+            paymaster_reservations.push(PaymasterReservation::for_funding_transaction(
+                &paymaster_signature_request.token_id,
+                &paymaster_signature_request.transaction,
+            ));
+            funding_shares.push((
+                paymaster_signature_request.token_id.clone(),
+                local_asset_fee.into(),
+            ));
+            signature_requests.push(paymaster_signature_request);
+            signature_requests.push(SignatureRequest::new(
+                &token_id,
+                user_chain_key.authorization,
+                transaction_request.clone(),
+                false,
+            ));
+            sponsored_chains.push((foreign_chain, gas_tokens_to_sponsor_transaction));
+        }
+
+        let signer_deposit =
+            self.signer_deposit_for(&deposit.asset_id, signature_requests.len())?;
+
+        let refund = refund_after_fee(deposit, total_fee, signer_deposit)?;
+
+        for (transaction_request, (mut foreign_chain, gas_tokens_to_sponsor_transaction)) in
+            transaction_requests.iter().zip(sponsored_chains)
+        {
+            Self::record_sponsorship(&mut foreign_chain, gas_tokens_to_sponsor_transaction);
+            self.foreign_chains
+                .insert(&transaction_request.chain_id, &foreign_chain);
+            self.record_account_spending(
+                sender,
+                transaction_request.chain_id,
+                gas_tokens_to_sponsor_transaction,
+            );
+        }
+
+        // After this point, the function should be virtually infallible, excluding out-of-gas errors.
+
+        let pending_transaction_sequence = PendingTransactionSequence {
+            signature_requests,
+            created_by_account_id: sender.clone(),
+            created_at_block_height: env::block_height().into(),
+            escrow: Some(AssetBalance {
+                amount: total_fee.into(),
+                asset_id: deposit.asset_id.clone(),
+            }),
+            tag,
+            paymaster_token_id: None,
+            settled_escrow: None,
+            priority: 0,
+            tip: None,
+            callback,
+            funding_shares,
+            paymaster_reservations,
+            signing_order: SigningOrder::PaymasterFirst,
+            is_funding_released: false,
+            signer_deposit: signer_deposit.into(),
+            tenant_id: None,
+            recurring_authorization_id: None,
+            is_cancellation: false,
+            replaced_sequence_id: None,
+            session_grantee_id: None,
+        };
+
+        let creation = self.insert_transaction_sequence(
+            transaction_requests[0].chain_id,
+            pending_transaction_sequence,
+        );
+
+        Ok((refund, creation))
+    }
+}
@@ -2,7 +2,7 @@ use lib::asset::{AssetBalance, AssetId};
 use near_sdk::{env, json_types::U128, near_bindgen, AccountId, PromiseOrValue};
 use near_sdk_contract_tools::ft::Nep141Receiver;
 
-use crate::{Contract, ContractExt, Nep141ReceiverCreateTransactionArgs};
+use crate::{Contract, ContractExt, CreateTransactionOptions, Nep141ReceiverCreateTransactionArgs};
 
 #[near_bindgen]
 impl Nep141Receiver for Contract {
@@ -25,28 +25,36 @@ impl Nep141Receiver for Contract {
             return PromiseOrValue::Value(0.into());
         }
 
-        let Ok(Nep141ReceiverCreateTransactionArgs {
-            token_id,
-            transaction_rlp_hex,
-            use_paymaster,
-        }) = near_sdk::serde_json::from_str(&msg)
+        let Ok(args) = near_sdk::serde_json::from_str::<Nep141ReceiverCreateTransactionArgs>(&msg)
         else {
             return PromiseOrValue::Value(0.into());
         };
 
+        let Some((fee_deposit, tip, unused)) = args.split_deposit(amount.0) else {
+            return PromiseOrValue::Value(amount);
+        };
+
         let creation_promise_or_value = self.create_transaction_inner(
-            token_id,
+            args.token_id,
             sender_id,
-            transaction_rlp_hex,
-            use_paymaster,
-            AssetBalance { asset_id, amount },
+            args.transaction_rlp_hex,
+            args.use_paymaster,
+            AssetBalance {
+                asset_id,
+                amount: fee_deposit.into(),
+            },
+            (tip > 0).then_some(U128(tip)),
+            CreateTransactionOptions {
+                callback: args.callback,
+                ..Default::default()
+            },
         );
 
         match creation_promise_or_value {
             PromiseOrValue::Promise(p) => p
-                .then(Self::ext(env::current_account_id()).return_zero())
+                .then(Self::ext(env::current_account_id()).return_unused(U128(unused)))
                 .into(),
-            PromiseOrValue::Value(_v) => PromiseOrValue::Value(U128(0)),
+            PromiseOrValue::Value(_v) => PromiseOrValue::Value(U128(unused)),
         }
     }
 }
@@ -54,7 +62,7 @@ impl Nep141Receiver for Contract {
 #[near_bindgen]
 impl Contract {
     #[private]
-    pub fn return_zero(&self) -> U128 {
-        U128(0)
+    pub fn return_unused(&self, unused: U128) -> U128 {
+        unused
     }
 }
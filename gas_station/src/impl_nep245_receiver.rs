@@ -0,0 +1,84 @@
+use lib::{
+    asset::{AssetBalance, AssetId},
+    nep245::Nep245Receiver,
+};
+use near_sdk::{env, json_types::U128, near_bindgen, AccountId, PromiseOrValue};
+
+use crate::{Contract, ContractExt, CreateTransactionOptions, Nep141ReceiverCreateTransactionArgs};
+
+#[near_bindgen]
+impl Nep245Receiver for Contract {
+    fn mt_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_ids: Vec<AccountId>,
+        token_ids: Vec<String>,
+        amounts: Vec<U128>,
+        msg: String,
+    ) -> PromiseOrValue<Vec<U128>> {
+        let _ = previous_owner_ids;
+
+        self.require_unpaused_or_administrator(&sender_id);
+
+        // Only single-token transfers can pay for a transaction.
+        if token_ids.len() != 1 || amounts.len() != 1 {
+            return PromiseOrValue::Value(amounts);
+        }
+
+        let amount = amounts[0];
+
+        let asset_id = AssetId::Nep245 {
+            contract_id: env::predecessor_account_id(),
+            token_id: token_ids[0].clone(),
+        };
+
+        let asset_is_supported = self.accepted_local_assets.get(&asset_id).is_some();
+
+        if !asset_is_supported {
+            // Unknown assets: refund.
+            return PromiseOrValue::Value(amounts);
+        }
+
+        let Ok(args) = near_sdk::serde_json::from_str::<Nep141ReceiverCreateTransactionArgs>(&msg)
+        else {
+            return PromiseOrValue::Value(amounts);
+        };
+
+        let Some((fee_deposit, tip, unused)) = args.split_deposit(amount.0) else {
+            return PromiseOrValue::Value(amounts);
+        };
+
+        let creation_promise_or_value = self.create_transaction_inner(
+            args.token_id,
+            sender_id,
+            args.transaction_rlp_hex,
+            args.use_paymaster,
+            AssetBalance {
+                asset_id,
+                amount: fee_deposit.into(),
+            },
+            (tip > 0).then_some(U128(tip)),
+            CreateTransactionOptions {
+                callback: args.callback,
+                ..Default::default()
+            },
+        );
+
+        match creation_promise_or_value {
+            PromiseOrValue::Promise(p) => p
+                .then(
+                    Self::ext(env::current_account_id()).return_unused_amounts(vec![U128(unused)]),
+                )
+                .into(),
+            PromiseOrValue::Value(_v) => PromiseOrValue::Value(vec![U128(unused)]),
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    #[private]
+    pub fn return_unused_amounts(&self, unused: Vec<U128>) -> Vec<U128> {
+        unused
+    }
+}
@@ -0,0 +1,325 @@
+use ethers_core::types::U256;
+use lib::{
+    asset::{AssetBalance, AssetId},
+    Rejectable,
+};
+use near_sdk::{
+    env,
+    json_types::{U128, U64},
+    near, near_bindgen, require, AccountId, Promise,
+};
+use near_sdk_contract_tools::{pause::Pause, rbac::Rbac, standard::nep297::Event};
+
+use crate::{
+    chain_configuration::PaymasterConfiguration,
+    contract_event::{
+        ContractEvent, PaymasterBondWithdrawn, PaymasterBonded, PaymasterSlashed,
+        PaymasterUnbonding,
+    },
+    Contract, ContractExt, Role,
+};
+
+/// Terms under which third parties may register their own paymasters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct PaymasterBondSettings {
+    /// Minimum bond, in yoctoNEAR.
+    pub minimum_bond: U128,
+    /// Fraction of the fees of sequences funded by a bonded paymaster that is
+    /// credited to the paymaster's owner. Fixed at registration time.
+    pub fee_share: (U128, U128),
+    /// Blocks after unregistering during which the bond can still be
+    /// slashed. Should exceed the sequence expiry so that sequences funded by
+    /// the paymaster are settled before the bond is released.
+    pub unbonding_period_blocks: U64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct BondedPaymaster {
+    pub owner_id: AccountId,
+    pub chain_id: U64,
+    /// Remaining bond, in yoctoNEAR.
+    pub bond: U128,
+    pub fee_share: (U128, U128),
+    /// Set once the owner unregisters the paymaster.
+    pub unbonding_since_block_height: Option<U64>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// `None` disables registration of new bonded paymasters. Existing
+    /// bonded paymasters are unaffected.
+    pub fn set_paymaster_bond_settings(&mut self, settings: Option<PaymasterBondSettings>) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_paymaster_bond_settings");
+
+        if let Some(ref settings) = settings {
+            require!(
+                settings.fee_share.1 .0 > 0 && settings.fee_share.0 .0 <= settings.fee_share.1 .0,
+                "Fee share must be a fraction between 0 and 1",
+            );
+        }
+
+        self.paymaster_bond_settings = settings;
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn get_paymaster_bond_settings(&self) -> Option<PaymasterBondSettings> {
+        self.paymaster_bond_settings.clone()
+    }
+
+    pub fn get_paymaster_providers(&self) -> Vec<AccountId> {
+        <Self as Rbac>::iter_members_of(&Role::PaymasterProvider).collect()
+    }
+
+    pub fn get_bonded_paymaster(&self, token_id: String) -> Option<BondedPaymaster> {
+        self.bonded_paymasters.get(&token_id)
+    }
+
+    /// Registers one of the predecessor's chain keys as a paymaster for a
+    /// foreign chain. The key must already be registered with this contract
+    /// as a user chain key (via `ckt_approve_call`), and stops being usable
+    /// as a user key. The attached deposit is held as a bond.
+    ///
+    /// `balance` is the amount of gas tokens the owner has made available to
+    /// the paymaster on the foreign chain. Misreporting it is grounds for
+    /// slashing.
+    #[payable]
+    pub fn register_paymaster(
+        &mut self,
+        chain_id: U64,
+        token_id: String,
+        nonce: u32,
+        balance: U128,
+    ) {
+        <Self as Pause>::require_unpaused();
+
+        let settings = self
+            .paymaster_bond_settings
+            .clone()
+            .expect_or_reject("Paymaster registration is disabled");
+
+        let bond = env::attached_deposit().as_yoctonear();
+        require!(
+            bond >= settings.minimum_bond.0,
+            format!(
+                "Bond must be at least {} yoctoNEAR",
+                settings.minimum_bond.0
+            ),
+        );
+
+        require!(
+            self.bonded_paymasters.get(&token_id).is_none(),
+            "Paymaster is already registered",
+        );
+
+        let owner_id = env::predecessor_account_id();
+
+        let key_data = self
+            .user_chain_keys
+            .get(&owner_id)
+            .and_then(|keys| keys.get(&token_id))
+            .expect_or_reject("Predecessor unauthorized for the requested chain key token ID");
+
+        require!(
+            key_data.has_public_key(),
+            "Chain key registration is incomplete; call `refresh_user_chain_key` first",
+        );
+
+        self.with_mut_chain(chain_id.0, |chain_config| {
+            require!(
+                chain_config.paymasters.get(&token_id).is_none(),
+                "Paymaster is already registered",
+            );
+
+            chain_config.paymasters.insert(
+                &token_id,
+                &PaymasterConfiguration {
+                    nonce,
+                    token_id: token_id.clone(),
+                    minimum_available_balance: U256::from(balance.0).0,
+                },
+            );
+        });
+
+        self.remove_user_chain_key_registration(&token_id);
+        self.paymaster_keys.insert(&token_id, &key_data);
+
+        self.bonded_paymasters.insert(
+            &token_id,
+            &BondedPaymaster {
+                owner_id: owner_id.clone(),
+                chain_id,
+                bond: bond.into(),
+                fee_share: settings.fee_share,
+                unbonding_since_block_height: None,
+            },
+        );
+
+        self.add_role(&owner_id, &Role::PaymasterProvider);
+
+        ContractEvent::PaymasterBonded(PaymasterBonded {
+            token_id,
+            owner_id,
+            chain_id,
+            bond: bond.into(),
+        })
+        .emit();
+    }
+
+    #[payable]
+    pub fn increase_paymaster_bond(&mut self, token_id: String) {
+        let mut bonded = self.require_bonded_paymaster_owner(&token_id);
+
+        require!(
+            bonded.unbonding_since_block_height.is_none(),
+            "Paymaster is unbonding",
+        );
+
+        bonded.bond.0 = bonded
+            .bond
+            .0
+            .checked_add(env::attached_deposit().as_yoctonear())
+            .unwrap_or_reject();
+
+        self.bonded_paymasters.insert(&token_id, &bonded);
+    }
+
+    /// Removes the paymaster from the rotation and starts the unbonding
+    /// period. Sequences it has already funded can still be signed.
+    pub fn unregister_paymaster(&mut self, token_id: String) {
+        let mut bonded = self.require_bonded_paymaster_owner(&token_id);
+
+        require!(
+            bonded.unbonding_since_block_height.is_none(),
+            "Paymaster is already unbonding",
+        );
+
+        self.remove_paymaster_from_rotation(bonded.chain_id.0, &token_id);
+
+        let block_height = env::block_height();
+        bonded.unbonding_since_block_height = Some(block_height.into());
+        self.bonded_paymasters.insert(&token_id, &bonded);
+
+        ContractEvent::PaymasterUnbonding(PaymasterUnbonding {
+            token_id,
+            owner_id: bonded.owner_id,
+            withdrawable_at_block_height: block_height
+                .saturating_add(self.unbonding_period_blocks())
+                .into(),
+        })
+        .emit();
+    }
+
+    /// Returns the remaining bond to the owner after the unbonding period.
+    /// The paymaster key is forgotten; approve it again to use it as a user
+    /// chain key.
+    pub fn withdraw_paymaster_bond(&mut self, token_id: String) -> Promise {
+        let bonded = self.require_bonded_paymaster_owner(&token_id);
+
+        let unbonding_since = bonded
+            .unbonding_since_block_height
+            .expect_or_reject("Paymaster must be unregistered first");
+
+        require!(
+            env::block_height()
+                >= unbonding_since
+                    .0
+                    .saturating_add(self.unbonding_period_blocks()),
+            "Unbonding period has not passed",
+        );
+
+        self.bonded_paymasters.remove(&token_id);
+        self.paymaster_keys.remove(&token_id);
+
+        ContractEvent::PaymasterBondWithdrawn(PaymasterBondWithdrawn {
+            token_id,
+            owner_id: bonded.owner_id.clone(),
+            amount: bonded.bond,
+        })
+        .emit();
+
+        Self::refund(bonded.owner_id, AssetBalance::native(bonded.bond.0))
+    }
+
+    /// Slashes the bond of a misbehaving paymaster (e.g. misreported balance,
+    /// revoked key) and removes it from the rotation. Slashed funds are added
+    /// to the collected fees. `amount` defaults to the entire bond.
+    pub fn slash_paymaster(&mut self, token_id: String, amount: Option<U128>, reason: String) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("slash_paymaster");
+
+        let mut bonded = self
+            .bonded_paymasters
+            .get(&token_id)
+            .expect_or_reject("Paymaster is not bonded");
+
+        let amount = amount.map_or(bonded.bond.0, |a| a.0);
+
+        bonded.bond.0 = bonded
+            .bond
+            .0
+            .checked_sub(amount)
+            .expect_or_reject("Slash amount exceeds bond");
+
+        self.remove_paymaster_from_rotation(bonded.chain_id.0, &token_id);
+        self.bonded_paymasters.insert(&token_id, &bonded);
+        self.add_collected_fees(&AssetId::Native, amount);
+
+        ContractEvent::PaymasterSlashed(PaymasterSlashed {
+            token_id,
+            owner_id: bonded.owner_id,
+            amount: amount.into(),
+            remaining_bond: bonded.bond,
+            reason,
+        })
+        .emit();
+    }
+
+    /// Withdraws the fee share credited to a bonded paymaster to its owner.
+    #[payable]
+    pub fn withdraw_paymaster_fees(
+        &mut self,
+        token_id: String,
+        asset_id: AssetId,
+        amount: Option<U128>,
+    ) -> Promise {
+        near_sdk::assert_one_yocto();
+        let bonded = self.require_bonded_paymaster_owner(&token_id);
+
+        self.transfer_paymaster_fees(token_id, asset_id, amount, bonded.owner_id)
+    }
+}
+
+impl Contract {
+    fn require_bonded_paymaster_owner(&self, token_id: &String) -> BondedPaymaster {
+        let bonded = self
+            .bonded_paymasters
+            .get(token_id)
+            .expect_or_reject("Paymaster is not bonded");
+
+        require!(
+            bonded.owner_id == env::predecessor_account_id(),
+            "Predecessor is not the owner of this paymaster",
+        );
+
+        bonded
+    }
+
+    fn unbonding_period_blocks(&self) -> u64 {
+        self.paymaster_bond_settings
+            .as_ref()
+            .map_or(0, |s| s.unbonding_period_blocks.0)
+            .max(self.expire_sequence_after_blocks)
+    }
+
+    fn remove_paymaster_from_rotation(&mut self, chain_id: u64, token_id: &String) {
+        if let Some(mut chain_config) = self.foreign_chains.get(&chain_id) {
+            if chain_config.paymasters.remove(token_id).is_some() {
+                self.foreign_chains.insert(&chain_id, &chain_config);
+            }
+        }
+    }
+}
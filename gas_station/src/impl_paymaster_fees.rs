@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+
+use ethers_core::types::U256;
+use lib::{
+    asset::{AssetBalance, AssetId},
+    Rejectable,
+};
+use near_sdk::{
+    collections::UnorderedMap, env, json_types::U128, near_bindgen, AccountId, Gas, Promise,
+    PromiseResult,
+};
+
+use crate::{transfer_options, Contract, ContractExt, PendingTransactionSequence, StorageKey};
+
+#[near_bindgen]
+impl Contract {
+    /// Fees credited to a paymaster, i.e. earned by sequences it funded and
+    /// not yet withdrawn. For bonded paymasters, this is the owner's share.
+    pub fn get_paymaster_fees(&self, token_id: String) -> HashMap<AssetId, U128> {
+        self.paymaster_fees
+            .get(&token_id)
+            .map(|fees| fees.iter().collect())
+            .unwrap_or_default()
+    }
+
+    const WITHDRAW_PAYMASTER_FEES_CALLBACK_GAS: Gas = Gas::from_tgas(5);
+
+    /// Re-credits the paymaster fees if the withdrawal transfer failed.
+    #[private]
+    pub fn withdraw_paymaster_fees_callback(
+        &mut self,
+        token_id: String,
+        asset_id: AssetId,
+        amount: U128,
+    ) -> bool {
+        let success = matches!(env::promise_result(0), PromiseResult::Successful(_));
+
+        if !success {
+            self.add_paymaster_fees(token_id, &asset_id, amount.0);
+        }
+
+        success
+    }
+}
+
+impl Contract {
+    pub(crate) fn add_collected_fees(&mut self, asset_id: &AssetId, amount: u128) {
+        let mut collected_fees = self.collected_fees.get(asset_id).unwrap_or(U128(0));
+        // This should not fail, but if it does fail, that means the token
+        // in question incorrectly implements the NEP-141 standard, which
+        // dictates that the total supply fits in 128 bits.
+        collected_fees.0 = collected_fees.0.checked_add(amount).unwrap_or_reject();
+        self.collected_fees.insert(asset_id, &collected_fees);
+    }
+
+    fn add_paymaster_fees(&mut self, token_id: String, asset_id: &AssetId, amount: u128) {
+        let mut fees = self
+            .paymaster_fees
+            .get(&token_id)
+            .unwrap_or_else(|| UnorderedMap::new(StorageKey::PaymasterFeesFor(token_id.clone())));
+        let mut balance = fees.get(asset_id).unwrap_or(U128(0));
+        balance.0 = balance.0.checked_add(amount).unwrap_or_reject();
+        fees.insert(asset_id, &balance);
+        self.paymaster_fees.insert(&token_id, &fees);
+    }
+
+    /// Debits `amount` (default: everything) of the paymaster's fees and
+    /// transfers it to `receiver_id`, re-crediting it if the transfer fails.
+    pub(crate) fn transfer_paymaster_fees(
+        &mut self,
+        token_id: String,
+        asset_id: AssetId,
+        amount: Option<U128>,
+        receiver_id: AccountId,
+    ) -> Promise {
+        let mut fees = self
+            .paymaster_fees
+            .get(&token_id)
+            .expect_or_reject("No fees credited to paymaster");
+        let mut balance = fees
+            .get(&asset_id)
+            .expect_or_reject("No fee entry for provided asset ID");
+
+        let amount = amount.unwrap_or(balance);
+
+        balance.0 = balance
+            .0
+            .checked_sub(amount.0)
+            .expect_or_reject("Not enough fees to withdraw");
+        fees.insert(&asset_id, &balance);
+        self.paymaster_fees.insert(&token_id, &fees);
+
+        asset_id
+            .transfer_with_options(
+                receiver_id,
+                amount,
+                &transfer_options("Paymaster fee withdrawal"),
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(Self::WITHDRAW_PAYMASTER_FEES_CALLBACK_GAS)
+                    .with_unused_gas_weight(0)
+                    .withdraw_paymaster_fees_callback(token_id, asset_id, amount),
+            )
+    }
+
+    /// Credits the escrowed fee of a sequence once it has been signed.
+    ///
+    /// Fees of sequences funded by a paymaster are credited to that
+    /// paymaster. For bonded paymasters only the owner's share is; the rest
+    /// goes to the collected fees, as do fees of sequences without a
+    /// paymaster.
+    pub(crate) fn settle_escrow(
+        &mut self,
+        escrow: AssetBalance,
+        paymaster_token_id: Option<String>,
+    ) {
+        let Some(token_id) = paymaster_token_id else {
+            self.add_collected_fees(&escrow.asset_id, escrow.amount.0);
+            return;
+        };
+
+        let paymaster_share = self.paymaster_share(&token_id, escrow.amount.0);
+
+        if paymaster_share > 0 {
+            self.add_paymaster_fees(token_id, &escrow.asset_id, paymaster_share);
+        }
+
+        let remainder = escrow.amount.0 - paymaster_share;
+        if remainder > 0 {
+            self.add_collected_fees(&escrow.asset_id, remainder);
+        }
+    }
+
+    /// Takes `refund` back from the fees that the settled escrow of
+    /// `sequence` was credited to, in proportion to the share of each
+    /// paymaster that funded it.
+    pub(crate) fn unsettle_escrow(
+        &mut self,
+        sequence: &PendingTransactionSequence,
+        refund: &AssetBalance,
+    ) -> Result<(), &'static str> {
+        let fee = sequence
+            .settled_escrow
+            .as_ref()
+            .ok_or("Sequence did not pay a fee")?;
+        if refund.amount.0 > fee.amount.0 {
+            return Err("Refund cannot exceed the fee");
+        }
+
+        let shares = if sequence.funding_shares.is_empty() {
+            vec![(sequence.paymaster_token_id.clone(), fee.amount.0)]
+        } else {
+            sequence
+                .funding_shares
+                .iter()
+                .map(|(token_id, amount)| (Some(token_id.clone()), amount.0))
+                .collect()
+        };
+
+        let mut remaining_refund = refund.amount.0;
+        for (i, (token_id, share)) in shares.iter().enumerate() {
+            // The last share takes the rounding remainder.
+            let share_refund = if i + 1 == shares.len() {
+                remaining_refund
+            } else {
+                // share <= fee, so the result never exceeds the refund.
+                (U256::from(refund.amount.0) * U256::from(*share) / U256::from(fee.amount.0))
+                    .as_u128()
+            };
+            remaining_refund -= share_refund;
+
+            let paymaster_share = token_id
+                .as_ref()
+                .map_or(0, |token_id| self.paymaster_share(token_id, share_refund));
+
+            if let Some(token_id) = token_id.as_ref().filter(|_| paymaster_share > 0) {
+                self.debit_paymaster_fees(token_id, &refund.asset_id, paymaster_share)?;
+            }
+            if share_refund > paymaster_share {
+                self.debit_collected_fees(&refund.asset_id, share_refund - paymaster_share)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The part of `amount` credited to the paymaster rather than to the
+    /// collected fees: all of it, or the owner's share for bonded paymasters.
+    fn paymaster_share(&self, token_id: &String, amount: u128) -> u128 {
+        self.bonded_paymasters
+            .get(token_id)
+            .map_or(amount, |bonded| {
+                // fee_share.0 <= fee_share.1, so the share never exceeds the amount.
+                (U256::from(amount) * U256::from(bonded.fee_share.0 .0)
+                    / U256::from(bonded.fee_share.1 .0))
+                .as_u128()
+            })
+    }
+
+    fn debit_paymaster_fees(
+        &mut self,
+        token_id: &String,
+        asset_id: &AssetId,
+        amount: u128,
+    ) -> Result<(), &'static str> {
+        let mut fees = self
+            .paymaster_fees
+            .get(token_id)
+            .ok_or("Not enough paymaster fees to refund")?;
+        let mut balance = fees.get(asset_id).unwrap_or(U128(0));
+        balance.0 = balance
+            .0
+            .checked_sub(amount)
+            .ok_or("Not enough paymaster fees to refund")?;
+        fees.insert(asset_id, &balance);
+        self.paymaster_fees.insert(token_id, &fees);
+
+        Ok(())
+    }
+
+    fn debit_collected_fees(
+        &mut self,
+        asset_id: &AssetId,
+        amount: u128,
+    ) -> Result<(), &'static str> {
+        let mut collected = self.collected_fees.get(asset_id).unwrap_or(U128(0));
+        collected.0 = collected
+            .0
+            .checked_sub(amount)
+            .ok_or("Not enough collected fees to refund")?;
+        self.collected_fees.insert(asset_id, &collected);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use ethers_core::types::U256;
+use near_sdk::{
+    env,
+    json_types::{U128, U64},
+    near, near_bindgen, require,
+};
+use near_sdk_contract_tools::rbac::Rbac;
+
+use crate::{
+    error::{ExpressionOverflowError, TryCreateTransactionCallbackError, UnknownPriorityTierError},
+    Contract, ContractExt, Role,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct SigningQueueEntry {
+    pub id: U64,
+    pub priority: u8,
+    pub created_at_block_height: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Creates, updates, or (with `None`) removes a priority tier. Fees of
+    /// sponsored sequences created with the tier are multiplied by
+    /// `fee_multiplier`, which must be at least 1. Tier 0 is the default and
+    /// cannot be configured.
+    pub fn set_priority_tier(&mut self, priority: u8, fee_multiplier: Option<(U128, U128)>) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_priority_tier");
+
+        require!(priority > 0, "Priority tier 0 cannot be configured");
+
+        if let Some(fee_multiplier) = fee_multiplier {
+            require!(
+                fee_multiplier.1 .0 > 0 && fee_multiplier.0 .0 >= fee_multiplier.1 .0,
+                "Fee multiplier must be at least 1",
+            );
+            self.priority_tiers.insert(&priority, &fee_multiplier);
+        } else {
+            self.priority_tiers.remove(&priority);
+        }
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn get_priority_tiers(&self) -> HashMap<u8, (U128, U128)> {
+        self.priority_tiers.iter().collect()
+    }
+
+    /// Unexpired sequences with signature requests left to sign, in signing
+    /// order: highest priority first, then oldest first. When the
+    /// `is_ordered_signing_enabled` flag is set, `sign_next` refuses to sign
+    /// a sequence while a sequence of a higher priority is queued.
+    pub fn get_signing_queue(
+        &self,
+        offset: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<SigningQueueEntry> {
+        let block_height = env::block_height();
+
+        self.signing_queue
+            .iter()
+            .filter(|(_, created_at)| !self.is_expired(*created_at, block_height))
+            .skip(offset.map_or(0, |o| o as usize))
+            .take(limit.map_or(usize::MAX, |l| l as usize))
+            .map(|((inverse_priority, id), created_at)| SigningQueueEntry {
+                id: id.into(),
+                priority: u8::MAX - inverse_priority,
+                created_at_block_height: created_at.into(),
+            })
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Applies the fee multiplier of the priority tier to `fee`.
+    pub(crate) fn apply_priority_fee(
+        &self,
+        priority: u8,
+        fee: u128,
+    ) -> Result<u128, TryCreateTransactionCallbackError> {
+        if priority == 0 {
+            return Ok(fee);
+        }
+
+        let (numerator, denominator) = self
+            .priority_tiers
+            .get(&priority)
+            .ok_or(UnknownPriorityTierError { priority })?;
+
+        let fee = U256::from(fee)
+            .checked_mul(U256::from(numerator.0))
+            .ok_or(ExpressionOverflowError)?
+            / U256::from(denominator.0);
+
+        u128::try_from(fee).map_err(|_| ExpressionOverflowError.into())
+    }
+
+    /// The signing queue is keyed by `(u8::MAX - priority, id)`, so that
+    /// iterating it yields the signing order.
+    pub(crate) fn enqueue_for_signing(&mut self, id: u64, priority: u8, created_at: u64) {
+        self.signing_queue
+            .insert(&(u8::MAX - priority, id), &created_at);
+    }
+
+    pub(crate) fn dequeue_from_signing(&mut self, id: u64, priority: u8) {
+        self.signing_queue.remove(&(u8::MAX - priority, id));
+    }
+
+    /// Ensures that no unexpired sequence of a higher priority than
+    /// `priority` is waiting to be signed. Expired entries encountered along
+    /// the way are dropped from the queue.
+    pub(crate) fn require_signing_order(&mut self, priority: u8) {
+        let block_height = env::block_height();
+
+        while let Some(key) = self.signing_queue.min() {
+            let created_at = self.signing_queue.get(&key).unwrap_or_default();
+
+            if !self.is_expired(created_at, block_height) {
+                require!(
+                    u8::MAX - key.0 <= priority,
+                    "Sequences of a higher priority must be signed first",
+                );
+                return;
+            }
+
+            self.signing_queue.remove(&key);
+        }
+    }
+
+    fn is_expired(&self, created_at: u64, block_height: u64) -> bool {
+        block_height.saturating_sub(created_at) > self.expire_sequence_after_blocks
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use near_sdk::{json_types::U64, near, near_bindgen, require, AccountId};
+use near_sdk_contract_tools::rbac::Rbac;
+
+use crate::{error::RateLimitExceededError, Contract, ContractExt, Role};
+
+/// At most `max_sequences` transaction sequences per `window_blocks` blocks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct RateLimit {
+    pub max_sequences: u32,
+    pub window_blocks: U64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[near]
+pub struct SequenceCreationWindow {
+    pub start_block_height: u64,
+    pub count: u32,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Limits how many transaction sequences each account can create. Applies
+    /// to accounts without a tier. `None` disables the global limit.
+    pub fn set_rate_limit(&mut self, rate_limit: Option<RateLimit>) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_rate_limit");
+
+        if let Some(ref rate_limit) = rate_limit {
+            require!(rate_limit.window_blocks.0 > 0, "Window must be nonzero");
+        }
+
+        self.rate_limit = rate_limit;
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn get_rate_limit(&self) -> Option<RateLimit> {
+        self.rate_limit.clone()
+    }
+
+    /// Creates, updates, or (with `None`) removes a named rate limit tier.
+    /// Accounts assigned to a removed tier fall back to the global limit.
+    pub fn set_rate_limit_tier(&mut self, tier: String, rate_limit: Option<RateLimit>) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_rate_limit_tier");
+
+        if let Some(rate_limit) = rate_limit {
+            require!(rate_limit.window_blocks.0 > 0, "Window must be nonzero");
+            self.rate_limit_tiers.insert(&tier, &rate_limit);
+        } else {
+            self.rate_limit_tiers.remove(&tier);
+        }
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn get_rate_limit_tiers(&self) -> HashMap<String, RateLimit> {
+        self.rate_limit_tiers.iter().collect()
+    }
+
+    /// Assigns an account to a rate limit tier, overriding the global limit.
+    /// `None` reverts the account to the global limit.
+    pub fn set_sender_rate_limit_tier(&mut self, account_id: AccountId, tier: Option<String>) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_sender_rate_limit_tier");
+
+        if let Some(tier) = tier {
+            require!(
+                self.rate_limit_tiers.get(&tier).is_some(),
+                "Rate limit tier does not exist",
+            );
+            self.sender_rate_limit_tiers.insert(&account_id, &tier);
+        } else {
+            self.sender_rate_limit_tiers.remove(&account_id);
+        }
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn get_sender_rate_limit_tier(&self, account_id: AccountId) -> Option<String> {
+        self.sender_rate_limit_tiers.get(&account_id)
+    }
+}
+
+impl Contract {
+    fn effective_rate_limit(&self, account_id: &AccountId) -> Option<RateLimit> {
+        self.sender_rate_limit_tiers
+            .get(account_id)
+            .and_then(|tier| self.rate_limit_tiers.get(&tier))
+            .or_else(|| self.rate_limit.clone())
+    }
+
+    /// Returns the account's creation window as of `block_height`, if the
+    /// account is rate limited.
+    fn current_creation_window(
+        &self,
+        account_id: &AccountId,
+        block_height: u64,
+    ) -> Option<(RateLimit, SequenceCreationWindow)> {
+        let rate_limit = self.effective_rate_limit(account_id)?;
+
+        let window = self
+            .sequence_creation_windows
+            .get(account_id)
+            .filter(|w| {
+                block_height
+                    < w.start_block_height
+                        .saturating_add(rate_limit.window_blocks.0)
+            })
+            .unwrap_or(SequenceCreationWindow {
+                start_block_height: block_height,
+                count: 0,
+            });
+
+        Some((rate_limit, window))
+    }
+
+    /// # Errors
+    ///
+    /// - If the account has reached its limit for the current window.
+    pub(crate) fn check_rate_limit(
+        &self,
+        account_id: &AccountId,
+        block_height: u64,
+    ) -> Result<(), RateLimitExceededError> {
+        match self.current_creation_window(account_id, block_height) {
+            Some((rate_limit, window)) if window.count >= rate_limit.max_sequences => {
+                Err(RateLimitExceededError {
+                    account_id: account_id.clone(),
+                    max_sequences: rate_limit.max_sequences,
+                    window_blocks: rate_limit.window_blocks.0,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts a sequence creation against the account's rate limit.
+    ///
+    /// # Errors
+    ///
+    /// - If the account has reached its limit for the current window.
+    pub(crate) fn consume_rate_limit(
+        &mut self,
+        account_id: &AccountId,
+        block_height: u64,
+    ) -> Result<(), RateLimitExceededError> {
+        self.check_rate_limit(account_id, block_height)?;
+
+        if let Some((_, mut window)) = self.current_creation_window(account_id, block_height) {
+            window.count += 1;
+            self.sequence_creation_windows.insert(account_id, &window);
+        }
+
+        Ok(())
+    }
+}
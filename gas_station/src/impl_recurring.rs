@@ -0,0 +1,454 @@
+use ethers_core::types::U256;
+use lib::{asset::AssetBalance, Rejectable};
+use near_sdk::{
+    env,
+    json_types::{U128, U64},
+    near, near_bindgen, require, AccountId, Gas, PromiseError, PromiseOrValue,
+};
+use near_sdk_contract_tools::{pause::Pause, standard::nep297::Event};
+
+use crate::{
+    contract_event::{
+        ContractEvent, RecurringAuthorizationCreated, RecurringAuthorizationEnded,
+        RecurringAuthorizationExecuted,
+    },
+    error::{
+        DrawRecurringAuthorizationError, InsufficientRecurringBalanceError,
+        RecurringAuthorizationCompletedError, RecurringAuthorizationNotDueError,
+    },
+    valid_transaction_request::{Eip1559JsonRequest, ValidTransactionRequest},
+    Contract, ContractExt, CreateTransactionOptions, TransactionSequenceCreation,
+};
+
+const RECURRING_SEQUENCE_CALLBACK_GAS: Gas = Gas::from_tgas(5);
+
+/// The transaction that a recurring authorization instantiates on each
+/// occurrence.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct RecurringTemplate {
+    /// The owner's chain key that signs the transaction.
+    pub token_id: String,
+    /// `nonce` is that of the first occurrence. If omitted, it is filled in
+    /// with the nonce following the last one this contract signed for the
+    /// chain key.
+    pub transaction: Eip1559JsonRequest,
+    pub use_paymaster: Option<bool>,
+    /// Drawn from the prepaid balance on each occurrence, as the deposit of
+    /// the sequence. Whatever the sequence does not use is refunded to the
+    /// owner, not to the authorization.
+    pub max_fee: U128,
+    pub tag: Option<String>,
+}
+
+/// Authorization for anyone (e.g. a market maker or a cron job) to create a
+/// sequence from a pre-approved transaction once every `period_blocks`, up
+/// to `max_occurrences` times, with fees drawn from a balance prepaid by the
+/// owner.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct RecurringAuthorization {
+    pub owner_id: AccountId,
+    pub token_id: String,
+    /// The transaction of the next occurrence. Its nonce is incremented after
+    /// each occurrence.
+    pub transaction: ValidTransactionRequest,
+    pub use_paymaster: Option<bool>,
+    pub max_fee: U128,
+    pub tag: Option<String>,
+    pub period_blocks: U64,
+    pub max_occurrences: u32,
+    pub occurrences: u32,
+    /// First block height at which the next occurrence may be created.
+    pub next_block_height: U64,
+    pub remaining_balance: U128,
+    /// Paid by the owner for storing the authorization, refunded when it is
+    /// removed.
+    pub storage_deposit: U128,
+}
+
+impl RecurringAuthorization {
+    pub const fn is_completed(&self) -> bool {
+        self.occurrences >= self.max_occurrences
+    }
+
+    /// Counts an occurrence against the authorization, takes `max_fee` from
+    /// its balance, and schedules the next occurrence. Missed periods are
+    /// skipped rather than caught up.
+    ///
+    /// # Errors
+    ///
+    /// - If the next occurrence is not due yet.
+    /// - If the authorization has already reached `max_occurrences`.
+    /// - If the remaining balance is less than `max_fee`.
+    pub fn draw(&mut self, block_height: u64) -> Result<(), DrawRecurringAuthorizationError> {
+        if block_height < self.next_block_height.0 {
+            return Err(RecurringAuthorizationNotDueError {
+                next_block_height: self.next_block_height.0,
+            }
+            .into());
+        }
+
+        if self.is_completed() {
+            return Err(RecurringAuthorizationCompletedError {
+                max_occurrences: self.max_occurrences,
+            }
+            .into());
+        }
+
+        self.remaining_balance.0 = self.remaining_balance.0.checked_sub(self.max_fee.0).ok_or(
+            InsufficientRecurringBalanceError {
+                balance: self.remaining_balance.0,
+                amount: self.max_fee.0,
+            },
+        )?;
+        self.occurrences += 1;
+
+        let missed_periods = (block_height - self.next_block_height.0) / self.period_blocks.0;
+        self.next_block_height.0 = self
+            .next_block_height
+            .0
+            .saturating_add((missed_periods + 1).saturating_mul(self.period_blocks.0));
+
+        Ok(())
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Pre-approves `template` to be instantiated into a sequence with the
+    /// predecessor's chain key once every `period_blocks`, up to
+    /// `max_occurrences` times, starting now. The attached deposit, less the
+    /// storage cost of the authorization, is the balance that fees are drawn
+    /// from.
+    #[payable]
+    pub fn create_recurring_authorization(
+        &mut self,
+        chain_id: U64,
+        template: RecurringTemplate,
+        period_blocks: U64,
+        max_occurrences: u32,
+    ) -> U64 {
+        <Self as Pause>::require_unpaused();
+
+        let owner_id = env::predecessor_account_id();
+
+        require!(
+            template.transaction.chain_id == chain_id,
+            "Template chain ID does not match",
+        );
+        self.get_chain(chain_id.0).unwrap_or_reject();
+        require!(period_blocks.0 > 0, "Period must be at least one block");
+        require!(
+            max_occurrences > 0,
+            "Authorization must allow at least one occurrence",
+        );
+        require!(
+            self.user_chain_keys
+                .get(&owner_id)
+                .is_some_and(|keys| keys.get(&template.token_id).is_some()),
+            "Predecessor unauthorized for the requested chain key token ID",
+        );
+
+        let nonce = template.transaction.nonce.map_or_else(
+            || {
+                self.next_foreign_nonce(&template.token_id, chain_id.0)
+                    .expect_or_reject("No nonce has been signed for this key yet; specify `nonce`")
+            },
+            u64::from,
+        );
+        let transaction = ValidTransactionRequest::from_json_request(template.transaction, nonce)
+            .unwrap_or_reject();
+
+        let id = self.next_recurring_authorization_id;
+        self.next_recurring_authorization_id += 1;
+
+        let mut authorization = RecurringAuthorization {
+            owner_id: owner_id.clone(),
+            token_id: template.token_id.clone(),
+            transaction,
+            use_paymaster: template.use_paymaster,
+            max_fee: template.max_fee,
+            tag: template.tag,
+            period_blocks,
+            max_occurrences,
+            occurrences: 0,
+            next_block_height: env::block_height().into(),
+            remaining_balance: U128(0),
+            storage_deposit: U128(0),
+        };
+
+        let storage_usage_start = env::storage_usage();
+        self.recurring_authorizations.insert(&id, &authorization);
+        let storage_cost = env::storage_byte_cost().as_yoctonear()
+            * u128::from(env::storage_usage().saturating_sub(storage_usage_start));
+
+        let balance = env::attached_deposit()
+            .as_yoctonear()
+            .checked_sub(storage_cost)
+            .expect_or_reject(format!(
+                "Deposit must cover the authorization storage cost of {storage_cost}"
+            ));
+
+        // Amounts are fixed-size, so updating them does not change the
+        // storage used.
+        authorization.remaining_balance = balance.into();
+        authorization.storage_deposit = storage_cost.into();
+        self.recurring_authorizations.insert(&id, &authorization);
+
+        ContractEvent::RecurringAuthorizationCreated(RecurringAuthorizationCreated {
+            authorization_id: id.into(),
+            owner_id,
+            token_id: template.token_id,
+            chain_id,
+            period_blocks,
+            max_occurrences,
+            balance: balance.into(),
+        })
+        .emit();
+
+        id.into()
+    }
+
+    pub fn get_recurring_authorization(
+        &self,
+        authorization_id: U64,
+    ) -> Option<RecurringAuthorization> {
+        self.recurring_authorizations.get(&authorization_id.0)
+    }
+
+    /// Adds the attached deposit to the prepaid balance of an authorization.
+    #[payable]
+    pub fn top_up_recurring_authorization(&mut self, authorization_id: U64) -> U128 {
+        let mut authorization = self.require_recurring_authorization_owner(authorization_id.0);
+
+        authorization.remaining_balance.0 = authorization
+            .remaining_balance
+            .0
+            .checked_add(env::attached_deposit().as_yoctonear())
+            .unwrap_or_reject();
+        self.recurring_authorizations
+            .insert(&authorization_id.0, &authorization);
+
+        authorization.remaining_balance
+    }
+
+    /// Ends an authorization before its last occurrence, refunding the
+    /// remaining balance and storage deposit to the owner.
+    pub fn cancel_recurring_authorization(&mut self, authorization_id: U64) {
+        let authorization = self.require_recurring_authorization_owner(authorization_id.0);
+
+        self.end_recurring_authorization(authorization_id.0, authorization, true);
+    }
+
+    /// Creates the sequence of the next occurrence of an authorization, as if
+    /// its owner had called `create_transaction_json` with a deposit of
+    /// `max_fee`. Callable by anyone once the occurrence is due, since the
+    /// transaction and schedule were approved by the owner. Anyone may sign
+    /// the created sequence.
+    ///
+    /// The authorization is removed after its last occurrence. If the sequence
+    /// cannot be created, the occurrence is not counted: its nonce and
+    /// schedule are restored, and `max_fee` is refunded to the owner.
+    pub fn execute_recurring_authorization(
+        &mut self,
+        authorization_id: U64,
+    ) -> PromiseOrValue<TransactionSequenceCreation> {
+        let mut authorization = self
+            .recurring_authorizations
+            .get(&authorization_id.0)
+            .expect_or_reject("Recurring authorization does not exist");
+
+        let nonce = self
+            .next_foreign_nonce(&authorization.token_id, authorization.transaction.chain_id)
+            .map_or(authorization.transaction.nonce(), |next_nonce| {
+                authorization
+                    .transaction
+                    .nonce()
+                    .max(U256::from(next_nonce))
+            });
+
+        let mut transaction = authorization.transaction.clone();
+        transaction.nonce = nonce.0;
+        let drawn = RecurringOccurrence {
+            nonce: nonce.0,
+            next_block_height: authorization.next_block_height,
+        };
+
+        authorization.draw(env::block_height()).unwrap_or_reject();
+        authorization.transaction.nonce = nonce
+            .checked_add(U256::one())
+            .expect_or_reject("Nonce overflow")
+            .0;
+
+        // Emitted only once the sequence is created, since a failed creation
+        // does not count the occurrence.
+        let executed = RecurringAuthorizationExecuted {
+            authorization_id,
+            executor_id: env::predecessor_account_id(),
+            amount: authorization.max_fee,
+            occurrences: authorization.occurrences,
+            remaining_balance: authorization.remaining_balance,
+            next_block_height: authorization.next_block_height,
+        };
+
+        // A completed authorization is kept until its last sequence is
+        // created, so that the occurrence can be restored if creation fails.
+        self.recurring_authorizations
+            .insert(&authorization_id.0, &authorization);
+
+        match self.create_validated_transaction(
+            authorization.token_id,
+            authorization.owner_id,
+            transaction,
+            authorization.use_paymaster,
+            AssetBalance::native(authorization.max_fee.0),
+            None,
+            CreateTransactionOptions {
+                tag: authorization.tag,
+                ..Default::default()
+            },
+        ) {
+            PromiseOrValue::Promise(promise) => promise
+                .then(
+                    Self::ext(env::current_account_id())
+                        .with_static_gas(RECURRING_SEQUENCE_CALLBACK_GAS)
+                        .with_unused_gas_weight(0)
+                        .recurring_sequence_callback(authorization_id, drawn, executed),
+                )
+                .into(),
+            PromiseOrValue::Value(creation) => {
+                self.complete_recurring_occurrence(&creation, executed);
+                PromiseOrValue::Value(creation)
+            }
+        }
+    }
+
+    #[private]
+    pub fn recurring_sequence_callback(
+        &mut self,
+        authorization_id: U64,
+        drawn: RecurringOccurrence,
+        executed: RecurringAuthorizationExecuted,
+        #[callback_result] result: Result<TransactionSequenceCreation, PromiseError>,
+    ) -> PromiseOrValue<TransactionSequenceCreation> {
+        let Ok(creation) = result else {
+            // The deposit was refunded to the owner by the failed creation, so
+            // only the nonce and schedule are restored. Throwing from a new
+            // promise keeps the restored state.
+            self.restore_recurring_occurrence(authorization_id.0, drawn);
+            return Self::ext(env::current_account_id())
+                .throw(
+                    "Failed to create transaction sequence for recurring authorization".to_string(),
+                )
+                .into();
+        };
+
+        self.complete_recurring_occurrence(&creation, executed);
+
+        PromiseOrValue::Value(creation)
+    }
+}
+
+/// The state of an authorization before an occurrence was drawn from it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct RecurringOccurrence {
+    pub nonce: [u64; 4],
+    pub next_block_height: U64,
+}
+
+impl Contract {
+    /// Marks the created sequence, emits the occurrence, and removes the
+    /// authorization if this was its last occurrence.
+    fn complete_recurring_occurrence(
+        &mut self,
+        creation: &TransactionSequenceCreation,
+        executed: RecurringAuthorizationExecuted,
+    ) {
+        let authorization_id = executed.authorization_id.0;
+        self.mark_recurring_sequence(creation, executed.authorization_id);
+        ContractEvent::RecurringAuthorizationExecuted(executed).emit();
+
+        if let Some(authorization) = self
+            .recurring_authorizations
+            .get(&authorization_id)
+            .filter(RecurringAuthorization::is_completed)
+        {
+            self.end_recurring_authorization(authorization_id, authorization, false);
+        }
+    }
+
+    /// Undoes [`RecurringAuthorization::draw`] for an occurrence whose
+    /// sequence was not created, except for the balance.
+    fn restore_recurring_occurrence(&mut self, authorization_id: u64, drawn: RecurringOccurrence) {
+        // The owner may have cancelled the authorization in the meantime.
+        let Some(mut authorization) = self.recurring_authorizations.get(&authorization_id) else {
+            return;
+        };
+
+        authorization.occurrences = authorization.occurrences.saturating_sub(1);
+        authorization.next_block_height = drawn.next_block_height;
+        authorization.transaction.nonce = drawn.nonce;
+        self.recurring_authorizations
+            .insert(&authorization_id, &authorization);
+    }
+
+    fn require_recurring_authorization_owner(
+        &self,
+        authorization_id: u64,
+    ) -> RecurringAuthorization {
+        let authorization = self
+            .recurring_authorizations
+            .get(&authorization_id)
+            .expect_or_reject("Recurring authorization does not exist");
+
+        require!(
+            authorization.owner_id == env::predecessor_account_id(),
+            "Predecessor must be the authorization owner",
+        );
+
+        authorization
+    }
+
+    /// Records on the pending sequence that it was instantiated from an
+    /// authorization, so that anyone may sign it.
+    fn mark_recurring_sequence(
+        &mut self,
+        creation: &TransactionSequenceCreation,
+        authorization_id: U64,
+    ) {
+        if let Some(mut sequence) = self.pending_transaction_sequences.get(&creation.id.0) {
+            sequence.recurring_authorization_id = Some(authorization_id);
+            self.pending_transaction_sequences
+                .insert(&creation.id.0, &sequence);
+        }
+    }
+
+    fn end_recurring_authorization(
+        &mut self,
+        id: u64,
+        authorization: RecurringAuthorization,
+        is_cancelled: bool,
+    ) {
+        self.recurring_authorizations.remove(&id);
+
+        let refund = authorization
+            .remaining_balance
+            .0
+            .checked_add(authorization.storage_deposit.0)
+            .unwrap_or_reject();
+
+        if refund > 0 {
+            Self::refund(authorization.owner_id.clone(), AssetBalance::native(refund));
+        }
+
+        ContractEvent::RecurringAuthorizationEnded(RecurringAuthorizationEnded {
+            authorization_id: id.into(),
+            owner_id: authorization.owner_id,
+            is_cancelled,
+            refund: refund.into(),
+        })
+        .emit();
+    }
+}
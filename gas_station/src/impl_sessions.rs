@@ -0,0 +1,373 @@
+use lib::{asset::AssetBalance, Rejectable};
+use near_sdk::{
+    env,
+    json_types::{U128, U64},
+    near, near_bindgen, require, AccountId, Gas, PromiseError, PromiseOrValue,
+};
+use near_sdk_contract_tools::{pause::Pause, standard::nep297::Event};
+
+use crate::{
+    contract_event::{ContractEvent, SessionEnded, SessionGranted, SessionUsed},
+    error::{
+        DrawSessionError, InsufficientSessionBudgetError, SessionExpiredError,
+        SessionSequenceLimitReachedError,
+    },
+    Contract, ContractExt, CreateTransactionOptions, PendingTransactionSequence,
+    TransactionSequenceCreation,
+};
+
+const SESSION_SEQUENCE_CALLBACK_GAS: Gas = Gas::from_tgas(5);
+
+/// Authorization for `grantee_id` (e.g. a dApp key) to create sequences with
+/// the owner's chain key `token_id` until `expires_at_block_height`. Fees are
+/// drawn from a budget deposited by the owner.
+///
+/// Until it expires, the grantee may also sign the sequences it created,
+/// even once `max_sequences` is reached.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct SessionGrant {
+    pub owner_id: AccountId,
+    pub grantee_id: AccountId,
+    pub token_id: String,
+    pub max_sequences: u32,
+    pub sequences_created: u32,
+    pub remaining_budget: U128,
+    /// Paid by the owner for storing the grant, refunded when it is removed.
+    pub storage_deposit: U128,
+    pub expires_at_block_height: U64,
+}
+
+impl SessionGrant {
+    pub const fn is_expired(&self, block_height: u64) -> bool {
+        block_height >= self.expires_at_block_height.0
+    }
+
+    /// Counts a new sequence against the session and takes `amount` from its
+    /// budget.
+    ///
+    /// # Errors
+    ///
+    /// - If the session has expired.
+    /// - If the session has already created `max_sequences` sequences.
+    /// - If the remaining budget is less than `amount`.
+    pub fn draw(&mut self, amount: u128, block_height: u64) -> Result<(), DrawSessionError> {
+        if self.is_expired(block_height) {
+            return Err(SessionExpiredError {
+                expires_at_block_height: self.expires_at_block_height.0,
+            }
+            .into());
+        }
+
+        if self.sequences_created >= self.max_sequences {
+            return Err(SessionSequenceLimitReachedError {
+                max_sequences: self.max_sequences,
+            }
+            .into());
+        }
+
+        self.remaining_budget.0 =
+            self.remaining_budget
+                .0
+                .checked_sub(amount)
+                .ok_or(InsufficientSessionBudgetError {
+                    budget: self.remaining_budget.0,
+                    amount,
+                })?;
+        self.sequences_created += 1;
+
+        Ok(())
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Authorizes `grantee_id` to create up to `max_sequences` sequences with
+    /// the predecessor's chain key `token_id` until `expires_at_block_height`.
+    /// The attached deposit, less the storage cost of the grant, is the
+    /// budget that fees are paid from.
+    ///
+    /// An account has at most one session per grantee; an expired session
+    /// for the same grantee is ended and refunded first.
+    #[payable]
+    pub fn grant_session(
+        &mut self,
+        grantee_id: AccountId,
+        token_id: String,
+        max_sequences: u32,
+        expires_at_block_height: U64,
+    ) -> U64 {
+        <Self as Pause>::require_unpaused();
+
+        let owner_id = env::predecessor_account_id();
+        let block_height = env::block_height();
+
+        require!(grantee_id != owner_id, "Cannot grant a session to oneself");
+        require!(
+            max_sequences > 0,
+            "Session must allow at least one sequence"
+        );
+        require!(
+            expires_at_block_height.0 > block_height,
+            "Session expiry must be in the future",
+        );
+        require!(
+            self.user_chain_keys
+                .get(&owner_id)
+                .is_some_and(|keys| keys.get(&token_id).is_some()),
+            "Predecessor unauthorized for the requested chain key token ID",
+        );
+
+        let key = (owner_id.clone(), grantee_id.clone());
+
+        if let Some(existing_id) = self.session_ids.get(&key) {
+            let existing = self.sessions.get(&existing_id).unwrap_or_reject();
+            require!(
+                existing.is_expired(block_height),
+                "A session is already active for this grantee",
+            );
+            self.end_session(existing_id, existing, false);
+        }
+
+        let id = self.next_session_id;
+        self.next_session_id += 1;
+
+        let mut session = SessionGrant {
+            owner_id: owner_id.clone(),
+            grantee_id: grantee_id.clone(),
+            token_id: token_id.clone(),
+            max_sequences,
+            sequences_created: 0,
+            remaining_budget: U128(0),
+            storage_deposit: U128(0),
+            expires_at_block_height,
+        };
+
+        let storage_usage_start = env::storage_usage();
+        self.sessions.insert(&id, &session);
+        self.session_ids.insert(&key, &id);
+        let storage_cost = env::storage_byte_cost().as_yoctonear()
+            * u128::from(env::storage_usage().saturating_sub(storage_usage_start));
+
+        let budget = env::attached_deposit()
+            .as_yoctonear()
+            .checked_sub(storage_cost)
+            .expect_or_reject(format!(
+                "Deposit must cover the session storage cost of {storage_cost}"
+            ));
+
+        // Amounts are fixed-size, so updating them does not change the
+        // storage used.
+        session.remaining_budget = budget.into();
+        session.storage_deposit = storage_cost.into();
+        self.sessions.insert(&id, &session);
+
+        ContractEvent::SessionGranted(SessionGranted {
+            session_id: id.into(),
+            owner_id,
+            grantee_id,
+            token_id,
+            max_sequences,
+            budget: budget.into(),
+            expires_at_block_height,
+        })
+        .emit();
+
+        id.into()
+    }
+
+    pub fn get_session(&self, session_id: U64) -> Option<SessionGrant> {
+        self.sessions.get(&session_id.0)
+    }
+
+    pub fn get_session_id(&self, owner_id: AccountId, grantee_id: AccountId) -> Option<U64> {
+        self.session_ids
+            .get(&(owner_id, grantee_id))
+            .map(Into::into)
+    }
+
+    /// Ends a session before its expiry, refunding the remaining budget and
+    /// storage deposit to the owner.
+    pub fn revoke_session(&mut self, session_id: U64) {
+        let session = self
+            .sessions
+            .get(&session_id.0)
+            .expect_or_reject("Session does not exist");
+
+        require!(
+            session.owner_id == env::predecessor_account_id(),
+            "Predecessor must be the session owner",
+        );
+
+        self.end_session(session_id.0, session, true);
+    }
+
+    /// Removes an expired session, refunding the remaining budget and
+    /// storage deposit to the owner. Callable by anyone.
+    pub fn clear_expired_session(&mut self, session_id: U64) {
+        let session = self
+            .sessions
+            .get(&session_id.0)
+            .expect_or_reject("Session does not exist");
+
+        require!(
+            session.is_expired(env::block_height()),
+            "Session has not expired",
+        );
+
+        self.end_session(session_id.0, session, false);
+    }
+
+    /// Creates a sequence with the session owner's chain key, as if the owner
+    /// had called `create_transaction` with a deposit of `max_fee`. The
+    /// predecessor must be the session grantee.
+    ///
+    /// `max_fee` is drawn from the session budget; whatever the sequence
+    /// does not use is refunded to the owner, not to the session.
+    /// Idempotency keys are not supported.
+    pub fn create_transaction_with_session(
+        &mut self,
+        session_id: U64,
+        transaction_rlp_hex: String,
+        use_paymaster: Option<bool>,
+        max_fee: U128,
+        options: Option<CreateTransactionOptions>,
+    ) -> PromiseOrValue<TransactionSequenceCreation> {
+        let options = options.unwrap_or_default();
+        require!(
+            options.idempotency_key.is_none(),
+            "Idempotency keys are not supported for session sequences",
+        );
+
+        let grantee_id = env::predecessor_account_id();
+
+        let mut session = self
+            .sessions
+            .get(&session_id.0)
+            .expect_or_reject("Session does not exist");
+
+        require!(
+            session.grantee_id == grantee_id,
+            "Predecessor must be the session grantee",
+        );
+
+        session
+            .draw(max_fee.0, env::block_height())
+            .unwrap_or_reject();
+        self.sessions.insert(&session_id.0, &session);
+
+        ContractEvent::SessionUsed(SessionUsed {
+            session_id,
+            grantee_id: grantee_id.clone(),
+            amount: max_fee,
+            sequences_created: session.sequences_created,
+            remaining_budget: session.remaining_budget,
+        })
+        .emit();
+
+        match self.create_transaction_inner(
+            session.token_id,
+            session.owner_id,
+            transaction_rlp_hex,
+            use_paymaster,
+            AssetBalance::native(max_fee.0),
+            None,
+            options,
+        ) {
+            PromiseOrValue::Promise(promise) => promise
+                .then(
+                    Self::ext(env::current_account_id())
+                        .with_static_gas(SESSION_SEQUENCE_CALLBACK_GAS)
+                        .with_unused_gas_weight(0)
+                        .session_sequence_callback(grantee_id),
+                )
+                .into(),
+            PromiseOrValue::Value(creation) => {
+                self.mark_session_sequence(&creation, grantee_id);
+                PromiseOrValue::Value(creation)
+            }
+        }
+    }
+
+    /// Records the grantee on the sequence it created, so that it may sign
+    /// it.
+    #[private]
+    pub fn session_sequence_callback(
+        &mut self,
+        grantee_id: AccountId,
+        #[callback_result] result: Result<TransactionSequenceCreation, PromiseError>,
+    ) -> TransactionSequenceCreation {
+        let creation = result
+            .ok()
+            .expect_or_reject("Failed to create transaction sequence");
+
+        self.mark_session_sequence(&creation, grantee_id);
+
+        creation
+    }
+}
+
+impl Contract {
+    /// Whether `account_id` is the grantee whose session created the
+    /// sequence, and still holds an unexpired session of the sequence creator
+    /// covering every user chain key in the sequence, and so may sign it on
+    /// the creator's behalf.
+    pub(crate) fn is_session_signer(
+        &self,
+        sequence: &PendingTransactionSequence,
+        account_id: &AccountId,
+    ) -> bool {
+        if sequence.session_grantee_id.as_ref() != Some(account_id) {
+            return false;
+        }
+
+        self.session_ids
+            .get(&(sequence.created_by_account_id.clone(), account_id.clone()))
+            .and_then(|id| self.sessions.get(&id))
+            .is_some_and(|session| {
+                !session.is_expired(env::block_height())
+                    && sequence
+                        .signature_requests
+                        .iter()
+                        .filter(|r| !r.is_paymaster)
+                        .all(|r| r.token_id == session.token_id)
+            })
+    }
+
+    fn mark_session_sequence(
+        &mut self,
+        creation: &TransactionSequenceCreation,
+        grantee_id: AccountId,
+    ) {
+        if let Some(mut sequence) = self.pending_transaction_sequences.get(&creation.id.0) {
+            sequence.session_grantee_id = Some(grantee_id);
+            self.pending_transaction_sequences
+                .insert(&creation.id.0, &sequence);
+        }
+    }
+
+    fn end_session(&mut self, id: u64, session: SessionGrant, is_revoked: bool) {
+        self.sessions.remove(&id);
+        self.session_ids
+            .remove(&(session.owner_id.clone(), session.grantee_id.clone()));
+
+        let refund = session
+            .remaining_budget
+            .0
+            .checked_add(session.storage_deposit.0)
+            .unwrap_or_reject();
+
+        if refund > 0 {
+            Self::refund(session.owner_id.clone(), AssetBalance::native(refund));
+        }
+
+        ContractEvent::SessionEnded(SessionEnded {
+            session_id: id.into(),
+            owner_id: session.owner_id,
+            grantee_id: session.grantee_id,
+            is_revoked,
+            refund: refund.into(),
+        })
+        .emit();
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use near_sdk::{env, json_types::U64, near, near_bindgen, AccountId};
+
+use crate::{Contract, ContractExt};
+
+/// Outcomes of the signature requests sent to a signer contract. Latencies
+/// are the blocks between sending a request and receiving its result.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[near]
+pub struct SignerStats {
+    pub successes: u64,
+    pub failures: u64,
+    pub success_latency_blocks: u64,
+    pub failure_latency_blocks: u64,
+    pub max_latency_blocks: u64,
+    pub last_failure_block_height: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct ViewSignerStats {
+    pub successes: U64,
+    pub failures: U64,
+    /// Average blocks to a signature, if any were produced.
+    pub average_success_latency_blocks: Option<U64>,
+    /// Average blocks to a failure, if any occurred.
+    pub average_failure_latency_blocks: Option<U64>,
+    pub max_latency_blocks: U64,
+    pub last_failure_block_height: Option<U64>,
+}
+
+impl From<SignerStats> for ViewSignerStats {
+    fn from(stats: SignerStats) -> Self {
+        Self {
+            successes: stats.successes.into(),
+            failures: stats.failures.into(),
+            average_success_latency_blocks: stats
+                .success_latency_blocks
+                .checked_div(stats.successes)
+                .map(Into::into),
+            average_failure_latency_blocks: stats
+                .failure_latency_blocks
+                .checked_div(stats.failures)
+                .map(Into::into),
+            max_latency_blocks: stats.max_latency_blocks.into(),
+            last_failure_block_height: stats.last_failure_block_height.map(Into::into),
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Signature request outcomes of every signer contract used so far, for
+    /// deciding when to switch signers.
+    pub fn get_signer_stats(&self) -> HashMap<AccountId, ViewSignerStats> {
+        self.signer_stats
+            .iter()
+            .map(|(signer_id, stats)| (signer_id, stats.into()))
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Records the result of a signature request sent to the current signer
+    /// contract at `requested_at`. Requests sent before the block height was
+    /// passed to the callback are counted without a latency.
+    pub(crate) fn record_signer_result(&mut self, requested_at: Option<U64>, is_success: bool) {
+        let block_height = env::block_height();
+        let latency = requested_at.map_or(0, |r| block_height.saturating_sub(r.0));
+
+        let mut stats = self
+            .signer_stats
+            .get(&self.signer_contract_id)
+            .unwrap_or_default();
+
+        if is_success {
+            stats.successes = stats.successes.saturating_add(1);
+            stats.success_latency_blocks = stats.success_latency_blocks.saturating_add(latency);
+        } else {
+            stats.failures = stats.failures.saturating_add(1);
+            stats.failure_latency_blocks = stats.failure_latency_blocks.saturating_add(latency);
+            stats.last_failure_block_height = Some(block_height);
+        }
+        stats.max_latency_blocks = stats.max_latency_blocks.max(latency);
+
+        self.signer_stats.insert(&self.signer_contract_id, &stats);
+    }
+}
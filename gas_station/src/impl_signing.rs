@@ -0,0 +1,722 @@
+use ethers_core::{
+    types::{transaction::eip2718::TypedTransaction, U256},
+    utils::{hex, keccak256},
+};
+use lib::{
+    asset::{AssetBalance, AssetId},
+    chain_key::ext_chain_key_token,
+    contract_error::ContractError,
+    foreign_address::ForeignAddress,
+    sequence_callback::ext_sequence_callback_receiver,
+    signing_policy::ext_signing_policy,
+    Rejectable,
+};
+use near_sdk::{
+    env,
+    json_types::{U128, U64},
+    near, near_bindgen, require,
+    serde_json::json,
+    Gas, NearToken, Promise, PromiseError, PromiseOrValue,
+};
+use near_sdk_contract_tools::{pause::Pause, rbac::Rbac, standard::nep297::Event};
+
+use crate::{
+    contract_event::{ContractEvent, TransactionSequenceSigned},
+    decode_hash,
+    error::{
+        NonceAlreadySignedError, SignatureRequestDoesNoteExistError,
+        SignerDepositRequiresNativeAssetError, TransactionAlreadySignedError,
+        TransactionSequenceDoesNotExistError,
+    },
+    sighash_for_mpc_signing,
+    signature_request::{SignatureBorsh, SignatureRequest, Status},
+    valid_transaction_request::ValidTransactionRequest,
+    ChainKeyData, Contract, ContractExt, PendingTransactionSequence, Role, SigningOrder,
+    TransactionSequenceSignedEventAt, SEQUENCE_CALLBACK_GAS, SIGNING_POLICY_CHECK_GAS,
+};
+
+/// Gas and deposit of the calls made to the signer contract for each
+/// signature request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct SigningGasBudget {
+    /// Static gas of `ckt_sign_hash`. `None` gives it all unreserved gas.
+    pub sign_gas: Option<Gas>,
+    /// Gas reserved for `sign_next_callback`.
+    pub callback_gas: Gas,
+    pub sign_attached_deposit: NearToken,
+    /// Most the signer may charge per signature request. This much is
+    /// escrowed in NEAR at creation for each signature request of a
+    /// sequence, and whatever is not spent on `sign_attached_deposit` is
+    /// refunded once the sequence is signed or removed.
+    #[serde(default)]
+    pub max_sign_attached_deposit: NearToken,
+}
+
+impl Default for SigningGasBudget {
+    fn default() -> Self {
+        Self {
+            sign_gas: None,
+            callback_gas: Gas::from_tgas(3),
+            sign_attached_deposit: NearToken::from_yoctonear(1),
+            max_sign_attached_deposit: NearToken::from_yoctonear(0),
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_signing_gas_budget(&self) -> &SigningGasBudget {
+        &self.signing_gas_budget
+    }
+
+    pub fn set_signing_gas_budget(&mut self, budget: SigningGasBudget) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("set_signing_gas_budget");
+
+        require!(
+            budget
+                .sign_gas
+                .unwrap_or(Gas::from_gas(0))
+                .saturating_add(budget.callback_gas)
+                .saturating_add(SEQUENCE_CALLBACK_GAS)
+                <= Gas::from_tgas(300),
+            "Signing gas budget exceeds the maximum prepaid gas",
+        );
+
+        self.signing_gas_budget = budget;
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Allows the funding transactions of a `SigningOrder::UserFirst`
+    /// sequence to be signed, once a market maker has confirmed that the
+    /// user is ready to broadcast. All user transactions must be signed.
+    pub fn release_funding(&mut self, id: U64) {
+        <Self as Rbac>::require_role(&Role::MarketMaker);
+        self.record_admin_action("release_funding");
+
+        let mut transaction = self
+            .pending_transaction_sequences
+            .get(&id.0)
+            .expect_or_reject(TransactionSequenceDoesNotExistError {
+                transaction_sequence_id: id.0,
+            });
+
+        require!(
+            transaction.signing_order == SigningOrder::UserFirst,
+            "Funding of this sequence is not gated",
+        );
+        require!(
+            transaction
+                .signature_requests
+                .iter()
+                .all(|r| r.is_paymaster || r.is_signed()),
+            "User transactions must be signed before funding is released",
+        );
+
+        transaction.is_funding_released = true;
+        self.pending_transaction_sequences
+            .insert(&id.0, &transaction);
+    }
+
+    /// Resolves the hash of a signed user transaction, as shown by block
+    /// explorers, to the ID of the sequence it was signed in.
+    pub fn get_sequence_by_foreign_tx_hash(&self, hash: String) -> Option<U64> {
+        let hash = decode_hash(&hash).expect_or_reject("Invalid transaction hash");
+        self.sequences_by_foreign_tx_hash.get(&hash).map(Into::into)
+    }
+
+    pub fn sign_next(&mut self, id: U64) -> Promise {
+        <Self as Pause>::require_unpaused();
+
+        let id = id.0;
+
+        let mut transaction = self
+            .pending_transaction_sequences
+            .get(&id)
+            .expect_or_reject(TransactionSequenceDoesNotExistError {
+                transaction_sequence_id: id,
+            });
+
+        // ensure not expired
+        require!(
+            env::block_height().saturating_sub(transaction.created_at_block_height.0)
+                <= self.expire_sequence_after_blocks,
+            "Transaction is expired",
+        );
+
+        // ensure only signed by original creator, or a session grantee of
+        // theirs, unless the creator pre-approved it as a recurring sequence
+        let predecessor = env::predecessor_account_id();
+        require!(
+            transaction.created_by_account_id == predecessor
+                || transaction.recurring_authorization_id.is_some()
+                || self.is_session_signer(&transaction, &predecessor),
+            "Predecessor must be the transaction creator",
+        );
+
+        if self.flags.is_ordered_signing_enabled {
+            self.require_signing_order(transaction.priority);
+        }
+
+        // Whichever signature completes the sequence pays for notifying the
+        // callback receiver. Several may be in flight at once, so every
+        // signature of a sequence with a callback reserves the gas.
+        let callback_gas = if transaction.callback.is_some() {
+            SEQUENCE_CALLBACK_GAS
+        } else {
+            Gas::from_gas(0)
+        };
+
+        let index = transaction
+            .next_pending_index()
+            .expect_or_reject("No pending or non-in-flight signature requests");
+
+        let is_replacement = transaction.is_replacement();
+        let next_signature_request = &mut transaction.signature_requests[index];
+
+        if next_signature_request.is_paymaster {
+            require!(
+                transaction.signing_order == SigningOrder::PaymasterFirst
+                    || transaction.is_funding_released,
+                "Funding has not been released by a market maker",
+            );
+        }
+
+        // The key may have changed hands since the sequence was created.
+        require!(
+            self.current_authorization(&transaction.created_by_account_id, next_signature_request,)
+                == Some(next_signature_request.authorization),
+            "Chain key authorization has changed since the transaction was created",
+        );
+
+        // A replacement overwrites the replay record once signed.
+        if !next_signature_request.is_paymaster && !is_replacement {
+            self.check_transaction_replay(
+                &next_signature_request.token_id,
+                &next_signature_request.transaction,
+            )
+            .unwrap_or_reject();
+        }
+
+        next_signature_request.status = Status::InFlight;
+
+        let signing_policy_id = if next_signature_request.is_paymaster {
+            None
+        } else {
+            self.chain_key_data(&transaction.created_by_account_id, next_signature_request)
+                .and_then(|k| k.signing_policy_id)
+        };
+
+        #[allow(clippy::cast_possible_truncation)]
+        let index = index as u32;
+
+        let is_signing_now = signing_policy_id.is_none();
+
+        let ret = match signing_policy_id {
+            Some(signing_policy_id) => ext_signing_policy::ext(signing_policy_id)
+                .with_static_gas(SIGNING_POLICY_CHECK_GAS)
+                .check_sign_request(
+                    transaction.created_by_account_id.clone(),
+                    next_signature_request.token_id.clone(),
+                    hex::encode_prefixed(
+                        next_signature_request
+                            .transaction
+                            .clone()
+                            .into_typed_transaction()
+                            .rlp(),
+                    ),
+                )
+                .then(
+                    Self::ext(env::current_account_id()).sign_next_policy_callback(
+                        id.into(),
+                        index,
+                        callback_gas,
+                    ),
+                ),
+            None => self.sign_request(id, index, next_signature_request, callback_gas),
+        };
+
+        if is_signing_now {
+            self.draw_signer_deposit(&mut transaction);
+        }
+
+        self.pending_transaction_sequences.insert(&id, &transaction);
+        self.record_sequence_gas(id);
+
+        ret
+    }
+
+    /// Requests the signature once the chain key's signing policy has
+    /// approved the transaction. On rejection, the request returns to
+    /// pending so that the sequence can be removed or retried.
+    #[private]
+    pub fn sign_next_policy_callback(
+        &mut self,
+        id: U64,
+        index: u32,
+        callback_gas: Gas,
+        #[callback_result] result: Result<bool, PromiseError>,
+    ) -> Promise {
+        let id = id.0;
+
+        let mut pending_transaction_sequence = self
+            .pending_transaction_sequences
+            .get(&id)
+            .expect_or_reject(TransactionSequenceDoesNotExistError {
+                transaction_sequence_id: id,
+            });
+
+        let request = pending_transaction_sequence
+            .signature_requests
+            .get_mut(index as usize)
+            .expect_or_reject(SignatureRequestDoesNoteExistError {
+                transaction_sequence_id: id,
+                index,
+            });
+
+        if !request.is_in_flight() {
+            env::panic_str(&format!(
+                "Inconsistent state: Signature request {id}.{index} should be in-flight but is not"
+            ));
+        }
+
+        let ret = if matches!(result, Ok(true)) {
+            let ret = self.sign_request(id, index, request, callback_gas);
+            self.draw_signer_deposit(&mut pending_transaction_sequence);
+            ret
+        } else {
+            request.status = Status::Pending;
+            Self::ext(env::current_account_id()).throw(
+                ContractError::new(
+                    "rejected_by_signing_policy",
+                    "Transaction rejected by signing policy",
+                    json!({ "id": U64(id), "index": index }),
+                )
+                .to_string(),
+            )
+        };
+
+        self.pending_transaction_sequences
+            .insert(&id, &pending_transaction_sequence);
+        self.record_sequence_gas(id);
+
+        ret
+    }
+
+    #[private]
+    pub fn sign_next_callback(
+        &mut self,
+        id: U64,
+        index: u32,
+        requested_at: Option<U64>,
+        #[callback_result] result: Result<String, PromiseError>,
+    ) -> PromiseOrValue<String> {
+        let id = id.0;
+
+        let mut pending_transaction_sequence = self
+            .pending_transaction_sequences
+            .get(&id)
+            .expect_or_reject(TransactionSequenceDoesNotExistError {
+                transaction_sequence_id: id,
+            });
+
+        let request = pending_transaction_sequence
+            .signature_requests
+            .get_mut(index as usize)
+            .expect_or_reject(SignatureRequestDoesNoteExistError {
+                transaction_sequence_id: id,
+                index,
+            });
+
+        if !request.is_in_flight() {
+            env::panic_str(&format!(
+                "Inconsistent state: Signature request {id}.{index} should be in-flight but is not"
+            ));
+        }
+
+        // TODO: Fraud proofs.
+        let signature = result.ok().and_then(|s| s.parse().ok());
+
+        self.record_signer_result(requested_at, signature.is_some());
+
+        // Return the request to pending so that it can be retried, and fail
+        // the call without reverting the signer statistics.
+        let Some(signature) = signature else {
+            request.status = Status::Pending;
+            self.pending_transaction_sequences
+                .insert(&id, &pending_transaction_sequence);
+            return PromiseOrValue::Promise(
+                Self::ext(env::current_account_id()).throw(
+                    ContractError::new(
+                        "signature_failed",
+                        "Failed to produce signature",
+                        json!({ "id": U64(id), "index": index }),
+                    )
+                    .to_string(),
+                ),
+            );
+        };
+
+        let transaction: TypedTransaction = request.transaction.clone().into();
+
+        let rlp_signed = transaction.rlp_signed(&signature);
+
+        request.set_signature(signature);
+
+        if !request.is_paymaster {
+            self.record_foreign_nonce(&request.token_id, &request.transaction);
+            self.record_signed_transaction(&request.token_id, &request.transaction);
+            self.sequences_by_foreign_tx_hash
+                .insert(&keccak256(&rlp_signed), &id);
+        }
+
+        self.settle_signed_escrow(&mut pending_transaction_sequence, index as usize);
+
+        let all_signatures = pending_transaction_sequence
+            .signature_requests
+            .iter()
+            .try_fold(vec![], |mut v, r| {
+                if let Status::Signed { signature } = &r.status {
+                    v.push((r.transaction.clone(), signature.clone()));
+                    Some(v)
+                } else {
+                    None
+                }
+            });
+
+        self.pending_transaction_sequences
+            .insert(&id, &pending_transaction_sequence);
+
+        if let Some(all_signatures) = all_signatures {
+            self.complete_signed_sequence(id, pending_transaction_sequence, all_signatures);
+        }
+
+        self.record_sequence_gas(id);
+
+        PromiseOrValue::Value(self.flags.encode_hex(&rlp_signed))
+    }
+}
+
+impl Contract {
+    /// Settles the escrow of a sequence once a funding transaction is signed,
+    /// or once the transaction of an unsponsored sequence is.
+    ///
+    /// Sequences funded on several chains settle the share of each chain's
+    /// paymaster only once its own funding transaction is signed. The rest
+    /// stays in escrow, so that it is refunded if the sequence is removed.
+    pub(crate) fn settle_signed_escrow(
+        &mut self,
+        sequence: &mut PendingTransactionSequence,
+        index: usize,
+    ) {
+        if !sequence.funding_shares.is_empty() {
+            self.settle_funding_share(sequence, index);
+            return;
+        }
+
+        // Remove escrow from record once a funding transaction is signed,
+        // since the paymaster is then committed to paying for the sequence.
+        // This is important to ensuring that refund logic works correctly.
+        // Unsponsored sequences settle their flat fee on their signature.
+        let is_unsponsored = !sequence.signature_requests.iter().any(|r| r.is_paymaster);
+        let escrow = (sequence.signature_requests[index].is_paymaster || is_unsponsored)
+            .then(|| sequence.escrow.take())
+            .flatten();
+        if let Some(escrow) = escrow {
+            self.settle_escrow(escrow.clone(), sequence.paymaster_token_id.clone());
+            sequence.settled_escrow = Some(escrow);
+        }
+    }
+
+    /// Moves the funding share of the paymaster request at `index` from the
+    /// escrow to the settled escrow. Funding shares are in the order of the
+    /// paymaster requests of the sequence.
+    fn settle_funding_share(&mut self, sequence: &mut PendingTransactionSequence, index: usize) {
+        if !sequence.signature_requests[index].is_paymaster {
+            return;
+        }
+
+        let share_index = sequence.signature_requests[..index]
+            .iter()
+            .filter(|r| r.is_paymaster)
+            .count();
+        let (token_id, amount) = sequence.funding_shares[share_index].clone();
+
+        let Some(mut escrow) = sequence.escrow.take() else {
+            return;
+        };
+        escrow.amount.0 = escrow
+            .amount
+            .0
+            .checked_sub(amount.0)
+            .expect_or_reject("Inconsistent state: funding share exceeds escrow");
+
+        let share = AssetBalance {
+            asset_id: escrow.asset_id.clone(),
+            amount,
+        };
+        self.settle_escrow(share.clone(), Some(token_id));
+
+        let settled = sequence.settled_escrow.get_or_insert(AssetBalance {
+            asset_id: share.asset_id,
+            amount: U128(0),
+        });
+        settled.amount.0 += amount.0;
+
+        sequence.escrow = (escrow.amount.0 > 0).then_some(escrow);
+    }
+
+    /// Emits the signed transactions of a fully signed sequence, notifies its
+    /// callback, and moves it out of the pending sequences.
+    fn complete_signed_sequence(
+        &mut self,
+        id: u64,
+        mut sequence: PendingTransactionSequence,
+        all_signatures: Vec<(ValidTransactionRequest, SignatureBorsh)>,
+    ) {
+        // The user transaction is always last.
+        let last_request = sequence.signature_requests.last().unwrap_or_reject();
+
+        let sender_foreign_address = self
+            .chain_key_data(&sequence.created_by_account_id, last_request)
+            .map(|k| ForeignAddress::from_raw_public_key(k.public_key_bytes));
+
+        let signed_transactions = all_signatures
+            .into_iter()
+            .map(|(t, s)| {
+                let chain_id = t.chain_id;
+                let rlp_signed = t.into_typed_transaction().rlp_signed(&s.into());
+                (chain_id, self.flags.encode_hex(rlp_signed))
+            })
+            .collect::<Vec<_>>();
+
+        // Sequences spanning several chains are reported once per chain,
+        // since their transactions are relayed to each chain separately.
+        for chain_id in sequence.chain_ids() {
+            let e = TransactionSequenceSigned {
+                id: id.into(),
+                foreign_chain_id: chain_id.to_string(),
+                created_by_account_id: sequence.created_by_account_id.clone(),
+                tag: sequence.tag.clone(),
+                token_id: last_request.token_id.clone(),
+                sender_foreign_address,
+                signed_transactions: signed_transactions
+                    .iter()
+                    .filter(|(c, _)| *c == chain_id)
+                    .map(|(_, t)| t.clone())
+                    .collect(),
+            };
+
+            self.signed_transaction_sequences
+                .push(&TransactionSequenceSignedEventAt {
+                    block_height: env::block_height(),
+                    event: e.clone(),
+                });
+
+            ContractEvent::TransactionSequenceSigned(e).emit();
+        }
+
+        if let Some(ref callback) = sequence.callback {
+            ext_sequence_callback_receiver::ext(callback.receiver_id.clone())
+                .with_static_gas(SEQUENCE_CALLBACK_GAS)
+                .with_unused_gas_weight(0)
+                .on_sequence_signed(
+                    id.into(),
+                    signed_transactions.into_iter().map(|(_, t)| t).collect(),
+                    callback.msg.clone(),
+                );
+        }
+
+        Self::refund_signer_deposit(&mut sequence);
+
+        // Remove transaction if all requests have been signed
+        self.pending_transaction_sequences.remove(&id);
+        self.dequeue_from_signing(id, sequence.priority);
+        self.signed_sequences.insert(&id, &sequence);
+    }
+
+    /// Sends a signature request to the signer contract, to be completed by
+    /// `sign_next_callback`.
+    fn sign_request(
+        &self,
+        id: u64,
+        index: u32,
+        request: &SignatureRequest,
+        callback_gas: Gas,
+    ) -> Promise {
+        let budget = &self.signing_gas_budget;
+
+        let mut sign = ext_chain_key_token::ext(self.signer_contract_id.clone())
+            .with_attached_deposit(budget.sign_attached_deposit);
+        if let Some(sign_gas) = budget.sign_gas {
+            sign = sign.with_static_gas(sign_gas);
+        }
+
+        sign.ckt_sign_hash(
+            request.token_id.clone(),
+            None,
+            sighash_for_mpc_signing(request.transaction.clone()).to_vec(),
+            request.authorization.to_approval_id(),
+            None,
+        )
+        .then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(budget.callback_gas.saturating_add(callback_gas))
+                .with_unused_gas_weight(0)
+                .sign_next_callback(id.into(), index, Some(env::block_height().into())),
+        )
+    }
+
+    /// The NEAR to escrow at creation for the signer deposits of
+    /// `signature_count` signature requests.
+    ///
+    /// # Errors
+    ///
+    /// - If the signer requires a deposit and the sequence is paid for in an
+    ///   asset other than NEAR, which cannot be converted without prices.
+    pub(crate) fn signer_deposit_for(
+        &self,
+        asset_id: &AssetId,
+        signature_count: usize,
+    ) -> Result<u128, SignerDepositRequiresNativeAssetError> {
+        let deposit = self
+            .signing_gas_budget
+            .max_sign_attached_deposit
+            .as_yoctonear()
+            .saturating_mul(signature_count as u128);
+
+        if deposit > 0 && *asset_id != AssetId::Native {
+            return Err(SignerDepositRequiresNativeAssetError {
+                asset_id: asset_id.clone(),
+            });
+        }
+
+        Ok(deposit)
+    }
+
+    /// Pays the deposit of a signing call out of the sequence's escrow, as
+    /// far as it reaches. The contract covers the rest.
+    fn draw_signer_deposit(&self, sequence: &mut PendingTransactionSequence) {
+        sequence.signer_deposit.0 = sequence
+            .signer_deposit
+            .0
+            .saturating_sub(self.signing_gas_budget.sign_attached_deposit.as_yoctonear());
+    }
+
+    /// Refunds the unspent part of the sequence's signer deposit escrow.
+    pub(crate) fn refund_signer_deposit(sequence: &mut PendingTransactionSequence) {
+        let unspent = std::mem::take(&mut sequence.signer_deposit.0);
+        if unspent > 0 {
+            Self::refund(
+                sequence.created_by_account_id.clone(),
+                AssetBalance::native(unspent),
+            );
+        }
+    }
+
+    /// Returns the next nonce that has not yet been signed for the key on the
+    /// given chain, if the contract has signed any transactions for it.
+    pub(crate) fn next_foreign_nonce(&self, token_id: &str, chain_id: u64) -> Option<u64> {
+        self.foreign_nonces
+            .get(&(token_id.to_string(), chain_id))
+            .and_then(|n| n.checked_add(1))
+    }
+
+    pub(crate) fn check_foreign_nonce(
+        &self,
+        token_id: &str,
+        user_chain_key: &ChainKeyData,
+        transaction: &ValidTransactionRequest,
+    ) -> Result<(), NonceAlreadySignedError> {
+        if !user_chain_key.is_nonce_tracking_enabled {
+            return Ok(());
+        }
+
+        let Some(next_nonce) = self.next_foreign_nonce(token_id, transaction.chain_id) else {
+            return Ok(());
+        };
+
+        if transaction.nonce() < U256::from(next_nonce) {
+            return Err(NonceAlreadySignedError {
+                token_id: token_id.to_string(),
+                chain_id: transaction.chain_id,
+                nonce: transaction.nonce(),
+                next_nonce,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn record_foreign_nonce(&mut self, token_id: &str, transaction: &ValidTransactionRequest) {
+        let nonce = transaction.nonce();
+        if nonce > U256::from(u64::MAX) {
+            return;
+        }
+        let nonce = nonce.as_u64();
+
+        let key = (token_id.to_string(), transaction.chain_id);
+        if self.foreign_nonces.get(&key).map_or(true, |n| n < nonce) {
+            self.foreign_nonces.insert(&key, &nonce);
+        }
+    }
+
+    /// Rejects a transaction if one with the same nonce has already been
+    /// signed for the key on the given chain, unless an administrator has
+    /// cleared it with `allow_foreign_transaction_resign`.
+    pub(crate) fn check_transaction_replay(
+        &self,
+        token_id: &str,
+        transaction: &ValidTransactionRequest,
+    ) -> Result<(), TransactionAlreadySignedError> {
+        let Some(key) = Self::signed_transaction_key(token_id, transaction) else {
+            return Ok(());
+        };
+
+        if self.signed_transaction_hashes.contains_key(&key) {
+            return Err(TransactionAlreadySignedError {
+                token_id: key.0,
+                chain_id: key.1,
+                nonce: key.2,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn record_signed_transaction(&mut self, token_id: &str, transaction: &ValidTransactionRequest) {
+        if let Some(key) = Self::signed_transaction_key(token_id, transaction) {
+            self.signed_transaction_hashes
+                .insert(&key, &sighash_for_mpc_signing(transaction.clone()));
+        }
+    }
+
+    /// Whether a replacement of `transaction` with the same nonce may be
+    /// signed, i.e. no different transaction has been signed with that
+    /// nonce since.
+    pub(crate) fn is_replaceable_signed_transaction(
+        &self,
+        token_id: &str,
+        transaction: &ValidTransactionRequest,
+    ) -> bool {
+        Self::signed_transaction_key(token_id, transaction).map_or(true, |key| {
+            self.signed_transaction_hashes
+                .get(&key)
+                .map_or(true, |hash| {
+                    hash == sighash_for_mpc_signing(transaction.clone())
+                })
+        })
+    }
+
+    /// Nonces that do not fit in a `u64` are never tracked.
+    fn signed_transaction_key(
+        token_id: &str,
+        transaction: &ValidTransactionRequest,
+    ) -> Option<(String, u64, u64)> {
+        let nonce = transaction.nonce();
+        (nonce <= U256::from(u64::MAX))
+            .then(|| (token_id.to_string(), transaction.chain_id, nonce.as_u64()))
+    }
+}
@@ -0,0 +1,89 @@
+use ethers_core::types::U256;
+use near_sdk::{
+    env,
+    json_types::{U128, U64},
+    near, near_bindgen, AccountId,
+};
+
+use crate::{Contract, ContractExt};
+
+/// Gas tokens sponsored for an account on one chain, halved for every epoch
+/// that passes. A value of `x` spent `n` epochs ago contributes `x / 2^n`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[near]
+pub struct AccountSpending {
+    pub value: [u64; 4],
+    pub updated_at_epoch_height: u64,
+}
+
+impl AccountSpending {
+    /// The accumulated value, decayed to `epoch_height`.
+    pub fn value_at(&self, epoch_height: u64) -> U256 {
+        let elapsed = epoch_height.saturating_sub(self.updated_at_epoch_height);
+        if elapsed >= 256 {
+            return U256::zero();
+        }
+
+        #[allow(clippy::cast_possible_truncation)]
+        let shift = elapsed as usize;
+        U256(self.value) >> shift
+    }
+
+    pub fn record(&mut self, amount: U256, epoch_height: u64) {
+        self.value = self.value_at(epoch_height).saturating_add(amount).0;
+        self.updated_at_epoch_height = epoch_height;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct ViewAccountSpending {
+    pub chain_id: U64,
+    /// Decayed value of sponsored gas tokens as of the current epoch.
+    pub value: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Recent sponsored spending of an account on each chain, weighted
+    /// towards the current epoch. Chains without spending are omitted.
+    pub fn get_account_spending(&self, account_id: AccountId) -> Vec<ViewAccountSpending> {
+        let epoch_height = env::epoch_height();
+
+        self.foreign_chains
+            .keys()
+            .filter_map(|chain_id| {
+                let value = self.account_spending(&account_id, chain_id, epoch_height);
+                (!value.is_zero()).then(|| ViewAccountSpending {
+                    chain_id: chain_id.into(),
+                    value: U128(value.try_into().unwrap_or(u128::MAX)),
+                })
+            })
+            .collect()
+    }
+}
+
+impl Contract {
+    pub(crate) fn account_spending(
+        &self,
+        account_id: &AccountId,
+        chain_id: u64,
+        epoch_height: u64,
+    ) -> U256 {
+        self.account_spending
+            .get(&(account_id.clone(), chain_id))
+            .map_or_else(U256::zero, |spending| spending.value_at(epoch_height))
+    }
+
+    pub(crate) fn record_account_spending(
+        &mut self,
+        account_id: &AccountId,
+        chain_id: u64,
+        amount: U256,
+    ) {
+        let key = (account_id.clone(), chain_id);
+        let mut spending = self.account_spending.get(&key).unwrap_or_default();
+        spending.record(amount, env::epoch_height());
+        self.account_spending.insert(&key, &spending);
+    }
+}
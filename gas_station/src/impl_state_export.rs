@@ -0,0 +1,124 @@
+use lib::Rejectable;
+use near_sdk::{
+    borsh,
+    json_types::{Base64VecU8, U64},
+    near, near_bindgen,
+};
+use near_sdk_contract_tools::rbac::Rbac;
+
+use crate::{Contract, ContractExt, Role};
+
+/// A map of the contract state that can be exported with
+/// [`Contract::export_state`].
+///
+/// Keys and values are Borsh-serialized. Nested collections are flattened
+/// into their entries, so that each value is self-contained:
+///
+/// - `ForeignChains`: `u64` to
+///   `(ForeignChainConfiguration, Vec<(String, PaymasterConfiguration)>, Vec<(String, PaymasterConfiguration)>)`,
+///   the paymasters and disabled paymasters following the configuration.
+/// - `PaymasterKeys`: `String` to `ChainKeyData`.
+/// - `UserChainKeys`: `AccountId` to `Vec<(String, ChainKeyData)>`.
+/// - `PendingTransactionSequences`: `u64` to `PendingTransactionSequence`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub enum StateSection {
+    ForeignChains,
+    PaymasterKeys,
+    UserChainKeys,
+    PendingTransactionSequences,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct StateExportEntry {
+    pub key: Base64VecU8,
+    pub value: Base64VecU8,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct StateExportChunk {
+    pub entries: Vec<StateExportEntry>,
+    /// Number of entries in the section, to page through it.
+    pub total: U64,
+}
+
+fn entry<K: borsh::BorshSerialize, V: borsh::BorshSerialize>(
+    key: &K,
+    value: &V,
+) -> StateExportEntry {
+    StateExportEntry {
+        key: borsh::to_vec(key).unwrap_or_reject().into(),
+        value: borsh::to_vec(value).unwrap_or_reject().into(),
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Exports a chunk of a section of the contract state, so that it can be
+    /// reconstructed by a redeployment without archival state sync.
+    ///
+    /// Administrators only, so it must be called in a transaction rather
+    /// than as a view call. The raw storage remains readable through RPC.
+    pub fn export_state(
+        &self,
+        section: StateSection,
+        offset: Option<u32>,
+        limit: Option<u32>,
+    ) -> StateExportChunk {
+        <Self as Rbac>::require_role(&Role::Administrator);
+
+        let offset = offset.map_or(0, |o| o as usize);
+        let limit = limit.map_or(usize::MAX, |l| l as usize);
+
+        let (entries, total) = match section {
+            StateSection::ForeignChains => (
+                self.foreign_chains
+                    .iter()
+                    .skip(offset)
+                    .take(limit)
+                    .map(|(chain_id, config)| {
+                        let paymasters: Vec<_> = config.paymasters.iter().collect();
+                        let disabled_paymasters: Vec<_> =
+                            config.disabled_paymasters.iter().collect();
+                        entry(&chain_id, &(config, paymasters, disabled_paymasters))
+                    })
+                    .collect(),
+                self.foreign_chains.len(),
+            ),
+            StateSection::PaymasterKeys => (
+                self.paymaster_keys
+                    .iter()
+                    .skip(offset)
+                    .take(limit)
+                    .map(|(token_id, key_data)| entry(&token_id, &key_data))
+                    .collect(),
+                self.paymaster_keys.len(),
+            ),
+            StateSection::UserChainKeys => (
+                self.user_chain_keys
+                    .iter()
+                    .skip(offset)
+                    .take(limit)
+                    .map(|(account_id, keys)| entry(&account_id, &keys.to_vec()))
+                    .collect(),
+                self.user_chain_keys.len(),
+            ),
+            StateSection::PendingTransactionSequences => (
+                self.pending_transaction_sequences
+                    .iter()
+                    .skip(offset)
+                    .take(limit)
+                    .map(|(id, sequence)| entry(&id, &sequence))
+                    .collect(),
+                self.pending_transaction_sequences.len(),
+            ),
+        };
+
+        StateExportChunk {
+            entries,
+            total: total.into(),
+        }
+    }
+}
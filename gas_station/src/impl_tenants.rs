@@ -0,0 +1,209 @@
+use lib::Rejectable;
+use near_sdk::{
+    env,
+    json_types::{U128, U64},
+    near, near_bindgen, require, AccountId,
+};
+use near_sdk_contract_tools::rbac::Rbac;
+
+use crate::{
+    chain_configuration::validate_fee_rate, impl_management::require_whitelist_batch_size,
+    valid_transaction_request::ValidTransactionRequest, Contract, ContractExt, Role,
+    MAX_TAG_LENGTH,
+};
+
+/// A dApp sharing the deployment, with its own settings. Sequences are
+/// created under a tenant by passing its ID as `tenant_id`.
+///
+/// Only the sender whitelist, the available chains and the fee rate are
+/// scoped to a tenant. Paymasters, chain configurations, flags and collected
+/// fees are shared by all tenants, and fees of the tenant's sequences are
+/// collected with those of every other sequence.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct Tenant {
+    /// Accounts that may change the tenant's settings, besides contract
+    /// administrators.
+    pub admins: Vec<AccountId>,
+    /// Chains the tenant's sequences may use. Empty allows every chain.
+    pub chain_ids: Vec<U64>,
+    /// Replaces the fee rate of every chain for the tenant's sponsored
+    /// sequences. Only contract administrators may set it, and it requires
+    /// the sender whitelist, so that only members get the tenant's rate.
+    pub fee_rate: Option<(U128, U128)>,
+    /// Only senders on the tenant's sender whitelist may create sequences
+    /// under the tenant.
+    pub is_sender_whitelist_enabled: bool,
+}
+
+fn validate_tenant(tenant: &Tenant) {
+    if let Some((numerator, denominator)) = tenant.fee_rate {
+        validate_fee_rate((numerator.0, denominator.0)).unwrap_or_reject();
+        require!(
+            tenant.is_sender_whitelist_enabled,
+            "A tenant with its own fee rate must enable its sender whitelist",
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn add_tenant(&mut self, tenant_id: String, tenant: Tenant) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("add_tenant");
+
+        require!(
+            !tenant_id.is_empty() && tenant_id.len() <= MAX_TAG_LENGTH,
+            format!("Tenant ID must be 1 to {MAX_TAG_LENGTH} bytes"),
+        );
+        require!(
+            self.tenants.get(&tenant_id).is_none(),
+            "Tenant already exists",
+        );
+        validate_tenant(&tenant);
+
+        self.tenants.insert(&tenant_id, &tenant);
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Replaces the settings of a tenant. Only contract administrators may
+    /// change its admins or fee rate.
+    pub fn update_tenant(&mut self, tenant_id: String, tenant: Tenant) {
+        let existing = self.require_tenant_admin(&tenant_id);
+        self.record_admin_action("update_tenant");
+
+        let is_administrator =
+            <Self as Rbac>::has_role(&env::predecessor_account_id(), &Role::Administrator);
+        require!(
+            tenant.admins == existing.admins || is_administrator,
+            "Only administrators may change the admins of a tenant",
+        );
+        require!(
+            tenant.fee_rate == existing.fee_rate || is_administrator,
+            "Only administrators may change the fee rate of a tenant",
+        );
+        validate_tenant(&tenant);
+
+        self.tenants.insert(&tenant_id, &tenant);
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    /// Removes a tenant. Its pending sequences are unaffected, but no new
+    /// sequences can be created under it.
+    pub fn remove_tenant(&mut self, tenant_id: String) {
+        <Self as Rbac>::require_role(&Role::Administrator);
+        self.record_admin_action("remove_tenant");
+
+        self.tenants
+            .remove(&tenant_id)
+            .expect_or_reject("Tenant does not exist");
+
+        self.emit_config_snapshot_if_due();
+    }
+
+    pub fn get_tenant(&self, tenant_id: String) -> Option<Tenant> {
+        self.tenants.get(&tenant_id)
+    }
+
+    pub fn list_tenants(&self) -> Vec<String> {
+        self.tenants.keys().collect()
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn add_to_tenant_sender_whitelist(
+        &mut self,
+        tenant_id: String,
+        accounts: Vec<AccountId>,
+    ) -> u32 {
+        self.require_tenant_admin(&tenant_id);
+        self.record_admin_action("add_to_tenant_sender_whitelist");
+        require_whitelist_batch_size(accounts.len());
+
+        accounts
+            .into_iter()
+            .filter(|account_id| {
+                self.tenant_sender_whitelist
+                    .insert(&(tenant_id.clone(), account_id.clone()))
+            })
+            .count() as u32
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn remove_from_tenant_sender_whitelist(
+        &mut self,
+        tenant_id: String,
+        accounts: Vec<AccountId>,
+    ) -> u32 {
+        self.require_tenant_admin(&tenant_id);
+        self.record_admin_action("remove_from_tenant_sender_whitelist");
+        require_whitelist_batch_size(accounts.len());
+
+        accounts
+            .into_iter()
+            .filter(|account_id| {
+                self.tenant_sender_whitelist
+                    .remove(&(tenant_id.clone(), account_id.clone()))
+            })
+            .count() as u32
+    }
+
+    pub fn is_tenant_sender_whitelisted(&self, tenant_id: String, account_id: AccountId) -> bool {
+        self.tenant_sender_whitelist
+            .contains(&(tenant_id, account_id))
+    }
+}
+
+impl Contract {
+    fn require_tenant_admin(&self, tenant_id: &String) -> Tenant {
+        let tenant = self
+            .tenants
+            .get(tenant_id)
+            .expect_or_reject("Tenant does not exist");
+
+        let predecessor = env::predecessor_account_id();
+        require!(
+            tenant.admins.contains(&predecessor)
+                || <Self as Rbac>::has_role(&predecessor, &Role::Administrator),
+            "Predecessor is not an admin of the tenant",
+        );
+
+        tenant
+    }
+
+    /// Checks that `sender_id` may create a sequence with `transaction`
+    /// under the tenant.
+    pub(crate) fn check_tenant_transaction(
+        &self,
+        tenant_id: &String,
+        sender_id: &AccountId,
+        transaction: &ValidTransactionRequest,
+    ) -> Result<(), &'static str> {
+        let tenant = self.tenants.get(tenant_id).ok_or("Tenant does not exist")?;
+
+        if !tenant.chain_ids.is_empty() && !tenant.chain_ids.contains(&transaction.chain_id.into())
+        {
+            return Err("Chain is not available to the tenant");
+        }
+
+        if tenant.is_sender_whitelist_enabled
+            && !self
+                .tenant_sender_whitelist
+                .contains(&(tenant_id.clone(), sender_id.clone()))
+        {
+            return Err("Sender is not whitelisted by the tenant");
+        }
+
+        Ok(())
+    }
+
+    /// The fee rate of the tenant's sponsored sequences, if it overrides
+    /// that of the chain.
+    pub(crate) fn tenant_fee_rate(&self, tenant_id: Option<&String>) -> Option<(u128, u128)> {
+        tenant_id
+            .and_then(|tenant_id| self.tenants.get(tenant_id))
+            .and_then(|tenant| tenant.fee_rate)
+            .map(|(numerator, denominator)| (numerator.0, denominator.0))
+    }
+}
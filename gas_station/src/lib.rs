@@ -1,58 +1,172 @@
-use ethers_core::{
-    types::{transaction::eip2718::TypedTransaction, U256},
-    utils::hex,
-};
+use std::collections::BTreeMap;
+
+use ethers_core::utils::hex;
 use lib::{
-    asset::{AssetBalance, AssetId},
-    chain_key::ext_chain_key_token,
+    asset::{AssetBalance, AssetId, TransferOptions},
     foreign_address::ForeignAddress,
-    pyth::{self, ext_pyth},
     Rejectable,
 };
 use near_sdk::{
-    collections::{UnorderedMap, UnorderedSet, Vector},
+    collections::{LookupMap, LookupSet, TreeMap, UnorderedMap, UnorderedSet, Vector},
     env,
     json_types::{U128, U64},
-    near, near_bindgen, require, AccountId, BorshStorageKey, Gas, NearToken, PanicOnDefault,
-    Promise, PromiseError, PromiseOrValue,
+    near, near_bindgen, AccountId, BorshStorageKey, Gas, PanicOnDefault, PublicKey,
 };
 #[allow(clippy::wildcard_imports)]
 use near_sdk_contract_tools::pause::*;
-use near_sdk_contract_tools::{rbac::Rbac, standard::nep297::Event, Pause, Rbac};
+use near_sdk_contract_tools::{rbac::Rbac, Pause, Rbac};
 
 pub mod chain_configuration;
-use chain_configuration::ForeignChainConfiguration;
+use chain_configuration::{
+    ChainOnboardingArgs, ForeignChainConfiguration, FundingOptions, PaymasterReservation,
+};
 
 pub mod contract_event;
-use contract_event::{ContractEvent, TransactionSequenceCreated, TransactionSequenceSigned};
 
 mod error;
 #[allow(clippy::wildcard_imports)]
 use error::*;
 
+mod impl_admin_audit;
+pub use impl_admin_audit::AdminAction;
+mod impl_balance_proof;
 mod impl_chain_key_nft;
-pub use impl_chain_key_nft::ChainKeyReceiverMsg;
+mod impl_chain_registry;
+pub use impl_chain_key_nft::{ChainKeyReceiverCreateTransaction, ChainKeyReceiverMsg};
+mod impl_creation;
 #[cfg(feature = "debug")]
 mod impl_debug;
+mod impl_denylist;
+mod impl_doomed;
+pub use impl_doomed::DoomedSequence;
+mod impl_dust;
+mod impl_escrow;
+mod impl_fee_bump;
+mod impl_gas_usage;
+mod impl_idempotency;
+pub use impl_gas_usage::{SequenceGasUsage, ViewSequenceGasTotals, ViewSequenceGasUsage};
+pub use impl_idempotency::{
+    IdempotencyKeyRecord, IdempotencyKeyStatus, IDEMPOTENCY_KEY_TTL_BLOCKS,
+    MAX_IDEMPOTENCY_KEY_LENGTH,
+};
+mod impl_intents;
+pub use impl_intents::IntentSettlementArgs;
+mod impl_keys;
+pub use impl_keys::{ChainKeyAuthorization, ChainKeyData};
+mod impl_legacy_storage;
+pub use impl_legacy_storage::LegacyStorageCleanup;
 mod impl_management;
+mod impl_multichain;
+pub use impl_multichain::MAX_SEQUENCE_CHAINS;
 mod impl_nep141_receiver;
+mod impl_nep245_receiver;
+mod impl_paymaster_bond;
+pub use impl_paymaster_bond::{BondedPaymaster, PaymasterBondSettings};
+mod impl_paymaster_fees;
+mod impl_priority;
+pub use impl_priority::SigningQueueEntry;
+mod impl_rate_limit;
+pub use impl_rate_limit::{RateLimit, SequenceCreationWindow};
+mod impl_sessions;
+pub use impl_sessions::SessionGrant;
+mod impl_recurring;
+pub use impl_recurring::{RecurringAuthorization, RecurringTemplate};
+mod impl_signer_stats;
+pub use impl_signer_stats::{SignerStats, ViewSignerStats};
+mod impl_signing;
+pub use impl_signing::SigningGasBudget;
+mod impl_spending;
+pub use impl_spending::{AccountSpending, ViewAccountSpending};
+mod impl_state_export;
+pub use impl_state_export::{StateExportChunk, StateExportEntry, StateSection};
+mod impl_tenants;
+pub use impl_tenants::Tenant;
+#[cfg(test)]
+mod unit_tests;
 
 pub mod signature_request;
-use signature_request::{SignatureRequest, Status};
+use signature_request::SignatureRequest;
 
 mod utils;
-use utils::{decode_transaction_request, sighash_for_mpc_signing};
+use utils::{
+    decode_hash, decode_transaction_request, sighash_for_mpc_signing, verify_merkle_proof,
+};
+pub use utils::{decode_hex, erc20_transfer_calldata, try_decode_transaction_request};
 
 pub mod valid_transaction_request;
-use valid_transaction_request::ValidTransactionRequest;
 
-const DEFAULT_EXPIRE_SEQUENCE_AFTER_BLOCKS: u64 = 5 * 60; // 5ish minutes at 1s/block
+pub use gas_station_client::{
+    CreateTransactionOptions, FundingTransactionPreview, Nep141ReceiverCreateTransactionArgs,
+    PaymasterFunding, SequenceCallback, SigningOrder, TransactionSequenceCreation,
+};
+
+/// Static gas for token transfers issued by the contract. Some tokens need
+/// more than the default share of unused gas.
+const TOKEN_TRANSFER_GAS: Gas = Gas::from_tgas(20);
 
+const DEFAULT_EXPIRE_SEQUENCE_AFTER_BLOCKS: u64 = 5 * 60; // 5ish minutes at 1s/block
+/// Upper bound on the storage used by registering the first chain key of a
+/// user, until a registration is measured.
+const INITIAL_REGISTRATION_STORAGE_USAGE: u64 = 1_000;
+pub const MAX_TAG_LENGTH: usize = 64;
+pub const MAX_CALLBACK_MSG_LENGTH: usize = 1024;
+/// Most whitelist entries added or removed per call, to stay within the gas
+/// limit.
+pub const MAX_WHITELIST_BATCH_SIZE: usize = 100;
+/// Gas of a plain transfer of a chain's native token to an externally owned
+/// account.
+pub const NATIVE_TRANSFER_GAS: u128 = 21_000;
+/// Gas attached to `on_sequence_signed` calls.
+pub const SEQUENCE_CALLBACK_GAS: Gas = Gas::from_tgas(10);
+/// Gas attached to `check_sign_request` calls to signing policy contracts.
+pub const SIGNING_POLICY_CHECK_GAS: Gas = Gas::from_tgas(10);
+/// Administrator calls emit a `ConfigSnapshot` event if at least this many
+/// blocks have passed since the last one.
+const CONFIG_SNAPSHOT_INTERVAL_BLOCKS: u64 = 24 * 60 * 60; // ~1 day at 1s/block
+
+// Each flag is an independent administrator toggle, set by name through
+// `set_flags` and exported with the configuration, so they stay plain bools.
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 #[near(serializers = [borsh, json])]
 pub struct Flags {
     pub is_sender_whitelist_enabled: bool,
+    /// Receivers must be in the receiver whitelist, or be proven to be in the
+    /// receiver whitelist Merkle tree, if one is set.
     pub is_receiver_whitelist_enabled: bool,
+    /// Sequences must be signed in priority order (see `get_signing_queue`).
+    #[serde(default)]
+    pub is_ordered_signing_enabled: bool,
+    /// Hex strings returned and emitted by the contract omit the `0x`
+    /// prefix. Hex inputs are accepted in either form regardless.
+    #[serde(default)]
+    pub is_bare_hex_output_enabled: bool,
+}
+
+impl Flags {
+    /// Encodes `bytes` as hex, `0x`-prefixed unless
+    /// `is_bare_hex_output_enabled` is set.
+    pub fn encode_hex(&self, bytes: impl AsRef<[u8]>) -> String {
+        if self.is_bare_hex_output_enabled {
+            hex::encode(bytes)
+        } else {
+            hex::encode_prefixed(bytes)
+        }
+    }
+}
+
+/// Optional parameters of `estimate_fee`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct EstimateFeeOptions {
+    #[serde(default)]
+    pub funding: Option<FundingOptions>,
+    /// Asset whose minimum fee and signer deposit apply; NEAR if omitted.
+    #[serde(default)]
+    pub local_asset_id: Option<AssetId>,
+    /// Priority tier of the sequence; 0 if omitted.
+    #[serde(default)]
+    pub priority: Option<u8>,
 }
 
 #[near(serializers = [json])]
@@ -62,80 +176,177 @@ pub struct GetForeignChain {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
-#[near(serializers = [borsh, json])]
-pub struct PendingTransactionSequence {
-    pub created_by_account_id: AccountId,
-    pub signature_requests: Vec<SignatureRequest>,
-    pub created_at_block_height: U64,
-    pub escrow: Option<AssetBalance>,
-}
-
-impl PendingTransactionSequence {
-    pub fn all_signed(&self) -> bool {
-        self.signature_requests
-            .iter()
-            .all(SignatureRequest::is_signed)
-    }
+#[near(serializers = [json])]
+pub struct LocalAssetOnboardingArgs {
+    pub asset_id: AssetId,
+    pub oracle_asset_id: String,
+    pub decimals: u8,
 }
 
+/// Portable snapshot of the administrative configuration of the contract.
 #[derive(Clone, Debug, PartialEq, Eq)]
-#[near]
-pub struct TransactionSequenceSignedEventAt {
-    pub block_height: u64,
-    pub event: contract_event::TransactionSequenceSigned,
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 #[near(serializers = [json])]
-pub struct Nep141ReceiverCreateTransactionArgs {
-    pub token_id: String,
-    pub transaction_rlp_hex: String,
-    pub use_paymaster: Option<bool>,
+pub struct ContractConfiguration {
+    pub expire_sequence_after_blocks: U64,
+    pub flags: Flags,
+    pub accepted_local_assets: Vec<LocalAssetOnboardingArgs>,
+    pub foreign_chains: Vec<ChainOnboardingArgs>,
+    pub sender_whitelist: Vec<AccountId>,
+    pub receiver_whitelist: Vec<ForeignAddress>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[near(serializers = [borsh, json])]
-pub struct TransactionSequenceCreation {
-    pub id: U64,
-    pub pending_signature_count: u32,
+pub struct PendingTransactionSequence {
+    pub created_by_account_id: AccountId,
+    pub signature_requests: Vec<SignatureRequest>,
+    pub created_at_block_height: U64,
+    pub escrow: Option<AssetBalance>,
+    /// Opaque caller-provided string, e.g. an order ID.
+    pub tag: Option<String>,
+    /// The paymaster funding the sequence, to which its fee is credited.
+    pub paymaster_token_id: Option<String>,
+    /// The escrow, once it has been credited on the first signature. Used to
+    /// price fee bumps.
+    pub settled_escrow: Option<AssetBalance>,
+    /// Priority tier, 0 being the default.
+    #[serde(default)]
+    pub priority: u8,
+    /// Paid to the relayer that reports broadcasting the signed transactions
+    /// (see `report_relayed`).
+    #[serde(default)]
+    pub tip: Option<AssetBalance>,
+    /// Notified once the sequence is fully signed.
+    #[serde(default)]
+    pub callback: Option<SequenceCallback>,
+    /// For sequences spanning several chains, the part of the escrow owed to
+    /// the paymaster funding each chain. Empty otherwise, in which case the
+    /// escrow is owed to `paymaster_token_id`.
+    #[serde(default)]
+    pub funding_shares: Vec<(String, U128)>,
+    /// Paymaster budget set aside for the sequence's funding transactions.
+    #[serde(default)]
+    pub paymaster_reservations: Vec<PaymasterReservation>,
+    #[serde(default)]
+    pub signing_order: SigningOrder,
+    /// Whether a market maker has released the funding transactions of a
+    /// `SigningOrder::UserFirst` sequence for signing.
+    #[serde(default)]
+    pub is_funding_released: bool,
+    /// NEAR escrowed to pay the signer deposits of the signature requests,
+    /// the unspent part of which is refunded to the creator.
+    #[serde(default)]
+    pub signer_deposit: U128,
+    /// The tenant the sequence was created under, if any.
+    #[serde(default)]
+    pub tenant_id: Option<String>,
+    /// The recurring authorization the sequence was instantiated from, if
+    /// any. Anyone may sign such a sequence.
+    #[serde(default)]
+    pub recurring_authorization_id: Option<U64>,
+    /// Whether the sequence was created by `create_cancellation`. Its
+    /// transaction may replace the one signed with the same nonce.
+    #[serde(default)]
+    pub is_cancellation: bool,
+    /// The signed sequence that this one replaces at higher fees, if it was
+    /// created by `rebump_sequence`.
+    #[serde(default)]
+    pub replaced_sequence_id: Option<U64>,
+    /// The grantee of the session the sequence was created with, if any,
+    /// who may sign it on the creator's behalf.
+    #[serde(default)]
+    pub session_grantee_id: Option<AccountId>,
 }
 
+/// When a pending sequence stops being signable.
 #[derive(Clone, Debug, PartialEq, Eq)]
-#[near(serializers = [borsh, json])]
-pub struct ChainKeyData {
-    pub public_key_bytes: Vec<u8>,
-    pub authorization: ChainKeyAuthorization,
+#[near(serializers = [json])]
+pub struct SequenceExpiry {
+    pub created_at_block_height: U64,
+    /// Last block height at which the sequence can be signed.
+    pub expires_at_block_height: U64,
+    /// Blocks remaining after the current one until expiry, or `None` once
+    /// the sequence has expired.
+    pub remaining_blocks: Option<U64>,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-#[near(serializers = [borsh, json])]
-pub enum ChainKeyAuthorization {
-    Owned,
-    Approved(u32),
+impl SequenceExpiry {
+    pub fn new(created_at_block_height: u64, expire_after_blocks: u64, block_height: u64) -> Self {
+        let expires_at_block_height = created_at_block_height.saturating_add(expire_after_blocks);
+
+        Self {
+            created_at_block_height: created_at_block_height.into(),
+            expires_at_block_height: expires_at_block_height.into(),
+            remaining_blocks: expires_at_block_height
+                .checked_sub(block_height)
+                .map(Into::into),
+        }
+    }
 }
 
-impl ChainKeyAuthorization {
-    pub fn is_owned(&self) -> bool {
-        matches!(self, Self::Owned)
+impl PendingTransactionSequence {
+    pub fn all_signed(&self) -> bool {
+        self.signature_requests
+            .iter()
+            .all(SignatureRequest::is_signed)
     }
 
-    pub fn is_approved(&self) -> bool {
-        matches!(self, Self::Approved(..))
+    /// Whether the sequence's transactions may replace those already signed
+    /// with the same nonces, which is checked when the sequence is created.
+    pub fn is_replacement(&self) -> bool {
+        self.is_cancellation || self.replaced_sequence_id.is_some()
     }
 
-    pub fn is_approved_with_id(&self, approval_id: u32) -> bool {
-        self == &Self::Approved(approval_id)
+    /// Index of the request to sign next according to the signing order,
+    /// if one is pending. With `SigningOrder::UserFirst`, funding
+    /// transactions wait until every user transaction has been signed.
+    pub fn next_pending_index(&self) -> Option<usize> {
+        let position = |is_paymaster: bool| {
+            self.signature_requests
+                .iter()
+                .position(|r| r.is_pending() && r.is_paymaster == is_paymaster)
+        };
+
+        match self.signing_order {
+            SigningOrder::PaymasterFirst => self
+                .signature_requests
+                .iter()
+                .position(SignatureRequest::is_pending),
+            SigningOrder::UserFirst => {
+                if self
+                    .signature_requests
+                    .iter()
+                    .any(|r| !r.is_paymaster && !r.is_signed())
+                {
+                    position(false)
+                } else {
+                    position(true)
+                }
+            }
+        }
     }
 
-    pub fn to_approval_id(&self) -> Option<u32> {
-        if let Self::Approved(approval_id) = self {
-            Some(*approval_id)
-        } else {
-            None
+    /// The chains of the signature requests, in order of first appearance.
+    pub fn chain_ids(&self) -> Vec<u64> {
+        let mut chain_ids = vec![];
+        for request in &self.signature_requests {
+            if !chain_ids.contains(&request.transaction.chain_id) {
+                chain_ids.push(request.transaction.chain_id);
+            }
         }
+        chain_ids
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near]
+pub struct TransactionSequenceSignedEventAt {
+    pub block_height: u64,
+    pub event: contract_event::TransactionSequenceSigned,
+}
+
+/// New variants must be appended: the discriminant prefixes every key of
+/// the corresponding collection in deployed state.
 #[derive(BorshStorageKey, Hash, Clone, Debug, PartialEq, Eq)]
 #[near]
 pub enum StorageKey {
@@ -150,6 +361,39 @@ pub enum StorageKey {
     UserChainKeys,
     UserChainKeysFor(AccountId),
     PaymasterKeys,
+    ForeignNonces,
+    FailedRefunds,
+    UserChainKeyRegistrants,
+    BondedPaymasters,
+    PaymasterFees,
+    PaymasterFeesFor(String),
+    SignedTransactionHashes,
+    SignedSequences,
+    AdminActions,
+    RateLimitTiers,
+    SenderRateLimitTiers,
+    SequenceCreationWindows,
+    PriorityTiers,
+    SigningQueue,
+    SequenceGasUsage,
+    RelayerTips,
+    AccountSpending,
+    SequencesByForeignTxHash,
+    DustThresholds,
+    DustBalances,
+    Sessions,
+    SessionIds,
+    DoomedSequences,
+    SignerStats,
+    Erc20TransferGas,
+    ReceiverDenylist,
+    SelectorDenylist,
+    Tenants,
+    TenantSenderWhitelist,
+    IdempotencyKeys,
+    RecurringAuthorizations,
+    IdempotencyKeyQueue,
+    DisabledPaymasters(u64),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, BorshStorageKey)]
@@ -157,6 +401,10 @@ pub enum StorageKey {
 pub enum Role {
     Administrator,
     MarketMaker,
+    IntentsContract,
+    Relayer,
+    /// Granted to accounts that register a bonded paymaster.
+    PaymasterProvider,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -185,6 +433,93 @@ pub struct Contract {
     /// TODO: Hopefully temporary measure to eliminate the need for an indexer.
     pub signed_transaction_sequences: Vector<TransactionSequenceSignedEventAt>,
     pub collected_fees: UnorderedMap<AssetId, U128>,
+    /// Highest nonce signed for each (user chain key token ID, chain ID).
+    pub foreign_nonces: LookupMap<(String, u64), u64>,
+    /// Refunds whose transfers failed, claimable by the account.
+    pub failed_refunds: LookupMap<(AccountId, AssetId), u128>,
+    /// The account each user chain key is currently registered to.
+    pub user_chain_key_registrants: LookupMap<String, AccountId>,
+    pub last_config_snapshot_block_height: u64,
+    pub paymaster_bond_settings: Option<PaymasterBondSettings>,
+    /// Paymasters registered by third parties, by token ID.
+    pub bonded_paymasters: UnorderedMap<String, BondedPaymaster>,
+    /// Fees credited to paymasters, by paymaster token ID.
+    pub paymaster_fees: UnorderedMap<String, UnorderedMap<AssetId, U128>>,
+    /// Sighash of the user transaction signed for each (user chain key token
+    /// ID, chain ID, nonce).
+    pub signed_transaction_hashes: LookupMap<(String, u64, u64), [u8; 32]>,
+    /// Fully signed sequences that can still be replaced with
+    /// `rebump_sequence`.
+    pub signed_sequences: LookupMap<u64, PendingTransactionSequence>,
+    /// Ring buffer of the most recent administrative actions.
+    pub admin_actions: Vector<AdminAction>,
+    /// Total number of administrative actions recorded.
+    pub admin_action_count: u64,
+    /// Rate limit for accounts without a tier.
+    pub rate_limit: Option<RateLimit>,
+    pub rate_limit_tiers: UnorderedMap<String, RateLimit>,
+    pub sender_rate_limit_tiers: LookupMap<AccountId, String>,
+    pub sequence_creation_windows: LookupMap<AccountId, SequenceCreationWindow>,
+    /// Verifies proofs submitted with `submit_paymaster_balance_proof`.
+    pub balance_prover_id: Option<AccountId>,
+    /// Fee multiplier of each priority tier above 0.
+    pub priority_tiers: UnorderedMap<u8, (U128, U128)>,
+    /// Creation block height of each sequence left to sign, keyed by
+    /// `(u8::MAX - priority, id)`.
+    pub signing_queue: TreeMap<(u8, u64), u64>,
+    pub sequence_gas_usage: LookupMap<u64, SequenceGasUsage>,
+    /// Number of sequences and gas used across all of them.
+    pub sequence_gas_totals: (u64, u128),
+    /// Root of a Merkle tree of additional whitelisted receivers, for
+    /// allowlists too large to store.
+    pub receiver_whitelist_merkle_root: Option<[u8; 32]>,
+    /// Tips credited to relayers, claimable by the relayer.
+    pub relayer_tips: LookupMap<(AccountId, AssetId), u128>,
+    /// Epoch-decayed sponsored gas tokens, keyed by `(sender, chain_id)`.
+    pub account_spending: LookupMap<(AccountId, u64), AccountSpending>,
+    /// Sequence ID by keccak256 hash of each signed user transaction.
+    pub sequences_by_foreign_tx_hash: LookupMap<[u8; 32], u64>,
+    /// Largest refund of each asset that is kept as dust.
+    pub dust_thresholds: LookupMap<AssetId, u128>,
+    /// Refund dust held for each account, applied to its next sponsored sequence.
+    pub dust_balances: LookupMap<(AccountId, AssetId), u128>,
+    pub signing_gas_budget: SigningGasBudget,
+    /// Delegated creation rights granted with `grant_session`, by session ID.
+    pub sessions: LookupMap<u64, SessionGrant>,
+    /// Active session of each `(owner, grantee)` pair.
+    pub session_ids: LookupMap<(AccountId, AccountId), u64>,
+    pub next_session_id: u64,
+    /// Bytes used by the most recent registration of a new user's chain key.
+    pub registration_storage_usage: u64,
+    /// Root public keys of the MPC signer behind the chain key NFT contract,
+    /// by key version, used to verify the public keys reported for chain
+    /// keys.
+    pub signer_root_public_keys: BTreeMap<u32, PublicKey>,
+    /// Signed sequences that market makers reported as impossible to relay.
+    pub doomed_sequences: LookupMap<u64, DoomedSequence>,
+    /// Signature request outcomes by signer contract.
+    pub signer_stats: UnorderedMap<AccountId, SignerStats>,
+    /// Gas limit of `transfer_erc20` by `(chain_id, token_address)`.
+    pub erc20_transfer_gas: LookupMap<(u64, ForeignAddress), u128>,
+    /// Contract that foreign chain parameters are synced from.
+    pub chain_registry_id: Option<AccountId>,
+    /// Receivers and function selectors that are denied even if whitelisted.
+    pub receiver_denylist: UnorderedSet<ForeignAddress>,
+    pub selector_denylist: UnorderedSet<[u8; 4]>,
+    /// If set, sponsored transactions are screened by this contract.
+    pub screening_id: Option<AccountId>,
+    pub tenants: UnorderedMap<String, Tenant>,
+    pub tenant_sender_whitelist: LookupSet<(String, AccountId)>,
+    /// Sequence creations by creator and client-provided idempotency key.
+    pub idempotency_keys: LookupMap<(AccountId, String), IdempotencyKeyRecord>,
+    /// Expiry of each idempotency key, in the order of first use, so that
+    /// expired keys can be removed as new ones are used.
+    pub idempotency_key_queue: LookupMap<u64, (u64, AccountId, String)>,
+    /// Index of the oldest entry of `idempotency_key_queue`, and of the next.
+    pub idempotency_key_queue_bounds: (u64, u64),
+    /// Pre-approved recurring sequences, by authorization ID.
+    pub recurring_authorizations: LookupMap<u64, RecurringAuthorization>,
+    pub next_recurring_authorization_id: u64,
 }
 
 #[near_bindgen]
@@ -214,6 +549,52 @@ impl Contract {
             ),
             signed_transaction_sequences: Vector::new(StorageKey::SignedTransactionSequences),
             collected_fees: UnorderedMap::new(StorageKey::CollectedFees),
+            foreign_nonces: LookupMap::new(StorageKey::ForeignNonces),
+            failed_refunds: LookupMap::new(StorageKey::FailedRefunds),
+            user_chain_key_registrants: LookupMap::new(StorageKey::UserChainKeyRegistrants),
+            last_config_snapshot_block_height: 0,
+            paymaster_bond_settings: None,
+            bonded_paymasters: UnorderedMap::new(StorageKey::BondedPaymasters),
+            paymaster_fees: UnorderedMap::new(StorageKey::PaymasterFees),
+            signed_transaction_hashes: LookupMap::new(StorageKey::SignedTransactionHashes),
+            signed_sequences: LookupMap::new(StorageKey::SignedSequences),
+            admin_actions: Vector::new(StorageKey::AdminActions),
+            admin_action_count: 0,
+            rate_limit: None,
+            rate_limit_tiers: UnorderedMap::new(StorageKey::RateLimitTiers),
+            sender_rate_limit_tiers: LookupMap::new(StorageKey::SenderRateLimitTiers),
+            sequence_creation_windows: LookupMap::new(StorageKey::SequenceCreationWindows),
+            balance_prover_id: None,
+            priority_tiers: UnorderedMap::new(StorageKey::PriorityTiers),
+            signing_queue: TreeMap::new(StorageKey::SigningQueue),
+            sequence_gas_usage: LookupMap::new(StorageKey::SequenceGasUsage),
+            sequence_gas_totals: (0, 0),
+            receiver_whitelist_merkle_root: None,
+            relayer_tips: LookupMap::new(StorageKey::RelayerTips),
+            account_spending: LookupMap::new(StorageKey::AccountSpending),
+            sequences_by_foreign_tx_hash: LookupMap::new(StorageKey::SequencesByForeignTxHash),
+            dust_thresholds: LookupMap::new(StorageKey::DustThresholds),
+            dust_balances: LookupMap::new(StorageKey::DustBalances),
+            signing_gas_budget: SigningGasBudget::default(),
+            sessions: LookupMap::new(StorageKey::Sessions),
+            session_ids: LookupMap::new(StorageKey::SessionIds),
+            next_session_id: 0,
+            registration_storage_usage: INITIAL_REGISTRATION_STORAGE_USAGE,
+            signer_root_public_keys: BTreeMap::new(),
+            doomed_sequences: LookupMap::new(StorageKey::DoomedSequences),
+            signer_stats: UnorderedMap::new(StorageKey::SignerStats),
+            erc20_transfer_gas: LookupMap::new(StorageKey::Erc20TransferGas),
+            chain_registry_id: None,
+            receiver_denylist: UnorderedSet::new(StorageKey::ReceiverDenylist),
+            selector_denylist: UnorderedSet::new(StorageKey::SelectorDenylist),
+            screening_id: None,
+            tenants: UnorderedMap::new(StorageKey::Tenants),
+            tenant_sender_whitelist: LookupSet::new(StorageKey::TenantSenderWhitelist),
+            idempotency_keys: LookupMap::new(StorageKey::IdempotencyKeys),
+            idempotency_key_queue: LookupMap::new(StorageKey::IdempotencyKeyQueue),
+            idempotency_key_queue_bounds: (0, 0),
+            recurring_authorizations: LookupMap::new(StorageKey::RecurringAuthorizations),
+            next_recurring_authorization_id: 0,
         };
 
         Rbac::add_role(
@@ -224,447 +605,6 @@ impl Contract {
 
         contract
     }
-
-    // Public methods
-
-    #[payable]
-    pub fn create_transaction(
-        &mut self,
-        token_id: String,
-        transaction_rlp_hex: String,
-        use_paymaster: Option<bool>,
-    ) -> PromiseOrValue<TransactionSequenceCreation> {
-        self.create_transaction_inner(
-            token_id,
-            env::predecessor_account_id(),
-            transaction_rlp_hex,
-            use_paymaster,
-            AssetBalance::native(env::attached_deposit().as_yoctonear()),
-        )
-    }
-
-    fn create_transaction_inner(
-        &mut self,
-        token_id: String,
-        account_id: AccountId,
-        transaction_rlp_hex: String,
-        use_paymaster: Option<bool>,
-        deposit: AssetBalance,
-    ) -> PromiseOrValue<TransactionSequenceCreation> {
-        <Self as Pause>::require_unpaused();
-
-        let transaction =
-            ValidTransactionRequest::try_from(decode_transaction_request(&transaction_rlp_hex))
-                .unwrap_or_reject();
-
-        // Whitelisting
-        self.filter_transaction(&account_id, &transaction);
-
-        // Assert predecessor can use requested key path
-        let user_chain_keys = self
-            .user_chain_keys
-            .get(&account_id)
-            .expect_or_reject("No managed keys for predecessor");
-
-        let user_chain_key = user_chain_keys
-            .get(&token_id)
-            .expect_or_reject("Predecessor unauthorized for the requested chain key token ID");
-
-        let use_paymaster = use_paymaster.unwrap_or(false);
-
-        if use_paymaster {
-            require!(deposit.amount.0 > 0, "Deposit is required to pay for gas");
-
-            let accepted_local_asset = self
-                .accepted_local_assets
-                .get(&deposit.asset_id)
-                .expect_or_reject("Unsupported deposit asset");
-
-            let chain_id = transaction.chain_id();
-            let foreign_chain_configuration = self.get_chain(chain_id.as_u64()).unwrap_or_reject();
-
-            ext_pyth::ext(self.oracle_id.clone())
-                .get_ema_price(pyth::PriceIdentifier(accepted_local_asset.oracle_asset_id))
-                .and(
-                    ext_pyth::ext(self.oracle_id.clone()).get_ema_price(pyth::PriceIdentifier(
-                        foreign_chain_configuration.oracle_asset_id,
-                    )),
-                )
-                .then(
-                    Self::ext(env::current_account_id()).create_transaction_callback(
-                        account_id,
-                        token_id,
-                        deposit,
-                        transaction,
-                    ),
-                )
-                .into()
-        } else {
-            let chain_id = transaction.chain_id;
-
-            let pending_transaction_sequence = PendingTransactionSequence {
-                signature_requests: vec![SignatureRequest::new(
-                    &token_id,
-                    user_chain_key.authorization,
-                    transaction,
-                    false,
-                )],
-                created_by_account_id: account_id,
-                created_at_block_height: env::block_height().into(),
-                escrow: None,
-            };
-
-            let creation = self.insert_transaction_sequence(&pending_transaction_sequence);
-
-            ContractEvent::TransactionSequenceCreated(TransactionSequenceCreated {
-                id: creation.id,
-                foreign_chain_id: chain_id.to_string(),
-                pending_transaction_sequence,
-            })
-            .emit();
-
-            PromiseOrValue::Value(creation)
-        }
-    }
-
-    fn try_create_transaction_callback(
-        &mut self,
-        sender: &AccountId,
-        token_id: String,
-        deposit: &AssetBalance,
-        transaction_request: ValidTransactionRequest,
-        local_asset_price_result: Result<pyth::Price, PromiseError>,
-        foreign_asset_price_result: Result<pyth::Price, PromiseError>,
-    ) -> Result<(u128, TransactionSequenceCreation), TryCreateTransactionCallbackError> {
-        let local_asset_price = local_asset_price_result.map_err(|_| OracleQueryFailureError)?;
-        let foreign_asset_price =
-            foreign_asset_price_result.map_err(|_| OracleQueryFailureError)?;
-
-        let accepted_local_asset = self
-            .accepted_local_assets
-            .get(&deposit.asset_id)
-            .unwrap_or_reject();
-
-        let user_chain_key = self
-            .user_chain_keys
-            .get(sender)
-            .and_then(|user_chain_keys| user_chain_keys.get(&token_id))
-            .ok_or_else(|| SenderUnauthorizedForNftChainKeyError {
-                sender: sender.clone(),
-                token_id: token_id.clone(),
-            })?;
-
-        let sender_foreign_address =
-            ForeignAddress::from_raw_public_key(&user_chain_key.public_key_bytes);
-
-        let mut foreign_chain = self
-            .foreign_chains
-            .get(&transaction_request.chain_id)
-            .ok_or(ChainConfigurationDoesNotExistError {
-                chain_id: transaction_request.chain_id,
-            })?;
-
-        let gas_tokens_to_sponsor_transaction =
-            foreign_chain.calculate_gas_tokens_to_sponsor_transaction(&transaction_request)?;
-
-        let local_asset_fee = foreign_chain.price_for_gas_tokens(
-            gas_tokens_to_sponsor_transaction,
-            &foreign_asset_price,
-            &local_asset_price,
-            accepted_local_asset.decimals,
-        )?;
-
-        let refund = deposit.amount.0.checked_sub(local_asset_fee).ok_or(
-            InsufficientDepositForFeeError {
-                deposit: deposit.amount.0,
-                fee: local_asset_fee,
-            },
-        )?;
-
-        let paymaster_signature_request = self.create_funding_signature_request(
-            &mut foreign_chain,
-            &transaction_request,
-            sender_foreign_address,
-            gas_tokens_to_sponsor_transaction,
-        )?;
-
-        self.foreign_chains
-            .insert(&transaction_request.chain_id, &foreign_chain);
-
-        // After this point, the function should be virtually infallible, excluding out-of-gas errors.
-
-        let signature_requests = vec![
-            paymaster_signature_request,
-            SignatureRequest::new(
-                &token_id,
-                user_chain_key.authorization,
-                transaction_request.clone(),
-                false,
-            ),
-        ];
-
-        let pending_transaction_sequence = PendingTransactionSequence {
-            signature_requests,
-            created_by_account_id: sender.clone(),
-            created_at_block_height: env::block_height().into(),
-            escrow: Some(AssetBalance {
-                amount: local_asset_fee.into(),
-                asset_id: deposit.asset_id.clone(),
-            }),
-        };
-
-        let creation = self.insert_transaction_sequence(&pending_transaction_sequence);
-
-        ContractEvent::TransactionSequenceCreated(TransactionSequenceCreated {
-            id: creation.id,
-            foreign_chain_id: transaction_request.chain_id.to_string(),
-            pending_transaction_sequence,
-        })
-        .emit();
-
-        Ok((refund, creation))
-    }
-
-    #[private]
-    pub fn create_transaction_callback(
-        &mut self,
-        #[serializer(borsh)] sender: AccountId,
-        #[serializer(borsh)] token_id: String,
-        #[serializer(borsh)] deposit: AssetBalance,
-        #[serializer(borsh)] transaction_request: ValidTransactionRequest,
-        #[callback_result] local_asset_price_result: Result<pyth::Price, PromiseError>,
-        #[callback_result] foreign_asset_price_result: Result<pyth::Price, PromiseError>,
-    ) -> PromiseOrValue<TransactionSequenceCreation> {
-        let (refund, creation) = match self.try_create_transaction_callback(
-            &sender,
-            token_id,
-            &deposit,
-            transaction_request,
-            local_asset_price_result,
-            foreign_asset_price_result,
-        ) {
-            Ok((refund, creation)) => (refund, creation),
-            Err(e) => {
-                // Failure: return deposit.
-                return PromiseOrValue::Promise(
-                    deposit
-                        .asset_id
-                        .transfer(sender, deposit.amount)
-                        .then(Self::ext(env::current_account_id()).throw(e.to_string())),
-                );
-            }
-        };
-
-        if refund > 0 {
-            // Refund excess
-            deposit.asset_id.transfer(sender, refund);
-        }
-
-        PromiseOrValue::Value(creation)
-    }
-
-    #[private]
-    pub fn throw(&mut self, #[serializer(borsh)] error_str: String) {
-        env::panic_str(&error_str);
-    }
-
-    pub fn sign_next(&mut self, id: U64) -> Promise {
-        <Self as Pause>::require_unpaused();
-
-        let id = id.0;
-
-        let mut transaction = self
-            .pending_transaction_sequences
-            .get(&id)
-            .expect_or_reject(TransactionSequenceDoesNotExistError {
-                transaction_sequence_id: id,
-            });
-
-        // ensure not expired
-        require!(
-            env::block_height().saturating_sub(transaction.created_at_block_height.0)
-                <= self.expire_sequence_after_blocks,
-            "Transaction is expired",
-        );
-
-        // ensure only signed by original creator
-        require!(
-            transaction.created_by_account_id == env::predecessor_account_id(),
-            "Predecessor must be the transaction creator",
-        );
-
-        let (index, next_signature_request) = transaction
-            .signature_requests
-            .iter_mut()
-            .enumerate()
-            .find(|(_, r)| r.is_pending())
-            .expect_or_reject("No pending or non-in-flight signature requests");
-
-        next_signature_request.status = Status::InFlight;
-
-        #[allow(clippy::cast_possible_truncation)]
-        let ret = ext_chain_key_token::ext(self.signer_contract_id.clone())
-            .with_attached_deposit(NearToken::from_yoctonear(1))
-            .ckt_sign_hash(
-                next_signature_request.token_id.clone(),
-                None,
-                sighash_for_mpc_signing(next_signature_request.transaction.clone()).to_vec(),
-                next_signature_request.authorization.to_approval_id(),
-            )
-            .then(
-                Self::ext(env::current_account_id())
-                    .with_static_gas(Self::SIGN_NEXT_CALLBACK_GAS)
-                    .with_unused_gas_weight(0)
-                    .sign_next_callback(id.into(), index as u32),
-            );
-
-        self.pending_transaction_sequences.insert(&id, &transaction);
-
-        ret
-    }
-
-    const SIGN_NEXT_CALLBACK_GAS: Gas = Gas::from_tgas(3);
-
-    #[private]
-    pub fn sign_next_callback(
-        &mut self,
-        id: U64,
-        index: u32,
-        #[callback_result] result: Result<String, PromiseError>,
-    ) -> String {
-        let id = id.0;
-
-        let mut pending_transaction_sequence = self
-            .pending_transaction_sequences
-            .get(&id)
-            .expect_or_reject(TransactionSequenceDoesNotExistError {
-                transaction_sequence_id: id,
-            });
-
-        let request = pending_transaction_sequence
-            .signature_requests
-            .get_mut(index as usize)
-            .expect_or_reject(SignatureRequestDoesNoteExistError {
-                transaction_sequence_id: id,
-                index,
-            });
-
-        if !request.is_in_flight() {
-            env::panic_str(&format!(
-                "Inconsistent state: Signature request {id}.{index} should be in-flight but is not"
-            ));
-        }
-
-        // TODO: Fraud proofs.
-        let signature = result
-            .ok()
-            .expect_or_reject("Failed to produce signature")
-            .parse()
-            .unwrap_or_reject();
-
-        let transaction: TypedTransaction = request.transaction.clone().into();
-
-        let rlp_signed = transaction.rlp_signed(&signature);
-
-        request.set_signature(signature);
-
-        // Remove escrow from record.
-        // This is important to ensuring that refund logic works correctly.
-        if let Some(escrow) = pending_transaction_sequence.escrow.take() {
-            let mut collected_fees = self.collected_fees.get(&escrow.asset_id).unwrap_or(U128(0));
-            // This should not fail, but if it does fail, that means the token
-            // in question incorrectly implements the NEP-141 standard, which
-            // dictates that the total supply fits in 128 bits.
-            collected_fees.0 = collected_fees
-                .0
-                .checked_add(escrow.amount.0)
-                .unwrap_or_reject();
-            self.collected_fees
-                .insert(&escrow.asset_id, &collected_fees);
-        }
-
-        let chain_id = request.transaction.chain_id;
-
-        let all_signatures = pending_transaction_sequence
-            .signature_requests
-            .iter()
-            .try_fold(vec![], |mut v, r| {
-                if let Status::Signed { signature } = &r.status {
-                    v.push((r.transaction.clone(), signature.clone()));
-                    Some(v)
-                } else {
-                    None
-                }
-            });
-
-        self.pending_transaction_sequences
-            .insert(&id, &pending_transaction_sequence);
-
-        if let Some(all_signatures) = all_signatures {
-            let e = TransactionSequenceSigned {
-                id: id.into(),
-                foreign_chain_id: chain_id.to_string(),
-                created_by_account_id: pending_transaction_sequence.created_by_account_id.clone(),
-                signed_transactions: all_signatures
-                    .into_iter()
-                    .map(|(t, s)| {
-                        hex::encode_prefixed(t.into_typed_transaction().rlp_signed(&s.into()))
-                    })
-                    .collect(),
-            };
-
-            self.signed_transaction_sequences
-                .push(&TransactionSequenceSignedEventAt {
-                    block_height: env::block_height(),
-                    event: e.clone(),
-                });
-
-            ContractEvent::TransactionSequenceSigned(e).emit();
-
-            // Remove transaction if all requests have been signed
-            self.pending_transaction_sequences.remove(&id);
-        }
-
-        hex::encode_prefixed(&rlp_signed)
-    }
-
-    pub fn remove_transaction(&mut self, id: U64) -> PromiseOrValue<()> {
-        <Self as Pause>::require_unpaused();
-
-        let transaction = self
-            .pending_transaction_sequences
-            .get(&id.0)
-            .expect_or_reject(TransactionSequenceDoesNotExistError {
-                transaction_sequence_id: id.0,
-            });
-
-        require!(
-            transaction.created_by_account_id == env::predecessor_account_id(),
-            "Unauthorized",
-        );
-
-        for signature_request in &transaction.signature_requests {
-            require!(
-                !signature_request.is_in_flight(),
-                "Signature request is in-flight and cannot be removed",
-            );
-        }
-
-        let ret = transaction
-            .escrow
-            .as_ref()
-            .map_or(PromiseOrValue::Value(()), |escrow| {
-                PromiseOrValue::Promise(
-                    escrow
-                        .asset_id
-                        .transfer(transaction.created_by_account_id.clone(), escrow.amount),
-                )
-            });
-
-        self.pending_transaction_sequences.remove(&id.0);
-
-        ret
-    }
 }
 
 impl Contract {
@@ -706,87 +646,9 @@ impl Contract {
             .expect_or_reject("Failed to generate unique ID");
         id
     }
+}
 
-    fn filter_transaction(&self, sender_id: &AccountId, transaction: &ValidTransactionRequest) {
-        // Check receiver whitelist
-        if self.flags.is_receiver_whitelist_enabled {
-            require!(
-                self.receiver_whitelist.contains(&transaction.to),
-                "Receiver is not whitelisted",
-            );
-        }
-
-        // Check sender whitelist
-        if self.flags.is_sender_whitelist_enabled {
-            require!(
-                self.sender_whitelist.contains(sender_id),
-                "Sender is not whitelisted",
-            );
-        }
-    }
-
-    /// Create a paymaster funding transaction that provides funding for the
-    /// maximum amount of gas required by the transaction.
-    ///
-    /// # Errors
-    ///
-    /// - If the foreign chain ID is not supported.
-    /// - If there is not a paymaster configured for the foreign chain.
-    /// - If the price data provided is invalid.
-    /// - If the paymaster does not have enough available balance.
-    pub fn create_funding_signature_request(
-        &self,
-        foreign_chain: &mut ForeignChainConfiguration,
-        transaction: &ValidTransactionRequest,
-        sender_foreign_address: ForeignAddress,
-        gas_tokens_to_sponsor_transaction: U256,
-    ) -> Result<SignatureRequest, RequestNonceError> {
-        foreign_chain.with_request_nonce(
-            gas_tokens_to_sponsor_transaction,
-            |foreign_chain, paymaster| {
-                let paymaster_transaction = ValidTransactionRequest {
-                    chain_id: transaction.chain_id,
-                    to: sender_foreign_address,
-                    value: gas_tokens_to_sponsor_transaction.0,
-                    gas: foreign_chain.transfer_gas,
-                    data: vec![],
-                    nonce: U256::from(paymaster.nonce).0,
-                    access_list_rlp: vec![0xc0 /* rlp encoding for empty list */],
-                    max_priority_fee_per_gas: transaction.max_priority_fee_per_gas,
-                    max_fee_per_gas: transaction.max_fee_per_gas,
-                };
-
-                let paymaster_authorization = self
-                    .paymaster_keys
-                    .get(&paymaster.token_id)
-                    .unwrap_or_reject() // inconsistent state if this fails
-                    .authorization;
-
-                SignatureRequest::new(
-                    &paymaster.token_id,
-                    paymaster_authorization,
-                    paymaster_transaction,
-                    true,
-                )
-            },
-        )
-    }
-
-    fn insert_transaction_sequence(
-        &mut self,
-        pending_transaction: &PendingTransactionSequence,
-    ) -> TransactionSequenceCreation {
-        #[allow(clippy::cast_possible_truncation)]
-        let pending_signature_count = pending_transaction.signature_requests.len() as u32;
-
-        let id = self.generate_unique_id();
-
-        self.pending_transaction_sequences
-            .insert(&id, pending_transaction);
-
-        TransactionSequenceCreation {
-            id: id.into(),
-            pending_signature_count,
-        }
-    }
+/// Options for token transfers issued by the contract.
+fn transfer_options(memo: &str) -> TransferOptions {
+    TransferOptions::with_memo(memo).with_gas(TOKEN_TRANSFER_GAS)
 }
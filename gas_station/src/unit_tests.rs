@@ -0,0 +1,995 @@
+use ethers_core::types::{U256, U512};
+use lib::{
+    asset::{AssetBalance, AssetId},
+    foreign_address::ForeignAddress,
+    kdf::derive_public_key_for,
+    oracle::PYTH_PRICE_ID_ETH_USD,
+    pyth::Price,
+};
+use near_sdk::{
+    env,
+    json_types::{I64, U128, U64},
+    test_utils::{accounts, VMContextBuilder},
+    testing_env, CurveType, PromiseOrValue, PublicKey,
+};
+use proptest::prelude::*;
+
+use crate::{
+    chain_configuration::{
+        CalldataPricing, ForeignChainConfiguration, PaymasterConfiguration, PaymasterReservation,
+        RollupFeeModel, SponsorshipStatistics,
+    },
+    impl_legacy_storage::legacy_paymaster_key,
+    impl_recurring::RecurringAuthorization,
+    signature_request::SignatureRequest,
+    valid_transaction_request::ValidTransactionRequest,
+    ChainKeyAuthorization, ChainKeyData, Contract, Flags, Nep141ReceiverCreateTransactionArgs,
+    PendingTransactionSequence, RateLimit, SigningOrder, TransactionSequenceCreation,
+    IDEMPOTENCY_KEY_TTL_BLOCKS,
+};
+
+const CHAIN_ID: u64 = 1;
+
+fn set_context(block_height: u64) {
+    testing_env!(VMContextBuilder::new()
+        .current_account_id(accounts(0))
+        .predecessor_account_id(accounts(0))
+        .block_height(block_height)
+        .build());
+}
+
+fn setup() -> Contract {
+    set_context(10);
+    let mut contract = Contract::new(accounts(1), accounts(2), None);
+    contract.add_foreign_chain(
+        CHAIN_ID.into(),
+        PYTH_PRICE_ID_ETH_USD.to_string(),
+        U128(21000),
+        (U128(120), U128(100)),
+        18,
+    );
+    contract
+}
+
+fn add_paymaster(contract: &mut Contract, token_id: &str, nonce: u32, balance: u128) {
+    contract.with_mut_chain(CHAIN_ID, |config| {
+        config.paymasters.insert(
+            &token_id.to_string(),
+            &PaymasterConfiguration {
+                nonce,
+                token_id: token_id.to_string(),
+                minimum_available_balance: U256::from(balance).0,
+            },
+        );
+    });
+}
+
+fn paymaster(contract: &Contract, token_id: &str) -> PaymasterConfiguration {
+    contract
+        .get_chain(CHAIN_ID)
+        .unwrap()
+        .paymasters
+        .get(&token_id.to_string())
+        .unwrap()
+}
+
+fn transaction(to: ForeignAddress) -> ValidTransactionRequest {
+    ValidTransactionRequest {
+        to,
+        gas: U256::from(21000).0,
+        value: U256::from(100).0,
+        data: vec![],
+        nonce: U256::zero().0,
+        access_list_rlp: vec![0xc0],
+        max_priority_fee_per_gas: U256::from(50_000_000).0,
+        max_fee_per_gas: U256::from(15_000_000_000u64).0,
+        chain_id: CHAIN_ID,
+    }
+}
+
+#[test]
+fn filter_allows_everything_without_whitelists() {
+    let contract = setup();
+
+    assert!(contract
+        .check_transaction_filter(&accounts(3), &transaction(ForeignAddress([1; 20])), None)
+        .is_ok());
+}
+
+#[test]
+fn filter_enforces_receiver_whitelist() {
+    let mut contract = setup();
+    contract.set_flags(Flags {
+        is_receiver_whitelist_enabled: true,
+        ..Flags::default()
+    });
+    contract.add_to_receiver_whitelist(vec![ForeignAddress([1; 20])]);
+
+    assert!(contract
+        .check_transaction_filter(&accounts(3), &transaction(ForeignAddress([1; 20])), None)
+        .is_ok());
+    assert_eq!(
+        contract.check_transaction_filter(
+            &accounts(3),
+            &transaction(ForeignAddress([2; 20])),
+            None
+        ),
+        Err("Receiver is not whitelisted"),
+    );
+}
+
+#[test]
+fn filter_enforces_sender_whitelist() {
+    let mut contract = setup();
+    contract.set_flags(Flags {
+        is_sender_whitelist_enabled: true,
+        ..Flags::default()
+    });
+    contract.add_to_sender_whitelist(vec![accounts(3)]);
+
+    let transaction = transaction(ForeignAddress([1; 20]));
+    assert!(contract
+        .check_transaction_filter(&accounts(3), &transaction, None)
+        .is_ok());
+    assert_eq!(
+        contract.check_transaction_filter(&accounts(4), &transaction, None),
+        Err("Sender is not whitelisted"),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Sender is not whitelisted")]
+fn filter_panics_on_rejected_transaction() {
+    let mut contract = setup();
+    contract.set_flags(Flags {
+        is_sender_whitelist_enabled: true,
+        ..Flags::default()
+    });
+
+    contract.filter_transaction(&accounts(3), &transaction(ForeignAddress([1; 20])), None);
+}
+
+#[test]
+fn denylist_overrides_whitelists() {
+    let mut contract = setup();
+    contract.set_flags(Flags {
+        is_receiver_whitelist_enabled: true,
+        ..Flags::default()
+    });
+    contract.add_to_receiver_whitelist(vec![ForeignAddress([1; 20]), ForeignAddress([2; 20])]);
+    contract.add_to_receiver_denylist(vec![ForeignAddress([2; 20])]);
+    contract.add_to_selector_denylist(vec!["0xa9059cbb".to_string()]);
+
+    let mut erc20_transfer = transaction(ForeignAddress([1; 20]));
+    erc20_transfer.data = vec![0xa9, 0x05, 0x9c, 0xbb, 0];
+
+    assert!(contract
+        .check_transaction_filter(&accounts(3), &transaction(ForeignAddress([1; 20])), None)
+        .is_ok());
+    assert_eq!(
+        contract.check_transaction_filter(
+            &accounts(3),
+            &transaction(ForeignAddress([2; 20])),
+            None
+        ),
+        Err("Receiver is denied"),
+    );
+    assert_eq!(
+        contract.check_transaction_filter(&accounts(3), &erc20_transfer, None),
+        Err("Function selector is denied"),
+    );
+
+    contract.remove_from_selector_denylist(vec!["a9059cbb".to_string()]);
+    assert!(contract
+        .check_transaction_filter(&accounts(3), &erc20_transfer, None)
+        .is_ok());
+}
+
+#[test]
+fn rate_limit_resets_after_window() {
+    let mut contract = setup();
+    contract.set_rate_limit(Some(RateLimit {
+        max_sequences: 2,
+        window_blocks: U64(10),
+    }));
+
+    contract.consume_rate_limit(&accounts(3), 10).unwrap();
+    contract.consume_rate_limit(&accounts(3), 15).unwrap();
+    assert!(contract.consume_rate_limit(&accounts(3), 19).is_err());
+    // Other accounts have their own window.
+    contract.consume_rate_limit(&accounts(4), 19).unwrap();
+
+    contract.consume_rate_limit(&accounts(3), 20).unwrap();
+}
+
+#[test]
+fn rate_limit_tier_overrides_global_limit() {
+    let mut contract = setup();
+    contract.set_rate_limit(Some(RateLimit {
+        max_sequences: 1,
+        window_blocks: U64(10),
+    }));
+    contract.set_rate_limit_tier(
+        "pro".to_string(),
+        Some(RateLimit {
+            max_sequences: 3,
+            window_blocks: U64(10),
+        }),
+    );
+    contract.set_sender_rate_limit_tier(accounts(3), Some("pro".to_string()));
+
+    for _ in 0..3 {
+        contract.consume_rate_limit(&accounts(3), 10).unwrap();
+    }
+    assert!(contract.consume_rate_limit(&accounts(3), 10).is_err());
+
+    contract.consume_rate_limit(&accounts(4), 10).unwrap();
+    assert!(contract.consume_rate_limit(&accounts(4), 10).is_err());
+}
+
+#[test]
+fn escrow_without_paymaster_is_collected() {
+    let mut contract = setup();
+
+    contract.settle_escrow(AssetBalance::native(1000), None);
+
+    assert_eq!(
+        contract.get_collected_fees().get(&AssetId::Native),
+        Some(&U128(1000)),
+    );
+}
+
+#[test]
+fn escrow_with_paymaster_is_credited_to_paymaster() {
+    let mut contract = setup();
+
+    contract.settle_escrow(AssetBalance::native(1000), Some("pm".to_string()));
+
+    assert_eq!(
+        contract
+            .get_paymaster_fees("pm".to_string())
+            .get(&AssetId::Native),
+        Some(&U128(1000)),
+    );
+    assert!(contract.get_collected_fees().is_empty());
+}
+
+#[test]
+fn doomed_refund_is_debited_from_funding_paymasters() {
+    let mut contract = setup();
+
+    contract.settle_escrow(AssetBalance::native(600), Some("pm1".to_string()));
+    contract.settle_escrow(AssetBalance::native(400), Some("pm2".to_string()));
+    contract.add_collected_fees(&AssetId::Native, 50);
+
+    let sequence = PendingTransactionSequence {
+        created_by_account_id: accounts(3),
+        signature_requests: vec![],
+        created_at_block_height: U64(10),
+        escrow: None,
+        tag: None,
+        paymaster_token_id: None,
+        settled_escrow: Some(AssetBalance::native(1000)),
+        priority: 0,
+        tip: None,
+        callback: None,
+        funding_shares: vec![
+            ("pm1".to_string(), U128(600)),
+            ("pm2".to_string(), U128(400)),
+        ],
+        paymaster_reservations: vec![],
+        signing_order: SigningOrder::default(),
+        is_funding_released: false,
+        signer_deposit: U128(0),
+        tenant_id: None,
+        recurring_authorization_id: None,
+        is_cancellation: false,
+        replaced_sequence_id: None,
+        session_grantee_id: None,
+    };
+
+    contract
+        .unsettle_escrow(&sequence, &AssetBalance::native(500))
+        .unwrap();
+
+    let fees = |contract: &Contract, token_id: &str| {
+        contract
+            .get_paymaster_fees(token_id.to_string())
+            .get(&AssetId::Native)
+            .copied()
+    };
+    assert_eq!(fees(&contract, "pm1"), Some(U128(300)));
+    assert_eq!(fees(&contract, "pm2"), Some(U128(200)));
+    assert_eq!(
+        contract.get_collected_fees().get(&AssetId::Native),
+        Some(&U128(50)),
+    );
+    assert!(contract
+        .unsettle_escrow(&sequence, &AssetBalance::native(1001))
+        .is_err());
+}
+
+#[test]
+fn multichain_escrow_is_settled_per_signed_funding() {
+    let mut contract = setup();
+
+    let request = |token_id: &str, is_paymaster: bool| {
+        SignatureRequest::new(
+            &token_id,
+            ChainKeyAuthorization::Owned,
+            transaction(ForeignAddress([1; 20])),
+            is_paymaster,
+        )
+    };
+    let mut sequence = PendingTransactionSequence {
+        created_by_account_id: accounts(3),
+        signature_requests: vec![
+            request("pm1", true),
+            request("user", false),
+            request("pm2", true),
+            request("user", false),
+        ],
+        created_at_block_height: U64(10),
+        escrow: Some(AssetBalance::native(1000)),
+        tag: None,
+        paymaster_token_id: None,
+        settled_escrow: None,
+        priority: 0,
+        tip: None,
+        callback: None,
+        funding_shares: vec![
+            ("pm1".to_string(), U128(600)),
+            ("pm2".to_string(), U128(400)),
+        ],
+        paymaster_reservations: vec![],
+        signing_order: SigningOrder::default(),
+        is_funding_released: false,
+        signer_deposit: U128(0),
+        tenant_id: None,
+        recurring_authorization_id: None,
+        is_cancellation: false,
+        replaced_sequence_id: None,
+        session_grantee_id: None,
+    };
+
+    let fees = |contract: &Contract, token_id: &str| {
+        contract
+            .get_paymaster_fees(token_id.to_string())
+            .get(&AssetId::Native)
+            .copied()
+    };
+
+    contract.settle_signed_escrow(&mut sequence, 0);
+    contract.settle_signed_escrow(&mut sequence, 1);
+
+    assert_eq!(fees(&contract, "pm1"), Some(U128(600)));
+    assert_eq!(fees(&contract, "pm2"), None);
+    assert_eq!(sequence.escrow, Some(AssetBalance::native(400)));
+    assert_eq!(sequence.settled_escrow, Some(AssetBalance::native(600)));
+
+    contract.settle_signed_escrow(&mut sequence, 2);
+
+    assert_eq!(fees(&contract, "pm2"), Some(U128(400)));
+    assert_eq!(sequence.escrow, None);
+    assert_eq!(sequence.settled_escrow, Some(AssetBalance::native(1000)));
+}
+
+#[test]
+fn request_nonce_consumes_nonce_and_balance() {
+    let mut contract = setup();
+    add_paymaster(&mut contract, "pm", 5, 1000);
+
+    let nonce = contract
+        .with_mut_chain(CHAIN_ID, |config| {
+            config.with_request_nonce(U256::from(400), |_, paymaster| paymaster.nonce)
+        })
+        .unwrap();
+
+    assert_eq!(nonce, 5);
+    let paymaster = paymaster(&contract, "pm");
+    assert_eq!(paymaster.nonce, 6);
+    assert_eq!(U256(paymaster.minimum_available_balance), U256::from(600));
+}
+
+#[test]
+fn request_nonce_fails_on_insufficient_balance() {
+    let mut contract = setup();
+    add_paymaster(&mut contract, "pm", 5, 100);
+
+    assert!(contract
+        .with_mut_chain(CHAIN_ID, |config| {
+            config.with_request_nonce(U256::from(400), |_, _| ())
+        })
+        .is_err());
+
+    let paymaster = paymaster(&contract, "pm");
+    assert_eq!(paymaster.nonce, 5);
+    assert_eq!(U256(paymaster.minimum_available_balance), U256::from(100));
+}
+
+#[test]
+fn released_reservation_restores_latest_nonce_only() {
+    let mut contract = setup();
+    add_paymaster(&mut contract, "pm", 5, 1000);
+
+    let reserve = |contract: &mut Contract| {
+        let nonce = contract
+            .with_mut_chain(CHAIN_ID, |config| {
+                config.with_request_nonce(U256::from(100), |_, paymaster| paymaster.nonce)
+            })
+            .unwrap();
+        PaymasterReservation {
+            chain_id: CHAIN_ID,
+            token_id: "pm".to_string(),
+            amount: U256::from(100).0,
+            nonce: Some(nonce),
+        }
+    };
+
+    let first = reserve(&mut contract);
+    let second = reserve(&mut contract);
+
+    // Releasing an earlier reservation would leave a gap in the nonces.
+    contract.with_mut_chain(CHAIN_ID, |config| config.release_reservation(&first));
+    let paymaster_config = paymaster(&contract, "pm");
+    assert_eq!(paymaster_config.nonce, 7);
+    assert_eq!(
+        U256(paymaster_config.minimum_available_balance),
+        U256::from(900),
+    );
+
+    contract.with_mut_chain(CHAIN_ID, |config| config.release_reservation(&second));
+    let paymaster_config = paymaster(&contract, "pm");
+    assert_eq!(paymaster_config.nonce, 6);
+    assert_eq!(
+        U256(paymaster_config.minimum_available_balance),
+        U256::from(1000),
+    );
+}
+
+#[test]
+fn chain_key_public_key_must_match_derivation() {
+    let mut contract = setup();
+
+    let root_public_key: PublicKey = "secp256k1:37aFybhUHCxRdDkuCcB3yHzxqK7N8EQ745MujyAQohXSsYymVeHzhLxKvZ2qYeRHf3pGFiAsxqFJZjpF9gP2JV5u"
+        .parse()
+        .unwrap();
+    let derived = derive_public_key_for(root_public_key.clone(), &accounts(1), "7,").unwrap();
+    let public_key =
+        PublicKey::from_parts(CurveType::SECP256K1, derived.as_bytes()[1..].to_vec()).unwrap();
+
+    // Unverifiable without the root key.
+    assert!(contract
+        .verify_chain_key_public_key("8", &public_key)
+        .is_ok());
+
+    contract.set_signer_root_public_key(0, Some(root_public_key));
+
+    assert!(contract
+        .verify_chain_key_public_key("7", &public_key)
+        .is_ok());
+    assert!(contract
+        .verify_chain_key_public_key("8", &public_key)
+        .is_err());
+
+    // Tokens upgraded to a new key version report keys derived from its root.
+    let upgraded_root_public_key = public_key;
+    let derived =
+        derive_public_key_for(upgraded_root_public_key.clone(), &accounts(1), "8,").unwrap();
+    let upgraded_public_key =
+        PublicKey::from_parts(CurveType::SECP256K1, derived.as_bytes()[1..].to_vec()).unwrap();
+
+    assert!(contract
+        .verify_chain_key_public_key("8", &upgraded_public_key)
+        .is_err());
+
+    contract.set_signer_root_public_key(1, Some(upgraded_root_public_key));
+
+    assert!(contract
+        .verify_chain_key_public_key("8", &upgraded_public_key)
+        .is_ok());
+}
+
+#[test]
+fn signer_stats_track_outcomes_and_latency() {
+    let mut contract = setup();
+
+    set_context(14);
+    contract.record_signer_result(Some(U64(10)), true);
+    set_context(20);
+    contract.record_signer_result(Some(U64(18)), true);
+    contract.record_signer_result(Some(U64(11)), false);
+
+    let stats = contract.get_signer_stats().remove(&accounts(1)).unwrap();
+    assert_eq!(stats.successes, U64(2));
+    assert_eq!(stats.failures, U64(1));
+    assert_eq!(stats.average_success_latency_blocks, Some(U64(3)));
+    assert_eq!(stats.average_failure_latency_blocks, Some(U64(9)));
+    assert_eq!(stats.max_latency_blocks, U64(9));
+    assert_eq!(stats.last_failure_block_height, Some(U64(20)));
+}
+
+#[test]
+fn legacy_paymasters_are_cleaned_up_without_touching_current_ones() {
+    let mut contract = setup();
+    add_paymaster(&mut contract, "pm", 5, 1000);
+    for index in 0..3 {
+        env::storage_write(&legacy_paymaster_key(CHAIN_ID, index), &[0; 64]);
+    }
+
+    let cleanup = contract.clean_up_legacy_paymasters(vec![CHAIN_ID.into()]);
+
+    assert_eq!(cleanup.removed_keys, U64(3));
+    assert!(cleanup.reclaimed_bytes.0 > 3 * 64);
+    assert!(!env::storage_has_key(&legacy_paymaster_key(CHAIN_ID, 0)));
+    assert_eq!(paymaster(&contract, "pm").nonce, 5);
+}
+
+#[test]
+fn whitelist_batches_count_changed_entries() {
+    let mut contract = setup();
+
+    assert_eq!(
+        contract.add_to_receiver_whitelist(vec![
+            ForeignAddress([1; 20]),
+            ForeignAddress([2; 20]),
+            ForeignAddress([1; 20]),
+        ]),
+        2,
+    );
+    assert_eq!(
+        contract
+            .remove_from_receiver_whitelist(
+                vec![ForeignAddress([2; 20]), ForeignAddress([3; 20]),]
+            ),
+        1,
+    );
+    assert_eq!(
+        contract.get_receiver_whitelist(),
+        vec![ForeignAddress([1; 20])]
+    );
+}
+
+#[test]
+#[should_panic(expected = "whitelist entries may be changed per call")]
+fn whitelist_batches_are_limited() {
+    let mut contract = setup();
+    contract.add_to_sender_whitelist(vec![accounts(3); crate::MAX_WHITELIST_BATCH_SIZE + 1]);
+}
+
+#[test]
+fn unsponsored_fee_is_charged_in_configured_assets_only() {
+    let mut contract = setup();
+    assert_eq!(contract.unsponsored_fee(CHAIN_ID, &AssetId::Native), Ok(0));
+
+    contract.set_foreign_chain_unsponsored_fee(CHAIN_ID.into(), AssetId::Native, Some(U128(50)));
+
+    assert_eq!(contract.unsponsored_fee(CHAIN_ID, &AssetId::Native), Ok(50));
+    assert!(contract
+        .unsponsored_fee(CHAIN_ID, &AssetId::Nep141(accounts(4)))
+        .is_err());
+    assert_eq!(
+        contract.unsponsored_fee(CHAIN_ID + 1, &AssetId::Native),
+        Ok(0)
+    );
+}
+
+#[test]
+fn idempotency_key_returns_original_creation_until_expired() {
+    let mut contract = setup();
+    let mut next_id = 0;
+    let mut create = |contract: &mut Contract| {
+        contract.with_idempotency_key(
+            accounts(3),
+            Some("retry".to_string()),
+            AssetBalance::native(0),
+            |_, _| {
+                next_id += 1;
+                PromiseOrValue::Value(TransactionSequenceCreation {
+                    id: U64(next_id),
+                    pending_signature_count: 1,
+                    paymaster_funding: None,
+                })
+            },
+        )
+    };
+    let id = |creation: PromiseOrValue<TransactionSequenceCreation>| match creation {
+        PromiseOrValue::Value(TransactionSequenceCreation { id, .. }) => id,
+        PromiseOrValue::Promise(_) => panic!("Expected a value"),
+    };
+
+    assert_eq!(id(create(&mut contract)), U64(1));
+    set_context(10 + IDEMPOTENCY_KEY_TTL_BLOCKS);
+    assert_eq!(id(create(&mut contract)), U64(1));
+    set_context(11 + IDEMPOTENCY_KEY_TTL_BLOCKS);
+    assert_eq!(id(create(&mut contract)), U64(2));
+}
+
+#[test]
+fn idempotency_keys_are_pruned_as_new_keys_are_used() {
+    let mut contract = setup();
+    let create = |contract: &mut Contract, idempotency_key: &str| {
+        contract.with_idempotency_key(
+            accounts(3),
+            Some(idempotency_key.to_string()),
+            AssetBalance::native(0),
+            |_, _| {
+                PromiseOrValue::Value(TransactionSequenceCreation {
+                    id: U64(0),
+                    pending_signature_count: 1,
+                    paymaster_funding: None,
+                })
+            },
+        );
+    };
+    let is_stored = |contract: &Contract, idempotency_key: &str| {
+        contract
+            .idempotency_keys
+            .get(&(accounts(3), idempotency_key.to_string()))
+            .is_some()
+    };
+
+    create(&mut contract, "a");
+    create(&mut contract, "b");
+    set_context(10 + IDEMPOTENCY_KEY_TTL_BLOCKS);
+    create(&mut contract, "c");
+    assert!(is_stored(&contract, "a"));
+
+    set_context(11 + IDEMPOTENCY_KEY_TTL_BLOCKS);
+    create(&mut contract, "d");
+    assert!(!is_stored(&contract, "a"));
+    assert!(!is_stored(&contract, "b"));
+    assert!(is_stored(&contract, "c"));
+    assert_eq!(contract.idempotency_key_queue_bounds, (2, 4));
+}
+
+#[test]
+fn recurring_authorization_skips_missed_periods() {
+    let mut authorization = RecurringAuthorization {
+        owner_id: accounts(3),
+        token_id: "1".to_string(),
+        transaction: transaction(ForeignAddress([1; 20])),
+        use_paymaster: Some(true),
+        max_fee: U128(100),
+        tag: None,
+        period_blocks: U64(10),
+        max_occurrences: 3,
+        occurrences: 0,
+        next_block_height: U64(20),
+        remaining_balance: U128(250),
+        storage_deposit: U128(0),
+    };
+
+    assert!(authorization.draw(19).is_err());
+    authorization.draw(20).unwrap();
+    assert_eq!(authorization.next_block_height, U64(30));
+    authorization.draw(55).unwrap();
+    assert_eq!(authorization.next_block_height, U64(60));
+    assert_eq!(authorization.remaining_balance, U128(50));
+    assert!(authorization.draw(60).is_err());
+    assert_eq!(authorization.occurrences, 2);
+}
+
+#[test]
+fn approval_ids() {
+    assert_eq!(ChainKeyAuthorization::Owned.to_approval_id(), None);
+    assert_eq!(ChainKeyAuthorization::Approved(3).to_approval_id(), Some(3));
+    assert!(ChainKeyAuthorization::Approved(3).is_approved_with_id(3));
+    assert!(!ChainKeyAuthorization::Approved(3).is_approved_with_id(4));
+    assert!(!ChainKeyAuthorization::Owned.is_approved());
+}
+
+#[test]
+fn keys_without_public_key_are_incomplete() {
+    let mut key = ChainKeyData {
+        public_key_bytes: vec![],
+        authorization: ChainKeyAuthorization::Owned,
+        is_nonce_tracking_enabled: false,
+        signing_policy_id: None,
+    };
+    assert!(!key.has_public_key());
+
+    key.public_key_bytes = vec![4; 65];
+    assert!(key.has_public_key());
+}
+
+fn split_args(max_fee: Option<u128>, tip: Option<u128>) -> Nep141ReceiverCreateTransactionArgs {
+    Nep141ReceiverCreateTransactionArgs {
+        token_id: String::new(),
+        transaction_rlp_hex: String::new(),
+        use_paymaster: Some(true),
+        max_fee: max_fee.map(U128),
+        tip: tip.map(U128),
+        callback: None,
+    }
+}
+
+#[test]
+fn whole_deposit_is_fee_by_default() {
+    assert_eq!(split_args(None, None).split_deposit(100), Some((100, 0, 0)));
+}
+
+#[test]
+fn deposit_fee_is_capped() {
+    assert_eq!(
+        split_args(Some(60), Some(10)).split_deposit(100),
+        Some((60, 10, 30))
+    );
+    assert_eq!(
+        split_args(Some(200), Some(10)).split_deposit(100),
+        Some((90, 10, 0))
+    );
+}
+
+#[test]
+fn tip_exceeding_deposit_is_rejected() {
+    assert_eq!(split_args(None, Some(101)).split_deposit(100), None);
+}
+
+proptest! {
+    #[test]
+    fn deposit_split_adds_up(
+        amount in any::<u128>(),
+        max_fee in proptest::option::of(any::<u128>()),
+        tip in proptest::option::of(any::<u128>()),
+    ) {
+        if let Some((fee, tip, unused)) = split_args(max_fee, tip).split_deposit(amount) {
+            prop_assert_eq!(fee + tip + unused, amount);
+            prop_assert!(max_fee.map_or(true, |max_fee| fee <= max_fee));
+        }
+    }
+}
+
+fn calldata_transaction(data_bytes: usize) -> ValidTransactionRequest {
+    ValidTransactionRequest {
+        to: ForeignAddress([1; 20]),
+        gas: U256::from(100_000).0,
+        value: U256::zero().0,
+        data: vec![0xab; data_bytes],
+        nonce: U256::zero().0,
+        access_list_rlp: vec![0xc0],
+        max_priority_fee_per_gas: U256::from(1).0,
+        max_fee_per_gas: U256::from(10).0,
+        chain_id: 10,
+    }
+}
+
+#[test]
+fn calldata_is_free_without_pricing() {
+    let cost = ForeignChainConfiguration::for_test(10)
+        .calculate_gas_tokens_to_sponsor_transaction(
+            &calldata_transaction(1000),
+            U256::from(21_000),
+        )
+        .unwrap();
+
+    assert_eq!(cost, U256::from(121_000 * 10));
+}
+
+#[test]
+fn calldata_is_charged_per_byte_with_overhead() {
+    let mut chain = ForeignChainConfiguration::for_test(10);
+    chain.calldata_pricing = Some(CalldataPricing {
+        per_byte_cost: 16.into(),
+        fixed_overhead: 2_100.into(),
+        max_data_bytes: None,
+    });
+
+    let cost = chain
+        .calculate_gas_tokens_to_sponsor_transaction(
+            &calldata_transaction(1000),
+            U256::from(21_000),
+        )
+        .unwrap();
+
+    assert_eq!(cost, U256::from(121_000 * 10 + 1000 * 16 + 2_100));
+}
+
+#[test]
+fn data_size_limit() {
+    let mut chain = ForeignChainConfiguration::for_test(10);
+    chain.calldata_pricing = Some(CalldataPricing {
+        max_data_bytes: Some(100),
+        ..Default::default()
+    });
+
+    assert!(chain.check_data_size(&calldata_transaction(100)).is_ok());
+    assert!(chain.check_data_size(&calldata_transaction(101)).is_err());
+}
+
+#[test]
+fn l1_data_gas_counts_zero_bytes_cheaper() {
+    assert_eq!(
+        RollupFeeModel::l1_data_gas(&[0, 0, 1, 0xff]),
+        4 + 4 + 16 + 16
+    );
+}
+
+#[test]
+fn rollup_l1_fee_is_added_and_rounded_up() {
+    let model = RollupFeeModel {
+        l1_base_fee: 7.into(),
+        scalar: (1.into(), 3.into()),
+        overhead: 188.into(),
+    };
+
+    let tx = calldata_transaction(10);
+    let encoded = tx.clone().into_typed_transaction().rlp();
+    let l1_gas = u128::from(RollupFeeModel::l1_data_gas(&encoded)) + 188;
+    let l1_fee = (l1_gas * 7).div_ceil(3);
+
+    assert_eq!(model.l1_fee(&encoded), Some(U256::from(l1_fee)));
+
+    let mut chain = ForeignChainConfiguration::for_test(10);
+    chain.rollup_fee_model = Some(model);
+
+    let cost = chain
+        .calculate_gas_tokens_to_sponsor_transaction(&tx, U256::from(21_000))
+        .unwrap();
+
+    assert_eq!(cost, U256::from(121_000 * 10 + l1_fee));
+}
+
+#[test]
+fn sliding_window_discounts_previous_window() {
+    const WINDOW: u64 = SponsorshipStatistics::WINDOW_BLOCKS;
+
+    let mut stats = SponsorshipStatistics::default();
+    stats.record(U256::from(1000), 0);
+
+    assert_eq!(stats.sliding_window_amount(WINDOW - 1), U256::from(1000));
+    assert_eq!(stats.sliding_window_amount(WINDOW), U256::from(1000));
+    assert_eq!(
+        stats.sliding_window_amount(WINDOW + WINDOW / 2),
+        U256::from(500)
+    );
+    assert_eq!(stats.sliding_window_amount(2 * WINDOW), U256::zero());
+}
+
+#[test]
+fn breaker_trips_once_above_cap() {
+    let mut chain = ForeignChainConfiguration::for_test(CHAIN_ID);
+    chain.sponsorship_cap = Some(U256::from(1000).0);
+
+    assert_eq!(chain.record_sponsorship(U256::from(600), 10), None);
+    assert!(chain.check_sponsorship_unpaused().is_ok());
+
+    assert_eq!(
+        chain.record_sponsorship(U256::from(600), 20),
+        Some(U256::from(1200)),
+    );
+    assert!(chain.check_sponsorship_unpaused().is_err());
+
+    // Already paused: no repeated alerts.
+    assert_eq!(chain.record_sponsorship(U256::from(600), 30), None);
+}
+
+#[test]
+fn no_cap_never_trips() {
+    let mut chain = ForeignChainConfiguration::for_test(CHAIN_ID);
+
+    assert_eq!(chain.record_sponsorship(U256::MAX, 0), None);
+    assert!(chain.check_sponsorship_unpaused().is_ok());
+}
+
+fn pricing_chain(decimals: u8, fee_rate: (u128, u128)) -> ForeignChainConfiguration {
+    let mut chain = ForeignChainConfiguration::for_test(0);
+    chain.decimals = decimals;
+    chain.fee_rate = fee_rate;
+    chain
+}
+
+fn price(price: i64, conf: u64, expo: i32) -> Price {
+    Price {
+        price: I64(price),
+        conf: U64(conf),
+        expo,
+        publish_time: 0,
+    }
+}
+
+fn pow10(exp: u32) -> U512 {
+    U512::exp10(exp as usize)
+}
+
+/// Exact conversion rate as `(numerator, denominator)`, with the same
+/// pessimistic confidence interval adjustments as the contract.
+fn exact_conversion_rate(
+    this: &Price,
+    into: &Price,
+    into_decimals: u8,
+    chain_decimals: u8,
+) -> (U512, U512) {
+    let this_price = u128::try_from(this.price.0).unwrap() - u128::from(this.conf.0);
+    let into_price = u128::try_from(into.price.0).unwrap() + u128::from(into.conf.0);
+
+    let exp = this.expo - into.expo + i32::from(into_decimals) - i32::from(chain_decimals);
+
+    if exp >= 0 {
+        (
+            U512::from(this_price) * pow10(exp.unsigned_abs()),
+            U512::from(into_price),
+        )
+    } else {
+        (
+            U512::from(this_price),
+            U512::from(into_price) * pow10(exp.unsigned_abs()),
+        )
+    }
+}
+
+proptest! {
+    #[test]
+    fn price_for_gas_tokens_never_panics(
+        quantity in any::<[u64; 4]>(),
+        this in (any::<i64>(), any::<u64>(), any::<i32>()),
+        into in (any::<i64>(), any::<u64>(), any::<i32>()),
+        into_decimals in any::<u8>(),
+        chain_decimals in any::<u8>(),
+        fee_rate in (any::<u128>(), any::<u128>()),
+    ) {
+        let _ = pricing_chain(chain_decimals, fee_rate).price_for_gas_tokens(
+            U256(quantity),
+            &price(this.0, this.1, this.2),
+            &price(into.0, into.1, into.2),
+            into_decimals,
+        );
+    }
+
+    #[test]
+    fn price_for_gas_tokens_rounds_up_to_exact_price(
+        quantity in 0u128..=1_000_000_000_000_000_000_000_000,
+        (this_price, this_conf) in (1i64..=1_000_000_000_000)
+            .prop_flat_map(|p| (Just(p), 0..=p.unsigned_abs())),
+        this_expo in -12i32..=0,
+        into_price in 1i64..=1_000_000_000_000,
+        into_conf in 0u64..=1_000_000_000,
+        into_expo in -12i32..=0,
+        into_decimals in 0u8..=24,
+        chain_decimals in 0u8..=24,
+        fee_rate in (1u128..=1_000, 1u128..=1_000),
+    ) {
+        let this = price(this_price, this_conf, this_expo);
+        let into = price(into_price, into_conf, into_expo);
+        let quantity = U256::from(quantity);
+
+        let result = pricing_chain(chain_decimals, fee_rate)
+            .price_for_gas_tokens(quantity, &this, &into, into_decimals);
+
+        let rate = exact_conversion_rate(&this, &into, into_decimals, chain_decimals);
+        let numerator = U512::from(quantity) * rate.0 * U512::from(fee_rate.0);
+        let denominator = rate.1 * U512::from(fee_rate.1);
+        let (floor, rem) = numerator.div_mod(denominator);
+        let expected = if rem.is_zero() { floor } else { floor + 1 };
+
+        let u128_max = U512::from(u128::MAX);
+
+        match result {
+            Ok(charged) => {
+                // Never undercharges, and rounds up by at most one unit.
+                prop_assert_eq!(U512::from(charged), expected);
+            }
+            Err(_) => {
+                // Only acceptable if a value does not fit in the contract's
+                // intermediate or output types.
+                prop_assert!(rate.0 > u128_max || rate.1 > u128_max || expected > u128_max);
+            }
+        }
+    }
+
+    #[test]
+    fn zero_into_price_is_rejected(
+        quantity in any::<u64>(),
+        this_price in 1i64..=1_000_000_000_000,
+        decimals in 0u8..=24,
+    ) {
+        let result = pricing_chain(decimals, (1, 1)).price_for_gas_tokens(
+            U256::from(quantity),
+            &price(this_price, 0, -8),
+            &price(0, 0, -8),
+            decimals,
+        );
+
+        prop_assert!(result.is_err());
+    }
+}
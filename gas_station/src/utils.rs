@@ -1,20 +1,39 @@
 use ethers_core::{
     types::{transaction::eip2718::TypedTransaction, Eip1559TransactionRequest},
     utils::{
-        hex,
-        rlp::{Decodable, Rlp},
+        hex, keccak256,
+        rlp::{Decodable, DecoderError, Rlp},
     },
 };
-use lib::Rejectable;
+use lib::{foreign_address::ForeignAddress, Rejectable};
+use thiserror::Error;
 
 use crate::valid_transaction_request::ValidTransactionRequest;
 
-pub fn decode_transaction_request(rlp_hex: &str) -> Eip1559TransactionRequest {
-    let rlp_bytes =
-        hex::decode(rlp_hex).expect_or_reject("Error decoding `transaction_rlp` as hex");
+#[derive(Debug, Error)]
+pub enum DecodeTransactionRequestError {
+    #[error("Error decoding `transaction_rlp` as hex: {0}")]
+    Hex(#[from] hex::FromHexError),
+    #[error("Error decoding `transaction_rlp` as transaction request RLP: {0}")]
+    Rlp(#[from] DecoderError),
+}
+
+/// Non-panicking version of [`decode_transaction_request`].
+///
+/// # Errors
+///
+/// - If the input is not valid hex.
+/// - If the input is not a valid EIP-1559 transaction request RLP.
+pub fn try_decode_transaction_request(
+    rlp_hex: &str,
+) -> Result<Eip1559TransactionRequest, DecodeTransactionRequestError> {
+    let rlp_bytes = decode_hex(rlp_hex)?;
     let rlp = Rlp::new(&rlp_bytes);
-    Eip1559TransactionRequest::decode(&rlp)
-        .expect_or_reject("Error decoding `transaction_rlp` as transaction request RLP")
+    Ok(Eip1559TransactionRequest::decode(&rlp)?)
+}
+
+pub fn decode_transaction_request(rlp_hex: &str) -> Eip1559TransactionRequest {
+    try_decode_transaction_request(rlp_hex).unwrap_or_reject()
 }
 
 pub fn sighash_for_mpc_signing(signature_request: ValidTransactionRequest) -> [u8; 32] {
@@ -22,3 +41,53 @@ pub fn sighash_for_mpc_signing(signature_request: ValidTransactionRequest) -> [u
         .sighash()
         .to_fixed_bytes()
 }
+
+/// Selector of ERC-20 `transfer(address,uint256)`.
+const ERC20_TRANSFER_SELECTOR: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+
+/// ABI-encoded ERC-20 `transfer(to, amount)` call.
+pub fn erc20_transfer_calldata(to: &ForeignAddress, amount: u128) -> Vec<u8> {
+    let mut data = Vec::with_capacity(4 + 32 + 32);
+    data.extend_from_slice(&ERC20_TRANSFER_SELECTOR);
+    data.extend_from_slice(&[0u8; 12]);
+    data.extend_from_slice(&to.0);
+    data.extend_from_slice(&[0u8; 16]);
+    data.extend_from_slice(&amount.to_be_bytes());
+    data
+}
+
+/// Decodes hex with or without a `0x` (or `0X`) prefix, ignoring
+/// surrounding whitespace.
+///
+/// # Errors
+///
+/// - If the input is not valid hex.
+pub fn decode_hex(value: &str) -> Result<Vec<u8>, hex::FromHexError> {
+    let value = value.trim();
+    let bare = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+    hex::decode(bare)
+}
+
+/// Decodes a hex-encoded 32-byte hash, with or without `0x` prefix.
+pub fn decode_hash(hash_hex: &str) -> Option<[u8; 32]> {
+    decode_hex(hash_hex).ok()?.try_into().ok()
+}
+
+/// Verifies that `leaf` is included in the Merkle tree with the given root.
+/// Pairs of nodes are sorted before hashing with Keccak-256, as in
+/// `OpenZeppelin`'s `MerkleProof`, so the proof does not need to specify
+/// sibling positions.
+pub fn verify_merkle_proof(root: &[u8; 32], leaf: [u8; 32], proof: &[[u8; 32]]) -> bool {
+    let computed_root = proof.iter().fold(leaf, |node, sibling| {
+        if node <= *sibling {
+            keccak256([node, *sibling].concat())
+        } else {
+            keccak256([*sibling, node].concat())
+        }
+    });
+
+    computed_root == *root
+}
@@ -3,12 +3,18 @@ use ethers_core::{
         transaction::{eip2718::TypedTransaction, eip2930::AccessList},
         Eip1559TransactionRequest, NameOrAddress, U256, U64,
     },
-    utils::rlp::{Decodable, Encodable, Rlp},
+    utils::{
+        hex,
+        rlp::{Decodable, Encodable, Rlp},
+    },
 };
+pub use gas_station_client::Eip1559JsonRequest;
 use lib::foreign_address::ForeignAddress;
 use near_sdk::near;
 use thiserror::Error;
 
+use crate::decode_hex;
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[near(serializers = [borsh, json])]
 pub struct ValidTransactionRequest {
@@ -136,10 +142,82 @@ impl From<ValidTransactionRequest> for TypedTransaction {
     }
 }
 
+impl ValidTransactionRequest {
+    /// Builds a transaction request from its JSON representation using the
+    /// provided nonce. The `nonce` field of the JSON request is ignored.
+    ///
+    /// # Errors
+    ///
+    /// - If `data` is not valid hex.
+    /// - If the priority fee exceeds the maximum fee.
+    pub fn from_json_request(
+        request: Eip1559JsonRequest,
+        nonce: u64,
+    ) -> Result<Self, TransactionValidationError> {
+        if request.max_priority_fee_per_gas.0 > request.max_fee_per_gas.0 {
+            return Err(TransactionValidationError::PriorityFeeExceedsMaxFee);
+        }
+
+        let data = request
+            .data
+            .as_deref()
+            .map(decode_hex)
+            .transpose()
+            .map_err(|_| TransactionValidationError::InvalidData)?
+            .unwrap_or_default();
+
+        Ok(Self {
+            to: request.to,
+            gas: U256::from(request.gas.0).0,
+            value: U256::from(request.value.0).0,
+            data,
+            nonce: U256::from(nonce).0,
+            access_list_rlp: vec![0xc0 /* rlp encoding for empty list */],
+            max_priority_fee_per_gas: U256::from(request.max_priority_fee_per_gas.0).0,
+            max_fee_per_gas: U256::from(request.max_fee_per_gas.0).0,
+            chain_id: request.chain_id.0,
+        })
+    }
+
+    /// The JSON representation of the transaction.
+    ///
+    /// # Errors
+    ///
+    /// - If an amount does not fit in 128 bits.
+    pub fn to_json_request(&self) -> Result<Eip1559JsonRequest, TransactionValidationError> {
+        let to_u128 = |value: [u64; 4]| {
+            u128::try_from(U256(value))
+                .map(Into::into)
+                .map_err(|_| TransactionValidationError::AmountOverflow)
+        };
+
+        Ok(Eip1559JsonRequest {
+            chain_id: self.chain_id.into(),
+            to: self.to,
+            value: to_u128(self.value)?,
+            data: Some(hex::encode_prefixed(&self.data)),
+            gas: to_u128(self.gas)?,
+            max_fee_per_gas: to_u128(self.max_fee_per_gas)?,
+            max_priority_fee_per_gas: to_u128(self.max_priority_fee_per_gas)?,
+            nonce: Some(
+                u64::try_from(U256(self.nonce))
+                    .map_err(|_| TransactionValidationError::AmountOverflow)?
+                    .into(),
+            ),
+        })
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum TransactionValidationError {
     #[error("Missing field: `{0}`")]
     Missing(&'static str),
     #[error("Invalid receiver")]
     InvalidReceiver,
+    #[error("Invalid data: expected hex")]
+    InvalidData,
+    #[error("Priority fee exceeds maximum fee")]
+    PriorityFeeExceedsMaxFee,
+    #[error("Amount does not fit in the JSON representation")]
+    AmountOverflow,
 }
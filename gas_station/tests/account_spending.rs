@@ -0,0 +1,23 @@
+use ethers_core::types::U256;
+use gas_station::AccountSpending;
+
+#[test]
+fn spending_halves_every_epoch() {
+    let mut spending = AccountSpending::default();
+    spending.record(U256::from(1000), 10);
+
+    assert_eq!(spending.value_at(10), U256::from(1000));
+    assert_eq!(spending.value_at(11), U256::from(500));
+    assert_eq!(spending.value_at(13), U256::from(125));
+    assert_eq!(spending.value_at(1000), U256::zero());
+}
+
+#[test]
+fn recording_accumulates_decayed_value() {
+    let mut spending = AccountSpending::default();
+    spending.record(U256::from(1000), 10);
+    spending.record(U256::from(100), 12);
+
+    assert_eq!(spending.updated_at_epoch_height, 12);
+    assert_eq!(spending.value_at(12), U256::from(350));
+}
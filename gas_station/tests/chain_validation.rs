@@ -0,0 +1,26 @@
+use gas_station::chain_configuration::{
+    validate_decimals, validate_fee_rate, MAX_ASSET_DECIMALS, MAX_FEE_RATE,
+};
+
+#[test]
+fn fee_rate_must_be_positive_and_bounded() {
+    assert!(validate_fee_rate((1, 1)).is_ok());
+    assert!(validate_fee_rate((120, 100)).is_ok());
+    assert!(validate_fee_rate((MAX_FEE_RATE, 1)).is_ok());
+    assert!(validate_fee_rate((u128::MAX, u128::MAX)).is_ok());
+
+    assert!(validate_fee_rate((0, 1)).is_err());
+    assert!(validate_fee_rate((1, 0)).is_err());
+    assert!(validate_fee_rate((MAX_FEE_RATE + 1, 1)).is_err());
+    assert!(validate_fee_rate((u128::MAX, 1)).is_err());
+}
+
+#[test]
+fn decimals_must_fit_u128() {
+    assert!(validate_decimals(0).is_ok());
+    assert!(validate_decimals(24).is_ok());
+    assert!(validate_decimals(MAX_ASSET_DECIMALS).is_ok());
+    assert!(10u128.checked_pow(u32::from(MAX_ASSET_DECIMALS)).is_some());
+
+    assert!(validate_decimals(MAX_ASSET_DECIMALS + 1).is_err());
+}
@@ -0,0 +1,17 @@
+use ethers_core::utils::hex;
+use gas_station::erc20_transfer_calldata;
+use lib::foreign_address::ForeignAddress;
+
+#[test]
+fn transfer_calldata_is_abi_encoded() {
+    let calldata = erc20_transfer_calldata(&ForeignAddress([0x11; 20]), 1_000_000);
+
+    assert_eq!(
+        hex::encode(calldata),
+        concat!(
+            "a9059cbb",
+            "0000000000000000000000001111111111111111111111111111111111111111",
+            "00000000000000000000000000000000000000000000000000000000000f4240",
+        ),
+    );
+}
@@ -0,0 +1,152 @@
+//! Gas burnt by commonly called methods with 1, 10, and 100 pending
+//! sequences, so that storage refactors that make them scale worse are
+//! noticed.
+//!
+//! ```text
+//! cargo test -p gas-station --test gas_benchmarks -- --ignored --nocapture
+//! ```
+//!
+//! The report is written to `target/gas_report.json`, or to the path in
+//! `GAS_REPORT_PATH`.
+
+use ethers_core::{types::transaction::eip1559::Eip1559TransactionRequest, utils::hex};
+use gas_station::TransactionSequenceCreation;
+use lib::foreign_address::ForeignAddress;
+use near_sdk::{
+    json_types::U64,
+    serde_json::{self, json, Value},
+};
+use near_workspaces::{
+    operations::CallTransaction,
+    types::{Gas, NearToken},
+};
+use test_support::{TestEnv, TestEnvBuilder};
+
+const PENDING_SEQUENCE_COUNTS: [usize; 3] = [1, 10, 100];
+
+fn construct_eth_transaction(nonce: u64) -> Eip1559TransactionRequest {
+    Eip1559TransactionRequest {
+        chain_id: Some(0.into()),
+        from: None,
+        to: Some(ForeignAddress([1; 20]).into()),
+        data: None,
+        gas: Some(21000.into()),
+        max_fee_per_gas: Some(15_000_000_000u128.into()),
+        max_priority_fee_per_gas: Some(50_000_000u128.into()),
+        access_list: vec![].into(),
+        value: Some(100.into()),
+        nonce: Some(nonce.into()),
+    }
+}
+
+/// Executes `call`, returning the gas burnt by all of its receipts along
+/// with its return value.
+async fn measure(call: CallTransaction) -> (u64, Value) {
+    let result = call.gas(Gas::from_tgas(100)).transact().await.unwrap();
+    let gas_burnt = result.total_gas_burnt.as_gas();
+    let value = result.into_result().unwrap().json().unwrap_or(Value::Null);
+    (gas_burnt, value)
+}
+
+fn create_transaction(env: &TestEnv, nonce: u64, use_paymaster: bool) -> CallTransaction {
+    env.alice
+        .call(env.gas_station.id(), "create_transaction")
+        .args_json(json!({
+            "token_id": env.alice_key,
+            "transaction_rlp_hex": hex::encode_prefixed(construct_eth_transaction(nonce).rlp()),
+            "use_paymaster": use_paymaster,
+        }))
+        .deposit(NearToken::from_near(1))
+}
+
+async fn pending_sequence_count(env: &TestEnv) -> usize {
+    env.gas_station
+        .view("list_pending_transaction_sequences")
+        .args_json(json!({}))
+        .await
+        .unwrap()
+        .json::<serde_json::Map<String, Value>>()
+        .unwrap()
+        .len()
+}
+
+fn sequence_id(value: Value) -> U64 {
+    serde_json::from_value::<TransactionSequenceCreation>(value)
+        .unwrap()
+        .id
+}
+
+#[tokio::test]
+#[ignore = "benchmark; creates over a hundred sequences"]
+async fn gas_per_method_by_pending_sequences() {
+    let env = TestEnvBuilder::new().build().await;
+
+    let mut nonce = 0;
+    let mut report = Vec::new();
+
+    for pending_sequences in PENDING_SEQUENCE_COUNTS {
+        while pending_sequence_count(&env).await < pending_sequences {
+            measure(create_transaction(&env, nonce, false)).await;
+            nonce += 1;
+        }
+
+        let mut record = |method: &str, gas_burnt: u64| {
+            println!("{pending_sequences:>4} pending: {method:<48} {gas_burnt}");
+            report.push(json!({
+                "method": method,
+                "pending_sequences": pending_sequences,
+                "gas_burnt": gas_burnt,
+            }));
+        };
+
+        let (gas_burnt, value) = measure(create_transaction(&env, nonce, false)).await;
+        nonce += 1;
+        record("create_transaction", gas_burnt);
+        let id = sequence_id(value);
+
+        let (gas_burnt, value) = measure(create_transaction(&env, nonce, true)).await;
+        nonce += 1;
+        record("create_transaction (paymaster)", gas_burnt);
+        let paymaster_id = sequence_id(value);
+
+        let (gas_burnt, _) = measure(
+            env.alice
+                .call(env.gas_station.id(), "sign_next")
+                .args_json(json!({ "id": id })),
+        )
+        .await;
+        record("sign_next", gas_burnt);
+
+        let (gas_burnt, _) = measure(
+            env.alice
+                .call(env.gas_station.id(), "sign_next")
+                .args_json(json!({ "id": paymaster_id })),
+        )
+        .await;
+        record("sign_next (paymaster funding)", gas_burnt);
+
+        // Views are free to call, but are executed as transactions here so
+        // that their gas usage can be measured.
+        let (gas_burnt, _) = measure(
+            env.alice
+                .call(env.gas_station.id(), "get_pending_transaction_sequence")
+                .args_json(json!({ "id": paymaster_id })),
+        )
+        .await;
+        record("get_pending_transaction_sequence", gas_burnt);
+
+        let (gas_burnt, _) = measure(
+            env.alice
+                .call(env.gas_station.id(), "list_pending_transaction_sequences")
+                .args_json(json!({ "limit": 10 })),
+        )
+        .await;
+        record("list_pending_transaction_sequences", gas_burnt);
+    }
+
+    let path = std::env::var("GAS_REPORT_PATH").unwrap_or_else(|_| {
+        concat!(env!("CARGO_MANIFEST_DIR"), "/../target/gas_report.json").to_string()
+    });
+    std::fs::write(&path, serde_json::to_string_pretty(&report).unwrap()).unwrap();
+    println!("Gas report written to {path}");
+}
@@ -0,0 +1,50 @@
+use ethers_core::{types::transaction::eip1559::Eip1559TransactionRequest, utils::hex};
+use gas_station::{decode_hex, try_decode_transaction_request, Flags};
+use lib::foreign_address::ForeignAddress;
+
+fn transaction() -> Eip1559TransactionRequest {
+    Eip1559TransactionRequest {
+        chain_id: Some(1.into()),
+        from: None,
+        to: Some(ForeignAddress([1; 20]).into()),
+        data: Some(vec![0xab, 0xcd].into()),
+        gas: Some(21000.into()),
+        max_fee_per_gas: Some(15_000_000_000u128.into()),
+        max_priority_fee_per_gas: Some(50_000_000u128.into()),
+        access_list: vec![].into(),
+        value: Some(100.into()),
+        nonce: Some(0.into()),
+    }
+}
+
+#[test]
+fn decode_hex_accepts_prefixed_and_bare() {
+    assert_eq!(decode_hex("0xabcd").unwrap(), vec![0xab, 0xcd]);
+    assert_eq!(decode_hex("0XABCD").unwrap(), vec![0xab, 0xcd]);
+    assert_eq!(decode_hex("abcd").unwrap(), vec![0xab, 0xcd]);
+    assert_eq!(decode_hex(" 0xabcd\n").unwrap(), vec![0xab, 0xcd]);
+    assert_eq!(decode_hex("0x").unwrap(), Vec::<u8>::new());
+
+    assert!(decode_hex("0xabc").is_err());
+    assert!(decode_hex("xyz").is_err());
+}
+
+#[test]
+fn transaction_requests_decode_in_either_form() {
+    let rlp = transaction().rlp();
+
+    let prefixed = try_decode_transaction_request(&hex::encode_prefixed(&rlp)).unwrap();
+    let bare = try_decode_transaction_request(&hex::encode(&rlp)).unwrap();
+
+    assert_eq!(prefixed, bare);
+    assert_eq!(prefixed.data, transaction().data);
+}
+
+#[test]
+fn hex_output_is_prefixed_by_default() {
+    let mut flags = Flags::default();
+    assert_eq!(flags.encode_hex([0xab, 0xcd]), "0xabcd");
+
+    flags.is_bare_hex_output_enabled = true;
+    assert_eq!(flags.encode_hex([0xab, 0xcd]), "abcd");
+}
@@ -0,0 +1,21 @@
+use gas_station::SequenceExpiry;
+
+#[test]
+fn counts_down_to_expiry() {
+    let expiry = SequenceExpiry::new(100, 300, 150);
+
+    assert_eq!(expiry.created_at_block_height.0, 100);
+    assert_eq!(expiry.expires_at_block_height.0, 400);
+    assert_eq!(expiry.remaining_blocks.map(|b| b.0), Some(250));
+}
+
+#[test]
+fn signable_until_expiry_block() {
+    assert_eq!(
+        SequenceExpiry::new(100, 300, 400)
+            .remaining_blocks
+            .map(|b| b.0),
+        Some(0),
+    );
+    assert_eq!(SequenceExpiry::new(100, 300, 401).remaining_blocks, None);
+}
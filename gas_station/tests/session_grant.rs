@@ -0,0 +1,47 @@
+use gas_station::SessionGrant;
+
+fn session(max_sequences: u32, budget: u128) -> SessionGrant {
+    SessionGrant {
+        owner_id: "owner.near".parse().unwrap(),
+        grantee_id: "dapp.near".parse().unwrap(),
+        token_id: "1".to_string(),
+        max_sequences,
+        sequences_created: 0,
+        remaining_budget: budget.into(),
+        storage_deposit: 0.into(),
+        expires_at_block_height: 100.into(),
+    }
+}
+
+#[test]
+fn draw_takes_from_budget() {
+    let mut session = session(3, 1000);
+
+    session.draw(400, 10).unwrap();
+    session.draw(600, 11).unwrap();
+
+    assert_eq!(session.sequences_created, 2);
+    assert_eq!(session.remaining_budget.0, 0);
+}
+
+#[test]
+fn draw_respects_limits() {
+    let mut session = session(2, 1000);
+
+    assert!(session.draw(1001, 10).is_err());
+    assert_eq!(session.sequences_created, 0);
+    assert_eq!(session.remaining_budget.0, 1000);
+
+    session.draw(0, 10).unwrap();
+    session.draw(0, 10).unwrap();
+    assert!(session.draw(0, 10).is_err());
+}
+
+#[test]
+fn draw_fails_once_expired() {
+    let mut session = session(2, 1000);
+
+    assert!(!session.is_expired(99));
+    assert!(session.is_expired(100));
+    assert!(session.draw(0, 100).is_err());
+}
@@ -13,242 +13,19 @@ use gas_station::{
 };
 use lib::{
     asset::AssetId,
+    events::gas_station::{GasStationEvent, GasStationEventV0_1_0},
     foreign_address::ForeignAddress,
     kdf::get_mpc_address,
     oracle::{decode_pyth_price_id, PYTH_PRICE_ID_ETH_USD, PYTH_PRICE_ID_NEAR_USD},
     pyth,
     signer::SignResult,
 };
-use near_sdk::{json_types::U128, serde::Deserialize, serde_json::json};
-use near_workspaces::{
-    network::Sandbox,
-    operations::Function,
-    types::{Gas, NearToken},
-    Account, Contract, Worker,
-};
+use near_sdk::{json_types::U128, serde_json::json};
+use near_workspaces::types::{Gas, NearToken};
+use test_support::{TestEnv, TestEnvBuilder};
 
-#[allow(dead_code)]
-struct Setup {
-    worker: Worker<Sandbox>,
-    gas_station: Contract,
-    oracle: Contract,
-    signer: Contract,
-    nft_key: Contract,
-    local_ft: Contract,
-    alice: Account,
-    alice_key: String,
-    paymaster_key: String,
-    mark_the_market_maker: Account,
-}
-
-async fn setup() -> Setup {
-    let worker = near_workspaces::sandbox().await.unwrap();
-
-    let (gas_station, oracle, signer, nft_key, local_ft, alice, mark_the_market_maker) = tokio::join!(
-        async {
-            let wasm = near_workspaces::compile_project("./").await.unwrap();
-            worker.dev_deploy(&wasm).await.unwrap()
-        },
-        async {
-            let wasm = near_workspaces::compile_project("../mock/oracle")
-                .await
-                .unwrap();
-            worker.dev_deploy(&wasm).await.unwrap()
-        },
-        async {
-            let wasm = near_workspaces::compile_project("../mock/signer")
-                .await
-                .unwrap();
-            worker.dev_deploy(&wasm).await.unwrap()
-        },
-        async {
-            let wasm = near_workspaces::compile_project("../nft_key")
-                .await
-                .unwrap();
-            worker.dev_deploy(&wasm).await.unwrap()
-        },
-        async {
-            let wasm = near_workspaces::compile_project("../mock/local_ft")
-                .await
-                .unwrap();
-            let c = worker.dev_deploy(&wasm).await.unwrap();
-            c.call("new")
-                .args_json(json!({}))
-                .transact()
-                .await
-                .unwrap()
-                .unwrap();
-            c
-        },
-        async { worker.dev_create_account().await.unwrap() },
-        async { worker.dev_create_account().await.unwrap() },
-    );
-
-    println!("{:<16} {}", "Gas Station:", gas_station.id());
-    println!("{:<16} {}", "Oracle:", oracle.id());
-    println!("{:<16} {}", "Signer:", signer.id());
-    println!("{:<16} {}", "NFT Key:", nft_key.id());
-    println!("{:<16} {}", "Local FT:", local_ft.id());
-    println!("{:<16} {}", "Alice:", alice.id());
-    println!("{:<16} {}", "Mark:", mark_the_market_maker.id());
-
-    println!("Initializing the contracts...");
-
-    println!("Initializing NFT key contract...");
-    nft_key
-        .call("new")
-        .args_json(json!({
-            "signer_contract_id": signer.id(),
-        }))
-        .max_gas()
-        .transact()
-        .await
-        .unwrap()
-        .unwrap();
-
-    println!("Initializing gas station contract with Alice as owner...");
-    alice
-        .batch(gas_station.id())
-        .call(Function::new("new").args_json(json!({
-            "signer_contract_id": nft_key.id(),
-            "oracle_id": oracle.id(),
-        })))
-        .call(Function::new("add_accepted_local_asset").args_json(json!({
-            "asset_id": AssetId::Native,
-            "oracle_asset_id": PYTH_PRICE_ID_NEAR_USD,
-            "decimals": 24,
-        })))
-        .call(Function::new("add_accepted_local_asset").args_json(json!({
-            "asset_id": AssetId::Nep141(local_ft.id().as_str().parse().unwrap()),
-            "oracle_asset_id": PYTH_PRICE_ID_ETH_USD,
-            "decimals": 18,
-        })))
-        .call(Function::new("add_foreign_chain").args_json(json!({
-            "chain_id": "0",
-            "oracle_asset_id": PYTH_PRICE_ID_ETH_USD,
-            "transfer_gas": "21000",
-            "fee_rate": ["120", "100"],
-            "decimals": 18,
-        })))
-        .call(Function::new("add_market_maker").args_json(json!({
-            "account_id": mark_the_market_maker.id(),
-        })))
-        .transact()
-        .await
-        .unwrap()
-        .unwrap();
-
-    println!("Performing storage deposits...");
-    tokio::join!(
-        async {
-            alice
-                .call(nft_key.id(), "storage_deposit")
-                .args_json(json!({}))
-                .deposit(NearToken::from_near(1))
-                .transact()
-                .await
-                .unwrap()
-                .unwrap();
-        },
-        async {
-            alice
-                .call(nft_key.id(), "storage_deposit")
-                .args_json(json!({
-                    "account_id": gas_station.id(),
-                }))
-                .deposit(NearToken::from_near(1))
-                .transact()
-                .await
-                .unwrap()
-                .unwrap();
-        }
-    );
-
-    println!("Generating paymaster NFT key...");
-    let paymaster_key = alice
-        .call(nft_key.id(), "mint")
-        .max_gas()
-        .transact()
-        .await
-        .unwrap()
-        .json::<u32>()
-        .unwrap()
-        .to_string();
-
-    println!("Paymaster key: {paymaster_key}");
-
-    println!("Approving paymaster NFT key to gas station...");
-    alice
-        .call(nft_key.id(), "ckt_approve_call")
-        .args_json(json!({
-            "account_id": gas_station.id(),
-            "token_id": paymaster_key,
-            "msg": near_sdk::serde_json::to_string(&gas_station::ChainKeyReceiverMsg {
-                is_paymaster: true,
-            }).unwrap(),
-        }))
-        .deposit(NearToken::from_yoctonear(1))
-        .max_gas()
-        .transact()
-        .await
-        .unwrap()
-        .unwrap();
-
-    println!("Adding paymaster...");
-    alice
-        .call(gas_station.id(), "add_paymaster")
-        .args_json(json!({
-            "chain_id": "0",
-            "balance": U128(10 * 10u128.pow(18)),
-            "nonce": 0,
-            "token_id": paymaster_key,
-        }))
-        .transact()
-        .await
-        .unwrap()
-        .unwrap();
-
-    println!("Generating Alice's NFT key...");
-    let alice_key = alice
-        .call(nft_key.id(), "mint")
-        .max_gas()
-        .transact()
-        .await
-        .unwrap()
-        .json::<u32>()
-        .unwrap()
-        .to_string();
-
-    println!("Alice's NFT key: {alice_key}");
-
-    println!("Approving Alice's NFT key to be used by gas station...");
-    alice
-        .call(nft_key.id(), "ckt_approve_call")
-        .args_json(json!({
-            "account_id": gas_station.id(),
-            "token_id": alice_key,
-        }))
-        .deposit(NearToken::from_yoctonear(1))
-        .max_gas()
-        .transact()
-        .await
-        .unwrap()
-        .unwrap();
-
-    println!("Initialization complete.");
-
-    Setup {
-        worker,
-        gas_station,
-        oracle,
-        signer,
-        nft_key,
-        local_ft,
-        alice,
-        alice_key,
-        paymaster_key,
-        mark_the_market_maker,
-    }
+async fn setup() -> TestEnv {
+    TestEnvBuilder::new().build().await
 }
 
 fn construct_eth_transaction(chain_id: u64) -> Eip1559TransactionRequest {
@@ -267,9 +44,9 @@ fn construct_eth_transaction(chain_id: u64) -> Eip1559TransactionRequest {
 }
 
 #[tokio::test]
-#[should_panic = "Smart contract panicked: Attached deposit is less than fee"]
+#[should_panic = "insufficient_deposit_for_fee"]
 async fn fail_price_estimation_minus_one_is_insufficient() {
-    let Setup {
+    let TestEnv {
         gas_station,
         oracle,
         alice,
@@ -337,7 +114,7 @@ async fn fail_price_estimation_minus_one_is_insufficient() {
 
 #[tokio::test]
 async fn test_price_estimation() {
-    let Setup {
+    let TestEnv {
         gas_station,
         oracle,
         alice,
@@ -438,7 +215,7 @@ async fn test_price_estimation() {
 #[tokio::test]
 #[should_panic = "Smart contract panicked: Configuration for chain ID 99999 does not exist"]
 async fn fail_unsupported_chain_id() {
-    let Setup {
+    let TestEnv {
         gas_station,
         alice,
         alice_key,
@@ -464,7 +241,7 @@ async fn fail_unsupported_chain_id() {
 
 #[tokio::test]
 async fn test_workflow_happy_path() {
-    let Setup {
+    let TestEnv {
         gas_station,
         oracle,
         local_ft,
@@ -519,6 +296,9 @@ async fn test_workflow_happy_path() {
                 token_id: alice_key.clone(),
                 transaction_rlp_hex: hex::encode_prefixed(&eth_transaction.rlp()),
                 use_paymaster: Some(true),
+                max_fee: None,
+                tip: None,
+                callback: None,
             }).unwrap(),
         }))
         .deposit(NearToken::from_yoctonear(1))
@@ -527,28 +307,16 @@ async fn test_workflow_happy_path() {
         .await
         .unwrap();
 
-    let id = {
-        #[derive(Deserialize)]
-        #[serde(crate = "near_sdk::serde")]
-        struct Event {
-            data: EventData,
-        }
-
-        #[derive(Deserialize)]
-        #[serde(crate = "near_sdk::serde")]
-        struct EventData {
-            id: near_sdk::json_types::U64,
-        }
-
-        res.logs()
-            .into_iter()
-            .find_map(|log| {
-                log.strip_prefix("EVENT_JSON:")
-                    .and_then(|s| near_sdk::serde_json::from_str(s).ok())
-            })
-            .map(|e: Event| e.data.id)
-            .unwrap()
-    };
+    let id = res
+        .logs()
+        .into_iter()
+        .find_map(|log| match GasStationEvent::from_log(log).ok()? {
+            GasStationEvent::V0_1_0(GasStationEventV0_1_0::TransactionSequenceCreated(e)) => {
+                Some(e.id)
+            }
+            GasStationEvent::V0_1_0(_) => None,
+        })
+        .unwrap();
 
     assert_eq!(id, 0.into(), "First transaction ID");
 
@@ -577,6 +345,17 @@ async fn test_workflow_happy_path() {
 
     assert_eq!(tx.pending_signature_count, 2, "Two signatures are pending");
 
+    let paymaster_funding = tx
+        .paymaster_funding
+        .as_ref()
+        .expect("Paymaster funding details are returned");
+    assert_eq!(paymaster_funding.paymaster_token_id, paymaster_key);
+    assert_eq!(
+        paymaster_funding.value.0,
+        ((21000 + 21000) * 15_000_000_000u128),
+        "Funding covers the maximum gas spend",
+    );
+
     println!("Dispatching first signature...");
 
     let signed_tx_1 = alice
@@ -641,6 +420,9 @@ async fn test_workflow_happy_path() {
             id: tx.id,
             foreign_chain_id: "0".to_string(),
             created_by_account_id: alice.id().as_str().parse().unwrap(),
+            tag: None,
+            token_id: alice_key.clone(),
+            sender_foreign_address: Some(alice_foreign_address),
             signed_transactions: vec![signed_tx_1, signed_tx_2],
         }]
     );
@@ -675,8 +457,8 @@ async fn test_workflow_happy_path() {
         },
         async {
             gas_station
-                .view("get_collected_fees")
-                .args_json(json!({}))
+                .view("get_paymaster_fees")
+                .args_json(json!({ "token_id": paymaster_key }))
                 .await
                 .unwrap()
                 .json::<std::collections::HashMap<AssetId, U128>>()
@@ -726,6 +508,7 @@ async fn test_workflow_happy_path() {
         .call(gas_station.id(), "withdraw_collected_fees")
         .args_json(json!({
             "asset_id": AssetId::Native,
+            "paymaster_token_id": paymaster_key,
         }))
         .deposit(NearToken::from_yoctonear(1))
         .transact()
@@ -747,7 +530,7 @@ async fn test_workflow_happy_path() {
 
 #[tokio::test]
 async fn test_nft_keys_approvals_revoked() {
-    let Setup {
+    let TestEnv {
         gas_station,
         nft_key,
         alice,
@@ -800,6 +583,103 @@ async fn test_nft_keys_approvals_revoked() {
     assert!(tx.is_failure(), "Contract should not have approval anymore");
 }
 
+#[tokio::test]
+async fn test_nft_key_transferred_mid_sequence() {
+    let TestEnv {
+        worker,
+        gas_station,
+        nft_key,
+        alice,
+        alice_key,
+        ..
+    } = setup().await;
+
+    let bob = worker.dev_create_account().await.unwrap();
+
+    println!("Alice creates a transaction...");
+    let tx = alice
+        .call(gas_station.id(), "create_transaction")
+        .args_json(json!({
+            "token_id": alice_key,
+            "transaction_rlp_hex": hex::encode_prefixed(&construct_eth_transaction(0).rlp()),
+            "use_paymaster": false,
+        }))
+        .gas(Gas::from_tgas(50))
+        .transact()
+        .await
+        .unwrap()
+        .json::<TransactionSequenceCreation>()
+        .unwrap();
+
+    println!("Alice transfers her NFT key to Bob...");
+    alice
+        .call(nft_key.id(), "nft_transfer")
+        .args_json(json!({
+            "receiver_id": bob.id(),
+            "token_id": alice_key,
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await
+        .unwrap()
+        .unwrap();
+
+    println!("Bob approves the key to the gas station...");
+    bob.call(nft_key.id(), "storage_deposit")
+        .args_json(json!({}))
+        .deposit(NearToken::from_near(1))
+        .transact()
+        .await
+        .unwrap()
+        .unwrap();
+    test_support::approve(&bob, &nft_key, &gas_station, &alice_key, None).await;
+
+    println!("Alice attempts to sign her pending transaction...");
+    let result = alice
+        .call(gas_station.id(), "sign_next")
+        .args_json(json!({
+            "id": tx.id,
+        }))
+        .gas(Gas::from_tgas(50))
+        .transact()
+        .await
+        .unwrap();
+
+    assert!(
+        result.is_failure(),
+        "Alice must not be able to sign with Bob's key",
+    );
+    assert!(format!("{:?}", result.into_result().unwrap_err())
+        .contains("Chain key authorization has changed since the transaction was created"));
+
+    println!("Bob can use the key...");
+    let bob_tx = bob
+        .call(gas_station.id(), "create_transaction")
+        .args_json(json!({
+            "token_id": alice_key,
+            "transaction_rlp_hex": hex::encode_prefixed(&construct_eth_transaction(0).rlp()),
+            "use_paymaster": false,
+        }))
+        .gas(Gas::from_tgas(50))
+        .transact()
+        .await
+        .unwrap()
+        .json::<TransactionSequenceCreation>()
+        .unwrap();
+
+    bob.call(gas_station.id(), "sign_next")
+        .args_json(json!({
+            "id": bob_tx.id,
+        }))
+        .gas(Gas::from_tgas(50))
+        .transact()
+        .await
+        .unwrap()
+        .json::<String>()
+        .unwrap();
+}
+
 #[test]
 #[ignore = "generate a payload signable by the contract"]
 fn generate_eth_rlp_hex() {
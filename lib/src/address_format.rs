@@ -0,0 +1,139 @@
+//! Addresses on other chains derived from the same secp256k1 public key.
+
+use near_sdk::near;
+
+use crate::{foreign_address::ForeignAddress, kdf::sha256};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[near(serializers = [json])]
+#[serde(rename_all = "snake_case")]
+pub enum AddressFormat {
+    /// EIP-55 checksummed address, as used by Ethereum and other EVM chains.
+    Evm,
+    /// Native SegWit (bech32, `bc1q...`) address on Bitcoin mainnet.
+    BitcoinP2wpkh,
+    /// Bech32 account address on the Cosmos Hub (`cosmos1...`).
+    Cosmos,
+}
+
+impl AddressFormat {
+    /// Formats the address of an uncompressed public key, given as the
+    /// 64-byte concatenation of its coordinates, optionally preceded by a
+    /// one-byte prefix (e.g. `0x04`, or the curve type of a NEAR public key).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key_bytes` is shorter than 64 bytes.
+    pub fn address_for(self, key_bytes: impl AsRef<[u8]>) -> String {
+        let key_bytes = key_bytes.as_ref();
+        let coordinates = &key_bytes[key_bytes.len() - 64..];
+
+        match self {
+            Self::Evm => {
+                let mut prefixed = vec![0x04];
+                prefixed.extend_from_slice(coordinates);
+                ForeignAddress::from_raw_public_key(prefixed).to_string()
+            }
+            Self::BitcoinP2wpkh => {
+                // Witness version 0, followed by the 20-byte key hash.
+                let mut data = vec![0];
+                data.extend(to_base32(&hash160(&compress(coordinates))));
+                bech32_encode("bc", &data)
+            }
+            Self::Cosmos => bech32_encode("cosmos", &to_base32(&hash160(&compress(coordinates)))),
+        }
+    }
+}
+
+/// SEC1 compressed encoding of an uncompressed public key.
+fn compress(coordinates: &[u8]) -> Vec<u8> {
+    let (x, y) = coordinates.split_at(32);
+    let mut compressed = vec![0x02 | (y[31] & 1)];
+    compressed.extend_from_slice(x);
+    compressed
+}
+
+fn hash160(bytes: &[u8]) -> Vec<u8> {
+    ripemd160(&sha256(bytes))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn ripemd160(bytes: &[u8]) -> Vec<u8> {
+    near_sdk::env::ripemd160_array(bytes).to_vec()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn ripemd160(bytes: &[u8]) -> Vec<u8> {
+    use ripemd::Digest;
+    let mut hasher = ripemd::Ripemd160::new();
+    hasher.update(bytes);
+    hasher.finalize().to_vec()
+}
+
+const BECH32_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Regroups bytes into 5-bit values, padding the last one with zeroes.
+#[allow(clippy::cast_possible_truncation)]
+fn to_base32(bytes: &[u8]) -> Vec<u8> {
+    let mut values = Vec::with_capacity((bytes.len() * 8).div_ceil(5));
+    let mut accumulator = 0u32;
+    let mut bits = 0;
+
+    for byte in bytes {
+        accumulator = ((accumulator << 8) | u32::from(*byte)) & 0xfff;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            values.push(((accumulator >> bits) & 31) as u8);
+        }
+    }
+
+    if bits > 0 {
+        values.push(((accumulator << (5 - bits)) & 31) as u8);
+    }
+
+    values
+}
+
+fn bech32_polymod(values: impl IntoIterator<Item = u8>) -> u32 {
+    const GENERATOR: [u32; 5] = [
+        0x3b6a_57b2,
+        0x2650_8e6d,
+        0x1ea1_19fa,
+        0x3d42_33dd,
+        0x2a14_62b3,
+    ];
+
+    values.into_iter().fold(1, |checksum, value| {
+        let top = checksum >> 25;
+        let checksum = ((checksum & 0x01ff_ffff) << 5) ^ u32::from(value);
+        GENERATOR
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| (top >> i) & 1 == 1)
+            .fold(checksum, |checksum, (_, g)| checksum ^ g)
+    })
+}
+
+/// BIP-173 bech32 encoding of 5-bit `data`.
+fn bech32_encode(hrp: &str, data: &[u8]) -> String {
+    let hrp_expanded = hrp
+        .bytes()
+        .map(|b| b >> 5)
+        .chain([0])
+        .chain(hrp.bytes().map(|b| b & 31));
+
+    let polymod = bech32_polymod(hrp_expanded.chain(data.iter().copied()).chain([0; 6])) ^ 1;
+
+    #[allow(clippy::cast_possible_truncation)]
+    let checksum = (0..6).map(|i| ((polymod >> (5 * (5 - i))) & 31) as u8);
+
+    let mut address = format!("{hrp}1");
+    address.extend(
+        data.iter()
+            .copied()
+            .chain(checksum)
+            .map(|value| char::from(BECH32_CHARSET[usize::from(value)])),
+    );
+    address
+}
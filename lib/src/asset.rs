@@ -1,24 +1,113 @@
-use near_sdk::{json_types::U128, near, AccountId, NearToken, Promise};
+use near_sdk::{json_types::U128, near, AccountId, Gas, NearToken, Promise};
 use near_sdk_contract_tools::standard::nep141::ext_nep141;
 
+use crate::{nep145::ext_nep145, nep245::ext_nep245};
+
 #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Debug)]
 #[near(serializers = [borsh, json])]
 pub enum AssetId {
     Native,
     Nep141(AccountId),
+    Nep245 {
+        contract_id: AccountId,
+        token_id: String,
+    },
+}
+
+/// Parameters of token transfers. Ignored for native transfers.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransferOptions {
+    /// Static gas for the token contract call. Unused gas is still
+    /// distributed if not set.
+    pub gas: Option<Gas>,
+    pub memo: Option<String>,
+    /// If set, the receiver is first registered with the token contract
+    /// (NEP-145 `storage_deposit` with `registration_only`), attaching this
+    /// deposit. Token contracts refund the deposit to already-registered
+    /// receivers.
+    pub storage_deposit: Option<NearToken>,
+}
+
+impl TransferOptions {
+    pub fn with_memo(memo: impl Into<String>) -> Self {
+        Self {
+            memo: Some(memo.into()),
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub fn with_gas(mut self, gas: Gas) -> Self {
+        self.gas = Some(gas);
+        self
+    }
+
+    #[must_use]
+    pub fn with_storage_deposit(mut self, deposit: NearToken) -> Self {
+        self.storage_deposit = Some(deposit);
+        self
+    }
 }
 
 impl AssetId {
     pub fn transfer(&self, receiver_id: AccountId, amount: impl Into<u128>) -> Promise {
-        match self {
+        self.transfer_with_options(receiver_id, amount, &TransferOptions::default())
+    }
+
+    pub fn transfer_with_options(
+        &self,
+        receiver_id: AccountId,
+        amount: impl Into<u128>,
+        options: &TransferOptions,
+    ) -> Promise {
+        let (contract_id, transfer) = match self {
             AssetId::Native => {
-                Promise::new(receiver_id).transfer(NearToken::from_yoctonear(amount.into()))
+                return Promise::new(receiver_id)
+                    .transfer(NearToken::from_yoctonear(amount.into()));
             }
-            AssetId::Nep141(contract_id) => ext_nep141::ext(contract_id.clone()).ft_transfer(
-                receiver_id,
-                U128(amount.into()),
-                None,
-            ),
+            AssetId::Nep141(contract_id) => {
+                let mut ext = ext_nep141::ext(contract_id.clone())
+                    .with_attached_deposit(NearToken::from_yoctonear(1));
+                if let Some(gas) = options.gas {
+                    ext = ext.with_static_gas(gas);
+                }
+                (
+                    contract_id,
+                    ext.ft_transfer(
+                        receiver_id.clone(),
+                        U128(amount.into()),
+                        options.memo.clone(),
+                    ),
+                )
+            }
+            AssetId::Nep245 {
+                contract_id,
+                token_id,
+            } => {
+                let mut ext = ext_nep245::ext(contract_id.clone())
+                    .with_attached_deposit(NearToken::from_yoctonear(1));
+                if let Some(gas) = options.gas {
+                    ext = ext.with_static_gas(gas);
+                }
+                (
+                    contract_id,
+                    ext.mt_transfer(
+                        receiver_id.clone(),
+                        token_id.clone(),
+                        U128(amount.into()),
+                        None,
+                        options.memo.clone(),
+                    ),
+                )
+            }
+        };
+
+        match options.storage_deposit {
+            Some(deposit) => ext_nep145::ext(contract_id.clone())
+                .with_attached_deposit(deposit)
+                .storage_deposit(Some(receiver_id), Some(true))
+                .then(transfer),
+            None => transfer,
         }
     }
 }
@@ -44,4 +133,14 @@ impl AssetBalance {
             amount: amount.into(),
         }
     }
+
+    pub fn nep245(contract_id: AccountId, token_id: String, amount: impl Into<U128>) -> Self {
+        Self {
+            asset_id: AssetId::Nep245 {
+                contract_id,
+                token_id,
+            },
+            amount: amount.into(),
+        }
+    }
 }
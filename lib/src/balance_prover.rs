@@ -0,0 +1,32 @@
+//! Interface for contracts that verify the state of foreign chain accounts,
+//! e.g. an EVM light client running on NEAR.
+
+use near_sdk::{
+    ext_contract,
+    json_types::{Base64VecU8, U128, U64},
+    near,
+};
+
+use crate::foreign_address::ForeignAddress;
+
+/// State of a foreign account at a finalized block, as verified by a prover.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct ProvenAccountState {
+    pub block_number: U64,
+    /// In the smallest unit of the chain's gas token.
+    pub balance: U128,
+    pub nonce: U64,
+}
+
+#[ext_contract(ext_balance_prover)]
+pub trait BalanceProver {
+    /// Verifies `proof` (in a prover-specific format) of the state of
+    /// `address` on the chain. Panics if the proof is invalid.
+    fn verify_account_state(
+        &self,
+        chain_id: U64,
+        address: ForeignAddress,
+        proof: Base64VecU8,
+    ) -> ProvenAccountState;
+}
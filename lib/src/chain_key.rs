@@ -1,4 +1,6 @@
-use near_sdk::{ext_contract, AccountId, PromiseOrValue, PublicKey};
+use near_sdk::{ext_contract, near, AccountId, PromiseOrValue, PublicKey};
+
+use crate::address_format::AddressFormat;
 
 #[ext_contract(ext_chain_key_token)]
 pub trait ChainKeyToken {
@@ -9,12 +11,28 @@ pub trait ChainKeyToken {
         path: Option<String>,
         payload: Vec<u8>,
         approval_id: Option<u32>,
+        key_version: Option<u32>,
     ) -> PromiseOrValue<String>;
+    fn ckt_sign_hashes(
+        &mut self,
+        token_id: String,
+        path: Option<String>,
+        payloads: Vec<Vec<u8>>,
+        approval_id: Option<u32>,
+        key_version: Option<u32>,
+    ) -> PromiseOrValue<Vec<String>>;
     fn ckt_public_key_for(
         &mut self,
         token_id: String,
         path: Option<String>,
     ) -> PromiseOrValue<PublicKey>;
+    /// Addresses of the key on other chains, in the order of `formats`.
+    fn ckt_addresses_for(
+        &mut self,
+        token_id: String,
+        path: Option<String>,
+        formats: Vec<AddressFormat>,
+    ) -> PromiseOrValue<Vec<String>>;
 }
 
 #[ext_contract(ext_chain_key_token_approval)]
@@ -37,6 +55,28 @@ pub trait ChainKeyTokenApproval {
     fn ckt_approval_id_for(&self, token_id: String, account_id: AccountId) -> Option<u32>;
 }
 
+/// Returned by `ckt_on_approved` once the receiver has handled an approval.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct ChainKeyApprovalResponse {
+    /// Whether the approval should be revoked.
+    pub revoke: bool,
+    pub deposit: ForwardedDepositStatus,
+}
+
+/// What the receiver of `ckt_on_approved` did with the deposit forwarded
+/// with the approval. Either way, the deposit is no longer held by the
+/// chain key token contract.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+#[serde(rename_all = "snake_case")]
+pub enum ForwardedDepositStatus {
+    /// The receiver kept the deposit, e.g. to pay for a transaction.
+    Kept,
+    /// The receiver returned the deposit to the approver.
+    Refunded,
+}
+
 #[ext_contract(ext_chain_key_token_approval_receiver)]
 pub trait ChainKeyTokenApprovalReceiver {
     fn ckt_on_approved(
@@ -45,7 +85,7 @@ pub trait ChainKeyTokenApprovalReceiver {
         token_id: String,
         approval_id: u32,
         msg: String,
-    ) -> PromiseOrValue<bool>;
+    ) -> PromiseOrValue<ChainKeyApprovalResponse>;
     fn ckt_on_revoked(
         &mut self,
         approver_id: AccountId,
@@ -0,0 +1,25 @@
+//! Interface for contracts that hold the reference configuration of foreign
+//! chains, shared by several gas station deployments.
+
+use near_sdk::{
+    ext_contract,
+    json_types::{U128, U64},
+    near,
+};
+
+/// Base parameters of a foreign chain, as in `update_foreign_chain`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct ChainRegistryEntry {
+    /// Pyth price identifier of the chain's gas token, hex-encoded.
+    pub oracle_asset_id: String,
+    pub transfer_gas: U128,
+    pub fee_rate: (U128, U128),
+    pub decimals: u8,
+}
+
+#[ext_contract(ext_chain_registry)]
+pub trait ChainRegistry {
+    /// The configuration of the chain, if the registry knows it.
+    fn get_chain(&self, chain_id: U64) -> Option<ChainRegistryEntry>;
+}
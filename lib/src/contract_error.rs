@@ -0,0 +1,47 @@
+//! Structured failures reported by the gas station contract.
+//!
+//! Calls that fail after a cross-contract round trip (e.g. sequence creation
+//! once the oracle prices are received) panic with a [`ContractError`]
+//! serialized as JSON, so that clients can branch on its `code` instead of
+//! matching on the message.
+
+use std::fmt::Display;
+
+use near_sdk::{near, serde_json};
+
+use crate::Rejectable;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct ContractError {
+    /// Stable, `snake_case` identifier of the failure cause.
+    pub code: String,
+    /// Human-readable description. Not stable.
+    pub message: String,
+    /// Fields specific to `code`, e.g. the chain ID or the amounts compared.
+    #[serde(default)]
+    pub context: serde_json::Value,
+}
+
+impl ContractError {
+    pub fn new(code: impl Into<String>, message: impl Display, context: serde_json::Value) -> Self {
+        Self {
+            code: code.into(),
+            message: message.to_string(),
+            context,
+        }
+    }
+
+    /// Extracts the error from the failure message of a call, which may be
+    /// prefixed by the runtime (e.g. `Smart contract panicked: `).
+    pub fn from_failure_message(message: &str) -> Option<Self> {
+        let start = message.find('{')?;
+        serde_json::from_str(&message[start..]).ok()
+    }
+}
+
+impl Display for ContractError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", serde_json::to_string(self).unwrap_or_reject())
+    }
+}
@@ -0,0 +1,133 @@
+//! Events emitted by the NFT chain key contract under the
+//! `x-chain-key-token` standard.
+
+use near_sdk::{json_types::U64, near, AccountId};
+
+use super::{EventLogError, RawEventLog};
+
+pub const STANDARD: &str = "x-chain-key-token";
+/// Version emitted by the current contract.
+pub const VERSION: &str = "0.1.0";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct Approved {
+    pub token_id: String,
+    pub owner_id: AccountId,
+    pub account_id: AccountId,
+    pub approval_id: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct Revoked {
+    pub token_id: String,
+    pub owner_id: AccountId,
+    pub account_id: AccountId,
+    pub approval_id: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct RevokedAll {
+    pub token_id: String,
+    pub owner_id: AccountId,
+    pub count: U64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct SignatureRequested {
+    pub token_id: String,
+    pub path: String,
+    pub requested_by: AccountId,
+    pub approval_id: Option<u32>,
+    /// Hex-encoded payloads, in the order they were submitted.
+    pub payloads: Vec<String>,
+}
+
+/// Emitted when the owner of a token sets or removes its recovery
+/// configuration. `guardians` is empty if recovery was disabled.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct RecoveryConfigured {
+    pub token_id: String,
+    pub owner_id: AccountId,
+    pub guardians: Vec<AccountId>,
+    pub threshold: u32,
+    pub timelock_blocks: U64,
+}
+
+/// Emitted when a guardian approves recovering a token to an account.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct RecoveryApproved {
+    pub token_id: String,
+    pub guardian_id: AccountId,
+    pub recovery_account_id: AccountId,
+    pub approval_count: u32,
+    /// Set once enough guardians have approved.
+    pub executable_at_block_height: Option<U64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct RecoveryCancelled {
+    pub token_id: String,
+    pub owner_id: AccountId,
+    pub recovery_account_id: AccountId,
+}
+
+/// Emitted when a token is transferred to its recovery account.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct RecoveryExecuted {
+    pub token_id: String,
+    pub previous_owner_id: AccountId,
+    pub recovery_account_id: AccountId,
+}
+
+/// Version `0.1.0` of the chain key token events, as decoded by off-chain
+/// consumers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+#[serde(tag = "event", content = "data")]
+pub enum ChainKeyTokenEventV0_1_0 {
+    Approved(Approved),
+    Revoked(Revoked),
+    RevokedAll(RevokedAll),
+    SignatureRequested(SignatureRequested),
+    RecoveryConfigured(RecoveryConfigured),
+    RecoveryApproved(RecoveryApproved),
+    RecoveryCancelled(RecoveryCancelled),
+    RecoveryExecuted(RecoveryExecuted),
+}
+
+/// Every version of the chain key token events that consumers can decode.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChainKeyTokenEvent {
+    V0_1_0(ChainKeyTokenEventV0_1_0),
+}
+
+impl ChainKeyTokenEvent {
+    /// # Errors
+    ///
+    /// - If the log is not an `x-chain-key-token` event.
+    /// - If the event version is not known to this crate.
+    /// - If the payload does not match the declared version.
+    pub fn from_log(log: &str) -> Result<Self, EventLogError> {
+        let raw = RawEventLog::parse(log)?.expect_standard(STANDARD)?;
+        match raw.version.as_str() {
+            "0.1.0" => Ok(Self::V0_1_0(raw.decode()?)),
+            _ => Err(EventLogError::UnsupportedVersion(raw.version)),
+        }
+    }
+
+    /// Formats the event as an `EVENT_JSON:` log, in the same shape the
+    /// contract emits.
+    pub fn to_log(&self) -> String {
+        match self {
+            Self::V0_1_0(event) => super::to_log(STANDARD, "0.1.0", event),
+        }
+    }
+}
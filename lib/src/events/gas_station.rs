@@ -0,0 +1,403 @@
+//! Events emitted by the gas station contract under the `x-gas-station`
+//! standard.
+
+use near_sdk::{
+    json_types::{U128, U64},
+    near, serde_json, AccountId,
+};
+
+use super::{EventLogError, RawEventLog};
+use crate::{
+    asset::{AssetBalance, AssetId},
+    contract_error::ContractError,
+    foreign_address::ForeignAddress,
+};
+
+pub const STANDARD: &str = "x-gas-station";
+/// Version emitted by the current contract.
+pub const VERSION: &str = "0.1.0";
+
+/// `pending_transaction_sequence` is the contract's
+/// `PendingTransactionSequence`. Consumers that do not depend on the contract
+/// crate decode it as [`serde_json::Value`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct TransactionSequenceCreated<S> {
+    pub id: U64,
+    pub foreign_chain_id: String,
+    /// Last block height at which the sequence can be signed. Missing from
+    /// events emitted by earlier contract versions.
+    #[serde(default)]
+    pub expires_at_block_height: Option<U64>,
+    pub pending_transaction_sequence: S,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct TransactionSequenceSigned {
+    pub id: U64,
+    pub foreign_chain_id: String,
+    pub created_by_account_id: AccountId,
+    pub tag: Option<String>,
+    /// Token ID of the chain key that signed the last transaction of the
+    /// sequence, i.e. the user transaction.
+    pub token_id: String,
+    /// Address of that chain key on the foreign chain. `None` if the key was
+    /// unregistered while the signature was in flight.
+    pub sender_foreign_address: Option<ForeignAddress>,
+    pub signed_transactions: Vec<String>,
+}
+
+/// Emitted once the public key of a chain key has been retrieved from the
+/// signer contract and the key is ready for use.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct ChainKeyRegistered {
+    pub account_id: AccountId,
+    pub token_id: String,
+    pub is_paymaster: bool,
+    pub foreign_address: ForeignAddress,
+}
+
+/// Emitted when a chain key is registered without a public key because the
+/// signer contract failed to provide one. The key cannot be used until
+/// `refresh_user_chain_key` succeeds, which emits `ChainKeyRegistered`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct ChainKeyPending {
+    pub account_id: AccountId,
+    pub token_id: String,
+    pub is_paymaster: bool,
+}
+
+/// Emitted when a market maker marks a signed sequence as impossible to
+/// relay. `refund` is the part of the fee returned to the creator, if any.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct TransactionSequenceDoomed {
+    pub id: U64,
+    pub marked_by: AccountId,
+    pub reason: String,
+    pub refund: Option<AssetBalance>,
+}
+
+/// Emitted when receivers or function selectors (hex-encoded) are added to
+/// (`is_denied`) or removed from the deny list. Only entries whose status
+/// changed are listed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct DenylistUpdated {
+    pub receivers: Vec<ForeignAddress>,
+    pub selectors: Vec<String>,
+    pub is_denied: bool,
+}
+
+/// A transaction sequence could not be created once the oracle prices were
+/// received. The deposit is refunded to `sender_id`, and the call fails with
+/// the same `error`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct TransactionSequenceCreationFailed {
+    pub sender_id: AccountId,
+    pub error: ContractError,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+#[serde(rename_all = "snake_case")]
+pub enum Whitelist {
+    Sender,
+    Receiver,
+}
+
+/// Emitted once per batch of entries added to (`is_whitelisted`) or removed
+/// from a whitelist. `skipped` counts the entries that were already in (or
+/// absent from) the whitelist, including duplicates within the batch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct WhitelistUpdated {
+    pub whitelist: Whitelist,
+    pub is_whitelisted: bool,
+    pub changed: u32,
+    pub skipped: u32,
+}
+
+/// Links a transaction sequence created by the intents contract to the
+/// intent whose settlement requested it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct IntentSettlementSequenceCreated {
+    pub intent_hash: String,
+    pub solver_id: AccountId,
+    pub id: U64,
+}
+
+/// Summary of the contract configuration, so that indexers can start from
+/// the most recent snapshot instead of replaying every administrator call.
+///
+/// `flags` and `foreign_chains` are the contract's `Flags` and
+/// `ViewForeignChainConfiguration` types.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct ConfigSnapshot<F, C> {
+    pub block_height: U64,
+    pub is_paused: bool,
+    pub flags: F,
+    pub expire_sequence_after_blocks: U64,
+    pub signer_contract_id: AccountId,
+    pub oracle_id: AccountId,
+    pub accepted_local_asset_count: U64,
+    pub sender_whitelist_count: U64,
+    pub receiver_whitelist_count: U64,
+    pub foreign_chains: Vec<C>,
+}
+
+/// Reported by a relayer after broadcasting a signed sequence. Transaction
+/// hashes are in the same order as the signed transactions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct TransactionSequenceRelayed {
+    pub id: U64,
+    pub relayed_by: AccountId,
+    pub transaction_hashes: Vec<String>,
+}
+
+/// The tip attached to a sequence was credited to the relayer that reported
+/// broadcasting it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct RelayerTipCredited {
+    pub id: U64,
+    pub relayer_id: AccountId,
+    pub tip: AssetBalance,
+}
+
+/// A third party registered a paymaster, bonding `bond` yoctoNEAR.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct PaymasterBonded {
+    pub token_id: String,
+    pub owner_id: AccountId,
+    pub chain_id: U64,
+    pub bond: U128,
+}
+
+/// A bonded paymaster left the rotation. Its bond can be slashed until
+/// `withdrawable_at_block_height`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct PaymasterUnbonding {
+    pub token_id: String,
+    pub owner_id: AccountId,
+    pub withdrawable_at_block_height: U64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct PaymasterBondWithdrawn {
+    pub token_id: String,
+    pub owner_id: AccountId,
+    pub amount: U128,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct PaymasterSlashed {
+    pub token_id: String,
+    pub owner_id: AccountId,
+    pub amount: U128,
+    pub remaining_bond: U128,
+    pub reason: String,
+}
+
+/// A signed sequence was replaced by sequence `id`, which re-signs the same
+/// nonces with higher fees. `fee_delta` is the additional fee charged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct TransactionSequenceRebumped {
+    pub id: U64,
+    pub replaced_id: U64,
+    pub max_fee_per_gas: U128,
+    pub max_priority_fee_per_gas: U128,
+    pub fee_delta: Option<AssetBalance>,
+}
+
+/// Sponsorship was refused because the oracle-derived conversion rate for
+/// `asset_id` on the chain fell outside the configured bounds. `rate` is in
+/// smallest units of the local asset per whole gas token, saturating at
+/// `u128::MAX`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct OracleAnomalyDetected {
+    pub chain_id: U64,
+    pub asset_id: AssetId,
+    pub rate: U128,
+    pub min_rate: Option<U128>,
+    pub max_rate: Option<U128>,
+}
+
+/// Sponsorship on the chain was paused because `window_amount` gas tokens
+/// were sponsored within the last hour, exceeding `cap`. It stays paused
+/// until an administrator resumes it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct SponsorshipCircuitBreakerTripped {
+    pub chain_id: U64,
+    pub window_amount: U128,
+    pub cap: U128,
+}
+
+/// The tracked balance of a paymaster was set from a verified proof of its
+/// state at `block_number` on the foreign chain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct PaymasterBalanceProven {
+    pub chain_id: U64,
+    pub token_id: String,
+    pub balance: U128,
+    pub block_number: U64,
+}
+
+/// `owner_id` authorized `grantee_id` to create sequences with the chain
+/// key `token_id` on its behalf, paid for from `budget`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct SessionGranted {
+    pub session_id: U64,
+    pub owner_id: AccountId,
+    pub grantee_id: AccountId,
+    pub token_id: String,
+    pub max_sequences: u32,
+    pub budget: U128,
+    pub expires_at_block_height: U64,
+}
+
+/// The grantee of a session created a sequence, drawing `amount` from the
+/// session budget. The sequence ID is reported by the creation event that
+/// follows.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct SessionUsed {
+    pub session_id: U64,
+    pub grantee_id: AccountId,
+    pub amount: U128,
+    pub sequences_created: u32,
+    pub remaining_budget: U128,
+}
+
+/// A session was removed after expiring, or revoked by its owner. `refund`
+/// is returned to the owner.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct SessionEnded {
+    pub session_id: U64,
+    pub owner_id: AccountId,
+    pub grantee_id: AccountId,
+    pub is_revoked: bool,
+    pub refund: U128,
+}
+
+/// `owner_id` pre-approved a transaction to be instantiated into a sequence
+/// up to `max_occurrences` times, once every `period_blocks`, paid for from
+/// `balance`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct RecurringAuthorizationCreated {
+    pub authorization_id: U64,
+    pub owner_id: AccountId,
+    pub token_id: String,
+    pub chain_id: U64,
+    pub period_blocks: U64,
+    pub max_occurrences: u32,
+    pub balance: U128,
+}
+
+/// `executor_id` instantiated a recurring authorization into a sequence,
+/// drawing `amount` from its balance. The sequence ID is reported by the
+/// creation event that follows.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct RecurringAuthorizationExecuted {
+    pub authorization_id: U64,
+    pub executor_id: AccountId,
+    pub amount: U128,
+    pub occurrences: u32,
+    pub remaining_balance: U128,
+    pub next_block_height: U64,
+}
+
+/// A recurring authorization was removed after its last occurrence, or
+/// cancelled by its owner. `refund` is returned to the owner.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct RecurringAuthorizationEnded {
+    pub authorization_id: U64,
+    pub owner_id: AccountId,
+    pub is_cancelled: bool,
+    pub refund: U128,
+}
+
+/// Version `0.1.0` of the gas station events, as decoded by off-chain
+/// consumers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+#[serde(tag = "event", content = "data")]
+pub enum GasStationEventV0_1_0 {
+    TransactionSequenceCreated(TransactionSequenceCreated<serde_json::Value>),
+    TransactionSequenceSigned(TransactionSequenceSigned),
+    ChainKeyRegistered(ChainKeyRegistered),
+    IntentSettlementSequenceCreated(IntentSettlementSequenceCreated),
+    ConfigSnapshot(ConfigSnapshot<serde_json::Value, serde_json::Value>),
+    TransactionSequenceRelayed(TransactionSequenceRelayed),
+    PaymasterBonded(PaymasterBonded),
+    PaymasterUnbonding(PaymasterUnbonding),
+    PaymasterBondWithdrawn(PaymasterBondWithdrawn),
+    PaymasterSlashed(PaymasterSlashed),
+    TransactionSequenceRebumped(TransactionSequenceRebumped),
+    OracleAnomalyDetected(OracleAnomalyDetected),
+    PaymasterBalanceProven(PaymasterBalanceProven),
+    RelayerTipCredited(RelayerTipCredited),
+    SponsorshipCircuitBreakerTripped(SponsorshipCircuitBreakerTripped),
+    SessionGranted(SessionGranted),
+    SessionUsed(SessionUsed),
+    SessionEnded(SessionEnded),
+    ChainKeyPending(ChainKeyPending),
+    TransactionSequenceDoomed(TransactionSequenceDoomed),
+    DenylistUpdated(DenylistUpdated),
+    WhitelistUpdated(WhitelistUpdated),
+    TransactionSequenceCreationFailed(TransactionSequenceCreationFailed),
+    RecurringAuthorizationCreated(RecurringAuthorizationCreated),
+    RecurringAuthorizationExecuted(RecurringAuthorizationExecuted),
+    RecurringAuthorizationEnded(RecurringAuthorizationEnded),
+}
+
+/// Every version of the gas station events that consumers can decode. New
+/// versions are added as new variants; old variants are never removed, so
+/// historical logs remain decodable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GasStationEvent {
+    V0_1_0(GasStationEventV0_1_0),
+}
+
+impl GasStationEvent {
+    /// # Errors
+    ///
+    /// - If the log is not an `x-gas-station` event.
+    /// - If the event version is not known to this crate.
+    /// - If the payload does not match the declared version.
+    pub fn from_log(log: &str) -> Result<Self, EventLogError> {
+        let raw = RawEventLog::parse(log)?.expect_standard(STANDARD)?;
+        match raw.version.as_str() {
+            "0.1.0" => Ok(Self::V0_1_0(raw.decode()?)),
+            _ => Err(EventLogError::UnsupportedVersion(raw.version)),
+        }
+    }
+
+    /// Formats the event as an `EVENT_JSON:` log, in the same shape the
+    /// contract emits.
+    pub fn to_log(&self) -> String {
+        match self {
+            Self::V0_1_0(event) => super::to_log(STANDARD, "0.1.0", event),
+        }
+    }
+}
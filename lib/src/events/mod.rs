@@ -0,0 +1,80 @@
+//! Event payloads emitted by the contracts in this workspace.
+//!
+//! The contracts emit events through `near_sdk_contract_tools`; off-chain
+//! consumers (indexers, the relayer CLI, integration tests) decode the
+//! `EVENT_JSON:` logs with the versioned enums in the submodules, so that the
+//! shapes are only declared once.
+
+use near_sdk::{near, serde_json};
+use thiserror::Error;
+
+use crate::Rejectable;
+
+pub mod chain_key_token;
+pub mod gas_station;
+
+pub const EVENT_JSON_PREFIX: &str = "EVENT_JSON:";
+
+/// An NEP-297 event log whose payload has not been decoded yet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct RawEventLog {
+    pub standard: String,
+    pub version: String,
+    pub event: String,
+    pub data: serde_json::Value,
+}
+
+impl RawEventLog {
+    /// # Errors
+    ///
+    /// - If the log does not start with [`EVENT_JSON_PREFIX`].
+    /// - If the remainder of the log is not an NEP-297 event.
+    pub fn parse(log: &str) -> Result<Self, EventLogError> {
+        let json = log
+            .strip_prefix(EVENT_JSON_PREFIX)
+            .ok_or(EventLogError::NotAnEvent)?;
+        Ok(serde_json::from_str(json)?)
+    }
+
+    fn expect_standard(self, standard: &str) -> Result<Self, EventLogError> {
+        if self.standard == standard {
+            Ok(self)
+        } else {
+            Err(EventLogError::UnexpectedStandard(self.standard))
+        }
+    }
+
+    fn decode<T: near_sdk::serde::de::DeserializeOwned>(self) -> Result<T, EventLogError> {
+        Ok(serde_json::from_value(serde_json::json!({
+            "event": self.event,
+            "data": self.data,
+        }))?)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum EventLogError {
+    #[error("Log is not an event")]
+    NotAnEvent,
+    #[error("Unexpected event standard: {0}")]
+    UnexpectedStandard(String),
+    #[error("Unsupported event version: {0}")]
+    UnsupportedVersion(String),
+    #[error("Invalid event JSON: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+}
+
+fn to_log<T: near_sdk::serde::Serialize>(standard: &str, version: &str, event: &T) -> String {
+    let mut value = serde_json::to_value(event).unwrap_or_reject();
+    let raw = RawEventLog {
+        standard: standard.to_string(),
+        version: version.to_string(),
+        event: value["event"].as_str().unwrap_or_reject().to_string(),
+        data: value["data"].take(),
+    };
+    format!(
+        "{EVENT_JSON_PREFIX}{}",
+        serde_json::to_string(&raw).unwrap_or_reject()
+    )
+}
@@ -1,12 +1,23 @@
 use std::fmt::Display;
 
+pub mod address_format;
 pub mod asset;
+pub mod balance_prover;
 pub mod chain_key;
+pub mod chain_registry;
+pub mod contract_error;
+pub mod events;
 pub mod foreign_address;
 pub mod kdf;
+pub mod nep145;
+pub mod nep245;
+pub mod nonce_oracle;
 pub mod oracle;
 pub mod pyth;
+pub mod screening;
+pub mod sequence_callback;
 pub mod signer;
+pub mod signing_policy;
 
 pub trait Rejectable<T> {
     fn unwrap_or_reject(self) -> T;
@@ -0,0 +1,21 @@
+//! Minimal interface for the [NEP-145 storage management standard](https://github.com/near/NEPs/blob/master/neps/nep-0145.md).
+
+use near_sdk::{ext_contract, json_types::U128, near, AccountId};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct StorageBalance {
+    pub total: U128,
+    pub available: U128,
+}
+
+#[ext_contract(ext_nep145)]
+pub trait Nep145 {
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance;
+
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance>;
+}
@@ -0,0 +1,28 @@
+//! Minimal interface for the [NEP-245 multi token standard](https://github.com/near/NEPs/blob/master/neps/nep-0245.md).
+
+use near_sdk::{ext_contract, json_types::U128, AccountId, PromiseOrValue};
+
+#[ext_contract(ext_nep245)]
+pub trait Nep245 {
+    fn mt_transfer(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: String,
+        amount: U128,
+        approval: Option<(AccountId, u64)>,
+        memo: Option<String>,
+    );
+}
+
+#[ext_contract(ext_nep245_receiver)]
+pub trait Nep245Receiver {
+    /// Returns the amounts of each token to refund to the previous owners.
+    fn mt_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_ids: Vec<AccountId>,
+        token_ids: Vec<String>,
+        amounts: Vec<U128>,
+        msg: String,
+    ) -> PromiseOrValue<Vec<U128>>;
+}
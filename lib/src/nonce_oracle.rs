@@ -0,0 +1,13 @@
+//! Interface for contracts that report the nonces of foreign chain accounts,
+//! e.g. a light client or an operator-run feed.
+
+use near_sdk::{ext_contract, json_types::U64};
+
+use crate::foreign_address::ForeignAddress;
+
+#[ext_contract(ext_nonce_oracle)]
+pub trait NonceOracle {
+    /// The next nonce of `address` on the chain, i.e. the number of
+    /// transactions it has sent.
+    fn get_nonce(&self, chain_id: U64, address: ForeignAddress) -> U64;
+}
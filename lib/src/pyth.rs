@@ -29,6 +29,7 @@ use near_sdk::{
     json_types::{I64, U64},
     near,
 };
+use schemars::JsonSchema;
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[near]
@@ -97,6 +98,20 @@ impl near_sdk::serde::Serialize for PriceIdentifier {
     }
 }
 
+impl JsonSchema for PriceIdentifier {
+    fn schema_name() -> String {
+        String::schema_name()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        String::json_schema(gen)
+    }
+
+    fn is_referenceable() -> bool {
+        false
+    }
+}
+
 /// A price with a degree of uncertainty, represented as a price +- a confidence interval.
 ///
 /// The confidence interval roughly corresponds to the standard error of a normal distribution.
@@ -0,0 +1,14 @@
+//! Interface for contracts that screen foreign addresses before the gas
+//! station sponsors transactions involving them, e.g. against sanctions
+//! lists.
+
+use near_sdk::ext_contract;
+
+use crate::foreign_address::ForeignAddress;
+
+#[ext_contract(ext_screening)]
+pub trait Screening {
+    /// Whether the gas station must not sponsor transactions sent from or
+    /// to `foreign_address`.
+    fn is_blocked(&self, foreign_address: ForeignAddress) -> bool;
+}
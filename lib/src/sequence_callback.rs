@@ -0,0 +1,12 @@
+//! Interface for contracts notified when a transaction sequence they were
+//! registered for is fully signed.
+
+use near_sdk::{ext_contract, json_types::U64};
+
+#[ext_contract(ext_sequence_callback_receiver)]
+pub trait SequenceCallbackReceiver {
+    /// `signed_transactions` are the RLP-encoded signed transactions of the
+    /// sequence, `0x`-prefixed, in the order they must be broadcast. `msg` is
+    /// the message registered with the callback.
+    fn on_sequence_signed(&mut self, id: U64, signed_transactions: Vec<String>, msg: String);
+}
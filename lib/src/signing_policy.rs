@@ -0,0 +1,19 @@
+//! Interface for contracts that vet transactions before the gas station
+//! requests signatures for them, e.g. to enforce an organization's payload
+//! filters on its chain keys.
+
+use near_sdk::{ext_contract, AccountId};
+
+#[ext_contract(ext_signing_policy)]
+pub trait SigningPolicy {
+    /// Whether the gas station may request a signature for a transaction
+    /// from the chain key `token_id` on behalf of `account_id`.
+    /// `transaction_rlp_hex` is the RLP-encoded unsigned transaction,
+    /// `0x`-prefixed. Returning `false` or failing rejects the request.
+    fn check_sign_request(
+        &self,
+        account_id: AccountId,
+        token_id: String,
+        transaction_rlp_hex: String,
+    ) -> bool;
+}
@@ -0,0 +1,37 @@
+use ethers_core::utils::hex;
+use lib::address_format::AddressFormat;
+
+/// Public key of the secret key 1, i.e. the secp256k1 generator point.
+const GENERATOR: &str = "0479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8";
+
+#[test]
+fn addresses_of_generator() {
+    let key = hex::decode(GENERATOR).unwrap();
+
+    assert_eq!(
+        AddressFormat::Evm.address_for(&key),
+        "0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf",
+    );
+    // BIP-173 test vector.
+    assert_eq!(
+        AddressFormat::BitcoinP2wpkh.address_for(&key),
+        "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+    );
+    assert_eq!(
+        AddressFormat::Cosmos.address_for(&key),
+        "cosmos1w508d6qejxtdg4y5r3zarvary0c5xw7k6ah60c",
+    );
+}
+
+#[test]
+fn prefix_is_optional() {
+    let key = hex::decode(GENERATOR).unwrap();
+
+    for format in [
+        AddressFormat::Evm,
+        AddressFormat::BitcoinP2wpkh,
+        AddressFormat::Cosmos,
+    ] {
+        assert_eq!(format.address_for(&key[1..]), format.address_for(&key));
+    }
+}
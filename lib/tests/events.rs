@@ -0,0 +1,104 @@
+use lib::{
+    events::{
+        chain_key_token::{ChainKeyTokenEvent, ChainKeyTokenEventV0_1_0, SignatureRequested},
+        gas_station::{
+            ChainKeyRegistered, GasStationEvent, GasStationEventV0_1_0, TransactionSequenceCreated,
+            TransactionSequenceSigned,
+        },
+        EventLogError,
+    },
+    foreign_address::ForeignAddress,
+};
+use near_sdk::serde_json::json;
+
+fn round_trip(event: GasStationEvent) {
+    let log = event.to_log();
+    assert!(log.starts_with("EVENT_JSON:{\"standard\":\"x-gas-station\",\"version\":\"0.1.0\""));
+    assert_eq!(GasStationEvent::from_log(&log).unwrap(), event);
+}
+
+#[test]
+fn gas_station_events_round_trip() {
+    round_trip(GasStationEvent::V0_1_0(
+        GasStationEventV0_1_0::TransactionSequenceSigned(TransactionSequenceSigned {
+            id: 7.into(),
+            foreign_chain_id: "97".to_string(),
+            created_by_account_id: "alice.near".parse().unwrap(),
+            tag: Some("order-1".to_string()),
+            token_id: "1".to_string(),
+            sender_foreign_address: Some(ForeignAddress([1; 20])),
+            signed_transactions: vec!["0x02f8".to_string()],
+        }),
+    ));
+
+    round_trip(GasStationEvent::V0_1_0(
+        GasStationEventV0_1_0::ChainKeyRegistered(ChainKeyRegistered {
+            account_id: "alice.near".parse().unwrap(),
+            token_id: "1".to_string(),
+            is_paymaster: false,
+            foreign_address: ForeignAddress([1; 20]),
+        }),
+    ));
+
+    round_trip(GasStationEvent::V0_1_0(
+        GasStationEventV0_1_0::TransactionSequenceCreated(TransactionSequenceCreated {
+            id: 0.into(),
+            foreign_chain_id: "1".to_string(),
+            expires_at_block_height: Some(300.into()),
+            pending_transaction_sequence: json!({ "created_by_account_id": "alice.near" }),
+        }),
+    ));
+}
+
+#[test]
+fn decodes_contract_log() {
+    let log = r#"EVENT_JSON:{"standard":"x-gas-station","version":"0.1.0","event":"TransactionSequenceRelayed","data":{"id":"3","relayed_by":"relayer.near","transaction_hashes":["0xabc"]}}"#;
+
+    let GasStationEvent::V0_1_0(GasStationEventV0_1_0::TransactionSequenceRelayed(event)) =
+        GasStationEvent::from_log(log).unwrap()
+    else {
+        panic!("Wrong event type");
+    };
+
+    assert_eq!(event.id, 3.into());
+    assert_eq!(event.relayed_by.as_str(), "relayer.near");
+    assert_eq!(event.transaction_hashes, vec!["0xabc".to_string()]);
+}
+
+#[test]
+fn chain_key_token_events_round_trip() {
+    let event = ChainKeyTokenEvent::V0_1_0(ChainKeyTokenEventV0_1_0::SignatureRequested(
+        SignatureRequested {
+            token_id: "1".to_string(),
+            path: String::new(),
+            requested_by: "gas-station.near".parse().unwrap(),
+            approval_id: Some(0),
+            payloads: vec!["00".repeat(32)],
+        },
+    ));
+
+    assert_eq!(
+        ChainKeyTokenEvent::from_log(&event.to_log()).unwrap(),
+        event
+    );
+}
+
+#[test]
+fn rejects_other_standards_and_versions() {
+    assert!(matches!(
+        GasStationEvent::from_log("not an event"),
+        Err(EventLogError::NotAnEvent),
+    ));
+    assert!(matches!(
+        GasStationEvent::from_log(
+            r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_transfer","data":[]}"#,
+        ),
+        Err(EventLogError::UnexpectedStandard(_)),
+    ));
+    assert!(matches!(
+        GasStationEvent::from_log(
+            r#"EVENT_JSON:{"standard":"x-gas-station","version":"9.9.9","event":"ConfigSnapshot","data":{}}"#,
+        ),
+        Err(EventLogError::UnsupportedVersion(_)),
+    ));
+}
@@ -0,0 +1,23 @@
+pub use lib::events::chain_key_token::{
+    Approved, RecoveryApproved, RecoveryCancelled, RecoveryConfigured, RecoveryExecuted, Revoked,
+    RevokedAll, SignatureRequested,
+};
+use near_sdk_contract_tools::event;
+
+/// Events emitted whenever the set of accounts able to use a chain key
+/// changes, when a signature is requested from a chain key, and as the
+/// social recovery of a chain key progresses.
+///
+/// Payloads are declared in [`lib::events::chain_key_token`], which off-chain
+/// consumers use to decode these events.
+#[event(version = "0.1.0", standard = "x-chain-key-token")]
+pub enum ContractEvent {
+    Approved(Approved),
+    Revoked(Revoked),
+    RevokedAll(RevokedAll),
+    SignatureRequested(SignatureRequested),
+    RecoveryConfigured(RecoveryConfigured),
+    RecoveryApproved(RecoveryApproved),
+    RecoveryCancelled(RecoveryCancelled),
+    RecoveryExecuted(RecoveryExecuted),
+}
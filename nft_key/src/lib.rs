@@ -1,25 +1,51 @@
+use ethers_core::utils::hex;
 use lib::{
-    chain_key::{ext_chain_key_token_approval_receiver, ChainKeyToken, ChainKeyTokenApproval},
+    address_format::AddressFormat,
+    chain_key::{
+        ext_chain_key_token_approval_receiver, ChainKeyApprovalResponse, ChainKeyToken,
+        ChainKeyTokenApproval,
+    },
     signer::{ext_signer, SignRequest, SignResult},
     Rejectable,
 };
 use near_sdk::{
-    assert_one_yocto, collections::UnorderedMap, env, near, require, AccountId, AccountIdRef,
-    BorshStorageKey, Gas, PanicOnDefault, Promise, PromiseError, PromiseOrValue, PublicKey,
+    assert_one_yocto,
+    collections::{LookupMap, UnorderedMap},
+    env,
+    json_types::U128,
+    near, require, AccountId, AccountIdRef, BorshStorageKey, Gas, NearToken, PanicOnDefault,
+    Promise, PromiseError, PromiseOrValue, PromiseResult, PublicKey,
 };
-use near_sdk_contract_tools::hook::Hook;
 #[allow(clippy::wildcard_imports)]
 use near_sdk_contract_tools::nft::*;
+use near_sdk_contract_tools::{hook::Hook, standard::nep297::Event};
+
+pub mod contract_event;
+use contract_event::{Approved, ContractEvent, Revoked, RevokedAll, SignatureRequested};
+mod recovery;
+pub use recovery::{PendingRecovery, RecoveryConfiguration, TokenRecovery, MAX_GUARDIANS};
 
 /// OID for secp256k1 curve.
 /// See: <https://oidref.com/1.3.132.0.10>
 static SCHEME_OID: &str = "1.3.132.0.10";
 
+/// Upper bound on the number of payloads accepted by `ckt_sign_hashes`, so
+/// that every signature request can be allocated a useful amount of gas.
+const MAX_SIGN_HASHES_BATCH_SIZE: usize = 4;
+
+/// Upper bounds on the storage used by minting a token and approving an
+/// account for it, used by `estimate_onboarding_cost` until the first mint
+/// and approval are measured.
+const INITIAL_MINT_STORAGE_USAGE: u64 = 1_000;
+const INITIAL_APPROVAL_STORAGE_USAGE: u64 = 300;
+
 #[derive(Debug, BorshStorageKey)]
 #[near]
 enum StorageKey {
     KeyData,
     ApprovalsFor(u32),
+    Recoveries,
+    VersionedKeyData,
 }
 
 #[derive(Debug)]
@@ -27,6 +53,72 @@ enum StorageKey {
 pub struct KeyData {
     pub approvals: UnorderedMap<AccountId, u32>,
     pub key_version: u32,
+    /// Set after an upgrade until the owner finalizes it. Signatures may be
+    /// requested from either version in the meantime.
+    pub previous_key_version: Option<u32>,
+    /// Soulbound tokens cannot be transferred, only burned.
+    pub is_soulbound: bool,
+}
+
+/// Layout of [`KeyData`] before key version upgrades and soulbound tokens.
+/// Tokens minted before the migration keep this layout until written again.
+#[derive(Debug)]
+#[near]
+pub struct KeyDataV0 {
+    pub approvals: UnorderedMap<AccountId, u32>,
+    pub key_version: u32,
+}
+
+impl From<KeyDataV0> for KeyData {
+    fn from(key_data: KeyDataV0) -> Self {
+        Self {
+            approvals: key_data.approvals,
+            key_version: key_data.key_version,
+            previous_key_version: None,
+            is_soulbound: false,
+        }
+    }
+}
+
+/// Stored form of [`KeyData`], so that later layout changes can be migrated
+/// lazily.
+#[derive(Debug)]
+#[near]
+pub enum VersionedKeyData {
+    V1(KeyData),
+}
+
+impl From<VersionedKeyData> for KeyData {
+    fn from(key_data: VersionedKeyData) -> Self {
+        match key_data {
+            VersionedKeyData::V1(key_data) => key_data,
+        }
+    }
+}
+
+impl KeyData {
+    fn versions(&self) -> KeyVersions {
+        KeyVersions {
+            key_version: self.key_version,
+            previous_key_version: self.previous_key_version,
+        }
+    }
+
+    /// Resolves the key version to sign with, defaulting to the current one.
+    fn resolve_key_version(&self, requested: Option<u32>) -> u32 {
+        match requested {
+            None => self.key_version,
+            Some(v) if v == self.key_version || Some(v) == self.previous_key_version => v,
+            Some(_) => env::panic_str("Key version is not available for this token"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct KeyVersions {
+    pub key_version: u32,
+    pub previous_key_version: Option<u32>,
 }
 
 #[derive(Debug, PanicOnDefault, NonFungibleToken)]
@@ -35,7 +127,24 @@ pub struct KeyData {
 pub struct NftKeyContract {
     pub next_id: u32,
     pub signer_contract_id: AccountId,
-    pub key_data: UnorderedMap<u32, KeyData>,
+    pub key_data: UnorderedMap<u32, VersionedKeyData>,
+    /// Key data of tokens minted before the migration to [`VersionedKeyData`]
+    /// and not written since.
+    pub legacy_key_data: UnorderedMap<u32, KeyDataV0>,
+    pub recoveries: LookupMap<u32, TokenRecovery>,
+    /// Bytes used by the most recent mint.
+    pub mint_storage_usage: u64,
+    /// Bytes used by the most recent approval of a new account.
+    pub approval_storage_usage: u64,
+}
+
+/// Contract state before key version upgrades, soulbound tokens and
+/// recovery.
+#[near]
+struct NftKeyContractV0 {
+    next_id: u32,
+    signer_contract_id: AccountId,
+    key_data: UnorderedMap<u32, KeyDataV0>,
 }
 
 fn generate_token_metadata(id: u32) -> TokenMetadata {
@@ -50,7 +159,11 @@ impl NftKeyContract {
         let mut contract = Self {
             next_id: 0,
             signer_contract_id,
-            key_data: UnorderedMap::new(StorageKey::KeyData),
+            key_data: UnorderedMap::new(StorageKey::VersionedKeyData),
+            legacy_key_data: UnorderedMap::new(StorageKey::KeyData),
+            recoveries: LookupMap::new(StorageKey::Recoveries),
+            mint_storage_usage: INITIAL_MINT_STORAGE_USAGE,
+            approval_storage_usage: INITIAL_APPROVAL_STORAGE_USAGE,
         };
 
         contract.set_contract_metadata(&ContractMetadata::new("Chain Key Token", "CKT", None));
@@ -58,6 +171,25 @@ impl NftKeyContract {
         contract
     }
 
+    /// Migrates the state of deployments predating key version upgrades,
+    /// soulbound tokens and recovery. Key data of existing tokens is
+    /// migrated lazily, the next time it is written.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let old = env::state_read::<NftKeyContractV0>().expect_or_reject("No state to migrate");
+
+        Self {
+            next_id: old.next_id,
+            signer_contract_id: old.signer_contract_id,
+            key_data: UnorderedMap::new(StorageKey::VersionedKeyData),
+            legacy_key_data: old.key_data,
+            recoveries: LookupMap::new(StorageKey::Recoveries),
+            mint_storage_usage: INITIAL_MINT_STORAGE_USAGE,
+            approval_storage_usage: INITIAL_APPROVAL_STORAGE_USAGE,
+        }
+    }
+
     #[cfg(feature = "debug")]
     pub fn set_signer_contract_id(&mut self, account_id: AccountId) {
         self.signer_contract_id = account_id;
@@ -74,6 +206,22 @@ impl NftKeyContract {
     }
 
     pub fn mint(&mut self) -> Promise {
+        self.mint_key(false)
+    }
+
+    /// Mints a token that can never be transferred, only burned.
+    pub fn mint_soulbound(&mut self) -> Promise {
+        self.mint_key(true)
+    }
+
+    pub fn is_soulbound(&self, token_id: TokenId) -> bool {
+        let id: u32 = token_id.parse().expect_or_reject("Invalid token ID");
+        self.get_key_data(id)
+            .expect_or_reject("Missing data for key")
+            .is_soulbound
+    }
+
+    fn mint_key(&mut self, is_soulbound: bool) -> Promise {
         let storage_usage_start = env::storage_usage();
         let id = self.generate_id();
         let predecessor = env::predecessor_account_id();
@@ -86,6 +234,7 @@ impl NftKeyContract {
                 id,
                 predecessor,
                 storage_usage_start,
+                is_soulbound,
             ))
     }
 
@@ -95,25 +244,134 @@ impl NftKeyContract {
         #[serializer(borsh)] id: u32,
         #[serializer(borsh)] predecessor: AccountId,
         #[serializer(borsh)] storage_usage_start: u64,
+        #[serializer(borsh)] is_soulbound: bool,
         #[callback_result] result: Result<u32, PromiseError>,
     ) -> u32 {
         let key_version = result.unwrap();
+        let mint_storage_usage_start = env::storage_usage();
 
-        self.key_data.insert(
-            &id,
-            &KeyData {
+        self.insert_key_data(
+            id,
+            KeyData {
                 key_version,
+                previous_key_version: None,
+                is_soulbound,
                 approvals: UnorderedMap::new(StorageKey::ApprovalsFor(id)),
             },
         );
         self.mint_with_metadata(&id.to_string(), &predecessor, &generate_token_metadata(id))
             .unwrap_or_reject();
+        self.mint_storage_usage = env::storage_usage().saturating_sub(mint_storage_usage_start);
 
         self.storage_accounting(&predecessor, storage_usage_start)
             .unwrap_or_reject();
 
         id
     }
+
+    /// Moves the token to the signer's latest key version. The previous
+    /// version remains usable until `finalize_key_version_upgrade` is called.
+    #[payable]
+    pub fn upgrade_key_version(&mut self, token_id: TokenId) -> Promise {
+        assert_one_yocto();
+        let predecessor = env::predecessor_account_id();
+        self.require_is_token_owner(&predecessor, &token_id);
+        let id: u32 = token_id.parse().expect_or_reject("Invalid token ID");
+
+        let key_data = self
+            .get_key_data(id)
+            .expect_or_reject("Missing data for key");
+        require!(
+            key_data.previous_key_version.is_none(),
+            "A key version upgrade is already in progress",
+        );
+
+        ext_signer::ext(self.signer_contract_id.clone())
+            .latest_key_version()
+            .then(
+                Self::ext(env::current_account_id()).upgrade_key_version_callback(id, predecessor),
+            )
+    }
+
+    #[private]
+    pub fn upgrade_key_version_callback(
+        &mut self,
+        #[serializer(borsh)] id: u32,
+        #[serializer(borsh)] owner_id: AccountId,
+        #[callback_result] result: Result<u32, PromiseError>,
+    ) -> KeyVersions {
+        let latest_key_version = result
+            .ok()
+            .expect_or_reject("Failed to retrieve latest key version");
+
+        let storage_usage_start = env::storage_usage();
+
+        let mut key_data = self
+            .get_key_data(id)
+            .expect_or_reject("Missing data for key");
+
+        if latest_key_version <= key_data.key_version || key_data.previous_key_version.is_some() {
+            return key_data.versions();
+        }
+
+        key_data.previous_key_version = Some(key_data.key_version);
+        key_data.key_version = latest_key_version;
+        let versions = key_data.versions();
+        self.insert_key_data(id, key_data);
+
+        self.storage_accounting(&owner_id, storage_usage_start)
+            .unwrap_or_reject();
+
+        versions
+    }
+
+    /// Ends the transition window of a key version upgrade, after which only
+    /// the current key version can be used to sign.
+    #[payable]
+    pub fn finalize_key_version_upgrade(&mut self, token_id: TokenId) {
+        assert_one_yocto();
+        let predecessor = env::predecessor_account_id();
+        self.require_is_token_owner(&predecessor, &token_id);
+        let id: u32 = token_id.parse().expect_or_reject("Invalid token ID");
+
+        let storage_usage_start = env::storage_usage();
+
+        let mut key_data = self
+            .get_key_data(id)
+            .expect_or_reject("Missing data for key");
+        key_data.previous_key_version = None;
+        self.insert_key_data(id, key_data);
+
+        self.storage_accounting(&predecessor, storage_usage_start)
+            .unwrap_or_reject();
+    }
+
+    /// NEAR a new user needs to mint a token and approve an account (e.g.
+    /// the gas station) for it with `ckt_approve_call`: the storage deposit,
+    /// which is at least the minimum storage balance, plus the yoctoNEAR
+    /// attached to `ckt_approve_call`.
+    ///
+    /// Storage is estimated from the most recent mint and approval, so it
+    /// can be off by a few bytes when token or account IDs differ in length.
+    pub fn estimate_onboarding_cost(&self) -> U128 {
+        let storage_cost = env::storage_byte_cost()
+            .saturating_mul(u128::from(
+                self.mint_storage_usage + self.approval_storage_usage,
+            ))
+            .max(self.storage_balance_bounds().min);
+
+        storage_cost
+            .saturating_add(NearToken::from_yoctonear(1))
+            .as_yoctonear()
+            .into()
+    }
+
+    pub fn get_key_versions(&self, token_id: TokenId) -> KeyVersions {
+        let id: u32 = token_id.parse().expect_or_reject("Invalid token ID");
+        self.get_key_data(id)
+            .expect_or_reject("Missing data for key")
+            .versions()
+    }
 }
 
 #[near]
@@ -125,36 +383,29 @@ impl ChainKeyToken for NftKeyContract {
         path: Option<String>,
         payload: Vec<u8>,
         approval_id: Option<u32>,
+        key_version: Option<u32>,
     ) -> PromiseOrValue<String> {
         assert_one_yocto();
 
-        let id = token_id.parse().expect_or_reject("Invalid token ID");
         let path = path.unwrap_or_default();
-
-        let expected_owner_id = env::predecessor_account_id();
-        let actual_owner_id = self.token_owner(&token_id.to_string());
-
-        let key_data = self
-            .key_data
-            .get(&id)
-            .expect_or_reject("Missing data for key");
-
-        require!(
-            Some(&expected_owner_id) == actual_owner_id.as_ref()
-                || key_data
-                    .approvals
-                    .get(&env::predecessor_account_id())
-                    .zip(approval_id)
-                    .map_or(false, |(actual, expected)| actual == expected),
-            "Unauthorized",
-        );
+        let key_data = self.require_can_sign(&token_id, approval_id);
+        let key_version = key_data.resolve_key_version(key_version);
+
+        ContractEvent::SignatureRequested(SignatureRequested {
+            token_id: token_id.clone(),
+            path: path.clone(),
+            requested_by: env::predecessor_account_id(),
+            approval_id,
+            payloads: vec![hex::encode(&payload)],
+        })
+        .emit();
 
         PromiseOrValue::Promise(
             ext_signer::ext(self.signer_contract_id.clone())
                 .sign(SignRequest::new(
                     payload.try_into().unwrap(),
                     make_path_string(&token_id, &path),
-                    key_data.key_version,
+                    key_version,
                 ))
                 .then(
                     Self::ext(env::current_account_id())
@@ -165,6 +416,67 @@ impl ChainKeyToken for NftKeyContract {
         )
     }
 
+    #[payable]
+    fn ckt_sign_hashes(
+        &mut self,
+        token_id: TokenId,
+        path: Option<String>,
+        payloads: Vec<Vec<u8>>,
+        approval_id: Option<u32>,
+        key_version: Option<u32>,
+    ) -> PromiseOrValue<Vec<String>> {
+        assert_one_yocto();
+
+        require!(!payloads.is_empty(), "No payloads provided");
+        require!(
+            payloads.len() <= MAX_SIGN_HASHES_BATCH_SIZE,
+            "Too many payloads",
+        );
+
+        let path = path.unwrap_or_default();
+        let key_data = self.require_can_sign(&token_id, approval_id);
+        let key_version = key_data.resolve_key_version(key_version);
+
+        ContractEvent::SignatureRequested(SignatureRequested {
+            token_id: token_id.clone(),
+            path: path.clone(),
+            requested_by: env::predecessor_account_id(),
+            approval_id,
+            payloads: payloads.iter().map(hex::encode).collect(),
+        })
+        .emit();
+
+        let path = make_path_string(&token_id, &path);
+
+        #[allow(clippy::cast_possible_truncation)]
+        let callback_gas = Gas::from_gas(Self::SIGN_CALLBACK_GAS.as_gas() * payloads.len() as u64);
+
+        // Each signature request receives an equal share of the unused gas.
+        let sign_all = payloads
+            .into_iter()
+            .map(|payload| {
+                ext_signer::ext(self.signer_contract_id.clone()).sign(SignRequest::new(
+                    payload
+                        .try_into()
+                        .ok()
+                        .expect_or_reject("Invalid payload length"),
+                    path.clone(),
+                    key_version,
+                ))
+            })
+            .reduce(Promise::and)
+            .unwrap_or_reject();
+
+        PromiseOrValue::Promise(
+            sign_all.then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(callback_gas)
+                    .with_unused_gas_weight(0)
+                    .sign_hashes_callback(),
+            ),
+        )
+    }
+
     fn ckt_public_key_for(
         &mut self,
         token_id: TokenId,
@@ -178,11 +490,44 @@ impl ChainKeyToken for NftKeyContract {
         )
     }
 
+    fn ckt_addresses_for(
+        &mut self,
+        token_id: TokenId,
+        path: Option<String>,
+        formats: Vec<AddressFormat>,
+    ) -> PromiseOrValue<Vec<String>> {
+        let path = path.unwrap_or_default();
+
+        PromiseOrValue::Promise(
+            ext_signer::ext(self.signer_contract_id.clone())
+                .derived_public_key(make_path_string(&token_id, &path), None)
+                .then(Self::ext(env::current_account_id()).addresses_for_callback(formats)),
+        )
+    }
+
     fn ckt_scheme_oid(&self) -> String {
         SCHEME_OID.to_string()
     }
 }
 
+impl NftKeyContract {
+    /// Key data of a token, whether or not it has been migrated out of
+    /// [`KeyDataV0`].
+    fn get_key_data(&self, id: u32) -> Option<KeyData> {
+        self.key_data
+            .get(&id)
+            .map(Into::into)
+            .or_else(|| self.legacy_key_data.get(&id).map(Into::into))
+    }
+
+    /// Writes the key data of a token in the current layout, migrating it
+    /// out of [`KeyDataV0`] if needed.
+    fn insert_key_data(&mut self, id: u32, key_data: KeyData) {
+        self.legacy_key_data.remove(&id);
+        self.key_data.insert(&id, &VersionedKeyData::V1(key_data));
+    }
+}
+
 fn make_path_string(token_id: &str, path: &str) -> String {
     format!("{token_id},{path}")
 }
@@ -203,20 +548,61 @@ impl NftKeyContract {
         ethers_signature.to_string()
     }
 
+    #[private]
+    #[must_use]
+    pub fn addresses_for_callback(
+        &self,
+        formats: Vec<AddressFormat>,
+        #[callback_result] result: Result<PublicKey, PromiseError>,
+    ) -> Vec<String> {
+        let public_key = result
+            .ok()
+            .expect_or_reject("Failed to retrieve public key");
+
+        formats
+            .into_iter()
+            .map(|format| format.address_for(public_key.as_bytes()))
+            .collect()
+    }
+
+    #[private]
+    #[must_use]
+    pub fn sign_hashes_callback(&self) -> Vec<String> {
+        (0..env::promise_results_count())
+            .map(|i| {
+                let PromiseResult::Successful(data) = env::promise_result(i) else {
+                    env::panic_str(&format!("Failed to produce signature {i}"));
+                };
+                let mpc_signature: SignResult =
+                    near_sdk::serde_json::from_slice(&data).unwrap_or_reject();
+                let ethers_signature: ethers_core::types::Signature =
+                    mpc_signature.try_into().unwrap_or_reject();
+                ethers_signature.to_string()
+            })
+            .collect()
+    }
+
     #[private]
     pub fn ckt_approve_callback(
         &mut self,
         #[serializer(borsh)] token_id: u32,
+        #[serializer(borsh)] owner_id: AccountId,
         #[serializer(borsh)] account_id: AccountId,
         #[serializer(borsh)] approval_id: u32,
-        #[callback_result] result: Result<bool, PromiseError>,
+        #[serializer(borsh)] forwarded_deposit: NearToken,
+        #[callback_result] result: Result<ChainKeyApprovalResponse, PromiseError>,
     ) -> Option<u32> {
-        if result == Ok(false) {
+        if let Ok(ChainKeyApprovalResponse { revoke: false, .. }) = result {
             Some(approval_id)
         } else {
-            let mut key_data = self.key_data.get(&token_id).unwrap_or_reject();
-            let ejected_id = key_data.approvals.remove(&account_id);
-            self.key_data.insert(&token_id, &key_data);
+            // A receiver that ran `ckt_on_approved` responds with what it did
+            // with the deposit, so an error means that the call itself
+            // failed, and the deposit was refunded to this contract.
+            if result.is_err() && !forwarded_deposit.is_zero() {
+                Promise::new(owner_id.clone()).transfer(forwarded_deposit);
+            }
+
+            let ejected_id = self.revoke(token_id, &owner_id, &account_id);
             require!(ejected_id == Some(approval_id), "Inconsistent approval ID");
             None
         }
@@ -225,30 +611,122 @@ impl NftKeyContract {
     #[private]
     pub fn ckt_revoke_callback(&self) {}
 
+    /// Asserts that the predecessor may sign with the token, either as its
+    /// owner or as an approved account, and returns the token's key data.
+    fn require_can_sign(&self, token_id: &TokenId, approval_id: Option<u32>) -> KeyData {
+        let id = token_id.parse().expect_or_reject("Invalid token ID");
+
+        let expected_owner_id = env::predecessor_account_id();
+        let actual_owner_id = self.token_owner(token_id);
+
+        let key_data = self
+            .get_key_data(id)
+            .expect_or_reject("Missing data for key");
+
+        require!(
+            Some(&expected_owner_id) == actual_owner_id.as_ref()
+                || key_data
+                    .approvals
+                    .get(&expected_owner_id)
+                    .zip(approval_id)
+                    .map_or(false, |(actual, expected)| actual == expected),
+            "Unauthorized",
+        );
+
+        key_data
+    }
+
     fn require_is_token_owner(&self, predecessor: &AccountId, token_id: &TokenId) {
         let actual_owner = Nep171Controller::token_owner(self, token_id);
         require!(actual_owner.as_ref() == Some(predecessor), "Unauthorized");
     }
 
-    fn approve(&mut self, token_id: u32, account_id: &AccountId) -> u32 {
+    /// Storage used by the approval is charged to `owner_id`.
+    fn approve(&mut self, token_id: u32, owner_id: &AccountId, account_id: &AccountId) -> u32 {
+        let storage_usage_start = env::storage_usage();
         let approval_id = self.generate_id();
 
         let mut key_data = self
-            .key_data
-            .get(&token_id)
+            .get_key_data(token_id)
             .expect_or_reject("Missing data for key");
         key_data.approvals.insert(account_id, &approval_id);
-        self.key_data.insert(&token_id, &key_data);
+        self.insert_key_data(token_id, key_data);
+
+        let approval_storage_usage = env::storage_usage().saturating_sub(storage_usage_start);
+        if approval_storage_usage > 0 {
+            self.approval_storage_usage = approval_storage_usage;
+        }
+
+        self.storage_accounting(owner_id, storage_usage_start)
+            .unwrap_or_reject();
+
+        ContractEvent::Approved(Approved {
+            token_id: token_id.to_string(),
+            owner_id: owner_id.clone(),
+            account_id: account_id.clone(),
+            approval_id,
+        })
+        .emit();
 
         approval_id
     }
 
-    fn revoke(&mut self, token_id: u32, account_id: &AccountId) -> Option<u32> {
-        self.key_data.get(&token_id).and_then(|mut key_data| {
+    /// Storage released by the revocation is refunded to `owner_id`.
+    fn revoke(
+        &mut self,
+        token_id: u32,
+        owner_id: &AccountId,
+        account_id: &AccountId,
+    ) -> Option<u32> {
+        let storage_usage_start = env::storage_usage();
+
+        let removed = self.get_key_data(token_id).and_then(|mut key_data| {
             let removed = key_data.approvals.remove(account_id);
-            self.key_data.insert(&token_id, &key_data);
+            self.insert_key_data(token_id, key_data);
             removed
-        })
+        });
+
+        self.storage_accounting(owner_id, storage_usage_start)
+            .unwrap_or_reject();
+
+        if let Some(approval_id) = removed {
+            ContractEvent::Revoked(Revoked {
+                token_id: token_id.to_string(),
+                owner_id: owner_id.clone(),
+                account_id: account_id.clone(),
+                approval_id,
+            })
+            .emit();
+        }
+
+        removed
+    }
+
+    /// Storage released by the revocations is refunded to `owner_id`.
+    fn revoke_all(&mut self, token_id: u32, owner_id: &AccountId) -> u64 {
+        let storage_usage_start = env::storage_usage();
+
+        let Some(mut key_data) = self.get_key_data(token_id) else {
+            return 0;
+        };
+
+        let len = key_data.approvals.len();
+        key_data.approvals.clear();
+        self.insert_key_data(token_id, key_data);
+
+        self.storage_accounting(owner_id, storage_usage_start)
+            .unwrap_or_reject();
+
+        if len > 0 {
+            ContractEvent::RevokedAll(RevokedAll {
+                token_id: token_id.to_string(),
+                owner_id: owner_id.clone(),
+                count: len.into(),
+            })
+            .emit();
+        }
+
+        len
     }
 }
 
@@ -260,9 +738,12 @@ impl ChainKeyTokenApproval for NftKeyContract {
         let predecessor = env::predecessor_account_id();
         self.require_is_token_owner(&predecessor, &token_id);
         let id = token_id.parse().expect_or_reject("Invalid token ID");
-        self.approve(id, &account_id)
+        self.approve(id, &predecessor, &account_id)
     }
 
+    /// Any deposit attached in excess of 1 yoctoNEAR is forwarded to the
+    /// receiver's `ckt_on_approved`. It is returned to the owner only if that
+    /// call fails; otherwise the receiver keeps or refunds it.
     #[payable]
     fn ckt_approve_call(
         &mut self,
@@ -270,19 +751,29 @@ impl ChainKeyTokenApproval for NftKeyContract {
         account_id: AccountId,
         msg: Option<String>,
     ) -> PromiseOrValue<Option<u32>> {
-        assert_one_yocto();
+        let forwarded_deposit = env::attached_deposit()
+            .checked_sub(NearToken::from_yoctonear(1))
+            .expect_or_reject("Requires attached deposit of at least 1 yoctoNEAR");
         let predecessor = env::predecessor_account_id();
         self.require_is_token_owner(&predecessor, &token_id);
         let id = token_id.parse().expect_or_reject("Invalid token ID");
-        let approval_id = self.approve(id, &account_id);
+        let approval_id = self.approve(id, &predecessor, &account_id);
 
         PromiseOrValue::Promise(
             ext_chain_key_token_approval_receiver::ext(account_id.clone())
-                .ckt_on_approved(predecessor, token_id, approval_id, msg.unwrap_or_default())
+                .with_attached_deposit(forwarded_deposit)
+                .ckt_on_approved(
+                    predecessor.clone(),
+                    token_id,
+                    approval_id,
+                    msg.unwrap_or_default(),
+                )
                 .then(Self::ext(env::current_account_id()).ckt_approve_callback(
                     id,
+                    predecessor,
                     account_id,
                     approval_id,
+                    forwarded_deposit,
                 )),
         )
     }
@@ -293,7 +784,7 @@ impl ChainKeyTokenApproval for NftKeyContract {
         let predecessor = env::predecessor_account_id();
         self.require_is_token_owner(&predecessor, &token_id);
         let id = token_id.parse().expect_or_reject("Invalid token ID");
-        self.revoke(id, &account_id);
+        self.revoke(id, &predecessor, &account_id);
     }
 
     #[payable]
@@ -307,7 +798,7 @@ impl ChainKeyTokenApproval for NftKeyContract {
         let predecessor = env::predecessor_account_id();
         self.require_is_token_owner(&predecessor, &token_id);
         let id = token_id.parse().expect_or_reject("Invalid token ID");
-        let revoked_approval_id = self.revoke(id, &account_id);
+        let revoked_approval_id = self.revoke(id, &predecessor, &account_id);
 
         if let Some(revoked_approval_id) = revoked_approval_id {
             PromiseOrValue::Promise(
@@ -332,22 +823,13 @@ impl ChainKeyTokenApproval for NftKeyContract {
         self.require_is_token_owner(&predecessor, &token_id);
 
         let id: u32 = token_id.parse().expect_or_reject("Invalid token ID");
-        let Some(mut key_data) = self.key_data.get(&id) else {
-            return 0.into();
-        };
-
-        let len = key_data.approvals.len();
-        key_data.approvals.clear();
-        self.key_data.insert(&id, &key_data);
-
-        len.into()
+        self.revoke_all(id, &predecessor).into()
     }
 
     fn ckt_approval_id_for(&self, token_id: TokenId, account_id: AccountId) -> Option<u32> {
         let id: u32 = token_id.parse().expect_or_reject("Invalid token ID");
 
-        self.key_data
-            .get(&id)
+        self.get_key_data(id)
             .and_then(|key_data| key_data.approvals.get(&account_id))
     }
 }
@@ -358,7 +840,19 @@ impl Hook<NftKeyContract, Nep171Transfer<'_>> for NftKeyContract {
         transfer: &Nep171Transfer<'_>,
         f: impl FnOnce(&mut NftKeyContract) -> R,
     ) -> R {
-        contract.ckt_revoke_all(transfer.token_id.clone());
+        let id: u32 = transfer
+            .token_id
+            .parse()
+            .expect_or_reject("Invalid token ID");
+        require!(
+            !contract.get_key_data(id).is_some_and(|k| k.is_soulbound),
+            "Soulbound tokens cannot be transferred",
+        );
+        let owner_id = contract
+            .token_owner(&transfer.token_id)
+            .expect_or_reject("Token does not exist");
+        contract.revoke_all(id, &owner_id);
+        contract.clear_recovery(id, &owner_id);
         f(contract)
     }
 }
@@ -369,8 +863,11 @@ impl Hook<NftKeyContract, Nep171Burn<'_>> for NftKeyContract {
         burn: &Nep171Burn,
         f: impl FnOnce(&mut NftKeyContract) -> R,
     ) -> R {
+        let owner_id = burn.owner_id.clone().into_owned();
         for token_id in &burn.token_ids {
-            contract.ckt_revoke_all(token_id.clone());
+            let id: u32 = token_id.parse().expect_or_reject("Invalid token ID");
+            contract.revoke_all(id, &owner_id);
+            contract.clear_recovery(id, &owner_id);
         }
         f(contract)
     }
@@ -0,0 +1,297 @@
+use lib::Rejectable;
+use near_sdk::{assert_one_yocto, env, json_types::U64, near, require, AccountId};
+#[allow(clippy::wildcard_imports)]
+use near_sdk_contract_tools::nft::*;
+use near_sdk_contract_tools::{nft::nep171::Nep171TransferAuthorization, standard::nep297::Event};
+
+use crate::{
+    contract_event::{
+        ContractEvent, RecoveryApproved, RecoveryCancelled, RecoveryConfigured, RecoveryExecuted,
+    },
+    NftKeyContract, NftKeyContractExt,
+};
+
+/// Upper bound on the number of guardians of a token, to bound the storage
+/// and gas used by a recovery.
+pub const MAX_GUARDIANS: usize = 10;
+
+/// Opt-in social recovery of a token: once `threshold` guardians agree on a
+/// recovery account, the token can be transferred to it after a timelock,
+/// during which the owner can cancel the recovery.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct RecoveryConfiguration {
+    pub guardians: Vec<AccountId>,
+    pub threshold: u32,
+    pub timelock_blocks: U64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct PendingRecovery {
+    pub recovery_account_id: AccountId,
+    pub approved_by: Vec<AccountId>,
+    /// Set once `threshold` guardians have approved.
+    pub executable_at_block_height: Option<U64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct TokenRecovery {
+    pub configuration: RecoveryConfiguration,
+    pub pending: Option<PendingRecovery>,
+}
+
+#[near]
+impl NftKeyContract {
+    /// Sets (or, with `None`, removes) the recovery configuration of a token.
+    /// Any pending recovery is cancelled. The configuration is cleared when
+    /// the token is transferred.
+    #[payable]
+    pub fn set_recovery_configuration(
+        &mut self,
+        token_id: TokenId,
+        configuration: Option<RecoveryConfiguration>,
+    ) {
+        assert_one_yocto();
+        let predecessor = env::predecessor_account_id();
+        self.require_is_token_owner(&predecessor, &token_id);
+        let id: u32 = token_id.parse().expect_or_reject("Invalid token ID");
+
+        if let Some(ref configuration) = configuration {
+            require!(
+                !self.get_key_data(id).is_some_and(|k| k.is_soulbound),
+                "Soulbound tokens cannot be recovered",
+            );
+            require!(
+                !configuration.guardians.is_empty()
+                    && configuration.guardians.len() <= MAX_GUARDIANS,
+                format!("Recovery requires between 1 and {MAX_GUARDIANS} guardians"),
+            );
+            require!(
+                configuration
+                    .guardians
+                    .iter()
+                    .enumerate()
+                    .all(|(i, g)| !configuration.guardians[..i].contains(g) && *g != predecessor),
+                "Guardians must be distinct and must not include the owner",
+            );
+            require!(
+                configuration.threshold > 0
+                    && configuration.threshold as usize <= configuration.guardians.len(),
+                "Threshold must be between 1 and the number of guardians",
+            );
+        }
+
+        let storage_usage_start = env::storage_usage();
+
+        self.cancel_pending_recovery(id, &predecessor);
+
+        match configuration {
+            Some(ref configuration) => {
+                self.recoveries.insert(
+                    &id,
+                    &TokenRecovery {
+                        configuration: configuration.clone(),
+                        pending: None,
+                    },
+                );
+            }
+            None => {
+                self.recoveries.remove(&id);
+            }
+        }
+
+        self.storage_accounting(&predecessor, storage_usage_start)
+            .unwrap_or_reject();
+
+        let configuration = configuration.unwrap_or(RecoveryConfiguration {
+            guardians: vec![],
+            threshold: 0,
+            timelock_blocks: 0.into(),
+        });
+
+        ContractEvent::RecoveryConfigured(RecoveryConfigured {
+            token_id,
+            owner_id: predecessor,
+            guardians: configuration.guardians,
+            threshold: configuration.threshold,
+            timelock_blocks: configuration.timelock_blocks,
+        })
+        .emit();
+    }
+
+    pub fn get_recovery(&self, token_id: TokenId) -> Option<TokenRecovery> {
+        let id: u32 = token_id.parse().expect_or_reject("Invalid token ID");
+        self.recoveries.get(&id)
+    }
+
+    /// Approves recovering the token to `recovery_account_id`. Approving a
+    /// different account than the pending recovery restarts the recovery
+    /// with only the predecessor's approval. The timelock starts once
+    /// `threshold` guardians have approved the same account.
+    ///
+    /// Storage is charged to the token owner.
+    pub fn approve_recovery(&mut self, token_id: TokenId, recovery_account_id: AccountId) {
+        let predecessor = env::predecessor_account_id();
+        let id: u32 = token_id.parse().expect_or_reject("Invalid token ID");
+        let owner_id = self
+            .token_owner(&token_id)
+            .expect_or_reject("Token does not exist");
+
+        let mut recovery = self
+            .recoveries
+            .get(&id)
+            .expect_or_reject("Recovery is not configured for this token");
+
+        require!(
+            recovery.configuration.guardians.contains(&predecessor),
+            "Predecessor is not a guardian of this token",
+        );
+        require!(
+            recovery_account_id != owner_id,
+            "Recovery account must differ from the owner",
+        );
+
+        let storage_usage_start = env::storage_usage();
+
+        let mut pending = recovery
+            .pending
+            .take()
+            .filter(|p| p.recovery_account_id == recovery_account_id)
+            .unwrap_or_else(|| PendingRecovery {
+                recovery_account_id: recovery_account_id.clone(),
+                approved_by: vec![],
+                executable_at_block_height: None,
+            });
+
+        require!(
+            !pending.approved_by.contains(&predecessor),
+            "Guardian has already approved this recovery",
+        );
+        pending.approved_by.push(predecessor.clone());
+
+        #[allow(clippy::cast_possible_truncation)]
+        let approval_count = pending.approved_by.len() as u32;
+
+        if pending.executable_at_block_height.is_none()
+            && approval_count >= recovery.configuration.threshold
+        {
+            pending.executable_at_block_height = Some(
+                env::block_height()
+                    .saturating_add(recovery.configuration.timelock_blocks.0)
+                    .into(),
+            );
+        }
+
+        let executable_at_block_height = pending.executable_at_block_height;
+        recovery.pending = Some(pending);
+        self.recoveries.insert(&id, &recovery);
+
+        self.storage_accounting(&owner_id, storage_usage_start)
+            .unwrap_or_reject();
+
+        ContractEvent::RecoveryApproved(RecoveryApproved {
+            token_id,
+            guardian_id: predecessor,
+            recovery_account_id,
+            approval_count,
+            executable_at_block_height,
+        })
+        .emit();
+    }
+
+    #[payable]
+    pub fn cancel_recovery(&mut self, token_id: TokenId) {
+        assert_one_yocto();
+        let predecessor = env::predecessor_account_id();
+        self.require_is_token_owner(&predecessor, &token_id);
+        let id: u32 = token_id.parse().expect_or_reject("Invalid token ID");
+
+        let storage_usage_start = env::storage_usage();
+
+        require!(
+            self.cancel_pending_recovery(id, &predecessor),
+            "No recovery is pending for this token",
+        );
+
+        self.storage_accounting(&predecessor, storage_usage_start)
+            .unwrap_or_reject();
+    }
+
+    /// Transfers the token to the recovery account once the timelock has
+    /// passed. Callable by anyone. Approvals of the token are revoked, and
+    /// the recovery configuration is cleared.
+    pub fn execute_recovery(&mut self, token_id: TokenId) {
+        let id: u32 = token_id.parse().expect_or_reject("Invalid token ID");
+        let owner_id = self
+            .token_owner(&token_id)
+            .expect_or_reject("Token does not exist");
+
+        let pending = self
+            .recoveries
+            .get(&id)
+            .and_then(|r| r.pending)
+            .expect_or_reject("No recovery is pending for this token");
+
+        let executable_at = pending
+            .executable_at_block_height
+            .expect_or_reject("Recovery has not been approved by enough guardians");
+        require!(
+            env::block_height() >= executable_at.0,
+            "Recovery timelock has not passed",
+        );
+
+        self.external_transfer(
+            &Nep171Transfer::new(
+                token_id.clone(),
+                &*owner_id,
+                &*pending.recovery_account_id,
+                Nep171TransferAuthorization::Owner,
+            )
+            .memo("Social recovery"),
+        )
+        .unwrap_or_reject();
+
+        ContractEvent::RecoveryExecuted(RecoveryExecuted {
+            token_id,
+            previous_owner_id: owner_id,
+            recovery_account_id: pending.recovery_account_id,
+        })
+        .emit();
+    }
+}
+
+impl NftKeyContract {
+    /// Returns whether a recovery was pending.
+    fn cancel_pending_recovery(&mut self, id: u32, owner_id: &AccountId) -> bool {
+        let Some(mut recovery) = self.recoveries.get(&id) else {
+            return false;
+        };
+        let Some(pending) = recovery.pending.take() else {
+            return false;
+        };
+
+        self.recoveries.insert(&id, &recovery);
+
+        ContractEvent::RecoveryCancelled(RecoveryCancelled {
+            token_id: id.to_string(),
+            owner_id: owner_id.clone(),
+            recovery_account_id: pending.recovery_account_id,
+        })
+        .emit();
+
+        true
+    }
+
+    /// Removes the recovery configuration of a token, e.g. when it changes
+    /// hands. Storage released is refunded to `owner_id`.
+    pub(crate) fn clear_recovery(&mut self, id: u32, owner_id: &AccountId) {
+        let storage_usage_start = env::storage_usage();
+
+        if self.recoveries.remove(&id).is_some() {
+            self.storage_accounting(owner_id, storage_usage_start)
+                .unwrap_or_reject();
+        }
+    }
+}
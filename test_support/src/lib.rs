@@ -0,0 +1,288 @@
+//! Sandbox fixtures for gas station integration tests.
+//!
+//! ```ignore
+//! let env = TestEnvBuilder::new().paymasters(2).chains([0, 1]).build().await;
+//! ```
+
+use lib::{
+    asset::AssetId,
+    oracle::{PYTH_PRICE_ID_ETH_USD, PYTH_PRICE_ID_NEAR_USD},
+};
+use near_sdk::{json_types::U128, serde_json::json};
+use near_workspaces::{
+    network::Sandbox, operations::Function, types::NearToken, Account, Contract, Worker,
+};
+
+const PROJECT_ROOT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/..");
+
+/// A deployed and initialized gas station, along with its dependencies.
+pub struct TestEnv {
+    pub worker: Worker<Sandbox>,
+    pub gas_station: Contract,
+    pub oracle: Contract,
+    pub signer: Contract,
+    pub nft_key: Contract,
+    pub local_ft: Contract,
+    /// Owner and administrator of the gas station.
+    pub alice: Account,
+    /// Alice's chain key, approved for use by the gas station.
+    pub alice_key: String,
+    /// The first entry of `paymaster_keys`.
+    pub paymaster_key: String,
+    /// Paymaster chain keys, each added to every chain in `chain_ids`.
+    pub paymaster_keys: Vec<String>,
+    pub chain_ids: Vec<u64>,
+    pub mark_the_market_maker: Account,
+}
+
+pub struct TestEnvBuilder {
+    paymasters: usize,
+    paymaster_balance: u128,
+    chain_ids: Vec<u64>,
+    accept_local_ft: bool,
+    gas_station_path: String,
+}
+
+impl Default for TestEnvBuilder {
+    fn default() -> Self {
+        Self {
+            paymasters: 1,
+            paymaster_balance: 10 * 10u128.pow(18),
+            chain_ids: vec![0],
+            accept_local_ft: true,
+            gas_station_path: format!("{PROJECT_ROOT}/gas_station"),
+        }
+    }
+}
+
+impl TestEnvBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of paymaster keys to mint and add to every chain. Must be at
+    /// least 1.
+    #[must_use]
+    pub fn paymasters(mut self, count: usize) -> Self {
+        self.paymasters = count;
+        self
+    }
+
+    /// Initial balance of each paymaster, in the foreign chain's smallest
+    /// unit.
+    #[must_use]
+    pub fn paymaster_balance(mut self, balance: u128) -> Self {
+        self.paymaster_balance = balance;
+        self
+    }
+
+    /// Foreign chain IDs to configure. Every chain is priced like Ethereum.
+    #[must_use]
+    pub fn chains(mut self, chain_ids: impl IntoIterator<Item = u64>) -> Self {
+        self.chain_ids = chain_ids.into_iter().collect();
+        self
+    }
+
+    /// Whether the mock NEP-141 token is accepted for fee payment. NEAR is
+    /// always accepted.
+    #[must_use]
+    pub fn accept_local_ft(mut self, accept: bool) -> Self {
+        self.accept_local_ft = accept;
+        self
+    }
+
+    /// Path to the gas station project, if it should be built with something
+    /// other than the default project.
+    #[must_use]
+    pub fn gas_station_path(mut self, path: impl Into<String>) -> Self {
+        self.gas_station_path = path.into();
+        self
+    }
+
+    #[allow(clippy::too_many_lines)]
+    pub async fn build(self) -> TestEnv {
+        assert!(self.paymasters > 0, "At least one paymaster is required");
+
+        let worker = near_workspaces::sandbox().await.unwrap();
+
+        let oracle_path = format!("{PROJECT_ROOT}/mock/oracle");
+        let signer_path = format!("{PROJECT_ROOT}/mock/signer");
+        let nft_key_path = format!("{PROJECT_ROOT}/nft_key");
+        let local_ft_path = format!("{PROJECT_ROOT}/mock/local_ft");
+
+        let (gas_station, oracle, signer, nft_key, local_ft, alice, mark_the_market_maker) = tokio::join!(
+            deploy(&worker, &self.gas_station_path),
+            deploy(&worker, &oracle_path),
+            deploy(&worker, &signer_path),
+            deploy(&worker, &nft_key_path),
+            async {
+                let c = deploy(&worker, &local_ft_path).await;
+                c.call("new")
+                    .args_json(json!({}))
+                    .transact()
+                    .await
+                    .unwrap()
+                    .unwrap();
+                c
+            },
+            async { worker.dev_create_account().await.unwrap() },
+            async { worker.dev_create_account().await.unwrap() },
+        );
+
+        nft_key
+            .call("new")
+            .args_json(json!({
+                "signer_contract_id": signer.id(),
+            }))
+            .max_gas()
+            .transact()
+            .await
+            .unwrap()
+            .unwrap();
+
+        let mut init = alice
+            .batch(gas_station.id())
+            .call(Function::new("new").args_json(json!({
+                "signer_contract_id": nft_key.id(),
+                "oracle_id": oracle.id(),
+            })))
+            .call(Function::new("add_accepted_local_asset").args_json(json!({
+                "asset_id": AssetId::Native,
+                "oracle_asset_id": PYTH_PRICE_ID_NEAR_USD,
+                "decimals": 24,
+            })));
+
+        if self.accept_local_ft {
+            init = init.call(Function::new("add_accepted_local_asset").args_json(json!({
+                "asset_id": AssetId::Nep141(local_ft.id().as_str().parse().unwrap()),
+                "oracle_asset_id": PYTH_PRICE_ID_ETH_USD,
+                "decimals": 18,
+            })));
+        }
+
+        for chain_id in &self.chain_ids {
+            init = init.call(Function::new("add_foreign_chain").args_json(json!({
+                "chain_id": chain_id.to_string(),
+                "oracle_asset_id": PYTH_PRICE_ID_ETH_USD,
+                "transfer_gas": "21000",
+                "fee_rate": ["120", "100"],
+                "decimals": 18,
+            })));
+        }
+
+        init.call(Function::new("add_market_maker").args_json(json!({
+            "account_id": mark_the_market_maker.id(),
+        })))
+        .transact()
+        .await
+        .unwrap()
+        .unwrap();
+
+        for account_id in [alice.id(), gas_station.id()] {
+            alice
+                .call(nft_key.id(), "storage_deposit")
+                .args_json(json!({
+                    "account_id": account_id,
+                }))
+                .deposit(NearToken::from_near(1))
+                .transact()
+                .await
+                .unwrap()
+                .unwrap();
+        }
+
+        let mut paymaster_keys = Vec::with_capacity(self.paymasters);
+
+        for _ in 0..self.paymasters {
+            let paymaster_key = mint(&alice, &nft_key).await;
+
+            approve(
+                &alice,
+                &nft_key,
+                &gas_station,
+                &paymaster_key,
+                Some(json!({ "is_paymaster": true }).to_string()),
+            )
+            .await;
+
+            for chain_id in &self.chain_ids {
+                alice
+                    .call(gas_station.id(), "add_paymaster")
+                    .args_json(json!({
+                        "chain_id": chain_id.to_string(),
+                        "balance": U128(self.paymaster_balance),
+                        "nonce": 0,
+                        "token_id": paymaster_key,
+                    }))
+                    .transact()
+                    .await
+                    .unwrap()
+                    .unwrap();
+            }
+
+            paymaster_keys.push(paymaster_key);
+        }
+
+        let alice_key = mint(&alice, &nft_key).await;
+        approve(&alice, &nft_key, &gas_station, &alice_key, None).await;
+
+        TestEnv {
+            worker,
+            gas_station,
+            oracle,
+            signer,
+            nft_key,
+            local_ft,
+            alice,
+            alice_key,
+            paymaster_key: paymaster_keys[0].clone(),
+            paymaster_keys,
+            chain_ids: self.chain_ids,
+            mark_the_market_maker,
+        }
+    }
+}
+
+async fn deploy(worker: &Worker<Sandbox>, project_path: &str) -> Contract {
+    let wasm = near_workspaces::compile_project(project_path)
+        .await
+        .unwrap();
+    worker.dev_deploy(&wasm).await.unwrap()
+}
+
+/// Mints a new chain key NFT owned by `owner`.
+pub async fn mint(owner: &Account, nft_key: &Contract) -> String {
+    owner
+        .call(nft_key.id(), "mint")
+        .max_gas()
+        .transact()
+        .await
+        .unwrap()
+        .json::<u32>()
+        .unwrap()
+        .to_string()
+}
+
+/// Approves `token_id` for use by the gas station, with an optional
+/// `ChainKeyReceiverMsg` as JSON.
+pub async fn approve(
+    owner: &Account,
+    nft_key: &Contract,
+    gas_station: &Contract,
+    token_id: &str,
+    msg: Option<String>,
+) {
+    owner
+        .call(nft_key.id(), "ckt_approve_call")
+        .args_json(json!({
+            "account_id": gas_station.id(),
+            "token_id": token_id,
+            "msg": msg,
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await
+        .unwrap()
+        .unwrap();
+}